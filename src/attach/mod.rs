@@ -0,0 +1,93 @@
+use crate::console::WindowSize;
+use std::io::{self, Read, Write};
+
+// frames larger than this are rejected rather than buffered
+const MAX_FRAME_LEN: usize = 1 << 20;
+
+const STDIN_FRAME: u8 = 0;
+const RESIZE_FRAME: u8 = 1;
+const STDOUT_FRAME: u8 = 2;
+const STDERR_FRAME: u8 = 3;
+const EXIT_FRAME: u8 = 4;
+
+/// Frame is a message in an interactive session with a container process.
+/// Clients send input and resize frames, the process' side sends output
+/// frames followed by an exit frame once the process exits.
+///
+/// Frames are spoken over a container's attach socket (served by its shim)
+/// encoded as a one byte kind, a big endian u32 payload length and the
+/// payload.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Frame {
+    Stdin(Vec<u8>),
+    Resize(WindowSize),
+    Stdout(Vec<u8>),
+    Stderr(Vec<u8>),
+    Exit(i32),
+}
+
+impl Frame {
+    /// write_to encodes the frame into `writer` with a single write
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let (kind, payload) = match *self {
+            Self::Stdin(ref input) => (STDIN_FRAME, input.clone()),
+            Self::Resize(size) => {
+                let mut payload = size.rows.to_be_bytes().to_vec();
+                payload.extend_from_slice(&size.cols.to_be_bytes());
+                (RESIZE_FRAME, payload)
+            }
+            Self::Stdout(ref output) => (STDOUT_FRAME, output.clone()),
+            Self::Stderr(ref output) => (STDERR_FRAME, output.clone()),
+            Self::Exit(exit_code) => (EXIT_FRAME, exit_code.to_be_bytes().to_vec()),
+        };
+        let mut encoded = Vec::with_capacity(5 + payload.len());
+        encoded.push(kind);
+        encoded.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+        encoded.extend_from_slice(&payload);
+        writer.write_all(&encoded)
+    }
+
+    /// read_from decodes the next frame from `reader`, returning None if
+    /// `reader` is closed between frames
+    pub fn read_from<R: Read>(reader: &mut R) -> io::Result<Option<Frame>> {
+        let mut header = [0u8; 5];
+        match reader.read_exact(&mut header[..1]) {
+            Ok(()) => (),
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(err) => return Err(err),
+        }
+        reader.read_exact(&mut header[1..])?;
+        let len = u32::from_be_bytes([header[1], header[2], header[3], header[4]]) as usize;
+        if len > MAX_FRAME_LEN {
+            return Err(invalid_frame(format!(
+                "frame of {} bytes is too large",
+                len
+            )));
+        }
+        let mut payload = vec![0u8; len];
+        reader.read_exact(&mut payload)?;
+        let frame = match header[0] {
+            STDIN_FRAME => Self::Stdin(payload),
+            RESIZE_FRAME if len == 4 => Self::Resize(WindowSize {
+                rows: u16::from_be_bytes([payload[0], payload[1]]),
+                cols: u16::from_be_bytes([payload[2], payload[3]]),
+            }),
+            STDOUT_FRAME => Self::Stdout(payload),
+            STDERR_FRAME => Self::Stderr(payload),
+            EXIT_FRAME if len == 4 => Self::Exit(i32::from_be_bytes([
+                payload[0], payload[1], payload[2], payload[3],
+            ])),
+            kind => {
+                return Err(invalid_frame(format!(
+                    "invalid frame of kind {} and {} bytes",
+                    kind, len
+                )))
+            }
+        };
+        Ok(Some(frame))
+    }
+}
+
+fn invalid_frame(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
@@ -1,27 +1,93 @@
-use clap::{App, AppSettings, Arg, SubCommand};
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
 use log::LevelFilter;
+use std::process;
 
 use cruise::client;
+use cruise::container::Resources;
 use cruise::logging::SimpleLogger;
 
 static LOGGER: SimpleLogger = SimpleLogger;
 
 const CONTAINER_SUBCMD: &str = "container";
+const VOLUME_SUBCMD: &str = "volume";
+const IMAGE_SUBCMD: &str = "image";
 const PORT: &str = "port";
 const DEBUG_ARG: &str = "debug";
 
 const CONTAINER_CREATE: &str = "create";
 const CONTAINER_START: &str = "start";
 const CONTAINER_STOP: &str = "stop";
+const CONTAINER_KILL: &str = "kill";
+const CONTAINER_PAUSE: &str = "pause";
+const CONTAINER_RESUME: &str = "resume";
+const CONTAINER_UPDATE: &str = "update";
+const CONTAINER_LABEL: &str = "label";
 const CONTAINER_GET: &str = "get";
 const CONTAINER_LIST: &str = "list";
 const CONTAINER_DELETE: &str = "delete";
+const CONTAINER_LOGS: &str = "logs";
+const CONTAINER_STATS: &str = "stats";
+const CONTAINER_EXEC: &str = "exec";
+const CONTAINER_ATTACH: &str = "attach";
+const CONTAINER_COMMIT: &str = "commit";
+const CONTAINER_EXPORT: &str = "export";
+const CONTAINER_COPY: &str = "cp";
+
+const VOLUME_CREATE: &str = "create";
+const VOLUME_LIST: &str = "list";
+const VOLUME_REMOVE: &str = "remove";
+const VOLUME_NAME: &str = "VOLUME_NAME";
+
+const IMAGE_IMPORT: &str = "import";
+const IMAGE_LIST: &str = "list";
+const IMAGE_REMOVE: &str = "remove";
+const IMAGE_PATH: &str = "IMAGE_PATH";
+const IMAGE_TAG: &str = "tag";
+const IMAGE_NAME: &str = "IMAGE";
 
 const CONTAINER_ROOTFS_PATH: &str = "rootfs";
+const CONTAINER_IMAGE: &str = "image";
 const CONTAINER_NAME: &str = "CONTAINER_NAME";
 const CONTAINER_ID: &str = "CONTAINER_ID";
 const CONTAINER_CMD: &str = "CONTAINER_CMD";
 const CONTAINER_ARGS: &str = "CONTAINER_ARGS";
+const CONTAINER_LABELS: &str = "LABELS";
+const TTY: &str = "tty";
+const STOP_SIGNAL: &str = "stop-signal";
+const STOP_TIMEOUT: &str = "stop-timeout";
+const RESTART_POLICY: &str = "restart";
+const LABEL: &str = "label";
+const ANNOTATION: &str = "annotation";
+const LIST_FILTER: &str = "filter";
+const SIGNAL: &str = "signal";
+const TIMEOUT: &str = "time";
+const LOGS_FOLLOW: &str = "follow";
+const LOGS_TAIL: &str = "tail";
+const LOGS_SINCE: &str = "since";
+const LOGS_STREAM: &str = "stream";
+const LOGS_TIMESTAMPS: &str = "timestamps";
+const EXPORT_OUTPUT: &str = "output";
+const COPY_SOURCE: &str = "SOURCE";
+const COPY_DESTINATION: &str = "DESTINATION";
+const STATS_NO_STREAM: &str = "no-stream";
+const EXEC_CMD: &str = "EXEC_CMD";
+const EXEC_ARGS: &str = "EXEC_ARGS";
+const ENV: &str = "env";
+const ENV_FILE: &str = "env-file";
+const WORKDIR: &str = "workdir";
+const USER: &str = "user";
+const VOLUME: &str = "volume";
+const MEMORY: &str = "memory";
+const MEMORY_RESERVATION: &str = "memory-reservation";
+const CPUS: &str = "cpus";
+const CPU_SHARES: &str = "cpu-shares";
+const CPU_QUOTA: &str = "cpu-quota";
+const CPU_PERIOD: &str = "cpu-period";
+const CPUSET_CPUS: &str = "cpuset-cpus";
+const CPUSET_MEMS: &str = "cpuset-mems";
+const PIDS_LIMIT: &str = "pids-limit";
+
+const EXEC_INTERACTIVE: &str = "interactive";
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let matches = App::new("Cruise client")
@@ -47,23 +113,132 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                     SubCommand::with_name(CONTAINER_CREATE)
                         .setting(AppSettings::TrailingVarArg)
                         .about("creates container")
+                        .arg(
+                            Arg::with_name(TTY)
+                                .help("allocate a pseudo-terminal for the container")
+                                .long(TTY)
+                                .short("t"),
+                        )
+                        .arg(
+                            Arg::with_name(STOP_SIGNAL)
+                                .help("signal to stop the container with, SIGTERM by default")
+                                .long(STOP_SIGNAL)
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::with_name(STOP_TIMEOUT)
+                                .help(
+                                    "seconds to wait for the container to stop before killing \
+                                     it, 10 by default",
+                                )
+                                .long(STOP_TIMEOUT)
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::with_name(ENV)
+                                .help("set an environment variable, as KEY=VALUE")
+                                .long(ENV)
+                                .short("e")
+                                .takes_value(true)
+                                .multiple(true)
+                                .number_of_values(1),
+                        )
+                        .arg(
+                            Arg::with_name(ENV_FILE)
+                                .help("read environment variables from a file of KEY=VALUE lines")
+                                .long(ENV_FILE)
+                                .takes_value(true)
+                                .multiple(true)
+                                .number_of_values(1),
+                        )
+                        .arg(
+                            Arg::with_name(WORKDIR)
+                                .help("working directory of the container process")
+                                .long(WORKDIR)
+                                .short("w")
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::with_name(USER)
+                                .help(
+                                    "user to run the container process as, as user[:group] by \
+                                     name or id",
+                                )
+                                .long(USER)
+                                .short("u")
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::with_name(VOLUME)
+                                .help(
+                                    "mount a host path or volume, as \
+                                     SOURCE:DESTINATION[:ro|rw,PROPAGATION]. volumes that don't \
+                                     exist are created",
+                                )
+                                .long(VOLUME)
+                                .short("v")
+                                .takes_value(true)
+                                .multiple(true)
+                                .number_of_values(1),
+                        )
+                        .args(&resource_args())
+                        .arg(
+                            Arg::with_name(LABEL)
+                                .help("set a label describing the container, as KEY=VALUE")
+                                .long(LABEL)
+                                .short("l")
+                                .takes_value(true)
+                                .multiple(true)
+                                .number_of_values(1),
+                        )
+                        .arg(
+                            Arg::with_name(ANNOTATION)
+                                .help(
+                                    "add an OCI annotation to the container's runtime spec, as \
+                                     KEY=VALUE",
+                                )
+                                .long(ANNOTATION)
+                                .takes_value(true)
+                                .multiple(true)
+                                .number_of_values(1),
+                        )
+                        .arg(
+                            Arg::with_name(RESTART_POLICY)
+                                .help(
+                                    "restart the container after it exits: no (the default), \
+                                     on-failure[:max], always or unless-stopped",
+                                )
+                                .long(RESTART_POLICY)
+                                .takes_value(true),
+                        )
                         .arg(
                             Arg::with_name(CONTAINER_ROOTFS_PATH)
-                                .help("container rootfs path")
+                                .help("container rootfs directory, or tarball optionally compressed with gzip or zstd")
                                 .long(CONTAINER_ROOTFS_PATH)
                                 .takes_value(true)
-                                .required(true),
+                                .required_unless(CONTAINER_IMAGE)
+                                .conflicts_with(CONTAINER_IMAGE),
+                        )
+                        .arg(
+                            Arg::with_name(CONTAINER_IMAGE)
+                                .help(
+                                    "imported image to create the container from, as \
+                                     NAME[:TAG] or id. its entrypoint, cmd, env, workdir and \
+                                     user apply unless given",
+                                )
+                                .long(CONTAINER_IMAGE)
+                                .takes_value(true),
                         )
                         .arg(
                             Arg::with_name(CONTAINER_NAME)
-                                .help("container name")
+                                .help("container name, unique among containers")
                                 .required(true)
                                 .index(1),
                         )
                         .arg(
                             Arg::with_name(CONTAINER_CMD)
-                                .help("container command")
-                                .required(true)
+                                .help("container command, the image's cmd by default")
+                                .required_unless(CONTAINER_IMAGE)
                                 .index(2),
                         )
                         .arg(
@@ -77,19 +252,101 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                         .about("starts container")
                         .arg(
                             Arg::with_name(CONTAINER_ID)
-                                .help("container id")
+                                .help("container id, name or unambiguous id prefix")
                                 .required(true)
                                 .index(1),
                         ),
                 )
                 .subcommand(
                     SubCommand::with_name(CONTAINER_STOP)
-                        .about("stops container")
+                        .about(
+                            "stops container, killing it if it doesn't stop within its stop \
+                             timeout",
+                        )
+                        .arg(
+                            Arg::with_name(SIGNAL)
+                                .help("signal to stop the container with")
+                                .long(SIGNAL)
+                                .short("s")
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::with_name(TIMEOUT)
+                                .help("seconds to wait for the container to stop before killing it")
+                                .long(TIMEOUT)
+                                .short("t")
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::with_name(CONTAINER_ID)
+                                .help("container id, name or unambiguous id prefix")
+                                .required(true)
+                                .index(1),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name(CONTAINER_KILL)
+                        .about("sends a signal to container")
+                        .arg(
+                            Arg::with_name(SIGNAL)
+                                .help("signal to send, by name or number")
+                                .long(SIGNAL)
+                                .short("s")
+                                .default_value("SIGKILL"),
+                        )
+                        .arg(
+                            Arg::with_name(CONTAINER_ID)
+                                .help("container id, name or unambiguous id prefix")
+                                .required(true)
+                                .index(1),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name(CONTAINER_PAUSE)
+                        .about("pauses container")
+                        .arg(
+                            Arg::with_name(CONTAINER_ID)
+                                .help("container id, name or unambiguous id prefix")
+                                .required(true)
+                                .index(1),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name(CONTAINER_RESUME)
+                        .about("resumes container")
+                        .arg(
+                            Arg::with_name(CONTAINER_ID)
+                                .help("container id, name or unambiguous id prefix")
+                                .required(true)
+                                .index(1),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name(CONTAINER_UPDATE)
+                        .about("updates cgroup limits of container, keeping limits not given")
+                        .args(&resource_args())
+                        .arg(
+                            Arg::with_name(CONTAINER_ID)
+                                .help("container id, name or unambiguous id prefix")
+                                .required(true)
+                                .index(1),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name(CONTAINER_LABEL)
+                        .about("sets and removes labels of container")
                         .arg(
                             Arg::with_name(CONTAINER_ID)
-                                .help("container id")
+                                .help("container id, name or unambiguous id prefix")
                                 .required(true)
                                 .index(1),
+                        )
+                        .arg(
+                            Arg::with_name(CONTAINER_LABELS)
+                                .help("label to set, as KEY=VALUE, or to remove, as KEY-")
+                                .required(true)
+                                .multiple(true)
+                                .index(2),
                         ),
                 )
                 .subcommand(
@@ -97,18 +354,295 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                         .about("gets container")
                         .arg(
                             Arg::with_name(CONTAINER_ID)
-                                .help("container id")
+                                .help("container id, name or unambiguous id prefix")
                                 .required(true)
                                 .index(1),
                         ),
                 )
-                .subcommand(SubCommand::with_name(CONTAINER_LIST).about("lists container"))
+                .subcommand(
+                    SubCommand::with_name(CONTAINER_LIST)
+                        .about("lists container")
+                        .arg(
+                            Arg::with_name(LIST_FILTER)
+                                .help(
+                                    "only list containers matching a filter: label=SELECTOR, \
+                                     such as label=env=prod,team!=infra, status=STATUS or \
+                                     name=GLOB. containers match all label filters, and one \
+                                     status and one name filter",
+                                )
+                                .long(LIST_FILTER)
+                                .short("f")
+                                .takes_value(true)
+                                .multiple(true)
+                                .number_of_values(1),
+                        ),
+                )
                 .subcommand(
                     SubCommand::with_name(CONTAINER_DELETE)
                         .about("deletes container")
                         .arg(
                             Arg::with_name(CONTAINER_ID)
-                                .help("container id")
+                                .help("container id, name or unambiguous id prefix")
+                                .required(true)
+                                .index(1),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name(CONTAINER_COMMIT)
+                        .about("saves created or stopped container's rootfs as an image")
+                        .arg(
+                            Arg::with_name(CONTAINER_ID)
+                                .help("container id, name or unambiguous id prefix")
+                                .required(true)
+                                .index(1),
+                        )
+                        .arg(
+                            Arg::with_name(IMAGE_NAME)
+                                .help("reference to give the image, as NAME[:TAG]")
+                                .required(true)
+                                .index(2),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name(CONTAINER_EXPORT)
+                        .about("exports container's rootfs as a tar archive")
+                        .arg(
+                            Arg::with_name(EXPORT_OUTPUT)
+                                .help("file to write the archive to, stdout by default")
+                                .long(EXPORT_OUTPUT)
+                                .short("o")
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::with_name(CONTAINER_ID)
+                                .help("container id, name or unambiguous id prefix")
+                                .required(true)
+                                .index(1),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name(CONTAINER_COPY)
+                        .about("copies files between a container and the local filesystem")
+                        .arg(
+                            Arg::with_name(COPY_SOURCE)
+                                .help("local path, or CONTAINER:PATH to copy from a container")
+                                .required(true)
+                                .index(1),
+                        )
+                        .arg(
+                            Arg::with_name(COPY_DESTINATION)
+                                .help("local path, or CONTAINER:PATH to copy into a container")
+                                .required(true)
+                                .index(2),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name(CONTAINER_LOGS)
+                        .about("prints container output")
+                        .arg(
+                            Arg::with_name(LOGS_FOLLOW)
+                                .help("keep printing new output until the container stops")
+                                .long(LOGS_FOLLOW)
+                                .short("f"),
+                        )
+                        .arg(
+                            Arg::with_name(LOGS_TAIL)
+                                .help("only print the last N lines")
+                                .long(LOGS_TAIL)
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::with_name(LOGS_SINCE)
+                                .help("only print lines logged since this RFC 3339 timestamp")
+                                .long(LOGS_SINCE)
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::with_name(LOGS_STREAM)
+                                .help("only print lines from this stream")
+                                .long(LOGS_STREAM)
+                                .possible_values(&["stdout", "stderr"])
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::with_name(LOGS_TIMESTAMPS)
+                                .help("prefix lines with the time they were logged")
+                                .long(LOGS_TIMESTAMPS)
+                                .short("t"),
+                        )
+                        .arg(
+                            Arg::with_name(CONTAINER_ID)
+                                .help("container id, name or unambiguous id prefix")
+                                .required(true)
+                                .index(1),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name(CONTAINER_STATS)
+                        .about(
+                            "shows resource usage of container, refreshed every second until it \
+                             stops",
+                        )
+                        .arg(
+                            Arg::with_name(STATS_NO_STREAM)
+                                .help("print a single sample and exit")
+                                .long(STATS_NO_STREAM),
+                        )
+                        .arg(
+                            Arg::with_name(CONTAINER_ID)
+                                .help("container id, name or unambiguous id prefix")
+                                .required(true)
+                                .index(1),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name(CONTAINER_EXEC)
+                        .setting(AppSettings::TrailingVarArg)
+                        .about("runs a process in a running container")
+                        .arg(
+                            Arg::with_name(EXEC_INTERACTIVE)
+                                .help("forward stdin to the process")
+                                .long(EXEC_INTERACTIVE)
+                                .short("i"),
+                        )
+                        .arg(
+                            Arg::with_name(TTY)
+                                .help("allocate a pseudo-terminal for the process")
+                                .long(TTY)
+                                .short("t"),
+                        )
+                        .arg(
+                            Arg::with_name(ENV)
+                                .help("set an environment variable, as KEY=VALUE")
+                                .long(ENV)
+                                .short("e")
+                                .takes_value(true)
+                                .multiple(true)
+                                .number_of_values(1),
+                        )
+                        .arg(
+                            Arg::with_name(ENV_FILE)
+                                .help("read environment variables from a file of KEY=VALUE lines")
+                                .long(ENV_FILE)
+                                .takes_value(true)
+                                .multiple(true)
+                                .number_of_values(1),
+                        )
+                        .arg(
+                            Arg::with_name(WORKDIR)
+                                .help("working directory of the process")
+                                .long(WORKDIR)
+                                .short("w")
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::with_name(USER)
+                                .help("user to run the process as, as user[:group] by name or id")
+                                .long(USER)
+                                .short("u")
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::with_name(CONTAINER_ID)
+                                .help("container id, name or unambiguous id prefix")
+                                .required(true)
+                                .index(1),
+                        )
+                        .arg(
+                            Arg::with_name(EXEC_CMD)
+                                .help("process command")
+                                .required(true)
+                                .index(2),
+                        )
+                        .arg(
+                            Arg::with_name(EXEC_ARGS)
+                                .help("process args")
+                                .multiple(true),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name(CONTAINER_ATTACH)
+                        .about(
+                            "attaches to a container's output, and its terminal if it has one. \
+                             detach with ctrl-p ctrl-q",
+                        )
+                        .arg(
+                            Arg::with_name(CONTAINER_ID)
+                                .help("container id, name or unambiguous id prefix")
+                                .required(true)
+                                .index(1),
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name(VOLUME_SUBCMD)
+                .about("controls volumes")
+                .arg(
+                    Arg::with_name(PORT)
+                        .help("port where client connects to daemon")
+                        .long(PORT)
+                        .default_value("50051"),
+                )
+                .subcommand(
+                    SubCommand::with_name(VOLUME_CREATE)
+                        .about("creates volume")
+                        .arg(
+                            Arg::with_name(VOLUME_NAME)
+                                .help("volume name, generated if not given")
+                                .index(1),
+                        ),
+                )
+                .subcommand(SubCommand::with_name(VOLUME_LIST).about("lists volumes"))
+                .subcommand(
+                    SubCommand::with_name(VOLUME_REMOVE)
+                        .about("removes volume that no container mounts, and its data")
+                        .arg(
+                            Arg::with_name(VOLUME_NAME)
+                                .help("volume name")
+                                .required(true)
+                                .index(1),
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name(IMAGE_SUBCMD)
+                .about("controls images")
+                .arg(
+                    Arg::with_name(PORT)
+                        .help("port where client connects to daemon")
+                        .long(PORT)
+                        .default_value("50051"),
+                )
+                .subcommand(
+                    SubCommand::with_name(IMAGE_IMPORT)
+                        .about("imports images from a docker save archive or OCI image layout")
+                        .arg(
+                            Arg::with_name(IMAGE_TAG)
+                                .help("reference to give the image, as NAME[:TAG]")
+                                .long(IMAGE_TAG)
+                                .short("t")
+                                .takes_value(true),
+                        )
+                        .arg(
+                            Arg::with_name(IMAGE_PATH)
+                                .help(
+                                    "path on the daemon's host of the archive or layout, as a \
+                                     directory or tarball",
+                                )
+                                .required(true)
+                                .index(1),
+                        ),
+                )
+                .subcommand(SubCommand::with_name(IMAGE_LIST).about("lists images"))
+                .subcommand(
+                    SubCommand::with_name(IMAGE_REMOVE)
+                        .about(
+                            "removes image reference, and the image once no reference or \
+                             container is left",
+                        )
+                        .arg(
+                            Arg::with_name(IMAGE_NAME)
+                                .help("image reference or id")
                                 .required(true)
                                 .index(1),
                         ),
@@ -121,7 +655,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     } else {
         LevelFilter::Error
     };
-    let _ = log::set_logger(&LOGGER)
+    log::set_logger(&LOGGER)
         .map(|()| log::set_max_level(log_level))
         .unwrap_or_else(|err| {
             eprintln!("failed to set logger: `{}`", err);
@@ -131,20 +665,38 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         let port = matches.value_of(PORT).unwrap();
         if let Some(matches) = matches.subcommand_matches(CONTAINER_CREATE) {
             let container_name = matches.value_of(CONTAINER_NAME).unwrap();
-            let container_cmd = matches.value_of(CONTAINER_CMD).unwrap();
-            let container_rootfs_path = matches.value_of(CONTAINER_ROOTFS_PATH).unwrap();
+            let container_cmd = matches.value_of(CONTAINER_CMD).unwrap_or("");
+            let container_rootfs_path = matches.value_of(CONTAINER_ROOTFS_PATH).unwrap_or("");
             let container_args = matches
                 .values_of(CONTAINER_ARGS)
-                .unwrap()
-                .map(|s| s.to_string())
-                .collect();
-            client::create_container(
-                port,
-                container_name,
-                container_cmd,
-                container_args,
-                container_rootfs_path,
-            )?;
+                .map(|values| values.map(|s| s.to_string()).collect())
+                .unwrap_or_default();
+            let stop_timeout = match matches.value_of(STOP_TIMEOUT) {
+                Some(stop_timeout) => Some(stop_timeout.parse()?),
+                None => None,
+            };
+            let create_opts = client::CreateOptions {
+                env: read_env(matches)?,
+                cwd: matches.value_of(WORKDIR).unwrap_or("").into(),
+                user: matches.value_of(USER).unwrap_or("").into(),
+                name: container_name.into(),
+                command: container_cmd.into(),
+                args: container_args,
+                rootfs_path: container_rootfs_path.into(),
+                image: matches.value_of(CONTAINER_IMAGE).unwrap_or("").into(),
+                tty: matches.is_present(TTY),
+                stop_signal: matches.value_of(STOP_SIGNAL).unwrap_or("").into(),
+                stop_timeout,
+                restart_policy: matches.value_of(RESTART_POLICY).unwrap_or("").into(),
+                volumes: matches
+                    .values_of(VOLUME)
+                    .map(|values| values.map(|s| s.to_string()).collect())
+                    .unwrap_or_default(),
+                resources: read_resources(matches)?,
+                labels: values(matches, LABEL),
+                annotations: values(matches, ANNOTATION),
+            };
+            client::create_container(port, create_opts)?;
         }
         if let Some(matches) = matches.subcommand_matches(CONTAINER_START) {
             let container_id = matches.value_of(CONTAINER_ID).unwrap();
@@ -152,19 +704,277 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
         if let Some(matches) = matches.subcommand_matches(CONTAINER_STOP) {
             let container_id = matches.value_of(CONTAINER_ID).unwrap();
-            client::stop_container(port, container_id.into())?;
+            let timeout = match matches.value_of(TIMEOUT) {
+                Some(timeout) => Some(timeout.parse()?),
+                None => None,
+            };
+            client::stop_container(
+                port,
+                container_id.into(),
+                matches.value_of(SIGNAL).unwrap_or(""),
+                timeout,
+            )?;
+        }
+        if let Some(matches) = matches.subcommand_matches(CONTAINER_KILL) {
+            let container_id = matches.value_of(CONTAINER_ID).unwrap();
+            let signal = matches.value_of(SIGNAL).unwrap();
+            client::kill_container(port, container_id.into(), signal)?;
+        }
+        if let Some(matches) = matches.subcommand_matches(CONTAINER_PAUSE) {
+            let container_id = matches.value_of(CONTAINER_ID).unwrap();
+            client::pause_container(port, container_id.into())?;
+        }
+        if let Some(matches) = matches.subcommand_matches(CONTAINER_RESUME) {
+            let container_id = matches.value_of(CONTAINER_ID).unwrap();
+            client::resume_container(port, container_id.into())?;
+        }
+        if let Some(matches) = matches.subcommand_matches(CONTAINER_UPDATE) {
+            let container_id = matches.value_of(CONTAINER_ID).unwrap();
+            client::update_container(port, container_id.into(), read_resources(matches)?)?;
+        }
+        if let Some(matches) = matches.subcommand_matches(CONTAINER_LABEL) {
+            let container_id = matches.value_of(CONTAINER_ID).unwrap();
+            client::label_container(port, container_id.into(), values(matches, CONTAINER_LABELS))?;
         }
         if let Some(matches) = matches.subcommand_matches(CONTAINER_GET) {
             let container_id = matches.value_of(CONTAINER_ID).unwrap();
             client::get_container(port, container_id.into())?;
         }
-        if let Some(_) = matches.subcommand_matches(CONTAINER_LIST) {
-            client::list_containers(port)?;
+        if let Some(matches) = matches.subcommand_matches(CONTAINER_LIST) {
+            client::list_containers(port, values(matches, LIST_FILTER))?;
+        }
+        if let Some(matches) = matches.subcommand_matches(CONTAINER_COMMIT) {
+            let container_id = matches.value_of(CONTAINER_ID).unwrap();
+            let reference = matches.value_of(IMAGE_NAME).unwrap();
+            client::commit_container(port, container_id.into(), reference.into())?;
+        }
+        if let Some(matches) = matches.subcommand_matches(CONTAINER_EXPORT) {
+            let container_id = matches.value_of(CONTAINER_ID).unwrap();
+            let output = matches
+                .value_of(EXPORT_OUTPUT)
+                .map(|output| output.to_string());
+            client::export_container(port, container_id.into(), output)?;
+        }
+        if let Some(matches) = matches.subcommand_matches(CONTAINER_COPY) {
+            let source = matches.value_of(COPY_SOURCE).unwrap();
+            let destination = matches.value_of(COPY_DESTINATION).unwrap();
+            match (
+                parse_container_path(source),
+                parse_container_path(destination),
+            ) {
+                (Some((container_id, path)), None) => client::copy_from_container(
+                    port,
+                    container_id.into(),
+                    path.into(),
+                    destination.into(),
+                )?,
+                (None, Some((container_id, path))) => client::copy_to_container(
+                    port,
+                    source.into(),
+                    container_id.into(),
+                    path.into(),
+                )?,
+                _ => {
+                    return Err(
+                        "exactly one of SOURCE and DESTINATION must be CONTAINER:PATH".into(),
+                    )
+                }
+            }
         }
         if let Some(matches) = matches.subcommand_matches(CONTAINER_DELETE) {
             let container_id = matches.value_of(CONTAINER_ID).unwrap();
             client::delete_container(port, container_id.into())?;
         }
+        if let Some(matches) = matches.subcommand_matches(CONTAINER_LOGS) {
+            let container_id = matches.value_of(CONTAINER_ID).unwrap();
+            let tail = match matches.value_of(LOGS_TAIL) {
+                Some(tail) => tail.parse()?,
+                None => 0,
+            };
+            client::container_logs(
+                port,
+                container_id.into(),
+                matches.is_present(LOGS_FOLLOW),
+                tail,
+                matches.value_of(LOGS_SINCE).unwrap_or("").into(),
+                matches.value_of(LOGS_STREAM).unwrap_or("").into(),
+                matches.is_present(LOGS_TIMESTAMPS),
+            )?;
+        }
+        if let Some(matches) = matches.subcommand_matches(CONTAINER_STATS) {
+            let container_id = matches.value_of(CONTAINER_ID).unwrap();
+            client::container_stats(
+                port,
+                container_id.into(),
+                !matches.is_present(STATS_NO_STREAM),
+            )?;
+        }
+        if let Some(matches) = matches.subcommand_matches(CONTAINER_EXEC) {
+            let container_id = matches.value_of(CONTAINER_ID).unwrap();
+            let exec_opts = client::ExecOptions {
+                command: matches.value_of(EXEC_CMD).unwrap().into(),
+                args: values(matches, EXEC_ARGS),
+                env: read_env(matches)?,
+                cwd: matches.value_of(WORKDIR).unwrap_or("").into(),
+                user: matches.value_of(USER).unwrap_or("").into(),
+            };
+            let interactive = matches.is_present(EXEC_INTERACTIVE);
+            let tty = matches.is_present(TTY);
+            let exit_code = if interactive || tty {
+                client::interactive_exec_container(
+                    port,
+                    container_id.into(),
+                    exec_opts,
+                    interactive,
+                    tty,
+                )?
+            } else {
+                client::exec_container(port, container_id.into(), exec_opts)?
+            };
+            // exit with the process' exit code, like the process was run directly
+            process::exit(exit_code);
+        }
+        if let Some(matches) = matches.subcommand_matches(CONTAINER_ATTACH) {
+            let container_id = matches.value_of(CONTAINER_ID).unwrap();
+            let exit_code = client::attach_container(port, container_id.into())?;
+            process::exit(exit_code);
+        }
+    }
+    if let Some(matches) = matches.subcommand_matches(VOLUME_SUBCMD) {
+        let port = matches.value_of(PORT).unwrap();
+        if let Some(matches) = matches.subcommand_matches(VOLUME_CREATE) {
+            let volume_name = matches.value_of(VOLUME_NAME).unwrap_or("");
+            client::create_volume(port, volume_name.into())?;
+        }
+        if matches.subcommand_matches(VOLUME_LIST).is_some() {
+            client::list_volumes(port)?;
+        }
+        if let Some(matches) = matches.subcommand_matches(VOLUME_REMOVE) {
+            let volume_name = matches.value_of(VOLUME_NAME).unwrap();
+            client::remove_volume(port, volume_name.into())?;
+        }
+    }
+    if let Some(matches) = matches.subcommand_matches(IMAGE_SUBCMD) {
+        let port = matches.value_of(PORT).unwrap();
+        if let Some(matches) = matches.subcommand_matches(IMAGE_IMPORT) {
+            let image_path = matches.value_of(IMAGE_PATH).unwrap();
+            let image_tag = matches.value_of(IMAGE_TAG).unwrap_or("");
+            client::import_image(port, image_path.into(), image_tag.into())?;
+        }
+        if matches.subcommand_matches(IMAGE_LIST).is_some() {
+            client::list_images(port)?;
+        }
+        if let Some(matches) = matches.subcommand_matches(IMAGE_REMOVE) {
+            let image = matches.value_of(IMAGE_NAME).unwrap();
+            client::remove_image(port, image.into())?;
+        }
     }
     Ok(())
 }
+
+/// parse_container_path splits a path given as CONTAINER:PATH into the
+/// container and the path in it. local paths may hold a colon after a slash,
+/// as in ./a:b
+fn parse_container_path(path: &str) -> Option<(&str, &str)> {
+    let colon = path.find(':')?;
+    let container_id = &path[..colon];
+    if container_id.is_empty() || container_id.contains('/') {
+        return None;
+    }
+    Some((container_id, &path[colon + 1..]))
+}
+
+/// values collects the values given to an argument, none if it wasn't given
+fn values(matches: &ArgMatches, name: &str) -> Vec<String> {
+    matches
+        .values_of(name)
+        .map(|values| values.map(|s| s.to_string()).collect())
+        .unwrap_or_default()
+}
+
+/// read_env collects the environment given with --env-file and --env, with
+/// --env taking precedence
+fn read_env(matches: &ArgMatches) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let mut env = vec![];
+    for env_file in matches.values_of(ENV_FILE).into_iter().flatten() {
+        env.extend(client::read_env_file(env_file)?);
+    }
+    env.extend(
+        matches
+            .values_of(ENV)
+            .into_iter()
+            .flatten()
+            .map(|pair| pair.to_string()),
+    );
+    Ok(env)
+}
+
+/// resource_args are the flags setting a container's cgroup limits
+fn resource_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name(MEMORY)
+            .help("memory limit, in bytes with an optional b, k, m or g unit, -1 for unlimited")
+            .long(MEMORY)
+            .short("m")
+            .takes_value(true)
+            .allow_hyphen_values(true),
+        Arg::with_name(MEMORY_RESERVATION)
+            .help("memory guaranteed under memory pressure, in the same units as --memory")
+            .long(MEMORY_RESERVATION)
+            .takes_value(true)
+            .allow_hyphen_values(true),
+        Arg::with_name(CPUS)
+            .help("number of CPUs the container may use, such as 1.5")
+            .long(CPUS)
+            .takes_value(true)
+            .conflicts_with(CPU_QUOTA),
+        Arg::with_name(CPU_SHARES)
+            .help("relative weight of the container's CPU time")
+            .long(CPU_SHARES)
+            .takes_value(true),
+        Arg::with_name(CPU_QUOTA)
+            .help("CPU time in microseconds the container may use every period, -1 for unlimited")
+            .long(CPU_QUOTA)
+            .takes_value(true)
+            .allow_hyphen_values(true),
+        Arg::with_name(CPU_PERIOD)
+            .help("length of a CPU period in microseconds")
+            .long(CPU_PERIOD)
+            .takes_value(true),
+        Arg::with_name(CPUSET_CPUS)
+            .help("CPUs the container may run on, such as 0-2,4")
+            .long(CPUSET_CPUS)
+            .takes_value(true),
+        Arg::with_name(CPUSET_MEMS)
+            .help("memory nodes the container may allocate from, such as 0-2,4")
+            .long(CPUSET_MEMS)
+            .takes_value(true),
+        Arg::with_name(PIDS_LIMIT)
+            .help("limit on the number of processes in the container, -1 for unlimited")
+            .long(PIDS_LIMIT)
+            .takes_value(true)
+            .allow_hyphen_values(true),
+    ]
+}
+
+/// read_resources collects the cgroup limits given with the flags of
+/// resource_args. --cpus is turned into a quota over the CPU period
+fn read_resources(matches: &ArgMatches) -> Result<Resources, Box<dyn std::error::Error>> {
+    let parse_memory = |name| matches.value_of(name).map(client::parse_memory).transpose();
+    let mut resources = Resources {
+        memory_limit: parse_memory(MEMORY)?,
+        memory_reservation: parse_memory(MEMORY_RESERVATION)?,
+        cpu_shares: matches.value_of(CPU_SHARES).map(str::parse).transpose()?,
+        cpu_quota: matches.value_of(CPU_QUOTA).map(str::parse).transpose()?,
+        cpu_period: matches.value_of(CPU_PERIOD).map(str::parse).transpose()?,
+        cpuset_cpus: matches.value_of(CPUSET_CPUS).unwrap_or("").into(),
+        cpuset_mems: matches.value_of(CPUSET_MEMS).unwrap_or("").into(),
+        pids_limit: matches.value_of(PIDS_LIMIT).map(str::parse).transpose()?,
+    };
+    if let Some(cpus) = matches.value_of(CPUS) {
+        let (cpu_period, cpu_quota) = client::parse_cpus(cpus, resources.cpu_period)?;
+        resources.cpu_period = Some(cpu_period);
+        resources.cpu_quota = Some(cpu_quota);
+    }
+    Ok(resources)
+}
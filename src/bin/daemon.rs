@@ -1,8 +1,10 @@
 use clap::{App, Arg, SubCommand};
 use log::{error, LevelFilter};
+use std::env;
 use std::process;
 
 use cruise::container_manager;
+use cruise::container_manager::container_runtime::{self, RuntimeKind};
 use cruise::daemon;
 use cruise::logging::SimpleLogger;
 
@@ -11,7 +13,9 @@ static LOGGER: SimpleLogger = SimpleLogger;
 const RUN_SUBCMD: &str = "run";
 const PORT: &str = "port";
 const LIB_ROOT: &str = "lib_root";
+const RUNTIME: &str = "runtime";
 const RUNTIME_PATH: &str = "runtime_path";
+const SHIM_PATH: &str = "shim_path";
 const DEBUG_ARG: &str = "debug";
 
 fn main() {
@@ -39,13 +43,30 @@ fn main() {
                     .long(LIB_ROOT)
                     .default_value("/var/lib/cruise"),
             )
+            .arg(
+                Arg::with_name(RUNTIME)
+                    .help(
+                        "OCI runtime used to run containers",
+                    )
+                    .long(RUNTIME)
+                    .possible_values(RuntimeKind::VARIANTS)
+                    .default_value("runc"),
+            )
             .arg(
                 Arg::with_name(RUNTIME_PATH)
                     .help(
-                        "path to runtime (runc) executable",
+                        "path to runtime executable, defaults to /usr/bin/<runtime>",
                     )
                     .long(RUNTIME_PATH)
-                    .default_value("/usr/bin/runc"),
+                    .takes_value(true),
+            )
+            .arg(
+                Arg::with_name(SHIM_PATH)
+                    .help(
+                        "path to shim executable, defaults to the shim next to the daemon executable",
+                    )
+                    .long(SHIM_PATH)
+                    .takes_value(true),
             ),
         )
         .get_matches();
@@ -55,7 +76,7 @@ fn main() {
     } else {
         LevelFilter::Error
     };
-    let _ = log::set_logger(&LOGGER)
+    log::set_logger(&LOGGER)
         .map(|()| log::set_max_level(log_level))
         .unwrap_or_else(|err| {
             eprintln!("failed to set logger: `{}`", err);
@@ -64,9 +85,25 @@ fn main() {
     if let Some(matches) = matches.subcommand_matches(RUN_SUBCMD) {
         let port = matches.value_of(PORT).unwrap();
         let root_dir = matches.value_of(LIB_ROOT).unwrap();
-        let runtime_path = matches.value_of(RUNTIME_PATH).unwrap();
+        // clap has already validated the runtime against RuntimeKind::VARIANTS
+        let runtime_kind: RuntimeKind = matches.value_of(RUNTIME).unwrap().parse().unwrap();
+        let runtime_path = match matches.value_of(RUNTIME_PATH) {
+            Some(runtime_path) => runtime_path.into(),
+            None => runtime_kind.default_path(),
+        };
+        let shim_path = match matches.value_of(SHIM_PATH) {
+            Some(shim_path) => shim_path.into(),
+            None => match default_shim_path() {
+                Some(shim_path) => shim_path,
+                None => {
+                    error!("failed to locate shim executable, pass --{}", SHIM_PATH);
+                    process::exit(1);
+                }
+            },
+        };
+        let container_runtime = container_runtime::new(runtime_kind, runtime_path, shim_path);
         let container_manager =
-            match container_manager::ContainerManager::new(root_dir.into(), runtime_path.into()) {
+            match container_manager::ContainerManager::new(root_dir.into(), container_runtime) {
                 Ok(container_manager) => container_manager,
                 Err(err) => {
                     error!("failed to create container manager: {}", err);
@@ -78,3 +115,11 @@ fn main() {
             .expect("Cruise daemon server failed");
     }
 }
+
+/// default_shim_path looks for the shim executable next to the daemon executable,
+/// which is where cargo builds it
+fn default_shim_path() -> Option<String> {
+    let daemon_path = env::current_exe().ok()?;
+    let shim_path = daemon_path.parent()?.join("shim");
+    Some(shim_path.to_string_lossy().to_string())
+}
@@ -0,0 +1,77 @@
+use clap::{App, AppSettings, Arg};
+use std::process;
+
+use cruise::shim::{self, ShimOptions};
+
+const EXIT_FILE: &str = "exit_file";
+const PID_FILE: &str = "pid_file";
+const LOG_FILE: &str = "log_file";
+const ATTACH_SOCKET: &str = "attach_socket";
+const CONSOLE_SOCKET: &str = "console_socket";
+const RUNTIME_CMD: &str = "RUNTIME_CMD";
+
+fn main() {
+    let matches = App::new("Cruise shim")
+        .version("0.0")
+        .author("Will D. <wjdeuschle@gmail.com>")
+        .about("Cruise container shim, supervises a single container for the daemon")
+        .setting(AppSettings::TrailingVarArg)
+        .arg(
+            Arg::with_name(EXIT_FILE)
+                .help("file the container's exit code and finish time are written to")
+                .long(EXIT_FILE)
+                .takes_value(true)
+                .required(true),
+        )
+        .arg(
+            Arg::with_name(PID_FILE)
+                .help("file the runtime writes the container's init pid to")
+                .long(PID_FILE)
+                .takes_value(true)
+                .required(true),
+        )
+        .arg(
+            Arg::with_name(LOG_FILE)
+                .help("file the container's stdout and stderr are logged to")
+                .long(LOG_FILE)
+                .takes_value(true)
+                .required(true),
+        )
+        .arg(
+            Arg::with_name(ATTACH_SOCKET)
+                .help("socket clients attach to the container through")
+                .long(ATTACH_SOCKET)
+                .takes_value(true)
+                .required(true),
+        )
+        .arg(
+            Arg::with_name(CONSOLE_SOCKET)
+                .help("socket the runtime sends the container's terminal over, if it has one")
+                .long(CONSOLE_SOCKET)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name(RUNTIME_CMD)
+                .help("runtime create command")
+                .required(true)
+                .multiple(true),
+        )
+        .get_matches();
+
+    let opts = ShimOptions {
+        exit_file: matches.value_of(EXIT_FILE).unwrap().into(),
+        pid_file: matches.value_of(PID_FILE).unwrap().into(),
+        log_file: matches.value_of(LOG_FILE).unwrap().into(),
+        attach_socket: matches.value_of(ATTACH_SOCKET).unwrap().into(),
+        console_socket: matches.value_of(CONSOLE_SOCKET).map(|s| s.to_string()),
+        runtime_cmd: matches
+            .values_of(RUNTIME_CMD)
+            .unwrap()
+            .map(|s| s.to_string())
+            .collect(),
+    };
+    if let Err(err) = shim::run(opts) {
+        eprintln!("{}", err);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,112 @@
+use futures::channel::mpsc;
+use futures::executor::block_on;
+use futures::SinkExt;
+use std::io::{self, Read, Write};
+use std::sync;
+
+// how many bytes of a byte stream, such as a tar archive, each message of a
+// streaming rpc carries
+pub const CHUNK_SIZE: usize = 64 * 1024;
+
+/// FromChunk is implemented by the messages of streaming rpcs that carry
+/// chunks of a byte stream
+pub trait FromChunk {
+    fn from_chunk(chunk: Vec<u8>) -> Self;
+}
+
+// the responses of server streaming rpcs are results
+impl<T: FromChunk, E> FromChunk for Result<T, E> {
+    fn from_chunk(chunk: Vec<u8>) -> Self {
+        Ok(T::from_chunk(chunk))
+    }
+}
+
+/// ChunkWriter sends what's written to it as messages of a streaming rpc,
+/// each carrying a chunk of CHUNK_SIZE bytes. what's left of the last chunk
+/// is sent on flush
+pub struct ChunkWriter<T> {
+    tx: mpsc::Sender<T>,
+    chunk: Vec<u8>,
+}
+
+impl<T: FromChunk> ChunkWriter<T> {
+    pub fn new(tx: mpsc::Sender<T>) -> ChunkWriter<T> {
+        ChunkWriter {
+            tx,
+            chunk: Vec::with_capacity(CHUNK_SIZE),
+        }
+    }
+
+    /// is_closed returns whether the other side of the rpc has gone away
+    pub fn is_closed(&self) -> bool {
+        self.tx.is_closed()
+    }
+
+    /// send sends a message that isn't a chunk, such as an error ending the
+    /// stream
+    pub fn send(&mut self, message: T) -> io::Result<()> {
+        block_on(self.tx.send(message))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "the other side went away"))
+    }
+
+    fn send_chunk(&mut self) -> io::Result<()> {
+        let chunk = std::mem::replace(&mut self.chunk, Vec::with_capacity(CHUNK_SIZE));
+        self.send(T::from_chunk(chunk))
+    }
+}
+
+impl<T: FromChunk> Write for ChunkWriter<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = buf.len().min(CHUNK_SIZE - self.chunk.len());
+        self.chunk.extend_from_slice(&buf[..len]);
+        if self.chunk.len() == CHUNK_SIZE {
+            self.send_chunk()?;
+        }
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.chunk.is_empty() {
+            return Ok(());
+        }
+        self.send_chunk()
+    }
+}
+
+/// ChunkReader reads the chunks of a byte stream received from a streaming
+/// rpc, until their sender is dropped
+pub struct ChunkReader {
+    rx: sync::mpsc::Receiver<Vec<u8>>,
+    chunk: Vec<u8>,
+    // how much of the chunk has been read
+    pos: usize,
+}
+
+impl ChunkReader {
+    pub fn new(rx: sync::mpsc::Receiver<Vec<u8>>) -> ChunkReader {
+        ChunkReader {
+            rx,
+            chunk: vec![],
+            pos: 0,
+        }
+    }
+}
+
+impl Read for ChunkReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.chunk.len() {
+            match self.rx.recv() {
+                Ok(chunk) => {
+                    self.chunk = chunk;
+                    self.pos = 0;
+                }
+                // the stream has ended
+                Err(_) => return Ok(0),
+            }
+        }
+        let len = buf.len().min(self.chunk.len() - self.pos);
+        buf[..len].copy_from_slice(&self.chunk[self.pos..self.pos + len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
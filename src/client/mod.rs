@@ -1,31 +1,127 @@
-use crate::container::ID;
+use crate::chunk::{ChunkReader, ChunkWriter, FromChunk};
+use crate::console::{self, RawMode};
+use crate::container::{Resources, ID, MOUNT_PROPAGATIONS};
+use crate::container_manager::tarball::{extract_archive, write_tarball_entry};
+use crate::image::short_id;
 use cruise_grpc::cruise_client::CruiseClient;
 use cruise_grpc::{
-    CreateContainerRequest, DeleteContainerRequest, GetContainerRequest, GetContainerResponse,
-    ListContainersRequest, StartContainerRequest, StopContainerRequest,
+    attach_container_request, copy_to_container_request, interactive_exec_container_request,
+    interactive_response, AttachContainerRequest, CopyToContainerRequest, CopyToContainerStart,
+    InteractiveExecContainerRequest, InteractiveExecContainerStart, InteractiveResponse,
+    TerminalSize,
 };
+use cruise_grpc::{
+    CommitContainerRequest, ContainerLogsRequest, ContainerStatsRequest, ContainerStatsResponse,
+    CopyFromContainerRequest, CreateContainerRequest, DeleteContainerRequest, ExecContainerRequest,
+    ExportContainerRequest, GetContainerRequest, GetContainerResponse, KillContainerRequest,
+    ListContainersRequest, PauseContainerRequest, ResumeContainerRequest, StartContainerRequest,
+    StopContainerRequest, StopTimeout, UpdateContainerLabelsRequest, UpdateContainerRequest,
+};
+use cruise_grpc::{CreateVolumeRequest, ListVolumesRequest, Mount, RemoveVolumeRequest, Volume};
+use cruise_grpc::{Image, ImportImageRequest, ListImagesRequest, RemoveImageRequest};
+use futures::channel::mpsc;
+use futures::executor::block_on;
+use futures::SinkExt;
 use log::debug;
 use std::cmp::max;
+use std::collections::HashMap;
+use std::env;
+use std::ffi::OsString;
+use std::fs::{canonicalize, read_to_string, remove_file, symlink_metadata, File};
+use std::io::{stderr, stdin, stdout, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync;
+use std::thread;
+use tokio::signal::unix::{signal, SignalKind};
+use tonic::Streaming;
 
 mod cruise_grpc {
     tonic::include_proto!("cruise");
 }
 
+// how many requests an interactive session buffers before waiting on the daemon
+const SESSION_BUFFER_SIZE: usize = 64;
+// ctrl-p followed by ctrl-q detaches from a container's terminal
+const DETACH_KEYS: [u8; 2] = [0x10, 0x11];
+// clears the terminal and moves the cursor to its top left corner
+const CLEAR_SCREEN: &str = "\x1b[2J\x1b[H";
+
+pub struct CreateOptions {
+    pub name: String,
+    pub command: String,
+    pub args: Vec<String>,
+    pub rootfs_path: String,
+    // reference or id of the image to create the container from instead of
+    // rootfs_path
+    pub image: String,
+    // allocate a pseudo-terminal for the container process
+    pub tty: bool,
+    // signal to stop the container with, the daemon's default if empty
+    pub stop_signal: String,
+    // seconds to wait for the container to stop before killing it, the
+    // daemon's default if None
+    pub stop_timeout: Option<u64>,
+    // whether to restart the container after it exits, never if empty
+    pub restart_policy: String,
+    // KEY=VALUE pairs added to, or overriding, the default environment
+    pub env: Vec<String>,
+    // working directory of the container process, `/` if empty
+    pub cwd: String,
+    // user[:group] to run the container process as, root if empty
+    pub user: String,
+    // SOURCE:DESTINATION[:OPTIONS] specs of host paths and volumes to mount
+    pub volumes: Vec<String>,
+    // cgroup limits of the container
+    pub resources: Resources,
+    // KEY=VALUE labels describing the container
+    pub labels: Vec<String>,
+    // KEY=VALUE OCI annotations to add to the container's runtime spec
+    pub annotations: Vec<String>,
+}
+
+pub struct ExecOptions {
+    pub command: String,
+    pub args: Vec<String>,
+    // KEY=VALUE pairs added to, or overriding, the container's environment
+    pub env: Vec<String>,
+    // working directory of the process, the container's if empty
+    pub cwd: String,
+    // user[:group] to run the process as, the container's user if empty
+    pub user: String,
+}
+
 #[tokio::main]
 pub async fn create_container(
     port: &str,
-    container_name: &str,
-    command: &str,
-    args: Vec<String>,
-    rootfs_path: &str,
+    opts: CreateOptions,
 ) -> Result<(), Box<dyn std::error::Error>> {
+    let mounts = opts
+        .volumes
+        .iter()
+        .map(|volume| parse_volume(volume))
+        .collect::<Result<_, _>>()?;
+    let labels = parse_key_values(&opts.labels, "label")?;
+    let annotations = parse_key_values(&opts.annotations, "annotation")?;
+
     let mut client = CruiseClient::connect(format!("http://[::1]:{}", port)).await?;
 
     let request = tonic::Request::new(CreateContainerRequest {
-        name: container_name.into(),
-        command: command.into(),
-        args,
-        rootfs_path: rootfs_path.into(),
+        name: opts.name,
+        command: opts.command,
+        args: opts.args,
+        rootfs_path: opts.rootfs_path,
+        image: opts.image,
+        tty: opts.tty,
+        stop_signal: opts.stop_signal,
+        stop_timeout: opts.stop_timeout.map(|seconds| StopTimeout { seconds }),
+        restart_policy: opts.restart_policy,
+        env: opts.env,
+        cwd: opts.cwd,
+        user: opts.user,
+        mounts,
+        resources: Some(map_resources_to_resources_request(&opts.resources)),
+        labels,
+        annotations,
     });
 
     let response = client.create_container(request).await?;
@@ -38,6 +134,136 @@ pub async fn create_container(
     Ok(())
 }
 
+/// parse_key_values parses KEY=VALUE pairs, such as labels, into a map. later
+/// pairs override earlier ones with the same key
+fn parse_key_values(pairs: &[String], what: &str) -> Result<HashMap<String, String>, String> {
+    pairs
+        .iter()
+        .map(|pair| match pair.split_once('=') {
+            Some((key, value)) => Ok((key.to_string(), value.to_string())),
+            None => Err(format!("invalid {} {}, expected KEY=VALUE", what, pair)),
+        })
+        .collect()
+}
+
+/// read_env_file reads the environment in an env file, of KEY=VALUE lines.
+/// blank lines and lines starting with # are skipped, and a line with just a
+/// KEY takes the variable's value from the client's environment, if it is set
+pub fn read_env_file(path: &str) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let env_file =
+        read_to_string(path).map_err(|err| format!("failed to read env file {}: {}", path, err))?;
+    Ok(env_file
+        .lines()
+        .map(|line| line.trim_start())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            if line.contains('=') {
+                Some(line.to_string())
+            } else {
+                env::var(line)
+                    .ok()
+                    .map(|value| format!("{}={}", line, value))
+            }
+        })
+        .collect())
+}
+
+/// parse_memory parses a number of bytes with an optional b, k, m or g unit,
+/// such as 512m. -1 stands for unlimited
+pub fn parse_memory(memory: &str) -> Result<i64, String> {
+    if memory == "-1" {
+        return Ok(-1);
+    }
+    let invalid_memory = || {
+        format!(
+            "invalid memory {}, expected a number of bytes with an optional b, k, m or g unit",
+            memory
+        )
+    };
+    let lowercase_memory = memory.to_lowercase();
+    let (number, unit) = match lowercase_memory.char_indices().last() {
+        Some((i, unit)) if unit.is_ascii_alphabetic() => (&lowercase_memory[..i], unit),
+        _ => (lowercase_memory.as_str(), 'b'),
+    };
+    let multiplier: i64 = match unit {
+        'b' => 1,
+        'k' => 1 << 10,
+        'm' => 1 << 20,
+        'g' => 1 << 30,
+        _ => return Err(invalid_memory()),
+    };
+    number
+        .parse::<i64>()
+        .ok()
+        .filter(|number| *number > 0)
+        .and_then(|number| number.checked_mul(multiplier))
+        .ok_or_else(invalid_memory)
+}
+
+// CPU period --cpus is applied over, unless --cpu-period is given, in
+// microseconds
+const DEFAULT_CPU_PERIOD: u64 = 100_000;
+
+/// parse_cpus parses a number of CPUs, such as 1.5, into the CPU period and
+/// quota that allow it, over `cpu_period` if given
+pub fn parse_cpus(cpus: &str, cpu_period: Option<u64>) -> Result<(u64, i64), String> {
+    let cpus = cpus
+        .parse::<f64>()
+        .ok()
+        .filter(|cpus| cpus.is_finite() && *cpus > 0.0)
+        .ok_or_else(|| format!("invalid cpus {}, expected more than 0", cpus))?;
+    let cpu_period = cpu_period.unwrap_or(DEFAULT_CPU_PERIOD);
+    Ok((cpu_period, (cpus * cpu_period as f64).round() as i64))
+}
+
+fn map_resources_to_resources_request(resources: &Resources) -> cruise_grpc::Resources {
+    // unset limits are sent as 0
+    cruise_grpc::Resources {
+        memory_limit: resources.memory_limit.unwrap_or_default(),
+        memory_reservation: resources.memory_reservation.unwrap_or_default(),
+        cpu_shares: resources.cpu_shares.unwrap_or_default(),
+        cpu_quota: resources.cpu_quota.unwrap_or_default(),
+        cpu_period: resources.cpu_period.unwrap_or_default(),
+        cpuset_cpus: resources.cpuset_cpus.clone(),
+        cpuset_mems: resources.cpuset_mems.clone(),
+        pids_limit: resources.pids_limit.unwrap_or_default(),
+    }
+}
+
+/// parse_volume parses a SOURCE:DESTINATION[:OPTIONS] volume spec, where
+/// SOURCE is an absolute host path or a volume name and OPTIONS is a comma
+/// separated list of `ro` or `rw` and a mount propagation
+fn parse_volume(volume: &str) -> Result<Mount, String> {
+    let invalid_volume = || {
+        format!(
+            "invalid volume {}, expected SOURCE:DESTINATION[:OPTIONS]",
+            volume
+        )
+    };
+    let parts: Vec<&str> = volume.split(':').collect();
+    if parts.len() < 2 || parts.len() > 3 || parts[0].is_empty() || parts[1].is_empty() {
+        return Err(invalid_volume());
+    }
+    let mut mount = Mount {
+        source: parts[0].to_string(),
+        destination: parts[1].to_string(),
+        read_only: false,
+        propagation: String::new(),
+    };
+    for option in parts
+        .get(2)
+        .map_or(vec![], |options| options.split(',').collect())
+    {
+        match option {
+            "ro" => mount.read_only = true,
+            "rw" => mount.read_only = false,
+            _ if MOUNT_PROPAGATIONS.contains(&option) => mount.propagation = option.to_string(),
+            _ => return Err(format!("invalid option {} for volume {}", option, volume)),
+        }
+    }
+    Ok(mount)
+}
+
 #[tokio::main]
 pub async fn start_container(
     port: &str,
@@ -63,11 +289,15 @@ pub async fn start_container(
 pub async fn stop_container(
     port: &str,
     container_id: ID,
+    signal: &str,
+    timeout: Option<u64>,
 ) -> Result<(), Box<dyn std::error::Error>> {
     let mut client = CruiseClient::connect(format!("http://[::1]:{}", port)).await?;
 
     let request = tonic::Request::new(StopContainerRequest {
         container_id: container_id.clone(),
+        signal: signal.into(),
+        timeout: timeout.map(|seconds| StopTimeout { seconds }),
     });
 
     let response = client.stop_container(request).await?;
@@ -80,6 +310,130 @@ pub async fn stop_container(
     Ok(())
 }
 
+#[tokio::main]
+pub async fn kill_container(
+    port: &str,
+    container_id: ID,
+    signal: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut client = CruiseClient::connect(format!("http://[::1]:{}", port)).await?;
+
+    let request = tonic::Request::new(KillContainerRequest {
+        container_id: container_id.clone(),
+        signal: signal.into(),
+    });
+
+    let response = client.kill_container(request).await?;
+
+    debug!("Got kill container response: {:?}", response);
+
+    // print container id
+    println!("killed: {}", container_id);
+
+    Ok(())
+}
+
+#[tokio::main]
+pub async fn pause_container(
+    port: &str,
+    container_id: ID,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut client = CruiseClient::connect(format!("http://[::1]:{}", port)).await?;
+
+    let request = tonic::Request::new(PauseContainerRequest {
+        container_id: container_id.clone(),
+    });
+
+    let response = client.pause_container(request).await?;
+
+    debug!("Got pause container response: {:?}", response);
+
+    // print container id
+    println!("paused: {}", container_id);
+
+    Ok(())
+}
+
+#[tokio::main]
+pub async fn resume_container(
+    port: &str,
+    container_id: ID,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut client = CruiseClient::connect(format!("http://[::1]:{}", port)).await?;
+
+    let request = tonic::Request::new(ResumeContainerRequest {
+        container_id: container_id.clone(),
+    });
+
+    let response = client.resume_container(request).await?;
+
+    debug!("Got resume container response: {:?}", response);
+
+    // print container id
+    println!("resumed: {}", container_id);
+
+    Ok(())
+}
+
+#[tokio::main]
+pub async fn update_container(
+    port: &str,
+    container_id: ID,
+    resources: Resources,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut client = CruiseClient::connect(format!("http://[::1]:{}", port)).await?;
+
+    let request = tonic::Request::new(UpdateContainerRequest {
+        container_id: container_id.clone(),
+        resources: Some(map_resources_to_resources_request(&resources)),
+    });
+
+    let response = client.update_container(request).await?;
+
+    debug!("Got update container response: {:?}", response);
+
+    // print container id
+    println!("updated: {}", container_id);
+
+    Ok(())
+}
+
+/// label_container changes the labels of a container, setting labels given as
+/// KEY=VALUE and removing labels given as KEY-
+#[tokio::main]
+pub async fn label_container(
+    port: &str,
+    container_id: ID,
+    labels: Vec<String>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut set = vec![];
+    let mut remove = vec![];
+    for label in labels {
+        match label.strip_suffix('-') {
+            Some(key) if !label.contains('=') => remove.push(key.to_string()),
+            _ => set.push(label),
+        }
+    }
+    let labels = parse_key_values(&set, "label")?;
+
+    let mut client = CruiseClient::connect(format!("http://[::1]:{}", port)).await?;
+
+    let request = tonic::Request::new(UpdateContainerLabelsRequest {
+        container_id: container_id.clone(),
+        labels,
+        remove,
+    });
+
+    let response = client.update_container_labels(request).await?;
+
+    debug!("Got update container labels response: {:?}", response);
+
+    // print container id
+    println!("labeled: {}", container_id);
+
+    Ok(())
+}
+
 #[tokio::main]
 pub async fn get_container(port: &str, container_id: ID) -> Result<(), Box<dyn std::error::Error>> {
     let mut client = CruiseClient::connect(format!("http://[::1]:{}", port)).await?;
@@ -95,11 +449,39 @@ pub async fn get_container(port: &str, container_id: ID) -> Result<(), Box<dyn s
     Ok(())
 }
 
+/// list_containers prints the containers selected by `filters`, given as
+/// label=SELECTOR, status=STATUS or name=GLOB. the containers meet all label
+/// selectors, and match one of the statuses and one of the name globs
 #[tokio::main]
-pub async fn list_containers(port: &str) -> Result<(), Box<dyn std::error::Error>> {
+pub async fn list_containers(
+    port: &str,
+    filters: Vec<String>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut label_selectors = vec![];
+    let mut statuses = vec![];
+    let mut names = vec![];
+    for filter in filters {
+        match filter.split_once('=') {
+            Some(("label", selector)) => label_selectors.push(selector.to_string()),
+            Some(("status", status)) => statuses.push(status.to_string()),
+            Some(("name", name)) => names.push(name.to_string()),
+            _ => {
+                return Err(format!(
+                    "invalid filter {}, expected label=SELECTOR, status=STATUS or name=GLOB",
+                    filter
+                )
+                .into())
+            }
+        }
+    }
+
     let mut client = CruiseClient::connect(format!("http://[::1]:{}", port)).await?;
 
-    let request = tonic::Request::new(ListContainersRequest {});
+    let request = tonic::Request::new(ListContainersRequest {
+        label_selector: label_selectors.join(","),
+        statuses,
+        names,
+    });
 
     let response = client.list_containers(request).await?;
 
@@ -131,6 +513,500 @@ pub async fn delete_container(
     Ok(())
 }
 
+#[tokio::main]
+pub async fn commit_container(
+    port: &str,
+    container_id: ID,
+    reference: String,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut client = CruiseClient::connect(format!("http://[::1]:{}", port)).await?;
+
+    let request = tonic::Request::new(CommitContainerRequest {
+        container_id,
+        reference,
+    });
+
+    let response = client.commit_container(request).await?;
+
+    debug!("Got commit container response: {:?}", response);
+
+    // print image id
+    if let Some(image) = response.into_inner().image {
+        println!("committed: {}", image.id);
+    }
+
+    Ok(())
+}
+
+/// export_container writes the rootfs of a container as a tar archive to
+/// `output`, or to stdout if it's None
+#[tokio::main]
+pub async fn export_container(
+    port: &str,
+    container_id: ID,
+    output: Option<String>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if output.is_none() && console::is_terminal(libc::STDOUT_FILENO) {
+        return Err("refusing to write the archive to a terminal, give a file with -o".into());
+    }
+
+    let mut client = CruiseClient::connect(format!("http://[::1]:{}", port)).await?;
+
+    let request = tonic::Request::new(ExportContainerRequest { container_id });
+
+    let mut response = client.export_container(request).await?.into_inner();
+
+    let mut out: Box<dyn Write> = match output {
+        Some(ref output) => Box::new(File::create(output)?),
+        None => Box::new(stdout()),
+    };
+    let exported = async {
+        while let Some(chunk) = response.message().await? {
+            out.write_all(&chunk.data)?;
+        }
+        out.flush()?;
+        Ok::<(), Box<dyn std::error::Error>>(())
+    }
+    .await;
+    // don't leave a partial archive behind
+    if let (Err(_), Some(output)) = (&exported, output) {
+        let _ = remove_file(output);
+    }
+    exported
+}
+
+/// copy_from_container copies `path` in a container to the local
+/// `destination`: into it if it's an existing directory, or else as it
+#[tokio::main]
+pub async fn copy_from_container(
+    port: &str,
+    container_id: ID,
+    path: String,
+    destination: String,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let (dir, name) = copy_destination(&destination)?;
+
+    let mut client = CruiseClient::connect(format!("http://[::1]:{}", port)).await?;
+
+    let request = tonic::Request::new(CopyFromContainerRequest { container_id, path });
+
+    let mut response = client.copy_from_container(request).await?.into_inner();
+
+    // extracting the archive blocks, so do it on its own thread
+    let (archive_tx, archive_rx) = sync::mpsc::channel();
+    let extraction = thread::spawn(move || {
+        extract_archive(&mut ChunkReader::new(archive_rx), dir, name.as_deref())
+    });
+    let received = async {
+        while let Some(chunk) = response.message().await? {
+            // the extraction failed, which its error tells about
+            if archive_tx.send(chunk.data).is_err() {
+                break;
+            }
+        }
+        Ok::<(), Box<dyn std::error::Error>>(())
+    }
+    .await;
+    drop(archive_tx);
+    let extracted = extraction
+        .join()
+        .map_err(|_| "failed to extract the archive")?;
+    received?;
+    Ok(extracted?)
+}
+
+/// copy_to_container copies the local `source` to `path` in a container: into
+/// it if it's an existing directory, or else as it
+#[tokio::main]
+pub async fn copy_to_container(
+    port: &str,
+    source: String,
+    container_id: ID,
+    path: String,
+) -> Result<(), Box<dyn std::error::Error>> {
+    symlink_metadata(&source).map_err(|err| format!("{}: {}", source, err))?;
+    // the source is archived under its own name, even if given as `.`
+    let name = match Path::new(&source).file_name() {
+        Some(name) => name.to_os_string(),
+        None => match canonicalize(&source)?.file_name() {
+            Some(name) => name.to_os_string(),
+            None => return Err("can't copy the root directory".into()),
+        },
+    };
+
+    let mut client = CruiseClient::connect(format!("http://[::1]:{}", port)).await?;
+
+    let (mut tx, rx) = mpsc::channel(SESSION_BUFFER_SIZE);
+    tx.send(CopyToContainerRequest {
+        event: Some(copy_to_container_request::Event::Start(
+            CopyToContainerStart { container_id, path },
+        )),
+    })
+    .await?;
+    // archiving the source blocks, so do it on its own thread
+    let archiving = thread::spawn(move || {
+        let mut writer = ChunkWriter::new(tx);
+        write_tarball_entry(&source, &name, &mut writer)
+    });
+
+    let response = client.copy_to_container(rx).await;
+    let archived = archiving
+        .join()
+        .map_err(|_| "failed to archive the source")?;
+    // the daemon tells why it stopped reading the archive, while archiving
+    // errors cut the archive short and are the more telling error
+    match archived {
+        Err(err) if err.kind() != ErrorKind::BrokenPipe => return Err(err.into()),
+        _ => (),
+    }
+
+    let response = response?;
+
+    debug!("Got copy to container response: {:?}", response);
+
+    Ok(())
+}
+
+impl FromChunk for CopyToContainerRequest {
+    fn from_chunk(data: Vec<u8>) -> Self {
+        CopyToContainerRequest {
+            event: Some(copy_to_container_request::Event::Data(data)),
+        }
+    }
+}
+
+/// copy_destination splits a local path to copy to into the directory to
+/// extract an archive into, and the name to extract its top-level entry as.
+/// existing directories are extracted into as they are
+fn copy_destination(
+    destination: &str,
+) -> Result<(PathBuf, Option<OsString>), Box<dyn std::error::Error>> {
+    let destination = Path::new(destination);
+    if destination.is_dir() {
+        return Ok((destination.to_path_buf(), None));
+    }
+    let name = match destination.file_name() {
+        Some(name) => name.to_os_string(),
+        None => return Err(format!("invalid destination {}", destination.display()).into()),
+    };
+    let dir = match destination.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    if !dir.is_dir() {
+        return Err(format!("directory {} does not exist", dir.display()).into());
+    }
+    Ok((dir.to_path_buf(), Some(name)))
+}
+
+#[tokio::main]
+pub async fn container_logs(
+    port: &str,
+    container_id: ID,
+    follow: bool,
+    tail: u64,
+    since: String,
+    stream: String,
+    timestamps: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut client = CruiseClient::connect(format!("http://[::1]:{}", port)).await?;
+
+    let request = tonic::Request::new(ContainerLogsRequest {
+        container_id,
+        follow,
+        tail,
+        since,
+        stream,
+    });
+
+    let mut response = client.container_logs(request).await?.into_inner();
+
+    // print each line to the stream it was logged from
+    while let Some(log_line) = response.message().await? {
+        let line = if timestamps {
+            format!("{} {}", log_line.timestamp, log_line.line)
+        } else {
+            log_line.line
+        };
+        if log_line.stream == "stderr" {
+            eprintln!("{}", line);
+        } else {
+            println!("{}", line);
+        }
+    }
+
+    Ok(())
+}
+
+#[tokio::main]
+pub async fn create_volume(port: &str, name: String) -> Result<(), Box<dyn std::error::Error>> {
+    let mut client = CruiseClient::connect(format!("http://[::1]:{}", port)).await?;
+
+    let request = tonic::Request::new(CreateVolumeRequest { name });
+
+    let response = client.create_volume(request).await?;
+
+    debug!("Got create volume response: {:?}", response);
+
+    // print volume name
+    println!("created: {}", response.into_inner().name);
+
+    Ok(())
+}
+
+#[tokio::main]
+pub async fn list_volumes(port: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let mut client = CruiseClient::connect(format!("http://[::1]:{}", port)).await?;
+
+    let request = tonic::Request::new(ListVolumesRequest {});
+
+    let response = client.list_volumes(request).await?;
+
+    debug!("Got list volumes response: {:?}", response);
+
+    print_volumes(response.into_inner().volumes);
+
+    Ok(())
+}
+
+#[tokio::main]
+pub async fn remove_volume(port: &str, name: String) -> Result<(), Box<dyn std::error::Error>> {
+    let mut client = CruiseClient::connect(format!("http://[::1]:{}", port)).await?;
+
+    let request = tonic::Request::new(RemoveVolumeRequest { name: name.clone() });
+
+    let response = client.remove_volume(request).await?;
+
+    debug!("Got remove volume response: {:?}", response);
+
+    // print volume name
+    println!("removed: {}", name);
+
+    Ok(())
+}
+
+fn print_volumes(volumes: Vec<Volume>) {
+    let name_column = "NAME";
+    let mut name_len = name_column.len();
+
+    let created_at_column = "CREATED_AT";
+    let mut created_at_len = created_at_column.len();
+
+    for volume in volumes.iter() {
+        name_len = max(name_len, volume.name.len());
+        created_at_len = max(created_at_len, volume.created_at.len());
+    }
+
+    println!(
+        "{:<name$} {:<created_at$}",
+        name_column,
+        created_at_column,
+        name = name_len,
+        created_at = created_at_len,
+    );
+    for volume in volumes.iter() {
+        println!(
+            "{:<name$} {:<created_at$}",
+            volume.name,
+            volume.created_at,
+            name = name_len,
+            created_at = created_at_len,
+        );
+    }
+}
+
+#[tokio::main]
+pub async fn import_image(
+    port: &str,
+    path: String,
+    reference: String,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut client = CruiseClient::connect(format!("http://[::1]:{}", port)).await?;
+
+    let request = tonic::Request::new(ImportImageRequest { path, reference });
+
+    let response = client.import_image(request).await?;
+
+    debug!("Got import image response: {:?}", response);
+
+    // print imported image ids
+    for image in response.into_inner().images {
+        println!("imported: {}", image.id);
+    }
+
+    Ok(())
+}
+
+#[tokio::main]
+pub async fn list_images(port: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let mut client = CruiseClient::connect(format!("http://[::1]:{}", port)).await?;
+
+    let request = tonic::Request::new(ListImagesRequest {});
+
+    let response = client.list_images(request).await?;
+
+    debug!("Got list images response: {:?}", response);
+
+    print_images(response.into_inner().images);
+
+    Ok(())
+}
+
+#[tokio::main]
+pub async fn remove_image(port: &str, image: String) -> Result<(), Box<dyn std::error::Error>> {
+    let mut client = CruiseClient::connect(format!("http://[::1]:{}", port)).await?;
+
+    let request = tonic::Request::new(RemoveImageRequest {
+        image: image.clone(),
+    });
+
+    let response = client.remove_image(request).await?;
+
+    debug!("Got remove image response: {:?}", response);
+
+    // print image reference or id
+    println!("removed: {}", image);
+
+    Ok(())
+}
+
+fn print_images(images: Vec<Image>) {
+    let id_column = "ID";
+    let mut id_len = id_column.len();
+
+    let references_column = "REFERENCES";
+    let mut references_len = references_column.len();
+
+    let created_at_column = "CREATED_AT";
+    let mut created_at_len = created_at_column.len();
+
+    for image in images.iter() {
+        id_len = max(id_len, short_id(&image.id).len());
+        references_len = max(references_len, image.references.join(", ").len());
+        created_at_len = max(created_at_len, image.created_at.len());
+    }
+
+    println!(
+        "{:<id$} {:<references$} {:<created_at$}",
+        id_column,
+        references_column,
+        created_at_column,
+        id = id_len,
+        references = references_len,
+        created_at = created_at_len,
+    );
+    for image in images.iter() {
+        println!(
+            "{:<id$} {:<references$} {:<created_at$}",
+            short_id(&image.id),
+            image.references.join(", "),
+            image.created_at,
+            id = id_len,
+            references = references_len,
+            created_at = created_at_len,
+        );
+    }
+}
+
+/// container_stats prints the resource usage of a container. when streaming,
+/// the table is redrawn with every new sample until the container stops
+#[tokio::main]
+pub async fn container_stats(
+    port: &str,
+    container_id: ID,
+    stream: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut client = CruiseClient::connect(format!("http://[::1]:{}", port)).await?;
+
+    let request = tonic::Request::new(ContainerStatsRequest {
+        container_id: container_id.clone(),
+        stream,
+    });
+
+    let mut response = client.container_stats(request).await?.into_inner();
+
+    while let Some(stats) = response.message().await? {
+        if stream {
+            print!("{}", CLEAR_SCREEN);
+        }
+        print_stats(&container_id, &stats);
+    }
+
+    Ok(())
+}
+
+fn print_stats(container_id: &str, stats: &ContainerStatsResponse) {
+    let cpu = format!("{:.2}%", stats.cpu_percent);
+    let (memory_limit, memory_percent) = if stats.memory_limit == 0 {
+        ("unlimited".to_string(), "-".to_string())
+    } else {
+        (
+            format_bytes(stats.memory_limit),
+            format!(
+                "{:.2}%",
+                stats.memory_usage as f64 / stats.memory_limit as f64 * 100.0
+            ),
+        )
+    };
+    let memory = format!("{} / {}", format_bytes(stats.memory_usage), memory_limit);
+    let pids = if stats.pids_limit == 0 {
+        stats.pids_current.to_string()
+    } else {
+        format!("{} / {}", stats.pids_current, stats.pids_limit)
+    };
+    let block_io = format!(
+        "{} / {}",
+        format_bytes(stats.block_read_bytes),
+        format_bytes(stats.block_write_bytes)
+    );
+
+    let columns = [
+        ("ID", container_id.to_string()),
+        ("CPU", cpu),
+        ("MEM_USAGE / LIMIT", memory),
+        ("MEM", memory_percent),
+        ("PIDS", pids),
+        ("BLOCK_READ / WRITE", block_io),
+    ];
+    let widths: Vec<usize> = columns
+        .iter()
+        .map(|(column, value)| max(column.len(), value.len()))
+        .collect();
+    let row = |values: Vec<&str>| {
+        values
+            .iter()
+            .zip(widths.iter())
+            .map(|(value, width)| format!("{:<width$}", value, width = width))
+            .collect::<Vec<String>>()
+            .join(" ")
+    };
+    println!(
+        "{}",
+        row(columns.iter().map(|(column, _)| *column).collect())
+    );
+    println!(
+        "{}",
+        row(columns.iter().map(|(_, value)| value.as_str()).collect())
+    );
+}
+
+/// format_bytes formats a number of bytes in the largest binary unit it
+/// reaches, such as 1.5GiB
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{}{}", bytes, UNITS[unit])
+    } else {
+        format!("{:.1}{}", value, UNITS[unit])
+    }
+}
+
 fn print_containers(containers: Vec<GetContainerResponse>) {
     let id_column = "ID";
     let mut id_len = id_column.len();
@@ -138,12 +1014,18 @@ fn print_containers(containers: Vec<GetContainerResponse>) {
     let name_column = "NAME";
     let mut name_len = name_column.len();
 
+    let image_column = "IMAGE";
+    let mut image_len = image_column.len();
+
     let status_column = "STATUS";
     let mut status_len = status_column.len();
 
     let exit_code_column = "EXIT_CODE";
     let mut exit_code_len = exit_code_column.len();
 
+    let reason_column = "REASON";
+    let mut reason_len = reason_column.len();
+
     let created_at_column = "CREATED_AT";
     let mut created_at_len = created_at_column.len();
 
@@ -153,66 +1035,440 @@ fn print_containers(containers: Vec<GetContainerResponse>) {
     let finished_at_column = "FINISHED_AT";
     let mut finished_at_len = finished_at_column.len();
 
+    let restart_policy_column = "RESTART_POLICY";
+    let mut restart_policy_len = restart_policy_column.len();
+
+    let restart_count_column = "RESTARTS";
+    let mut restart_count_len = restart_count_column.len();
+
+    let restarted_at_column = "RESTARTED_AT";
+    let mut restarted_at_len = restarted_at_column.len();
+
     let command_column = "COMMAND";
     let mut command_len = command_column.len();
 
     let args_column = "ARGS";
     let mut args_len = args_column.len();
 
+    let labels_column = "LABELS";
+
+    // labels are shown as KEY=VALUE pairs, sorted by key
+    let labels: Vec<String> = containers
+        .iter()
+        .map(|container| {
+            let mut labels: Vec<String> = container
+                .labels
+                .iter()
+                .map(|(key, value)| format!("{}={}", key, value))
+                .collect();
+            labels.sort();
+            labels.join(",")
+        })
+        .collect();
+
     for container in containers.iter() {
         id_len = max(id_len, container.id.len());
         name_len = max(name_len, container.name.len());
+        image_len = max(image_len, short_id(&container.image).len());
         status_len = max(status_len, container.status.len());
         exit_code_len = max(exit_code_len, format!("{}", container.exit_code).len());
+        reason_len = max(reason_len, container.reason.len());
         created_at_len = max(created_at_len, container.created_at.len());
         started_at_len = max(started_at_len, container.started_at.len());
         finished_at_len = max(finished_at_len, container.finished_at.len());
+        restart_policy_len = max(restart_policy_len, container.restart_policy.len());
+        restart_count_len = max(
+            restart_count_len,
+            format!("{}", container.restart_count).len(),
+        );
+        restarted_at_len = max(restarted_at_len, container.restarted_at.len());
         command_len = max(command_len, container.command.len());
         args_len = max(args_len, container.args.len());
     }
 
     println!(
-        "{:<id$} {:<name$} {:<status$} {:<exit_code$} {:<created_at$} {:<started_at$} {:<finished_at$} {:<command$} {:<args$}",
+        "{:<id$} {:<name$} {:<image$} {:<status$} {:<exit_code$} {:<reason$} {:<created_at$} {:<started_at$} {:<finished_at$} {:<restart_policy$} {:<restart_count$} {:<restarted_at$} {:<command$} {:<args$} {}",
         id_column,
         name_column,
+        image_column,
         status_column,
         exit_code_column,
+        reason_column,
         created_at_column,
         started_at_column,
         finished_at_column,
+        restart_policy_column,
+        restart_count_column,
+        restarted_at_column,
         command_column,
         args_column,
+        labels_column,
         id = id_len,
         name = name_len,
+        image = image_len,
         status = status_len,
         exit_code = exit_code_len,
+        reason = reason_len,
         created_at = created_at_len,
         started_at = started_at_len,
         finished_at = finished_at_len,
+        restart_policy = restart_policy_len,
+        restart_count = restart_count_len,
+        restarted_at = restarted_at_len,
         command = command_len,
         args = args_len,
     );
-    for container in containers.iter() {
+    for (container, labels) in containers.iter().zip(labels) {
         println!(
-            "{:<id$} {:<name$} {:<status$} {:<exit_code$} {:<created_at$} {:<started_at$} {:<finished_at$} {:<command$} {:<args$}",
+            "{:<id$} {:<name$} {:<image$} {:<status$} {:<exit_code$} {:<reason$} {:<created_at$} {:<started_at$} {:<finished_at$} {:<restart_policy$} {:<restart_count$} {:<restarted_at$} {:<command$} {:<args$} {}",
             container.id,
             container.name,
+            short_id(&container.image),
             container.status,
             container.exit_code,
+            container.reason,
             container.created_at,
             container.started_at,
             container.finished_at,
+            container.restart_policy,
+            container.restart_count,
+            container.restarted_at,
             container.command,
             container.args.join(", "),
+            labels,
             id = id_len,
             name = name_len,
+            image = image_len,
             status = status_len,
             exit_code = exit_code_len,
+            reason = reason_len,
             created_at = created_at_len,
             started_at = started_at_len,
             finished_at = finished_at_len,
+            restart_policy = restart_policy_len,
+            restart_count = restart_count_len,
+            restarted_at = restarted_at_len,
             command = command_len,
             args = args_len,
         );
     }
 }
+
+/// exec_container runs a process in a running container, prints its output
+/// and returns its exit code
+#[tokio::main]
+pub async fn exec_container(
+    port: &str,
+    container_id: ID,
+    opts: ExecOptions,
+) -> Result<i32, Box<dyn std::error::Error>> {
+    let mut client = CruiseClient::connect(format!("http://[::1]:{}", port)).await?;
+
+    let request = tonic::Request::new(map_exec_options_to_exec_container_request(
+        container_id,
+        opts,
+    ));
+
+    let response = client.exec_container(request).await?;
+
+    debug!("Got exec container response: {:?}", response);
+
+    // print process output as is, it isn't necessarily text
+    let response = response.into_inner();
+    stdout().write_all(&response.stdout)?;
+    stderr().write_all(&response.stderr)?;
+
+    Ok(response.exit_code)
+}
+
+/// interactive_exec_container runs a process in a running container,
+/// relaying the local terminal to it until it exits, and returns its exit
+/// code. stdin is only forwarded if `interactive` is set, and the process
+/// gets a pseudo-terminal if `tty` is set
+#[tokio::main]
+pub async fn interactive_exec_container(
+    port: &str,
+    container_id: ID,
+    opts: ExecOptions,
+    interactive: bool,
+    tty: bool,
+) -> Result<i32, Box<dyn std::error::Error>> {
+    let mut client = CruiseClient::connect(format!("http://[::1]:{}", port)).await?;
+
+    let (mut tx, rx) = mpsc::channel(SESSION_BUFFER_SIZE);
+    tx.send(InteractiveExecContainerRequest {
+        event: Some(interactive_exec_container_request::Event::Start(
+            InteractiveExecContainerStart {
+                exec: Some(map_exec_options_to_exec_container_request(
+                    container_id,
+                    opts,
+                )),
+                tty,
+            },
+        )),
+    })
+    .await?;
+    let _raw_mode = enable_raw_mode(tty)?;
+    forward_terminal(
+        tx,
+        tty,
+        interactive,
+        false,
+        |input| InteractiveExecContainerRequest {
+            event: Some(interactive_exec_container_request::Event::Stdin(input)),
+        },
+        |size| InteractiveExecContainerRequest {
+            event: Some(interactive_exec_container_request::Event::Resize(size)),
+        },
+    );
+
+    let response = client.interactive_exec_container(rx).await?;
+
+    debug!("Got interactive exec container response: {:?}", response);
+
+    print_session_output(response.into_inner()).await
+}
+
+/// attach_container relays the local terminal to a container until the
+/// container exits, returning its exit code, or until the user detaches
+/// (ctrl-p ctrl-q for containers with a terminal), returning 0
+#[tokio::main]
+pub async fn attach_container(
+    port: &str,
+    container_id: ID,
+) -> Result<i32, Box<dyn std::error::Error>> {
+    let mut client = CruiseClient::connect(format!("http://[::1]:{}", port)).await?;
+
+    // only containers with a terminal get the local terminal in raw mode
+    let tty = client
+        .get_container(tonic::Request::new(GetContainerRequest {
+            container_id: container_id.clone(),
+        }))
+        .await?
+        .into_inner()
+        .tty;
+
+    let (mut tx, rx) = mpsc::channel(SESSION_BUFFER_SIZE);
+    tx.send(AttachContainerRequest {
+        event: Some(attach_container_request::Event::ContainerId(container_id)),
+    })
+    .await?;
+    let _raw_mode = enable_raw_mode(tty)?;
+    forward_terminal(
+        tx,
+        tty,
+        true,
+        tty,
+        |input| AttachContainerRequest {
+            event: Some(attach_container_request::Event::Stdin(input)),
+        },
+        |size| AttachContainerRequest {
+            event: Some(attach_container_request::Event::Resize(size)),
+        },
+    );
+
+    let response = client.attach_container(rx).await?;
+
+    debug!("Got attach container response: {:?}", response);
+
+    print_session_output(response.into_inner()).await
+}
+
+fn map_exec_options_to_exec_container_request(
+    container_id: ID,
+    opts: ExecOptions,
+) -> ExecContainerRequest {
+    ExecContainerRequest {
+        container_id,
+        command: opts.command,
+        args: opts.args,
+        env: opts.env,
+        cwd: opts.cwd,
+        user: opts.user,
+    }
+}
+
+/// enable_raw_mode puts the local terminal in raw mode for sessions with a
+/// terminal, so keys like ctrl-c reach the remote terminal. The terminal is
+/// restored when the returned RawMode is dropped
+fn enable_raw_mode(tty: bool) -> std::io::Result<Option<RawMode>> {
+    if tty && console::is_terminal(libc::STDIN_FILENO) {
+        RawMode::enable(libc::STDIN_FILENO).map(Some)
+    } else {
+        Ok(None)
+    }
+}
+
+/// forward_terminal sends the local terminal's size and every change of it
+/// for sessions with a terminal, and stdin if `interactive` is set, as
+/// requests built by `resize_request` and `stdin_request`. Input ends, along
+/// with the session's request stream, once stdin is closed or, if
+/// `detachable` is set, the detach keys are pressed
+fn forward_terminal<T, I, R>(
+    tx: mpsc::Sender<T>,
+    tty: bool,
+    interactive: bool,
+    detachable: bool,
+    stdin_request: I,
+    resize_request: R,
+) where
+    T: Send + 'static,
+    I: Fn(Vec<u8>) -> T + Send + 'static,
+    R: Fn(TerminalSize) -> T + Send + 'static,
+{
+    if tty {
+        let mut tx = tx.clone();
+        tokio::spawn(async move {
+            let mut window_changes = match signal(SignalKind::window_change()) {
+                Ok(window_changes) => window_changes,
+                Err(_) => return,
+            };
+            loop {
+                if let Ok(size) = console::window_size(libc::STDOUT_FILENO) {
+                    let size = TerminalSize {
+                        rows: size.rows.into(),
+                        cols: size.cols.into(),
+                    };
+                    if tx.send(resize_request(size)).await.is_err() {
+                        return;
+                    }
+                }
+                if window_changes.recv().await.is_none() {
+                    return;
+                }
+            }
+        });
+    }
+    if !interactive {
+        return;
+    }
+    // reading stdin blocks, so do it on its own thread
+    thread::spawn(move || {
+        let mut tx = tx;
+        let mut buf = [0u8; 1024];
+        let mut previous = 0u8;
+        'read: loop {
+            let read = match stdin().read(&mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(read) => read,
+            };
+            if detachable {
+                for &byte in &buf[..read] {
+                    if previous == DETACH_KEYS[0] && byte == DETACH_KEYS[1] {
+                        break 'read;
+                    }
+                    previous = byte;
+                }
+            }
+            if block_on(tx.send(stdin_request(buf[..read].to_vec()))).is_err() {
+                return;
+            }
+        }
+        tx.close_channel();
+    });
+}
+
+/// print_session_output prints the output of an interactive session as it
+/// comes, returning the exit code of the session's process, or 0 if the
+/// session ended before the process exited
+async fn print_session_output(
+    mut responses: Streaming<InteractiveResponse>,
+) -> Result<i32, Box<dyn std::error::Error>> {
+    let mut exit_code = 0;
+    while let Some(response) = responses.message().await? {
+        match response.event {
+            Some(interactive_response::Event::Stdout(output)) => {
+                let mut stdout = stdout();
+                stdout.write_all(&output)?;
+                stdout.flush()?;
+            }
+            Some(interactive_response::Event::Stderr(output)) => {
+                stderr().write_all(&output)?;
+            }
+            Some(interactive_response::Event::ExitCode(code)) => exit_code = code,
+            None => (),
+        }
+    }
+    Ok(exit_code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    #[test]
+    fn parses_key_values() {
+        let pairs =
+            parse_key_values(&strings(&["env=prod", "empty=", "url=a=b"]), "label").unwrap();
+        assert_eq!(pairs.len(), 3);
+        assert_eq!(pairs["env"], "prod");
+        assert_eq!(pairs["empty"], "");
+        // values may hold `=`
+        assert_eq!(pairs["url"], "a=b");
+        assert!(parse_key_values(&[], "label").unwrap().is_empty());
+    }
+
+    #[test]
+    fn later_key_values_override_earlier_ones() {
+        let pairs = parse_key_values(&strings(&["env=dev", "env=prod"]), "label").unwrap();
+        assert_eq!(pairs.len(), 1);
+        assert_eq!(pairs["env"], "prod");
+    }
+
+    #[test]
+    fn rejects_pairs_without_values() {
+        assert_eq!(
+            parse_key_values(&strings(&["env=prod", "team"]), "annotation").unwrap_err(),
+            "invalid annotation team, expected KEY=VALUE"
+        );
+    }
+
+    #[test]
+    fn parses_memory() {
+        assert_eq!(parse_memory("512M"), Ok(512 << 20));
+        assert_eq!(parse_memory("512m"), Ok(512 << 20));
+        assert_eq!(parse_memory("1g"), Ok(1 << 30));
+        assert_eq!(parse_memory("64k"), Ok(64 << 10));
+        assert_eq!(parse_memory("100b"), Ok(100));
+        assert_eq!(parse_memory("4096"), Ok(4096));
+        assert_eq!(parse_memory("-1"), Ok(-1));
+        for memory in &["0", "0m", "12x", "-2", "m", "", "1.5g", "99999999999g"] {
+            assert!(parse_memory(memory).is_err(), "{}", memory);
+        }
+    }
+
+    #[test]
+    fn parses_cpus() {
+        assert_eq!(parse_cpus("1.5", None), Ok((100_000, 150_000)));
+        assert_eq!(parse_cpus("1.5", Some(50_000)), Ok((50_000, 75_000)));
+        assert_eq!(parse_cpus("0.25", None), Ok((100_000, 25_000)));
+        for cpus in &["0", "-1", "many", "inf", "NaN", ""] {
+            assert!(parse_cpus(cpus, None).is_err(), "{}", cpus);
+        }
+    }
+
+    #[test]
+    fn parses_volumes() {
+        let mount = parse_volume("/src:/dst:ro,rshared").unwrap();
+        assert_eq!(mount.source, "/src");
+        assert_eq!(mount.destination, "/dst");
+        assert!(mount.read_only);
+        assert_eq!(mount.propagation, "rshared");
+        let mount = parse_volume("data:/data").unwrap();
+        assert_eq!(mount.source, "data");
+        assert!(!mount.read_only);
+        assert_eq!(mount.propagation, "");
+        assert!(!parse_volume("data:/data:ro,rw").unwrap().read_only);
+        for volume in &["/src", ":/dst", "/src:", "/src:/dst:ro:rw", "a:b:c:d"] {
+            assert!(parse_volume(volume).is_err(), "{}", volume);
+        }
+        assert_eq!(
+            parse_volume("/src:/dst:ro,shared-ish").unwrap_err(),
+            "invalid option shared-ish for volume /src:/dst:ro,shared-ish"
+        );
+    }
+}
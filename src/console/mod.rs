@@ -0,0 +1,165 @@
+use std::fs::{remove_file, File};
+use std::io;
+use std::mem;
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::ptr;
+
+/// WindowSize is the size of a terminal, in characters
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WindowSize {
+    pub rows: u16,
+    pub cols: u16,
+}
+
+/// ConsoleSocket is the socket an OCI runtime sends the pseudo-terminal
+/// master of a process with a terminal over (`--console-socket`). The socket
+/// file is removed when the ConsoleSocket is dropped.
+pub struct ConsoleSocket {
+    path: String,
+    listener: UnixListener,
+}
+
+impl ConsoleSocket {
+    pub fn bind(path: &str) -> io::Result<ConsoleSocket> {
+        // a stale socket file would fail the bind
+        let _ = remove_file(path);
+        let listener = UnixListener::bind(path)?;
+        listener.set_nonblocking(true)?;
+        Ok(ConsoleSocket {
+            path: path.to_string(),
+            listener,
+        })
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// try_accept_master returns the pseudo-terminal master if the runtime
+    /// has sent it, without blocking
+    pub fn try_accept_master(&self) -> io::Result<Option<File>> {
+        let (stream, _) = match self.listener.accept() {
+            Ok(accepted) => accepted,
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        stream.set_nonblocking(false)?;
+        receive_fd(&stream).map(Some)
+    }
+}
+
+impl Drop for ConsoleSocket {
+    fn drop(&mut self) {
+        let _ = remove_file(&self.path);
+    }
+}
+
+/// receive_fd receives a single file descriptor passed over `stream` as
+/// SCM_RIGHTS ancillary data
+fn receive_fd(stream: &UnixStream) -> io::Result<File> {
+    // the runtime sends the name of the terminal along with it
+    let mut data = [0u8; 4096];
+    let mut iov = libc::iovec {
+        iov_base: data.as_mut_ptr() as *mut libc::c_void,
+        iov_len: data.len(),
+    };
+    // safe: CMSG_SPACE only computes a size
+    let control_len = unsafe { libc::CMSG_SPACE(mem::size_of::<RawFd>() as u32) } as usize;
+    let mut control = vec![0u8; control_len];
+    // safe: an all zero msghdr is valid, and the buffers it points to outlive it
+    let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+    msg.msg_iov = &mut iov;
+    msg.msg_iovlen = 1;
+    msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+    msg.msg_controllen = control.len() as _;
+    // safe: msg describes valid buffers
+    if unsafe { libc::recvmsg(stream.as_raw_fd(), &mut msg, libc::MSG_CMSG_CLOEXEC) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // safe: msg was filled in by recvmsg, and CMSG_DATA points within control
+    unsafe {
+        let cmsg = libc::CMSG_FIRSTHDR(&msg);
+        if cmsg.is_null()
+            || (*cmsg).cmsg_level != libc::SOL_SOCKET
+            || (*cmsg).cmsg_type != libc::SCM_RIGHTS
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "no file descriptor received",
+            ));
+        }
+        let fd = ptr::read_unaligned(libc::CMSG_DATA(cmsg) as *const RawFd);
+        Ok(File::from_raw_fd(fd))
+    }
+}
+
+/// window_size gets the size of the terminal `fd` refers to
+pub fn window_size(fd: RawFd) -> io::Result<WindowSize> {
+    // safe: an all zero winsize is valid
+    let mut size: libc::winsize = unsafe { mem::zeroed() };
+    // safe: size is a valid winsize for the duration of the call
+    if unsafe { libc::ioctl(fd, libc::TIOCGWINSZ, &mut size) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(WindowSize {
+        rows: size.ws_row,
+        cols: size.ws_col,
+    })
+}
+
+/// set_window_size resizes the terminal `terminal` refers to
+pub fn set_window_size(terminal: &File, window_size: WindowSize) -> io::Result<()> {
+    let size = libc::winsize {
+        ws_row: window_size.rows,
+        ws_col: window_size.cols,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    };
+    // safe: size is a valid winsize for the duration of the call
+    if unsafe { libc::ioctl(terminal.as_raw_fd(), libc::TIOCSWINSZ, &size) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// is_terminal returns whether `fd` refers to a terminal
+pub fn is_terminal(fd: RawFd) -> bool {
+    // safe: isatty only inspects the descriptor
+    unsafe { libc::isatty(fd) == 1 }
+}
+
+/// RawMode puts a terminal into raw mode, so input is passed through byte by
+/// byte without being echoed or interpreted, and restores the terminal's
+/// original mode when dropped
+pub struct RawMode {
+    fd: RawFd,
+    original: libc::termios,
+}
+
+impl RawMode {
+    pub fn enable(fd: RawFd) -> io::Result<RawMode> {
+        // safe: an all zero termios is valid, and is filled in by tcgetattr
+        let mut original: libc::termios = unsafe { mem::zeroed() };
+        // safe: original is a valid termios for the duration of the call
+        if unsafe { libc::tcgetattr(fd, &mut original) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        let mut raw = original;
+        // safe: raw is a valid termios for the duration of the calls
+        unsafe {
+            libc::cfmakeraw(&mut raw);
+            if libc::tcsetattr(fd, libc::TCSANOW, &raw) == -1 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+        Ok(RawMode { fd, original })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        // safe: original is the valid termios read in enable
+        unsafe { libc::tcsetattr(self.fd, libc::TCSANOW, &self.original) };
+    }
+}
@@ -1,22 +1,75 @@
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fmt;
+use std::str::FromStr;
 use std::time::SystemTime;
 use uuid::Uuid;
 
+/// DEFAULT_STOP_SIGNAL is sent to stop a container unless it was created with
+/// a stop signal of its own
+pub const DEFAULT_STOP_SIGNAL: &str = "SIGTERM";
+/// DEFAULT_STOP_TIMEOUT is how many seconds a container is given to stop
+/// before it is killed, unless it was created with a stop timeout of its own
+pub const DEFAULT_STOP_TIMEOUT: u64 = 10;
+
 #[derive(Serialize, Deserialize, Clone)]
 pub struct Container {
     pub id: ID,
     pub name: String,
     pub status: Status,
-    // TODO: will require the shim integration to properly update this
     pub exit_code: i32,
 
     pub created_at: Option<SystemTime>,
     pub started_at: Option<SystemTime>,
-    // TODO: will require the shim integration to properly update this
     pub finished_at: Option<SystemTime>,
     pub command: String,
     pub args: Vec<String>,
+    // whether the container process has a pseudo-terminal
+    #[serde(default)]
+    pub tty: bool,
+    // signal sent to the container process to stop it
+    #[serde(default = "default_stop_signal")]
+    pub stop_signal: String,
+    // seconds to wait for the container to stop before killing it
+    #[serde(default = "default_stop_timeout")]
+    pub stop_timeout: u64,
+    // whether the container is restarted after it exits
+    #[serde(default)]
+    pub restart_policy: RestartPolicy,
+    // how many times the container has been restarted
+    #[serde(default)]
+    pub restart_count: u32,
+    // when the container was last restarted
+    #[serde(default)]
+    pub restarted_at: Option<SystemTime>,
+    // whether the container was stopped through the daemon, rather than
+    // exiting on its own
+    #[serde(default)]
+    pub stopped_by_user: bool,
+    // host paths and volumes mounted into the container
+    #[serde(default)]
+    pub mounts: Vec<Mount>,
+    // cgroup limits of the container
+    #[serde(default)]
+    pub resources: Resources,
+    // whether the kernel OOM killer killed a process of the container before
+    // it last exited
+    #[serde(default)]
+    pub oom_killed: bool,
+    // why the container last exited, see ContainerExit::reason
+    #[serde(default)]
+    pub reason: String,
+    // id of the image the container was created from, empty if it was
+    // created from a rootfs
+    #[serde(default)]
+    pub image: String,
+    // key/value pairs describing the container, which containers can be
+    // listed by
+    #[serde(default)]
+    pub labels: HashMap<String, String>,
+    // OCI annotations, passed to the runtime in the container's runtime spec
+    #[serde(default)]
+    pub annotations: HashMap<String, String>,
 }
 
 impl Container {
@@ -25,17 +78,173 @@ impl Container {
     }
 }
 
-pub fn new(id: &ID, name: &String, command: &String, args: &Vec<String>) -> Container {
+pub fn new(
+    id: &str,
+    name: &str,
+    command: &str,
+    args: &[String],
+    tty: bool,
+    stop_signal: &str,
+    stop_timeout: u64,
+) -> Container {
     Container {
-        id: id.clone(),
-        name: name.clone(),
+        id: id.to_string(),
+        name: name.to_string(),
         status: Status::Initialized,
         exit_code: -1,
         created_at: None,
         started_at: None,
         finished_at: None,
-        command: command.clone(),
-        args: args.clone(),
+        command: command.to_string(),
+        args: args.to_vec(),
+        tty,
+        stop_signal: stop_signal.to_string(),
+        stop_timeout,
+        restart_policy: RestartPolicy::No,
+        restart_count: 0,
+        restarted_at: None,
+        stopped_by_user: false,
+        mounts: vec![],
+        resources: Resources::default(),
+        oom_killed: false,
+        reason: String::new(),
+        image: String::new(),
+        labels: HashMap::new(),
+        annotations: HashMap::new(),
+    }
+}
+
+fn default_stop_signal() -> String {
+    DEFAULT_STOP_SIGNAL.to_string()
+}
+
+fn default_stop_timeout() -> u64 {
+    DEFAULT_STOP_TIMEOUT
+}
+
+/// Resources are the cgroup limits of a container. unset limits are left to
+/// the runtime, which doesn't limit the container
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Resources {
+    // memory limit in bytes, -1 for unlimited
+    pub memory_limit: Option<i64>,
+    // memory the container is guaranteed under memory pressure, in bytes
+    pub memory_reservation: Option<i64>,
+    // relative weight of the container's CPU time
+    pub cpu_shares: Option<u64>,
+    // CPU time in microseconds the container may use every period, -1 for
+    // unlimited
+    pub cpu_quota: Option<i64>,
+    // length of a CPU period in microseconds
+    pub cpu_period: Option<u64>,
+    // CPUs the container may run on, as a list such as `0-2,4`
+    #[serde(default)]
+    pub cpuset_cpus: String,
+    // memory nodes the container may allocate from, as a list such as `0-2,4`
+    #[serde(default)]
+    pub cpuset_mems: String,
+    // limit on the number of processes in the container, -1 for unlimited
+    pub pids_limit: Option<i64>,
+}
+
+impl Resources {
+    /// update applies the limits set in `update`, keeping the others
+    pub fn update(&mut self, update: &Resources) {
+        fn set<T: Clone>(limit: &mut Option<T>, update: &Option<T>) {
+            if update.is_some() {
+                *limit = update.clone();
+            }
+        }
+        set(&mut self.memory_limit, &update.memory_limit);
+        set(&mut self.memory_reservation, &update.memory_reservation);
+        set(&mut self.cpu_shares, &update.cpu_shares);
+        set(&mut self.cpu_quota, &update.cpu_quota);
+        set(&mut self.cpu_period, &update.cpu_period);
+        if !update.cpuset_cpus.is_empty() {
+            self.cpuset_cpus = update.cpuset_cpus.clone();
+        }
+        if !update.cpuset_mems.is_empty() {
+            self.cpuset_mems = update.cpuset_mems.clone();
+        }
+        set(&mut self.pids_limit, &update.pids_limit);
+    }
+}
+
+/// Mount is a host path or volume mounted into a container
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Mount {
+    // absolute host path, or the name of a volume
+    pub source: String,
+    pub destination: String,
+    pub read_only: bool,
+    // propagation of mounts beneath the mount point, rprivate if empty
+    pub propagation: String,
+}
+
+/// MOUNT_PROPAGATIONS are the mount propagation modes a mount can have
+pub const MOUNT_PROPAGATIONS: &[&str] = &[
+    "private", "rprivate", "shared", "rshared", "slave", "rslave",
+];
+
+impl Mount {
+    /// volume is the name of the volume the mount mounts, if it isn't a host
+    /// path
+    pub fn volume(&self) -> Option<&str> {
+        if self.source.starts_with('/') {
+            None
+        } else {
+            Some(&self.source)
+        }
+    }
+}
+
+/// RestartPolicy decides whether a container is restarted after it exits.
+/// Containers stopped through the daemon are never restarted, except for
+/// containers restarting `Always`, which are restarted again once the daemon
+/// restarts.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub enum RestartPolicy {
+    #[default]
+    No,
+    // restart the container if it exits with a non-zero exit code, at most
+    // `max_retries` times if set
+    OnFailure {
+        max_retries: Option<u32>,
+    },
+    Always,
+    UnlessStopped,
+}
+
+impl RestartPolicy {
+    /// parse parses a restart policy given as `no`, `on-failure[:max]`,
+    /// `always` or `unless-stopped`
+    pub fn parse(policy: &str) -> Option<RestartPolicy> {
+        let mut parts = policy.splitn(2, ':');
+        let policy = match (parts.next(), parts.next()) {
+            (Some("no"), None) => RestartPolicy::No,
+            (Some("on-failure"), None) => RestartPolicy::OnFailure { max_retries: None },
+            (Some("on-failure"), Some(max_retries)) => RestartPolicy::OnFailure {
+                max_retries: Some(max_retries.parse().ok()?),
+            },
+            (Some("always"), None) => RestartPolicy::Always,
+            (Some("unless-stopped"), None) => RestartPolicy::UnlessStopped,
+            _ => return None,
+        };
+        Some(policy)
+    }
+}
+
+impl fmt::Display for RestartPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Self::No => write!(f, "no"),
+            Self::OnFailure { max_retries: None } => write!(f, "on-failure"),
+            Self::OnFailure {
+                max_retries: Some(max_retries),
+            } => write!(f, "on-failure:{}", max_retries),
+            Self::Always => write!(f, "always"),
+            Self::UnlessStopped => write!(f, "unless-stopped"),
+        }
     }
 }
 
@@ -73,8 +282,378 @@ impl fmt::Display for Status {
     }
 }
 
+impl FromStr for Status {
+    type Err = ();
+
+    // statuses are parsed ignoring case, as in `running` or `Running`
+    fn from_str(s: &str) -> Result<Status, ()> {
+        match s.to_ascii_lowercase().as_str() {
+            "initialized" => Ok(Self::Initialized),
+            "created" => Ok(Self::Created),
+            "running" => Ok(Self::Running),
+            "paused" => Ok(Self::Paused),
+            "stopped" => Ok(Self::Stopped),
+            "unknown" => Ok(Self::Unknown),
+            _ => Err(()),
+        }
+    }
+}
+
 pub type ID = String;
 
+/// is_valid_name returns whether `name` can name a container: an
+/// alphanumeric character followed by alphanumerics, `_`, `.` or `-`, so it
+/// never reads as a path or a CONTAINER:PATH pair
+pub fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) if first.is_ascii_alphanumeric() => (),
+        _ => return false,
+    }
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '-')
+}
+
+/// is_valid_label_key returns whether `key` can be the key of a label: an
+/// alphanumeric character followed by alphanumerics, `_`, `.`, `-` or `/`,
+/// so it can't be mistaken for part of a label selector
+pub fn is_valid_label_key(key: &str) -> bool {
+    let mut chars = key.chars();
+    match chars.next() {
+        Some(first) if first.is_ascii_alphanumeric() => (),
+        _ => return false,
+    }
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '-' || c == '/')
+}
+
 pub fn rand_id() -> ID {
     Uuid::new_v4().to_string()
 }
+
+/// ContainerExit is written by the container shim once the container's init
+/// process has been reaped
+#[derive(Serialize, Deserialize)]
+pub struct ContainerExit {
+    pub exit_code: i32,
+    pub finished_at: SystemTime,
+    // whether the kernel OOM killer killed a process of the container while
+    // it ran
+    #[serde(default)]
+    pub oom_killed: bool,
+}
+
+impl ContainerExit {
+    /// reason summarizes why the container exited: `OOMKilled` if the OOM
+    /// killer got involved, otherwise `Completed` or `Error` depending on the
+    /// exit code
+    pub fn reason(&self) -> &'static str {
+        if self.oom_killed {
+            "OOMKilled"
+        } else if self.exit_code == 0 {
+            "Completed"
+        } else {
+            "Error"
+        }
+    }
+}
+
+/// LabelRequirement is a requirement of a label selector on the labels of a
+/// container
+#[derive(Debug, Clone, PartialEq)]
+pub enum LabelRequirement {
+    // KEY=VALUE, the container has the label with the value
+    Equals { key: String, value: String },
+    // KEY!=VALUE, the container doesn't have the label with the value,
+    // possibly because it doesn't have the label at all
+    NotEquals { key: String, value: String },
+    // KEY, the container has the label
+    Exists { key: String },
+    // !KEY, the container doesn't have the label
+    NotExists { key: String },
+}
+
+impl LabelRequirement {
+    /// parse parses a requirement given as `KEY=VALUE`, `KEY!=VALUE`, `KEY`
+    /// or `!KEY`
+    pub fn parse(requirement: &str) -> Option<LabelRequirement> {
+        let requirement = if let Some((key, value)) = requirement.split_once("!=") {
+            LabelRequirement::NotEquals {
+                key: key.to_string(),
+                value: value.to_string(),
+            }
+        } else if let Some((key, value)) = requirement.split_once('=') {
+            LabelRequirement::Equals {
+                key: key.to_string(),
+                value: value.to_string(),
+            }
+        } else if let Some(key) = requirement.strip_prefix('!') {
+            LabelRequirement::NotExists {
+                key: key.to_string(),
+            }
+        } else {
+            LabelRequirement::Exists {
+                key: requirement.to_string(),
+            }
+        };
+        if !is_valid_label_key(requirement.key()) {
+            return None;
+        }
+        Some(requirement)
+    }
+
+    pub fn key(&self) -> &str {
+        match *self {
+            Self::Equals { ref key, .. } => key,
+            Self::NotEquals { ref key, .. } => key,
+            Self::Exists { ref key } => key,
+            Self::NotExists { ref key } => key,
+        }
+    }
+
+    pub fn matches(&self, labels: &HashMap<String, String>) -> bool {
+        match *self {
+            Self::Equals { ref key, ref value } => labels.get(key) == Some(value),
+            Self::NotEquals { ref key, ref value } => labels.get(key) != Some(value),
+            Self::Exists { ref key } => labels.contains_key(key),
+            Self::NotExists { ref key } => !labels.contains_key(key),
+        }
+    }
+}
+
+/// parse_label_selector parses a label selector of comma separated
+/// requirements, such as `env=prod,team!=infra`. whitespace around the
+/// requirements is ignored, and an empty selector has none
+pub fn parse_label_selector(selector: &str) -> Option<Vec<LabelRequirement>> {
+    if selector.trim().is_empty() {
+        return Some(vec![]);
+    }
+    selector
+        .split(',')
+        .map(|requirement| LabelRequirement::parse(requirement.trim()))
+        .collect()
+}
+
+/// ContainerFilter selects which containers are listed. a container is
+/// selected if it meets all of the filter's requirements on its labels, is in
+/// one of its statuses and has a name matching one of its name globs. empty
+/// statuses and name globs select any container
+#[derive(Debug, Clone, Default)]
+pub struct ContainerFilter {
+    pub labels: Vec<LabelRequirement>,
+    pub statuses: Vec<Status>,
+    // globs in which `*` matches any characters and `?` a single one
+    pub names: Vec<String>,
+}
+
+impl ContainerFilter {
+    pub fn matches(&self, container: &Container) -> bool {
+        self.labels
+            .iter()
+            .all(|requirement| requirement.matches(&container.labels))
+            && (self.statuses.is_empty() || self.statuses.contains(&container.status))
+            && (self.names.is_empty()
+                || self
+                    .names
+                    .iter()
+                    .any(|glob| glob_matches(glob, &container.name)))
+    }
+}
+
+/// glob_matches returns whether `name` matches `glob`, in which `*` matches
+/// any characters and `?` a single one
+fn glob_matches(glob: &str, name: &str) -> bool {
+    let glob: Vec<char> = glob.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    let (mut g, mut n) = (0, 0);
+    // where the last `*` is in the glob, and where in the name it started
+    // matching, so it can match one more character if the rest doesn't match
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        match glob.get(g) {
+            Some('*') => {
+                star = Some((g, n));
+                g += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                g += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((star_g, star_n)) => {
+                    star = Some((star_g, star_n + 1));
+                    g = star_g + 1;
+                    n = star_n + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    glob[g..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labels(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect()
+    }
+
+    fn container(name: &str, status: Status, labels: HashMap<String, String>) -> Container {
+        let mut container = new("abc123", name, "/bin/sh", &[], false, "SIGTERM", 10);
+        container.status = status;
+        container.labels = labels;
+        container
+    }
+
+    #[test]
+    fn parses_label_requirements() {
+        assert_eq!(
+            LabelRequirement::parse("env=prod"),
+            Some(LabelRequirement::Equals {
+                key: "env".into(),
+                value: "prod".into()
+            })
+        );
+        assert_eq!(
+            LabelRequirement::parse("env!=prod"),
+            Some(LabelRequirement::NotEquals {
+                key: "env".into(),
+                value: "prod".into()
+            })
+        );
+        assert_eq!(
+            LabelRequirement::parse("env="),
+            Some(LabelRequirement::Equals {
+                key: "env".into(),
+                value: "".into()
+            })
+        );
+        assert_eq!(
+            LabelRequirement::parse("app.io/tier"),
+            Some(LabelRequirement::Exists {
+                key: "app.io/tier".into()
+            })
+        );
+        assert_eq!(
+            LabelRequirement::parse("!env"),
+            Some(LabelRequirement::NotExists { key: "env".into() })
+        );
+        for requirement in &["", "=prod", "!=prod", "!", "!!env", "env prod", "-env"] {
+            assert_eq!(
+                LabelRequirement::parse(requirement),
+                None,
+                "{}",
+                requirement
+            );
+        }
+    }
+
+    #[test]
+    fn parses_label_selectors() {
+        assert_eq!(parse_label_selector(""), Some(vec![]));
+        assert_eq!(parse_label_selector("  "), Some(vec![]));
+        assert_eq!(
+            parse_label_selector(" env=prod , !debug"),
+            Some(vec![
+                LabelRequirement::Equals {
+                    key: "env".into(),
+                    value: "prod".into()
+                },
+                LabelRequirement::NotExists {
+                    key: "debug".into()
+                },
+            ])
+        );
+        assert_eq!(parse_label_selector("env=prod,"), None);
+        assert_eq!(parse_label_selector("env=prod,,team"), None);
+    }
+
+    #[test]
+    fn matches_label_requirements() {
+        let labels = labels(&[("env", "prod"), ("team", "")]);
+        let matches = |requirement: &str| {
+            LabelRequirement::parse(requirement)
+                .unwrap()
+                .matches(&labels)
+        };
+        assert!(matches("env=prod"));
+        assert!(!matches("env=dev"));
+        assert!(matches("team="));
+        assert!(matches("env!=dev"));
+        assert!(!matches("env!=prod"));
+        // containers without the label don't have it with any value
+        assert!(matches("tier!=web"));
+        assert!(!matches("tier=web"));
+        assert!(matches("team"));
+        assert!(!matches("tier"));
+        assert!(matches("!tier"));
+        assert!(!matches("!team"));
+    }
+
+    #[test]
+    fn parses_statuses_ignoring_case() {
+        assert_eq!("running".parse(), Ok(Status::Running));
+        assert_eq!("Stopped".parse(), Ok(Status::Stopped));
+        assert_eq!("PAUSED".parse(), Ok(Status::Paused));
+        assert_eq!("created".parse(), Ok(Status::Created));
+        assert_eq!("exited".parse::<Status>(), Err(()));
+        assert_eq!("".parse::<Status>(), Err(()));
+        // statuses parse as they're displayed
+        for status in &[
+            Status::Created,
+            Status::Running,
+            Status::Paused,
+            Status::Stopped,
+        ] {
+            assert_eq!(status.to_string().parse().as_ref(), Ok(status));
+        }
+    }
+
+    #[test]
+    fn matches_globs() {
+        assert!(glob_matches("web", "web"));
+        assert!(!glob_matches("web", "web-1"));
+        assert!(glob_matches("web-*", "web-1"));
+        assert!(glob_matches("web-*", "web-"));
+        assert!(glob_matches("*-db", "users-db"));
+        assert!(glob_matches("w?b", "web"));
+        assert!(!glob_matches("w?b", "wb"));
+        assert!(glob_matches("*", ""));
+        assert!(glob_matches("*a*b*", "xxaxxbxx"));
+        assert!(glob_matches("a*b", "abab"));
+        assert!(!glob_matches("a*b", "abac"));
+        assert!(!glob_matches("?", ""));
+    }
+
+    #[test]
+    fn filters_containers() {
+        let web = container("web-1", Status::Running, labels(&[("env", "prod")]));
+        let db = container("db", Status::Stopped, labels(&[("env", "dev")]));
+        assert!(ContainerFilter::default().matches(&web));
+        let filter = ContainerFilter {
+            labels: parse_label_selector("env=prod").unwrap(),
+            ..ContainerFilter::default()
+        };
+        assert!(filter.matches(&web));
+        assert!(!filter.matches(&db));
+        // any of the statuses and names match
+        let filter = ContainerFilter {
+            statuses: vec![Status::Paused, Status::Stopped],
+            names: vec!["web-*".into(), "d?".into()],
+            ..ContainerFilter::default()
+        };
+        assert!(!filter.matches(&web));
+        assert!(filter.matches(&db));
+        // but all requirements must hold
+        let filter = ContainerFilter {
+            labels: parse_label_selector("env=prod").unwrap(),
+            statuses: vec![Status::Running],
+            names: vec!["db".into()],
+        };
+        assert!(!filter.matches(&web));
+        assert!(!filter.matches(&db));
+    }
+}
@@ -0,0 +1,217 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+use std::fmt;
+use std::fs::File;
+use std::io::Read;
+use std::str::FromStr;
+
+/// Stream identifies which of a container's output streams a log line came from
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Stream {
+    Stdout,
+    Stderr,
+}
+
+impl fmt::Display for Stream {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Stdout => write!(f, "stdout"),
+            Self::Stderr => write!(f, "stderr"),
+        }
+    }
+}
+
+impl FromStr for Stream {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Stream, ()> {
+        match s {
+            "stdout" => Ok(Self::Stdout),
+            "stderr" => Ok(Self::Stderr),
+            _ => Err(()),
+        }
+    }
+}
+
+/// LogLine is a single line of container output. On disk each line is stored as
+/// `<rfc3339 timestamp> <stream> <line>`, one per line of the log file.
+#[derive(Debug, Clone)]
+pub struct LogLine {
+    pub timestamp: DateTime<Utc>,
+    pub stream: Stream,
+    pub line: String,
+}
+
+impl LogLine {
+    pub fn new(stream: Stream, line: &[u8]) -> LogLine {
+        LogLine {
+            timestamp: Utc::now(),
+            stream,
+            line: String::from_utf8_lossy(line).to_string(),
+        }
+    }
+
+    /// format renders the line as stored in the log file, including the
+    /// trailing newline
+    pub fn format(&self) -> String {
+        format!(
+            "{} {} {}\n",
+            self.timestamp.to_rfc3339_opts(SecondsFormat::Nanos, true),
+            self.stream,
+            self.line
+        )
+    }
+
+    /// parse parses a line of the log file, without its trailing newline
+    pub fn parse(s: &str) -> Option<LogLine> {
+        let mut parts = s.splitn(3, ' ');
+        let timestamp = DateTime::parse_from_rfc3339(parts.next()?).ok()?;
+        let stream = parts.next()?.parse().ok()?;
+        Some(LogLine {
+            timestamp: timestamp.with_timezone(&Utc),
+            stream,
+            line: parts.next().unwrap_or("").to_string(),
+        })
+    }
+}
+
+/// LogFilter selects which log lines are returned to a reader
+#[derive(Debug, Clone, Default)]
+pub struct LogFilter {
+    // only lines logged at or after this time
+    pub since: Option<DateTime<Utc>>,
+    // only lines from this stream
+    pub stream: Option<Stream>,
+}
+
+impl LogFilter {
+    pub fn matches(&self, log_line: &LogLine) -> bool {
+        if let Some(since) = self.since {
+            if log_line.timestamp < since {
+                return false;
+            }
+        }
+        match self.stream {
+            Some(stream) => log_line.stream == stream,
+            None => true,
+        }
+    }
+}
+
+/// LogReader incrementally reads complete lines from a log file that may
+/// still be written to
+pub struct LogReader {
+    file: File,
+    // bytes of a line that hasn't been completely written yet
+    partial: Vec<u8>,
+}
+
+impl LogReader {
+    pub fn new(file: File) -> LogReader {
+        LogReader {
+            file,
+            partial: vec![],
+        }
+    }
+
+    /// read_lines returns the lines written since the last call, skipping
+    /// any that can't be parsed
+    pub fn read_lines(&mut self) -> Result<Vec<LogLine>, std::io::Error> {
+        let mut buf = std::mem::take(&mut self.partial);
+        self.file.read_to_end(&mut buf)?;
+        let complete = match buf.iter().rposition(|b| *b == b'\n') {
+            Some(last_newline) => {
+                self.partial = buf.split_off(last_newline + 1);
+                buf
+            }
+            None => {
+                self.partial = buf;
+                return Ok(vec![]);
+            }
+        };
+        Ok(String::from_utf8_lossy(&complete)
+            .lines()
+            .filter_map(LogLine::parse)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::OpenOptions;
+    use std::io::Write;
+
+    #[test]
+    fn format_parse_round_trip() {
+        let log_line = LogLine::new(Stream::Stderr, b"error: disk full");
+        let formatted = log_line.format();
+        assert!(formatted.ends_with(" stderr error: disk full\n"));
+        let parsed = LogLine::parse(formatted.trim_end_matches('\n')).unwrap();
+        assert_eq!(parsed.timestamp, log_line.timestamp);
+        assert_eq!(parsed.stream, Stream::Stderr);
+        assert_eq!(parsed.line, "error: disk full");
+    }
+
+    #[test]
+    fn keeps_spaces_within_lines() {
+        for line in &["  indented", "trailing  ", "a  b   c", " ", ""] {
+            let log_line = LogLine::new(Stream::Stdout, line.as_bytes());
+            let parsed = LogLine::parse(log_line.format().trim_end_matches('\n')).unwrap();
+            assert_eq!(parsed.line, *line);
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_lines() {
+        assert!(LogLine::parse("").is_none());
+        assert!(LogLine::parse("yesterday stdout hello").is_none());
+        assert!(LogLine::parse("2020-01-01T00:00:00Z stdin hello").is_none());
+        assert!(LogLine::parse("2020-01-01T00:00:00Z").is_none());
+    }
+
+    #[test]
+    fn holds_back_partial_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("log");
+        let mut writer = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .unwrap();
+        let mut reader = LogReader::new(File::open(&path).unwrap());
+        assert!(reader.read_lines().unwrap().is_empty());
+
+        let first = LogLine::new(Stream::Stdout, b"first").format();
+        let second = LogLine::new(Stream::Stdout, b"second line").format();
+        let (head, tail) = second.split_at(second.len() - 5);
+        write!(writer, "{}{}", first, head).unwrap();
+        let lines = reader.read_lines().unwrap();
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].line, "first");
+        // the partial line is held back until its newline is written
+        assert!(reader.read_lines().unwrap().is_empty());
+        write!(writer, "{}", tail).unwrap();
+        let lines = reader.read_lines().unwrap();
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].line, "second line");
+        assert!(reader.read_lines().unwrap().is_empty());
+    }
+
+    #[test]
+    fn filters_lines() {
+        let stdout = LogLine::new(Stream::Stdout, b"out");
+        let stderr = LogLine::new(Stream::Stderr, b"err");
+        assert!(LogFilter::default().matches(&stdout));
+        let filter = LogFilter {
+            since: None,
+            stream: Some(Stream::Stderr),
+        };
+        assert!(!filter.matches(&stdout));
+        assert!(filter.matches(&stderr));
+        let filter = LogFilter {
+            since: Some(stderr.timestamp + chrono::Duration::seconds(1)),
+            stream: None,
+        };
+        assert!(!filter.matches(&stderr));
+    }
+}
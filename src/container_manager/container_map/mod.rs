@@ -4,12 +4,15 @@ use std::fmt;
 use std::sync::Mutex;
 use std::time::SystemTime;
 
-use crate::container::{Container, Status, ID};
+use crate::container::{Container, ContainerExit, Resources, Status, ID};
 
 #[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum ContainerMapError {
     ContainerAlreadyExistsError { container_id: ID },
     ContainerNotFoundError { container_id: ID },
+    ContainerNameInUseError { name: String, container_id: ID },
+    AmbiguousContainerIDError { prefix: String },
 }
 
 impl fmt::Display for ContainerMapError {
@@ -23,6 +26,19 @@ impl fmt::Display for ContainerMapError {
             Self::ContainerNotFoundError { ref container_id } => {
                 write!(f, "container with container_id {} not found", container_id)
             }
+            Self::ContainerNameInUseError {
+                ref name,
+                ref container_id,
+            } => write!(
+                f,
+                "container name {} is already in use by container with container_id {}",
+                name, container_id
+            ),
+            Self::AmbiguousContainerIDError { ref prefix } => write!(
+                f,
+                "container_id prefix {} matches more than one container",
+                prefix
+            ),
         }
     }
 }
@@ -32,12 +48,17 @@ impl Error for ContainerMapError {
         match *self {
             Self::ContainerAlreadyExistsError { .. } => None,
             Self::ContainerNotFoundError { .. } => None,
+            Self::ContainerNameInUseError { .. } => None,
+            Self::AmbiguousContainerIDError { .. } => None,
         }
     }
 }
 
 pub struct ContainerMap {
     map: Mutex<HashMap<ID, Container>>,
+    // ids of containers by their name, which are unique among containers.
+    // locked after map when both are held
+    names: Mutex<HashMap<String, ID>>,
 }
 
 impl fmt::Debug for ContainerMap {
@@ -51,22 +72,69 @@ impl ContainerMap {
         let mutex_guarded_map = Mutex::new(HashMap::<ID, Container>::new());
         ContainerMap {
             map: mutex_guarded_map,
+            names: Mutex::new(HashMap::new()),
         }
     }
 
-    /// add adds a container to the map
+    /// add adds a container to the map, unless another container has its
+    /// name. containers without a name don't take one up
     pub fn add(&self, container: Container) -> Result<ID, ContainerMapError> {
+        self.insert(container, true)
+    }
+
+    /// add_unnamed adds a container to the map without taking up its name,
+    /// so that it can only be found by its id
+    pub fn add_unnamed(&self, container: Container) -> Result<ID, ContainerMapError> {
+        self.insert(container, false)
+    }
+
+    fn insert(&self, container: Container, named: bool) -> Result<ID, ContainerMapError> {
         let mut map = self.map.lock().unwrap();
+        let mut names = self.names.lock().unwrap();
         if map.contains_key(container.id()) {
             return Err(ContainerMapError::ContainerAlreadyExistsError {
                 container_id: container.id().clone(),
             });
         }
+        if named && !container.name.is_empty() {
+            if let Some(container_id) = names.get(&container.name) {
+                return Err(ContainerMapError::ContainerNameInUseError {
+                    name: container.name.clone(),
+                    container_id: container_id.clone(),
+                });
+            }
+            names.insert(container.name.clone(), container.id().clone());
+        }
         let container_id: String = container.id().clone();
         map.insert(container.id().clone(), container);
         Ok(container_id)
     }
 
+    /// resolve returns the id of the container `reference` refers to: by its
+    /// id, its name, or a prefix of its id that no other container's id starts
+    /// with, in that order
+    pub fn resolve(&self, reference: &str) -> Result<ID, ContainerMapError> {
+        let map = self.map.lock().unwrap();
+        if map.contains_key(reference) {
+            return Ok(reference.to_string());
+        }
+        if let Some(container_id) = self.names.lock().unwrap().get(reference) {
+            return Ok(container_id.clone());
+        }
+        let mut matches = map
+            .keys()
+            .filter(|container_id| !reference.is_empty() && container_id.starts_with(reference));
+        match (matches.next(), matches.next()) {
+            (Some(container_id), None) => Ok(container_id.clone()),
+            (Some(_), Some(_)) => Err(ContainerMapError::AmbiguousContainerIDError {
+                prefix: reference.to_string(),
+            }),
+            (None, _) => Err(ContainerMapError::ContainerNotFoundError {
+                container_id: reference.to_string(),
+            }),
+        }
+    }
+
     /// get gets a container from the map
     pub fn get(&self, container_id: &ID) -> Result<Box<Container>, ContainerMapError> {
         let map = self.map.lock().unwrap();
@@ -82,16 +150,21 @@ impl ContainerMap {
     /// list lists containers in the map
     pub fn list(&self) -> Result<Vec<Container>, ContainerMapError> {
         let map = self.map.lock().unwrap();
-        Ok(map.values().map(|c| c.clone()).collect())
+        Ok(map.values().cloned().collect())
     }
 
-    /// remove removes a container from the map
+    /// remove removes a container from the map, freeing up its name
     pub fn remove(&self, container_id: &ID) {
         let mut map = self.map.lock().unwrap();
-        if !map.contains_key(container_id) {
-            return;
+        let container = match map.remove(container_id) {
+            Some(container) => container,
+            None => return,
+        };
+        let mut names = self.names.lock().unwrap();
+        // unless the container was added without taking up its name
+        if names.get(&container.name) == Some(container_id) {
+            names.remove(&container.name);
         }
-        map.remove(container_id);
     }
 
     /// update_status updates the status of a container
@@ -144,4 +217,200 @@ impl ContainerMap {
         container.started_at = Some(started_at);
         Ok(())
     }
+
+    /// update_restart records a restart of a container, which starts it again
+    /// without an exit
+    pub fn update_restart(
+        &self,
+        container_id: &ID,
+        restarted_at: SystemTime,
+    ) -> Result<(), ContainerMapError> {
+        let mut map = self.map.lock().unwrap();
+        if !map.contains_key(container_id) {
+            return Err(ContainerMapError::ContainerNotFoundError {
+                container_id: container_id.clone(),
+            });
+        }
+        let container = map.get_mut(container_id).unwrap();
+        container.restart_count += 1;
+        container.restarted_at = Some(restarted_at);
+        container.started_at = Some(restarted_at);
+        container.exit_code = -1;
+        container.finished_at = None;
+        container.oom_killed = false;
+        container.reason = String::new();
+        Ok(())
+    }
+
+    /// update_stopped_by_user records whether a container was stopped through
+    /// the daemon
+    pub fn update_stopped_by_user(
+        &self,
+        container_id: &ID,
+        stopped_by_user: bool,
+    ) -> Result<(), ContainerMapError> {
+        let mut map = self.map.lock().unwrap();
+        if !map.contains_key(container_id) {
+            return Err(ContainerMapError::ContainerNotFoundError {
+                container_id: container_id.clone(),
+            });
+        }
+        let container = map.get_mut(container_id).unwrap();
+        container.stopped_by_user = stopped_by_user;
+        Ok(())
+    }
+
+    /// update_resources records the cgroup limits of a container
+    pub fn update_resources(
+        &self,
+        container_id: &ID,
+        resources: Resources,
+    ) -> Result<(), ContainerMapError> {
+        let mut map = self.map.lock().unwrap();
+        if !map.contains_key(container_id) {
+            return Err(ContainerMapError::ContainerNotFoundError {
+                container_id: container_id.clone(),
+            });
+        }
+        let container = map.get_mut(container_id).unwrap();
+        container.resources = resources;
+        Ok(())
+    }
+
+    /// update_labels sets the labels in `labels` on a container, replacing
+    /// the values of labels it already has, and removes the labels keyed by
+    /// `remove`
+    pub fn update_labels(
+        &self,
+        container_id: &ID,
+        labels: HashMap<String, String>,
+        remove: &[String],
+    ) -> Result<(), ContainerMapError> {
+        let mut map = self.map.lock().unwrap();
+        if !map.contains_key(container_id) {
+            return Err(ContainerMapError::ContainerNotFoundError {
+                container_id: container_id.clone(),
+            });
+        }
+        let container = map.get_mut(container_id).unwrap();
+        container.labels.extend(labels);
+        for key in remove {
+            container.labels.remove(key);
+        }
+        Ok(())
+    }
+
+    /// update_exit records the exit of a container, as recorded by its shim
+    pub fn update_exit(
+        &self,
+        container_id: &ID,
+        container_exit: &ContainerExit,
+    ) -> Result<(), ContainerMapError> {
+        let mut map = self.map.lock().unwrap();
+        if !map.contains_key(container_id) {
+            return Err(ContainerMapError::ContainerNotFoundError {
+                container_id: container_id.clone(),
+            });
+        }
+        let container = map.get_mut(container_id).unwrap();
+        container.exit_code = container_exit.exit_code;
+        container.finished_at = Some(container_exit.finished_at);
+        container.oom_killed = container_exit.oom_killed;
+        container.reason = container_exit.reason().to_string();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::container;
+
+    fn new_container(id: &str, name: &str) -> Container {
+        container::new(id, name, "/bin/sh", &[], false, "SIGTERM", 10)
+    }
+
+    #[test]
+    fn rejects_duplicate_names() {
+        let map = ContainerMap::new();
+        map.add(new_container("abc123", "web")).unwrap();
+        match map.add(new_container("def456", "web")) {
+            Err(ContainerMapError::ContainerNameInUseError { name, container_id }) => {
+                assert_eq!(name, "web");
+                assert_eq!(container_id, "abc123");
+            }
+            result => panic!("expected name in use, got {:?}", result),
+        }
+        assert!(matches!(
+            map.get(&"def456".to_string()),
+            Err(ContainerMapError::ContainerNotFoundError { .. })
+        ));
+        // containers without a name don't take one up
+        map.add(new_container("ghi789", "")).unwrap();
+        map.add(new_container("jkl012", "")).unwrap();
+    }
+
+    #[test]
+    fn rejects_duplicate_ids() {
+        let map = ContainerMap::new();
+        map.add(new_container("abc123", "web")).unwrap();
+        assert!(matches!(
+            map.add(new_container("abc123", "db")),
+            Err(ContainerMapError::ContainerAlreadyExistsError { .. })
+        ));
+        // the failed add doesn't take up its name
+        map.add(new_container("def456", "db")).unwrap();
+    }
+
+    #[test]
+    fn resolves_ids_names_and_prefixes() {
+        let map = ContainerMap::new();
+        map.add(new_container("abc123", "web")).unwrap();
+        map.add(new_container("abd456", "db")).unwrap();
+        assert_eq!(map.resolve("abc123").unwrap(), "abc123");
+        assert_eq!(map.resolve("web").unwrap(), "abc123");
+        assert_eq!(map.resolve("abc").unwrap(), "abc123");
+        assert_eq!(map.resolve("abd").unwrap(), "abd456");
+        assert!(matches!(
+            map.resolve("ab"),
+            Err(ContainerMapError::AmbiguousContainerIDError { .. })
+        ));
+        assert!(matches!(
+            map.resolve("xyz"),
+            Err(ContainerMapError::ContainerNotFoundError { .. })
+        ));
+        assert!(matches!(
+            map.resolve(""),
+            Err(ContainerMapError::ContainerNotFoundError { .. })
+        ));
+    }
+
+    #[test]
+    fn resolves_names_before_prefixes() {
+        let map = ContainerMap::new();
+        map.add(new_container("abc123", "")).unwrap();
+        map.add(new_container("def456", "abc")).unwrap();
+        assert_eq!(map.resolve("abc").unwrap(), "def456");
+    }
+
+    #[test]
+    fn frees_names_on_remove() {
+        let map = ContainerMap::new();
+        map.add(new_container("abc123", "web")).unwrap();
+        map.remove(&"abc123".to_string());
+        assert!(map.resolve("web").is_err());
+        map.add(new_container("def456", "web")).unwrap();
+        assert_eq!(map.resolve("web").unwrap(), "def456");
+    }
+
+    #[test]
+    fn unnamed_adds_keep_names() {
+        let map = ContainerMap::new();
+        map.add(new_container("abc123", "web")).unwrap();
+        map.add_unnamed(new_container("def456", "web")).unwrap();
+        assert_eq!(map.resolve("web").unwrap(), "abc123");
+        // removing the unnamed container leaves the name to its owner
+        map.remove(&"def456".to_string());
+        assert_eq!(map.resolve("web").unwrap(), "abc123");
+    }
 }
@@ -0,0 +1,257 @@
+use super::{
+    ContainerRuntimeError, ContainerStats, ExecOutput, RuncMethod, Runtime, RuntimeCreateOptions,
+};
+use crate::container::{ContainerExit, Status, ID};
+use std::collections::{HashMap, HashSet};
+use std::fs::write;
+use std::process::Child;
+use std::sync::{Arc, Barrier, Mutex};
+use std::time::SystemTime;
+
+/// FakeRuntime is an in-memory Runtime for testing the container manager
+/// without an OCI runtime. its containers exit when they are sent a signal
+/// they don't ignore, recording their exit like the shim does. paused
+/// containers only exit once resumed, like frozen processes under the cgroup
+/// v1 freezer
+#[derive(Debug, Default)]
+pub struct FakeRuntime {
+    containers: Mutex<HashMap<ID, FakeContainer>>,
+    // signals containers ignore, SIGKILL can't be
+    ignored_signals: Vec<i32>,
+    // how many times a signalled container's status is queried before it
+    // exits
+    exit_delay: usize,
+    // containers created so far, which are recreated when restarted
+    created: Mutex<HashSet<ID>>,
+    // what recreating a container waits on, once when it's created and once
+    // more before it returns
+    recreate_gate: Option<Arc<Barrier>>,
+}
+
+#[derive(Debug)]
+struct FakeContainer {
+    status: Status,
+    // where the container's exit is recorded
+    exit_file: String,
+    // the signal the container is exiting from, and how many more status
+    // queries it keeps running for
+    exiting: Option<(i32, usize)>,
+}
+
+impl FakeRuntime {
+    pub fn new() -> FakeRuntime {
+        FakeRuntime::default()
+    }
+
+    /// ignoring makes containers ignore `signal`
+    pub fn ignoring(mut self, signal: i32) -> FakeRuntime {
+        self.ignored_signals.push(signal);
+        self
+    }
+
+    /// exiting_after makes signalled containers keep running for `polls`
+    /// status queries before they exit, like processes that take a while to
+    /// die
+    pub fn exiting_after(mut self, polls: usize) -> FakeRuntime {
+        self.exit_delay = polls;
+        self
+    }
+
+    /// holding_recreates makes recreating a container, as restarting it
+    /// does, wait on `gate` once the container is created, and again before
+    /// returning, so tests can act while a restart is in progress
+    pub fn holding_recreates(mut self, gate: Arc<Barrier>) -> FakeRuntime {
+        self.recreate_gate = Some(gate);
+        self
+    }
+
+    /// transition moves a container from status `from` to status `to`, and
+    /// fails like the runtime would if the container isn't in status `from`
+    fn transition(
+        &self,
+        method: RuncMethod,
+        container_id: &ID,
+        from: &[Status],
+        to: Status,
+    ) -> Result<(), ContainerRuntimeError> {
+        let mut containers = self.containers.lock().unwrap();
+        let container = containers.get_mut(container_id).ok_or_else(|| {
+            ContainerRuntimeError::ContainerNotFoundError {
+                container_id: container_id.clone(),
+            }
+        })?;
+        if !from.contains(&container.status) {
+            return Err(ContainerRuntimeError::RuncExitError {
+                method,
+                container_id: container_id.clone(),
+                stderr: format!("container is {}", container.status),
+            });
+        }
+        container.status = to;
+        Ok(())
+    }
+}
+
+impl Runtime for FakeRuntime {
+    fn create_container(&self, opts: RuntimeCreateOptions) -> Result<(), ContainerRuntimeError> {
+        {
+            let mut containers = self.containers.lock().unwrap();
+            if containers.contains_key(&opts.container_id) {
+                return Err(ContainerRuntimeError::RuncExitError {
+                    method: RuncMethod::Create,
+                    container_id: opts.container_id,
+                    stderr: "container already exists".into(),
+                });
+            }
+            containers.insert(
+                opts.container_id.clone(),
+                FakeContainer {
+                    status: Status::Created,
+                    exit_file: opts.exit_file,
+                    exiting: None,
+                },
+            );
+        }
+        let recreated = !self.created.lock().unwrap().insert(opts.container_id);
+        if let (true, Some(gate)) = (recreated, &self.recreate_gate) {
+            gate.wait();
+            gate.wait();
+        }
+        Ok(())
+    }
+
+    fn start_container(&self, container_id: &ID) -> Result<(), ContainerRuntimeError> {
+        self.transition(
+            RuncMethod::Start,
+            container_id,
+            &[Status::Created],
+            Status::Running,
+        )
+    }
+
+    fn kill_container(&self, container_id: &ID, signal: i32) -> Result<(), ContainerRuntimeError> {
+        {
+            let mut containers = self.containers.lock().unwrap();
+            let container = containers.get_mut(container_id).ok_or_else(|| {
+                ContainerRuntimeError::ContainerNotFoundError {
+                    container_id: container_id.clone(),
+                }
+            })?;
+            if container.status != Status::Running && container.status != Status::Paused {
+                return Err(ContainerRuntimeError::RuncExitError {
+                    method: RuncMethod::Kill,
+                    container_id: container_id.clone(),
+                    stderr: format!("container is {}", container.status),
+                });
+            }
+            if container.exiting.is_none()
+                && (signal == libc::SIGKILL || !self.ignored_signals.contains(&signal))
+            {
+                container.exiting = Some((signal, self.exit_delay));
+            }
+        }
+        // containers without an exit delay exit straight away
+        self.get_container_status(container_id).map(|_| ())
+    }
+
+    fn pause_container(&self, container_id: &ID) -> Result<(), ContainerRuntimeError> {
+        self.transition(
+            RuncMethod::Pause,
+            container_id,
+            &[Status::Running],
+            Status::Paused,
+        )
+    }
+
+    fn resume_container(&self, container_id: &ID) -> Result<(), ContainerRuntimeError> {
+        self.transition(
+            RuncMethod::Resume,
+            container_id,
+            &[Status::Paused],
+            Status::Running,
+        )
+    }
+
+    fn update_container(
+        &self,
+        container_id: &ID,
+        _resources_path: &str,
+    ) -> Result<(), ContainerRuntimeError> {
+        self.get_container_status(container_id).map(|_| ())
+    }
+
+    fn delete_container(&self, container_id: &ID) -> Result<(), ContainerRuntimeError> {
+        self.transition(
+            RuncMethod::Delete,
+            container_id,
+            &[Status::Created, Status::Stopped],
+            Status::Stopped,
+        )?;
+        self.containers.lock().unwrap().remove(container_id);
+        Ok(())
+    }
+
+    fn container_stats(&self, container_id: &ID) -> Result<ContainerStats, ContainerRuntimeError> {
+        self.get_container_status(container_id)
+            .map(|_| ContainerStats::default())
+    }
+
+    fn get_container_status(&self, container_id: &ID) -> Result<Status, ContainerRuntimeError> {
+        let mut containers = self.containers.lock().unwrap();
+        let container = containers.get_mut(container_id).ok_or_else(|| {
+            ContainerRuntimeError::ContainerNotFoundError {
+                container_id: container_id.clone(),
+            }
+        })?;
+        match container.exiting {
+            _ if container.status == Status::Paused => (),
+            Some((signal, 0)) => {
+                container.exiting = None;
+                container.status = Status::Stopped;
+                let container_exit = ContainerExit {
+                    exit_code: 128 + signal,
+                    finished_at: SystemTime::now(),
+                    oom_killed: false,
+                };
+                write(
+                    &container.exit_file,
+                    serde_json::to_string(&container_exit).unwrap(),
+                )
+                .map_err(|source| ContainerRuntimeError::RuncError {
+                    method: RuncMethod::State,
+                    container_id: container_id.clone(),
+                    source,
+                })?;
+            }
+            Some((signal, polls)) => container.exiting = Some((signal, polls - 1)),
+            None => (),
+        }
+        Ok(container.status.clone())
+    }
+
+    fn exec_container(
+        &self,
+        container_id: &ID,
+        _process_path: &str,
+    ) -> Result<ExecOutput, ContainerRuntimeError> {
+        self.get_container_status(container_id)?;
+        Ok(ExecOutput {
+            exit_code: 0,
+            stdout: vec![],
+            stderr: vec![],
+        })
+    }
+
+    fn exec_container_interactive(
+        &self,
+        container_id: &ID,
+        _process_path: &str,
+        _console_socket: Option<&str>,
+    ) -> Result<Child, ContainerRuntimeError> {
+        Err(ContainerRuntimeError::RuncExitError {
+            method: RuncMethod::Exec,
+            container_id: container_id.clone(),
+            stderr: "the fake runtime can't run interactive processes".into(),
+        })
+    }
+}
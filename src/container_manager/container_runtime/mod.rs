@@ -1,35 +1,228 @@
-use crate::container::{RuncStatus, Status, ID};
-use log::debug;
+#[cfg(test)]
+pub mod fake;
+mod oci_cli;
+pub mod spec;
+
+use crate::container::{Resources, Status, ID};
+use oci_cli::OciCliRuntime;
+use std::collections::HashMap;
 use std::error::Error;
 use std::fmt;
-use std::process::Command;
+use std::os::unix::process::ExitStatusExt;
+use std::process::{Child, ExitStatus};
+use std::str::FromStr;
 use std::string::FromUtf8Error;
 
-#[derive(Debug)]
-pub struct ContainerRuntime {
-    runtime_path: String,
+/// Runtime is the interface the container manager uses to drive an OCI
+/// runtime. Implementations are expected to be cheap to share across threads.
+pub trait Runtime: fmt::Debug + Send + Sync {
+    /// new_runtime_spec creates a new runtime spec file for a container
+    fn new_runtime_spec(&self, opts: &RuntimeSpecOptions) -> Result<(), ContainerRuntimeError> {
+        spec::Spec::rootless(opts).write(&opts.bundle_path)
+    }
+
+    /// create_container creates a new container
+    fn create_container(&self, opts: RuntimeCreateOptions) -> Result<(), ContainerRuntimeError>;
+
+    /// start_container starts a container
+    fn start_container(&self, container_id: &ID) -> Result<(), ContainerRuntimeError>;
+
+    /// kill_container sends `signal` to a container process
+    fn kill_container(&self, container_id: &ID, signal: i32) -> Result<(), ContainerRuntimeError>;
+
+    /// pause_container freezes all processes in a container
+    fn pause_container(&self, container_id: &ID) -> Result<(), ContainerRuntimeError>;
+
+    /// resume_container thaws all processes in a paused container
+    fn resume_container(&self, container_id: &ID) -> Result<(), ContainerRuntimeError>;
+
+    /// update_container applies the cgroup limits described by the resources
+    /// spec at `resources_path` to a container
+    fn update_container(
+        &self,
+        container_id: &ID,
+        resources_path: &str,
+    ) -> Result<(), ContainerRuntimeError>;
+
+    /// delete_container deletes internal container state
+    fn delete_container(&self, container_id: &ID) -> Result<(), ContainerRuntimeError>;
+
+    /// container_stats samples the resource usage of a container, as
+    /// accounted by its cgroup
+    fn container_stats(&self, container_id: &ID) -> Result<ContainerStats, ContainerRuntimeError>;
+
+    /// get_container_status gets container state
+    fn get_container_status(&self, container_id: &ID) -> Result<Status, ContainerRuntimeError>;
+
+    /// exec_container runs the process described by the process spec at
+    /// `process_path` in a running container, waits for it to exit and
+    /// returns its output
+    fn exec_container(
+        &self,
+        container_id: &ID,
+        process_path: &str,
+    ) -> Result<ExecOutput, ContainerRuntimeError>;
+
+    /// exec_container_interactive starts the process described by the process
+    /// spec at `process_path` in a running container without waiting for it.
+    /// processes with a terminal have it sent over `console_socket`, otherwise
+    /// the returned child's stdin and stdout are piped to the process. the
+    /// child's stderr is always piped, and the child exits with the process
+    fn exec_container_interactive(
+        &self,
+        container_id: &ID,
+        process_path: &str,
+        console_socket: Option<&str>,
+    ) -> Result<Child, ContainerRuntimeError>;
 }
 
-pub struct RuntimeSpecOptions {
-    pub bundle_path: String,
-    pub command: String,
-    pub args: Vec<String>,
+/// exit_code gets the exit code of a runtime process that exits along with
+/// the container process it runs, using the shell convention of 128 + signal
+/// for processes killed by a signal
+pub fn exit_code(status: &ExitStatus) -> i32 {
+    match (status.code(), status.signal()) {
+        (Some(code), _) => code,
+        (None, Some(signal)) => 128 + signal,
+        (None, None) => -1,
+    }
 }
 
-impl RuntimeSpecOptions {
-    pub fn new(bundle_path: String, command: String, args: Vec<String>) -> RuntimeSpecOptions {
-        RuntimeSpecOptions {
-            bundle_path,
-            command,
-            args,
+/// parse_signal parses a signal given by number (`15`) or by name, with or
+/// without its SIG prefix and in any case (`SIGTERM`, `term`)
+pub fn parse_signal(signal: &str) -> Option<i32> {
+    if let Ok(number) = signal.parse::<i32>() {
+        return if number > 0 && number <= libc::SIGRTMAX() {
+            Some(number)
+        } else {
+            None
+        };
+    }
+    let name = signal.to_uppercase();
+    let name = name.trim_start_matches("SIG");
+    let number = match name {
+        "HUP" => libc::SIGHUP,
+        "INT" => libc::SIGINT,
+        "QUIT" => libc::SIGQUIT,
+        "ILL" => libc::SIGILL,
+        "TRAP" => libc::SIGTRAP,
+        "ABRT" | "IOT" => libc::SIGABRT,
+        "BUS" => libc::SIGBUS,
+        "FPE" => libc::SIGFPE,
+        "KILL" => libc::SIGKILL,
+        "USR1" => libc::SIGUSR1,
+        "SEGV" => libc::SIGSEGV,
+        "USR2" => libc::SIGUSR2,
+        "PIPE" => libc::SIGPIPE,
+        "ALRM" => libc::SIGALRM,
+        "TERM" => libc::SIGTERM,
+        "STKFLT" => libc::SIGSTKFLT,
+        "CHLD" => libc::SIGCHLD,
+        "CONT" => libc::SIGCONT,
+        "STOP" => libc::SIGSTOP,
+        "TSTP" => libc::SIGTSTP,
+        "TTIN" => libc::SIGTTIN,
+        "TTOU" => libc::SIGTTOU,
+        "URG" => libc::SIGURG,
+        "XCPU" => libc::SIGXCPU,
+        "XFSZ" => libc::SIGXFSZ,
+        "VTALRM" => libc::SIGVTALRM,
+        "PROF" => libc::SIGPROF,
+        "WINCH" => libc::SIGWINCH,
+        "IO" | "POLL" => libc::SIGIO,
+        "PWR" => libc::SIGPWR,
+        "SYS" => libc::SIGSYS,
+        _ => return None,
+    };
+    Some(number)
+}
+
+/// RuntimeKind enumerates the OCI runtimes cruise knows how to drive
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RuntimeKind {
+    Runc,
+    Crun,
+    Youki,
+}
+
+impl RuntimeKind {
+    pub const VARIANTS: &'static [&'static str] = &["runc", "crun", "youki"];
+
+    /// default_path is where the runtime executable is usually installed
+    pub fn default_path(&self) -> String {
+        format!("/usr/bin/{}", self)
+    }
+
+    /// supports_stats returns whether the runtime reports container stats
+    /// through `events --stats`, which crun doesn't implement
+    pub fn supports_stats(&self) -> bool {
+        *self != Self::Crun
+    }
+}
+
+impl fmt::Display for RuntimeKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Runc => write!(f, "runc"),
+            Self::Crun => write!(f, "crun"),
+            Self::Youki => write!(f, "youki"),
+        }
+    }
+}
+
+impl FromStr for RuntimeKind {
+    type Err = ContainerRuntimeError;
+
+    fn from_str(s: &str) -> Result<RuntimeKind, ContainerRuntimeError> {
+        match s {
+            "runc" => Ok(Self::Runc),
+            "crun" => Ok(Self::Crun),
+            "youki" => Ok(Self::Youki),
+            _ => Err(ContainerRuntimeError::UnknownRuntimeError {
+                runtime: s.to_string(),
+            }),
         }
     }
 }
 
+/// new creates the runtime implementation for `kind`, invoking the executable
+/// at `runtime_path` and supervising containers with the shim at `shim_path`
+pub fn new(kind: RuntimeKind, runtime_path: String, shim_path: String) -> Box<dyn Runtime> {
+    Box::new(OciCliRuntime::new(kind, runtime_path, shim_path))
+}
+
+pub struct RuntimeSpecOptions {
+    pub bundle_path: String,
+    pub command: String,
+    pub args: Vec<String>,
+    // allocate a pseudo-terminal for the container process
+    pub tty: bool,
+    // KEY=VALUE pairs added to, or overriding, the default environment
+    pub env: Vec<String>,
+    // working directory of the container process, `/` if empty
+    pub cwd: String,
+    pub user: spec::User,
+    // ids the container's user namespace maps
+    pub id_mappings: spec::IdMappings,
+    // bind mounts added after the default mounts
+    pub mounts: Vec<spec::Mount>,
+    pub resources: Resources,
+    // OCI annotations added to the spec
+    pub annotations: HashMap<String, String>,
+}
+
 pub struct RuntimeCreateOptions {
     pub bundle_path: String,
     pub container_pidfile: String,
     pub container_id: String,
+    // where the shim records the container's exit code and finish time
+    pub exit_file: String,
+    // where the shim logs the container's stdout and stderr
+    pub log_file: String,
+    // where the shim serves clients attaching to the container
+    pub attach_socket: String,
+    // where the runtime sends the container's terminal to the shim, for
+    // containers with a terminal
+    pub console_socket: Option<String>,
 }
 
 impl RuntimeCreateOptions {
@@ -37,59 +230,128 @@ impl RuntimeCreateOptions {
         bundle_path: String,
         container_pidfile: String,
         container_id: String,
+        exit_file: String,
+        log_file: String,
+        attach_socket: String,
+        console_socket: Option<String>,
     ) -> RuntimeCreateOptions {
         RuntimeCreateOptions {
             bundle_path,
             container_pidfile,
             container_id,
+            exit_file,
+            log_file,
+            attach_socket,
+            console_socket,
         }
     }
 }
 
+/// ContainerStats is the resource usage of a container at some point in time
+#[derive(Debug, Clone, Default)]
+pub struct ContainerStats {
+    // CPU time consumed since the container was created, in nanoseconds
+    pub cpu_usage: u64,
+    // memory in use, in bytes
+    pub memory_usage: u64,
+    // memory limit in bytes, None if unlimited
+    pub memory_limit: Option<u64>,
+    pub pids_current: u64,
+    // limit on the number of processes, None if unlimited
+    pub pids_limit: Option<u64>,
+    // bytes read from and written to block devices
+    pub block_read_bytes: u64,
+    pub block_write_bytes: u64,
+}
+
+/// ExecOutput is the result of a process run in a container
+pub struct ExecOutput {
+    pub exit_code: i32,
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+}
+
 #[derive(Debug, PartialEq)]
 pub enum RuncMethod {
-    Spec,
     Create,
     Start,
     Kill,
     Delete,
     State,
+    Exec,
+    Pause,
+    Resume,
+    Update,
+    Events,
 }
 
-impl RuncMethod {
-    fn to_string(&self) -> String {
+impl fmt::Display for RuncMethod {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            Self::Spec => String::from("spec"),
-            Self::Create => String::from("create"),
-            Self::Start => String::from("start"),
-            Self::Kill => String::from("kill"),
-            Self::Delete => String::from("delete"),
-            Self::State => String::from("state"),
+            Self::Create => write!(f, "create"),
+            Self::Start => write!(f, "start"),
+            Self::Kill => write!(f, "kill"),
+            Self::Delete => write!(f, "delete"),
+            Self::State => write!(f, "state"),
+            Self::Exec => write!(f, "exec"),
+            Self::Pause => write!(f, "pause"),
+            Self::Resume => write!(f, "resume"),
+            Self::Update => write!(f, "update"),
+            Self::Events => write!(f, "events"),
         }
     }
 }
 
 #[derive(Debug)]
 pub enum ContainerRuntimeError {
-    // represents an error executing runc
+    // represents an error executing the runtime
     RuncError {
         method: RuncMethod,
         container_id: ID,
         source: std::io::Error,
     },
-    // represents an error executing sed
-    SedError {
-        updating: String,
+    // represents the runtime exiting unsuccessfully
+    RuncExitError {
+        method: RuncMethod,
+        container_id: ID,
+        stderr: String,
+    },
+    // represents an error reported by the container shim
+    ShimError {
+        container_id: ID,
+        message: String,
+    },
+    // represents an error serializing a runtime spec
+    SerializeSpecError(serde_json::Error),
+    // represents an error writing a runtime spec into a bundle
+    WriteSpecError {
+        source: std::io::Error,
+    },
+    // represents an error reading a runtime spec from a bundle
+    ReadSpecError {
         source: std::io::Error,
     },
+    // represents an error parsing a runtime spec
+    ParseSpecError(serde_json::Error),
     // represents an error converting the status of a container from bytes to a string
     ConvertContainerStatusError(FromUtf8Error),
     // represents an error parsing the status of a container
     ParseContainerStatusError(serde_json::Error),
+    // represents an error parsing the resource usage of a container
+    ParseContainerStatsError(serde_json::Error),
     // represents an error when the container is not found by the runtime
     ContainerNotFoundError {
         container_id: ID,
     },
+    // represents an error when asked for a runtime we don't support
+    UnknownRuntimeError {
+        runtime: String,
+    },
+    // represents asking for container stats from a runtime that can't report
+    // them
+    StatsUnsupportedError {
+        runtime: RuntimeKind,
+    },
 }
 
 impl fmt::Display for ContainerRuntimeError {
@@ -100,24 +362,55 @@ impl fmt::Display for ContainerRuntimeError {
                 ref container_id,
                 ..
             } => {
-                if *method == RuncMethod::Create || *method == RuncMethod::Spec {
-                    write!(f, "failed to execute runc {:?}", method)
+                if *method == RuncMethod::Create {
+                    write!(f, "failed to execute runtime {:?}", method)
                 } else {
                     write!(
                         f,
-                        "failed to execute runc {:?} for container_id {}",
+                        "failed to execute runtime {:?} for container_id {}",
                         method, container_id
                     )
                 }
             }
-            Self::SedError { ref updating, .. } => {
-                write!(f, "failed to use sed to update {}", updating)
-            }
+            Self::RuncExitError {
+                ref method,
+                ref container_id,
+                ref stderr,
+            } => write!(
+                f,
+                "runtime {:?} failed for container_id {}: {}",
+                method, container_id, stderr
+            ),
+            Self::ShimError {
+                ref container_id,
+                ref message,
+            } => write!(
+                f,
+                "shim failed for container_id {}: {}",
+                container_id, message
+            ),
+            Self::SerializeSpecError(ref err) => err.fmt(f),
+            Self::WriteSpecError { .. } => write!(f, "failed to write runtime spec"),
+            Self::ReadSpecError { .. } => write!(f, "failed to read runtime spec"),
+            Self::ParseSpecError(ref err) => err.fmt(f),
             Self::ConvertContainerStatusError(ref err) => err.fmt(f),
             Self::ParseContainerStatusError(ref err) => err.fmt(f),
+            Self::ParseContainerStatsError(ref err) => err.fmt(f),
             Self::ContainerNotFoundError { ref container_id } => {
                 write!(f, "container with container_id {} not found", container_id)
             }
+            Self::UnknownRuntimeError { ref runtime } => write!(
+                f,
+                "unknown runtime {}, expected one of {}",
+                runtime,
+                RuntimeKind::VARIANTS.join(", ")
+            ),
+            Self::StatsUnsupportedError { ref runtime } => write!(
+                f,
+                "container stats are unsupported for runtime {}, which doesn't implement \
+                 `events --stats`",
+                runtime
+            ),
         }
     }
 }
@@ -126,176 +419,18 @@ impl Error for ContainerRuntimeError {
     fn source(&self) -> Option<&(dyn Error + 'static)> {
         match *self {
             Self::RuncError { ref source, .. } => Some(source),
-            Self::SedError { ref source, .. } => Some(source),
+            Self::RuncExitError { .. } => None,
+            Self::ShimError { .. } => None,
+            Self::SerializeSpecError(_) => None,
+            Self::WriteSpecError { ref source } => Some(source),
+            Self::ReadSpecError { ref source } => Some(source),
+            Self::ParseSpecError(_) => None,
             Self::ConvertContainerStatusError(_) => None,
             Self::ParseContainerStatusError(_) => None,
+            Self::ParseContainerStatsError(_) => None,
             Self::ContainerNotFoundError { .. } => None,
+            Self::UnknownRuntimeError { .. } => None,
+            Self::StatsUnsupportedError { .. } => None,
         }
     }
 }
-
-impl ContainerRuntime {
-    pub fn new(runtime_path: String) -> ContainerRuntime {
-        ContainerRuntime { runtime_path }
-    }
-
-    /// new_runtime_spec creates a new runtime spec file for a container
-    pub fn new_runtime_spec(&self, opts: &RuntimeSpecOptions) -> Result<(), ContainerRuntimeError> {
-        // generate generic spec
-        let mut runc = Command::new(&self.runtime_path);
-        runc.arg(RuncMethod::Spec.to_string())
-            .arg("--rootless")
-            .arg("--bundle")
-            .arg(&opts.bundle_path);
-        runc.output()
-            .map_err(|source| ContainerRuntimeError::RuncError {
-                method: RuncMethod::Spec,
-                container_id: String::from(""),
-                source,
-            })?;
-        let config_path = format!("{}/config.json", &opts.bundle_path);
-        // override `args` in spec with combination of command and args options
-        let mut args = String::from(format!("\"{}\"", opts.command));
-        for arg in opts.args.iter() {
-            args.push_str(&format!(", \"{}\"", arg));
-        }
-        let mut args_sed = Command::new("sed");
-        args_sed
-            .arg("-i")
-            .arg(format!("s/\"sh\"/{}/", args))
-            .arg(&config_path);
-        args_sed
-            .output()
-            .map_err(|source| ContainerRuntimeError::SedError {
-                updating: String::from("args"),
-                source,
-            })?;
-        // override `"terminal": true` to `"terminal": false` in spec
-        let mut terminal_sed = Command::new("sed");
-        terminal_sed
-            .arg("-i")
-            .arg("s/\"terminal\": true/\"terminal\": false/")
-            .arg(&config_path);
-        terminal_sed
-            .output()
-            .map_err(|source| ContainerRuntimeError::SedError {
-                updating: String::from("terminal settings"),
-                source,
-            })?;
-        Ok(())
-    }
-
-    /// create_container creates a new container
-    pub fn create_container(
-        &self,
-        opts: RuntimeCreateOptions,
-    ) -> Result<(), ContainerRuntimeError> {
-        // command to execute: runc create --bundle bundle --pid-file container_pidfile container_id
-        let mut runc_create = Command::new(&self.runtime_path);
-        runc_create
-            .arg(RuncMethod::Create.to_string())
-            .arg("--bundle")
-            .arg(&opts.bundle_path)
-            .arg("--pid-file")
-            .arg(format!("{}/{}", &opts.bundle_path, &opts.container_pidfile))
-            .arg(opts.container_id);
-        match runc_create.spawn() {
-            Ok(out) => debug!("runc create output: {:?}", out),
-            Err(source) => {
-                return Err(ContainerRuntimeError::RuncError {
-                    method: RuncMethod::Create,
-                    container_id: String::from(""),
-                    source,
-                });
-            }
-        }
-        Ok(())
-    }
-
-    /// start_container starts a container
-    pub fn start_container(&self, container_id: &ID) -> Result<(), ContainerRuntimeError> {
-        let mut runc_start = Command::new(&self.runtime_path);
-        runc_start
-            .arg(RuncMethod::Start.to_string())
-            .arg(format!("{}", container_id));
-        match runc_start.spawn() {
-            Ok(out) => debug!("runc start output: {:?}", out),
-            Err(source) => {
-                return Err(ContainerRuntimeError::RuncError {
-                    method: RuncMethod::Start,
-                    container_id: container_id.clone(),
-                    source,
-                });
-            }
-        }
-        Ok(())
-    }
-
-    /// kill_container sends a SIGKILL to a container process
-    pub fn kill_container(&self, container_id: &ID) -> Result<(), ContainerRuntimeError> {
-        let mut runc_kill = Command::new(&self.runtime_path);
-        runc_kill
-            .arg(RuncMethod::Kill.to_string())
-            .arg(format!("{}", container_id))
-            .arg("9");
-        match runc_kill.output() {
-            Ok(out) => debug!("runc kill output: {:?}", out),
-            Err(source) => {
-                return Err(ContainerRuntimeError::RuncError {
-                    method: RuncMethod::Kill,
-                    container_id: container_id.clone(),
-                    source,
-                });
-            }
-        }
-        Ok(())
-    }
-
-    /// delete_container deletes internal container state
-    pub fn delete_container(&self, container_id: &ID) -> Result<(), ContainerRuntimeError> {
-        let mut runc_delete = Command::new(&self.runtime_path);
-        runc_delete
-            .arg(RuncMethod::Delete.to_string())
-            .arg(format!("{}", container_id));
-        match runc_delete.output() {
-            Ok(out) => debug!("runc delete output: {:?}", out),
-            Err(source) => {
-                return Err(ContainerRuntimeError::RuncError {
-                    method: RuncMethod::Delete,
-                    container_id: container_id.clone(),
-                    source,
-                });
-            }
-        }
-        Ok(())
-    }
-
-    /// get_container_status gets container state
-    pub fn get_container_status(&self, container_id: &ID) -> Result<Status, ContainerRuntimeError> {
-        let mut runc_status_cmd = Command::new(&self.runtime_path);
-        runc_status_cmd
-            .arg(RuncMethod::State.to_string())
-            .arg(format!("{}", container_id));
-        let status = match runc_status_cmd.output() {
-            Ok(out) => out.stdout,
-            Err(source) => {
-                return Err(ContainerRuntimeError::RuncError {
-                    method: RuncMethod::State,
-                    container_id: container_id.clone(),
-                    source,
-                });
-            }
-        };
-        let runc_status_str = String::from_utf8(status)
-            .map_err(|source| ContainerRuntimeError::ConvertContainerStatusError(source))?;
-        if runc_status_str == "" {
-            // runc does not know about this container
-            return Err(ContainerRuntimeError::ContainerNotFoundError {
-                container_id: container_id.clone(),
-            });
-        }
-        let runc_status: RuncStatus = serde_json::from_str(&runc_status_str)
-            .map_err(|source| ContainerRuntimeError::ParseContainerStatusError(source))?;
-        Ok(Status::from_runc_status(&runc_status))
-    }
-}
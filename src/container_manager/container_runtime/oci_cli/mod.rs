@@ -0,0 +1,411 @@
+use super::{
+    exit_code, ContainerRuntimeError, ContainerStats, ExecOutput, RuncMethod, Runtime,
+    RuntimeCreateOptions, RuntimeKind,
+};
+use crate::container::{RuncStatus, Status, ID};
+use log::debug;
+use serde::Deserialize;
+use std::process::{Child, Command, Stdio};
+
+// cgroups report memory limits at or above this for unlimited memory
+const UNLIMITED_MEMORY: u64 = 1 << 62;
+
+/// OciCliRuntime drives an OCI runtime through its command line interface.
+/// runc, crun and youki all share runc's CLI semantics, so a single
+/// implementation parameterized by `kind` covers all of them.
+#[derive(Debug)]
+pub struct OciCliRuntime {
+    kind: RuntimeKind,
+    runtime_path: String,
+    shim_path: String,
+}
+
+impl OciCliRuntime {
+    pub fn new(kind: RuntimeKind, runtime_path: String, shim_path: String) -> OciCliRuntime {
+        OciCliRuntime {
+            kind,
+            runtime_path,
+            shim_path,
+        }
+    }
+
+    fn command(&self, method: RuncMethod) -> Command {
+        let mut cmd = Command::new(&self.runtime_path);
+        cmd.arg(method.to_string());
+        cmd
+    }
+
+    /// run runs a runtime command to completion, failing if the runtime exits
+    /// unsuccessfully
+    fn run(
+        &self,
+        method: RuncMethod,
+        container_id: &ID,
+        cmd: Command,
+    ) -> Result<(), ContainerRuntimeError> {
+        self.output(method, container_id, cmd)?;
+        Ok(())
+    }
+
+    /// output runs a runtime command to completion like run, returning its
+    /// stdout
+    fn output(
+        &self,
+        method: RuncMethod,
+        container_id: &ID,
+        mut cmd: Command,
+    ) -> Result<Vec<u8>, ContainerRuntimeError> {
+        let out = match cmd.output() {
+            Ok(out) => out,
+            Err(source) => {
+                return Err(ContainerRuntimeError::RuncError {
+                    method,
+                    container_id: container_id.clone(),
+                    source,
+                })
+            }
+        };
+        debug!("{} {} output: {:?}", self.kind, method, out);
+        if !out.status.success() {
+            return Err(ContainerRuntimeError::RuncExitError {
+                method,
+                container_id: container_id.clone(),
+                stderr: String::from_utf8_lossy(&out.stderr).trim().to_string(),
+            });
+        }
+        Ok(out.stdout)
+    }
+}
+
+/// RuncEvent is the subset of an event printed by `runc events` that cruise
+/// reads
+#[derive(Deserialize)]
+struct RuncEvent {
+    #[serde(default)]
+    data: RuncStats,
+}
+
+#[derive(Default, Deserialize)]
+struct RuncStats {
+    #[serde(default)]
+    cpu: RuncCpuStats,
+    #[serde(default)]
+    memory: RuncMemoryStats,
+    #[serde(default)]
+    pids: RuncPidsStats,
+    #[serde(default)]
+    blkio: RuncBlkioStats,
+}
+
+#[derive(Default, Deserialize)]
+struct RuncCpuStats {
+    #[serde(default)]
+    usage: RuncCpuUsage,
+}
+
+#[derive(Default, Deserialize)]
+struct RuncCpuUsage {
+    #[serde(default)]
+    total: u64,
+}
+
+#[derive(Default, Deserialize)]
+struct RuncMemoryStats {
+    #[serde(default)]
+    usage: RuncMemoryUsage,
+}
+
+#[derive(Default, Deserialize)]
+struct RuncMemoryUsage {
+    #[serde(default)]
+    usage: u64,
+    #[serde(default)]
+    limit: u64,
+}
+
+#[derive(Default, Deserialize)]
+struct RuncPidsStats {
+    #[serde(default)]
+    current: u64,
+    // 0 if unlimited
+    #[serde(default)]
+    limit: u64,
+}
+
+#[derive(Default, Deserialize)]
+struct RuncBlkioStats {
+    #[serde(default, rename = "ioServiceBytesRecursive")]
+    io_service_bytes_recursive: Option<Vec<RuncBlkioEntry>>,
+}
+
+#[derive(Deserialize)]
+struct RuncBlkioEntry {
+    #[serde(default)]
+    op: String,
+    #[serde(default)]
+    value: u64,
+}
+
+impl From<RuncStats> for ContainerStats {
+    fn from(stats: RuncStats) -> ContainerStats {
+        let block_bytes = |op: &str| {
+            stats
+                .blkio
+                .io_service_bytes_recursive
+                .iter()
+                .flatten()
+                .filter(|entry| entry.op.eq_ignore_ascii_case(op))
+                .map(|entry| entry.value)
+                .sum()
+        };
+        ContainerStats {
+            cpu_usage: stats.cpu.usage.total,
+            memory_usage: stats.memory.usage.usage,
+            memory_limit: Some(stats.memory.usage.limit)
+                .filter(|limit| *limit > 0 && *limit < UNLIMITED_MEMORY),
+            pids_current: stats.pids.current,
+            pids_limit: Some(stats.pids.limit).filter(|limit| *limit > 0),
+            block_read_bytes: block_bytes("read"),
+            block_write_bytes: block_bytes("write"),
+        }
+    }
+}
+
+impl Runtime for OciCliRuntime {
+    fn create_container(&self, opts: RuntimeCreateOptions) -> Result<(), ContainerRuntimeError> {
+        // command to execute:
+        //   shim --exit_file exit_file --pid_file pidfile --log_file log_file \
+        //     --attach_socket attach_socket [--console_socket console_socket] -- \
+        //     runc create --bundle bundle --pid-file pidfile \
+        //     [--console-socket console_socket] container_id
+        // the shim returns once the runtime has created the container, and
+        // stays behind to log the container's output, serve clients attaching
+        // to it and record its exit
+        let pidfile = format!("{}/{}", &opts.bundle_path, &opts.container_pidfile);
+        let mut shim = Command::new(&self.shim_path);
+        shim.arg("--exit_file")
+            .arg(&opts.exit_file)
+            .arg("--pid_file")
+            .arg(&pidfile)
+            .arg("--log_file")
+            .arg(&opts.log_file)
+            .arg("--attach_socket")
+            .arg(&opts.attach_socket);
+        if let Some(ref console_socket) = opts.console_socket {
+            shim.arg("--console_socket").arg(console_socket);
+        }
+        shim.arg("--")
+            .arg(&self.runtime_path)
+            .arg(RuncMethod::Create.to_string())
+            .arg("--bundle")
+            .arg(&opts.bundle_path)
+            .arg("--pid-file")
+            .arg(&pidfile);
+        if let Some(ref console_socket) = opts.console_socket {
+            shim.arg("--console-socket").arg(console_socket);
+        }
+        shim.arg(&opts.container_id);
+        let out = shim
+            .output()
+            .map_err(|source| ContainerRuntimeError::RuncError {
+                method: RuncMethod::Create,
+                container_id: opts.container_id.clone(),
+                source,
+            })?;
+        debug!("{} create output: {:?}", self.kind, out);
+        if !out.status.success() {
+            return Err(ContainerRuntimeError::ShimError {
+                container_id: opts.container_id,
+                message: String::from_utf8_lossy(&out.stderr).trim().to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    fn start_container(&self, container_id: &ID) -> Result<(), ContainerRuntimeError> {
+        let mut runtime_start = self.command(RuncMethod::Start);
+        runtime_start.arg(container_id);
+        match runtime_start.spawn() {
+            Ok(out) => debug!("{} start output: {:?}", self.kind, out),
+            Err(source) => {
+                return Err(ContainerRuntimeError::RuncError {
+                    method: RuncMethod::Start,
+                    container_id: container_id.clone(),
+                    source,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn kill_container(&self, container_id: &ID, signal: i32) -> Result<(), ContainerRuntimeError> {
+        let mut runtime_kill = self.command(RuncMethod::Kill);
+        runtime_kill.arg(container_id).arg(signal.to_string());
+        self.run(RuncMethod::Kill, container_id, runtime_kill)
+    }
+
+    fn pause_container(&self, container_id: &ID) -> Result<(), ContainerRuntimeError> {
+        let mut runtime_pause = self.command(RuncMethod::Pause);
+        runtime_pause.arg(container_id);
+        self.run(RuncMethod::Pause, container_id, runtime_pause)
+    }
+
+    fn resume_container(&self, container_id: &ID) -> Result<(), ContainerRuntimeError> {
+        let mut runtime_resume = self.command(RuncMethod::Resume);
+        runtime_resume.arg(container_id);
+        self.run(RuncMethod::Resume, container_id, runtime_resume)
+    }
+
+    fn update_container(
+        &self,
+        container_id: &ID,
+        resources_path: &str,
+    ) -> Result<(), ContainerRuntimeError> {
+        // command to execute: runc update --resources resources_path container_id
+        let mut runtime_update = self.command(RuncMethod::Update);
+        runtime_update
+            .arg("--resources")
+            .arg(resources_path)
+            .arg(container_id);
+        self.run(RuncMethod::Update, container_id, runtime_update)
+    }
+
+    fn delete_container(&self, container_id: &ID) -> Result<(), ContainerRuntimeError> {
+        let mut runtime_delete = self.command(RuncMethod::Delete);
+        runtime_delete.arg(container_id);
+        match runtime_delete.output() {
+            Ok(out) => debug!("{} delete output: {:?}", self.kind, out),
+            Err(source) => {
+                return Err(ContainerRuntimeError::RuncError {
+                    method: RuncMethod::Delete,
+                    container_id: container_id.clone(),
+                    source,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn container_stats(&self, container_id: &ID) -> Result<ContainerStats, ContainerRuntimeError> {
+        if !self.kind.supports_stats() {
+            return Err(ContainerRuntimeError::StatsUnsupportedError { runtime: self.kind });
+        }
+        // command to execute: runc events --stats container_id
+        let mut runtime_events = self.command(RuncMethod::Events);
+        runtime_events.arg("--stats").arg(container_id);
+        let events = self.output(RuncMethod::Events, container_id, runtime_events)?;
+        let event: RuncEvent = serde_json::from_slice(&events)
+            .map_err(ContainerRuntimeError::ParseContainerStatsError)?;
+        Ok(event.data.into())
+    }
+
+    fn get_container_status(&self, container_id: &ID) -> Result<Status, ContainerRuntimeError> {
+        let mut runtime_state = self.command(RuncMethod::State);
+        runtime_state.arg(container_id);
+        let status = match runtime_state.output() {
+            Ok(out) => out.stdout,
+            Err(source) => {
+                return Err(ContainerRuntimeError::RuncError {
+                    method: RuncMethod::State,
+                    container_id: container_id.clone(),
+                    source,
+                });
+            }
+        };
+        let runc_status_str = String::from_utf8(status)
+            .map_err(ContainerRuntimeError::ConvertContainerStatusError)?;
+        if runc_status_str.is_empty() {
+            // the runtime does not know about this container
+            return Err(ContainerRuntimeError::ContainerNotFoundError {
+                container_id: container_id.clone(),
+            });
+        }
+        let runc_status: RuncStatus = serde_json::from_str(&runc_status_str)
+            .map_err(ContainerRuntimeError::ParseContainerStatusError)?;
+        Ok(Status::from_runc_status(&runc_status))
+    }
+
+    fn exec_container(
+        &self,
+        container_id: &ID,
+        process_path: &str,
+    ) -> Result<ExecOutput, ContainerRuntimeError> {
+        // command to execute: runc exec --process process_path container_id
+        let mut runtime_exec = self.command(RuncMethod::Exec);
+        runtime_exec
+            .arg("--process")
+            .arg(process_path)
+            .arg(container_id);
+        let out = runtime_exec
+            .output()
+            .map_err(|source| ContainerRuntimeError::RuncError {
+                method: RuncMethod::Exec,
+                container_id: container_id.clone(),
+                source,
+            })?;
+        debug!("{} exec output: {:?}", self.kind, out);
+        Ok(ExecOutput {
+            exit_code: exit_code(&out.status),
+            stdout: out.stdout,
+            stderr: out.stderr,
+        })
+    }
+
+    fn exec_container_interactive(
+        &self,
+        container_id: &ID,
+        process_path: &str,
+        console_socket: Option<&str>,
+    ) -> Result<Child, ContainerRuntimeError> {
+        // command to execute:
+        //   runc exec [--console-socket console_socket] --process process_path container_id
+        let mut runtime_exec = self.command(RuncMethod::Exec);
+        match console_socket {
+            Some(console_socket) => {
+                runtime_exec
+                    .arg("--console-socket")
+                    .arg(console_socket)
+                    .stdin(Stdio::null())
+                    .stdout(Stdio::null());
+            }
+            None => {
+                runtime_exec.stdin(Stdio::piped()).stdout(Stdio::piped());
+            }
+        }
+        runtime_exec
+            .arg("--process")
+            .arg(process_path)
+            .arg(container_id)
+            .stderr(Stdio::piped());
+        let child = runtime_exec
+            .spawn()
+            .map_err(|source| ContainerRuntimeError::RuncError {
+                method: RuncMethod::Exec,
+                container_id: container_id.clone(),
+                source,
+            })?;
+        debug!("{} exec child: {:?}", self.kind, child);
+        Ok(child)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crun_stats_are_unsupported() {
+        // the runtime isn't executed, so it needn't exist
+        let runtime = OciCliRuntime::new(
+            RuntimeKind::Crun,
+            "/nonexistent/crun".to_string(),
+            "/nonexistent/shim".to_string(),
+        );
+        assert!(matches!(
+            runtime.container_stats(&"container".to_string()),
+            Err(ContainerRuntimeError::StatsUnsupportedError {
+                runtime: RuntimeKind::Crun
+            })
+        ));
+    }
+}
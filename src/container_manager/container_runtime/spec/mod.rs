@@ -0,0 +1,703 @@
+use super::{ContainerRuntimeError, RuntimeSpecOptions};
+use crate::container::Resources;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+use std::ffi::CStr;
+use std::fs::{read_to_string, write};
+
+// the runtime-spec version we generate configs for
+pub const OCI_VERSION: &str = "1.0.2";
+
+const DEFAULT_PATH_ENV: &str = "PATH=/usr/local/sbin:/usr/local/bin:/usr/sbin:/usr/bin:/sbin:/bin";
+const DEFAULT_TERM_ENV: &str = "TERM=xterm";
+const DEFAULT_CAPABILITIES: &[&str] = &["CAP_AUDIT_WRITE", "CAP_KILL", "CAP_NET_BIND_SERVICE"];
+const DEFAULT_MASKED_PATHS: &[&str] = &[
+    "/proc/acpi",
+    "/proc/asound",
+    "/proc/kcore",
+    "/proc/keys",
+    "/proc/latency_stats",
+    "/proc/timer_list",
+    "/proc/timer_stats",
+    "/proc/sched_debug",
+    "/sys/firmware",
+    "/proc/scsi",
+];
+// where the subordinate ids delegated to users are listed
+const SUBUID_PATH: &str = "/etc/subuid";
+const SUBGID_PATH: &str = "/etc/subgid";
+const DEFAULT_READONLY_PATHS: &[&str] = &[
+    "/proc/bus",
+    "/proc/fs",
+    "/proc/irq",
+    "/proc/sys",
+    "/proc/sysrq-trigger",
+];
+
+/// Spec models the subset of the OCI runtime spec (config.json) that cruise
+/// generates. Fields we don't set are left out of the serialized document so
+/// the runtime falls back to its own defaults.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Spec {
+    pub oci_version: String,
+    pub process: Process,
+    pub root: Root,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub hostname: String,
+    #[serde(default)]
+    pub mounts: Vec<Mount>,
+    pub linux: Linux,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub annotations: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Process {
+    #[serde(default)]
+    pub terminal: bool,
+    pub user: User,
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub env: Vec<String>,
+    pub cwd: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub capabilities: Option<Capabilities>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rlimits: Vec<Rlimit>,
+    #[serde(default)]
+    pub no_new_privileges: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct User {
+    pub uid: u32,
+    pub gid: u32,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub additional_gids: Vec<u32>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct Capabilities {
+    #[serde(default)]
+    pub bounding: Vec<String>,
+    #[serde(default)]
+    pub effective: Vec<String>,
+    #[serde(default)]
+    pub inheritable: Vec<String>,
+    #[serde(default)]
+    pub permitted: Vec<String>,
+    #[serde(default)]
+    pub ambient: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Rlimit {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub hard: u64,
+    pub soft: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Root {
+    pub path: String,
+    #[serde(default)]
+    pub readonly: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Mount {
+    pub destination: String,
+    #[serde(rename = "type", default, skip_serializing_if = "String::is_empty")]
+    pub kind: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub source: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub options: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Linux {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub uid_mappings: Vec<IdMapping>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub gid_mappings: Vec<IdMapping>,
+    #[serde(default)]
+    pub namespaces: Vec<Namespace>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub masked_paths: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub readonly_paths: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub resources: Option<LinuxResources>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct LinuxResources {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory: Option<Memory>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cpu: Option<Cpu>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pids: Option<Pids>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct Memory {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reservation: Option<i64>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct Cpu {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shares: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quota: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub period: Option<u64>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub cpus: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub mems: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Pids {
+    pub limit: i64,
+}
+
+impl LinuxResources {
+    /// from_resources builds the `linux.resources` block for a container's
+    /// limits, leaving out the controllers it doesn't limit
+    pub fn from_resources(resources: &Resources) -> LinuxResources {
+        let memory = Memory {
+            limit: resources.memory_limit,
+            reservation: resources.memory_reservation,
+        };
+        let cpu = Cpu {
+            shares: resources.cpu_shares,
+            quota: resources.cpu_quota,
+            period: resources.cpu_period,
+            cpus: resources.cpuset_cpus.clone(),
+            mems: resources.cpuset_mems.clone(),
+        };
+        LinuxResources {
+            memory: Some(memory).filter(|memory| *memory != Memory::default()),
+            cpu: Some(cpu).filter(|cpu| *cpu != Cpu::default()),
+            pids: resources.pids_limit.map(|limit| Pids { limit }),
+        }
+    }
+
+    /// write serializes the resources into `path`, for use with
+    /// `runc update --resources`
+    pub fn write(&self, path: &str) -> Result<(), ContainerRuntimeError> {
+        let serialized_resources = serde_json::to_string_pretty(self)
+            .map_err(ContainerRuntimeError::SerializeSpecError)?;
+        write(path, serialized_resources)
+            .map_err(|source| ContainerRuntimeError::WriteSpecError { source })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct IdMapping {
+    #[serde(rename = "containerID")]
+    pub container_id: u32,
+    #[serde(rename = "hostID")]
+    pub host_id: u32,
+    pub size: u32,
+}
+
+/// IdMappings are the host uids and gids a container's user namespace maps
+/// its own to
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct IdMappings {
+    pub uid_mappings: Vec<IdMapping>,
+    pub gid_mappings: Vec<IdMapping>,
+}
+
+impl IdMappings {
+    /// for_daemon returns the mappings of the containers the daemon runs. a
+    /// daemon running as root maps every id to itself. a rootless daemon maps
+    /// root to its own uid and gid, and the ids after it to the subordinate
+    /// ids delegated to its user in /etc/subuid and /etc/subgid, as long as
+    /// newuidmap and newgidmap, which runc writes those mappings with, are
+    /// installed
+    pub fn for_daemon() -> IdMappings {
+        let (host_uid, host_gid) = host_ids();
+        if host_uid == 0 {
+            let identity = vec![IdMapping {
+                container_id: 0,
+                host_id: 0,
+                size: u32::MAX,
+            }];
+            return IdMappings {
+                uid_mappings: identity.clone(),
+                gid_mappings: identity,
+            };
+        }
+        let user_name = user_name(host_uid).unwrap_or_default();
+        let subordinate_ranges = |path: &str, helper: &str| {
+            if !in_path(helper) {
+                return vec![];
+            }
+            read_to_string(path)
+                .map(|contents| subordinate_ranges(&contents, &user_name, host_uid))
+                .unwrap_or_default()
+        };
+        IdMappings {
+            uid_mappings: rootless_mappings(host_uid, subordinate_ranges(SUBUID_PATH, "newuidmap")),
+            gid_mappings: rootless_mappings(host_gid, subordinate_ranges(SUBGID_PATH, "newgidmap")),
+        }
+    }
+
+    /// maps_uid returns whether the container uid `uid` is mapped
+    pub fn maps_uid(&self, uid: u32) -> bool {
+        maps(&self.uid_mappings, uid)
+    }
+
+    /// maps_gid returns whether the container gid `gid` is mapped
+    pub fn maps_gid(&self, gid: u32) -> bool {
+        maps(&self.gid_mappings, gid)
+    }
+}
+
+fn maps(mappings: &[IdMapping], id: u32) -> bool {
+    mappings
+        .iter()
+        .any(|mapping| id >= mapping.container_id && id - mapping.container_id < mapping.size)
+}
+
+/// rootless_mappings maps root to `host_id`, and the ids after it to each
+/// of the subordinate id `ranges`, given as start and count, in turn
+fn rootless_mappings(host_id: u32, ranges: Vec<(u32, u32)>) -> Vec<IdMapping> {
+    let mut mappings = vec![IdMapping {
+        container_id: 0,
+        host_id,
+        size: 1,
+    }];
+    let mut next_id = 1u32;
+    for (start, count) in ranges {
+        mappings.push(IdMapping {
+            container_id: next_id,
+            host_id: start,
+            size: count,
+        });
+        next_id = match next_id.checked_add(count) {
+            Some(next_id) => next_id,
+            None => break,
+        };
+    }
+    mappings
+}
+
+/// subordinate_ranges returns the start and count of the ranges of
+/// subordinate ids that `contents`, an /etc/subuid or /etc/subgid file,
+/// delegates to the user named `user_name` or with uid `uid`
+fn subordinate_ranges(contents: &str, user_name: &str, uid: u32) -> Vec<(u32, u32)> {
+    let uid = uid.to_string();
+    // user:start:count
+    contents
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.trim().split(':').collect();
+            if fields.len() != 3 || (fields[0] != user_name && fields[0] != uid) {
+                return None;
+            }
+            let start = fields[1].parse().ok()?;
+            let count = fields[2].parse().ok()?;
+            Some((start, count)).filter(|&(_, count)| count > 0)
+        })
+        .collect()
+}
+
+/// user_name returns the name of the user with uid `uid`, if it has one
+fn user_name(uid: u32) -> Option<String> {
+    let mut buf = vec![0 as libc::c_char; 16 * 1024];
+    // safe: passwd is plain old data, which getpwuid_r fills in
+    let mut passwd: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut result = std::ptr::null_mut();
+    // safe: passwd, buf and result outlive the call, which only writes
+    // within them
+    let ret =
+        unsafe { libc::getpwuid_r(uid, &mut passwd, buf.as_mut_ptr(), buf.len(), &mut result) };
+    if ret != 0 || result.is_null() {
+        return None;
+    }
+    // safe: a found entry's name is a nul terminated string within buf
+    let name = unsafe { CStr::from_ptr(passwd.pw_name) };
+    Some(name.to_string_lossy().into_owned())
+}
+
+/// in_path returns whether `program` is in a directory of $PATH
+fn in_path(program: &str) -> bool {
+    env::var_os("PATH")
+        .is_some_and(|path| env::split_paths(&path).any(|dir| dir.join(program).is_file()))
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Namespace {
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub path: String,
+}
+
+fn strings(values: &[&str]) -> Vec<String> {
+    values.iter().map(|value| value.to_string()).collect()
+}
+
+fn mount(destination: &str, kind: &str, source: &str, options: &[&str]) -> Mount {
+    Mount {
+        destination: destination.into(),
+        kind: kind.into(),
+        source: source.into(),
+        options: strings(options),
+    }
+}
+
+impl Spec {
+    /// rootless builds the equivalent of `runc spec --rootless` for the given
+    /// options, with the user namespace mapping the options' ids
+    pub fn rootless(opts: &RuntimeSpecOptions) -> Spec {
+        let mut args = vec![opts.command.clone()];
+        args.extend(opts.args.iter().cloned());
+        let capabilities = strings(DEFAULT_CAPABILITIES);
+        let mut env = strings(&[DEFAULT_PATH_ENV, DEFAULT_TERM_ENV]);
+        set_env(&mut env, &opts.env);
+        let mut mounts = vec![
+            mount("/proc", "proc", "proc", &[]),
+            mount(
+                "/dev",
+                "tmpfs",
+                "tmpfs",
+                &["nosuid", "strictatime", "mode=755", "size=65536k"],
+            ),
+            mount(
+                "/dev/pts",
+                "devpts",
+                "devpts",
+                &[
+                    "nosuid",
+                    "noexec",
+                    "newinstance",
+                    "ptmxmode=0666",
+                    "mode=0620",
+                ],
+            ),
+            mount(
+                "/dev/shm",
+                "tmpfs",
+                "shm",
+                &["nosuid", "noexec", "nodev", "mode=1777", "size=65536k"],
+            ),
+            mount(
+                "/dev/mqueue",
+                "mqueue",
+                "mqueue",
+                &["nosuid", "noexec", "nodev"],
+            ),
+            // rootless containers can't mount sysfs, so bind the host's
+            mount(
+                "/sys",
+                "none",
+                "/sys",
+                &["rbind", "nosuid", "noexec", "nodev", "ro"],
+            ),
+        ];
+        mounts.extend(opts.mounts.iter().cloned());
+        Spec {
+            oci_version: OCI_VERSION.into(),
+            process: Process {
+                terminal: opts.tty,
+                user: opts.user.clone(),
+                args,
+                env,
+                cwd: if opts.cwd.is_empty() {
+                    "/".into()
+                } else {
+                    opts.cwd.clone()
+                },
+                capabilities: Some(Capabilities {
+                    bounding: capabilities.clone(),
+                    effective: capabilities.clone(),
+                    inheritable: capabilities.clone(),
+                    permitted: capabilities.clone(),
+                    ambient: capabilities,
+                }),
+                rlimits: vec![Rlimit {
+                    kind: "RLIMIT_NOFILE".into(),
+                    hard: 1024,
+                    soft: 1024,
+                }],
+                no_new_privileges: true,
+            },
+            root: Root {
+                path: "rootfs".into(),
+                readonly: true,
+            },
+            hostname: "cruise".into(),
+            mounts,
+            linux: Linux {
+                uid_mappings: opts.id_mappings.uid_mappings.clone(),
+                gid_mappings: opts.id_mappings.gid_mappings.clone(),
+                namespaces: ["pid", "ipc", "uts", "mount", "user"]
+                    .iter()
+                    .map(|kind| Namespace {
+                        kind: kind.to_string(),
+                        path: String::new(),
+                    })
+                    .collect(),
+                masked_paths: strings(DEFAULT_MASKED_PATHS),
+                readonly_paths: strings(DEFAULT_READONLY_PATHS),
+                resources: Some(LinuxResources::from_resources(&opts.resources))
+                    .filter(|resources| *resources != LinuxResources::default()),
+            },
+            annotations: opts.annotations.clone(),
+        }
+    }
+
+    /// id_mappings returns the mappings of the spec's user namespace
+    pub fn id_mappings(&self) -> IdMappings {
+        IdMappings {
+            uid_mappings: self.linux.uid_mappings.clone(),
+            gid_mappings: self.linux.gid_mappings.clone(),
+        }
+    }
+
+    /// load reads the spec from `config.json` in the bundle directory
+    pub fn load(bundle_path: &str) -> Result<Spec, ContainerRuntimeError> {
+        let serialized_spec = read_to_string(config_path(bundle_path))
+            .map_err(|source| ContainerRuntimeError::ReadSpecError { source })?;
+        serde_json::from_str(&serialized_spec).map_err(ContainerRuntimeError::ParseSpecError)
+    }
+
+    /// write serializes the spec into `config.json` in the bundle directory
+    pub fn write(&self, bundle_path: &str) -> Result<(), ContainerRuntimeError> {
+        let serialized_spec = serde_json::to_string_pretty(self)
+            .map_err(ContainerRuntimeError::SerializeSpecError)?;
+        write(config_path(bundle_path), serialized_spec)
+            .map_err(|source| ContainerRuntimeError::WriteSpecError { source })
+    }
+}
+
+impl Process {
+    /// set_env adds the `KEY=VALUE` pairs in `env` to the process environment,
+    /// replacing any existing values for the same keys
+    pub fn set_env(&mut self, env: &[String]) {
+        set_env(&mut self.env, env)
+    }
+
+    /// write serializes the process into `path`, for use with `runc exec --process`
+    pub fn write(&self, path: &str) -> Result<(), ContainerRuntimeError> {
+        let serialized_process = serde_json::to_string_pretty(self)
+            .map_err(ContainerRuntimeError::SerializeSpecError)?;
+        write(path, serialized_process)
+            .map_err(|source| ContainerRuntimeError::WriteSpecError { source })
+    }
+}
+
+/// set_env adds the `KEY=VALUE` pairs in `env` to `existing`, replacing any
+/// existing values for the same keys
+fn set_env(existing: &mut Vec<String>, env: &[String]) {
+    for pair in env {
+        let key = env_key(pair);
+        match existing.iter_mut().find(|current| env_key(current) == key) {
+            Some(current) => *current = pair.clone(),
+            None => existing.push(pair.clone()),
+        }
+    }
+}
+
+fn env_key(pair: &str) -> &str {
+    pair.split('=').next().unwrap_or(pair)
+}
+
+/// config_path is the location of the spec within a bundle
+pub fn config_path(bundle_path: &str) -> String {
+    format!("{}/config.json", bundle_path)
+}
+
+/// host_ids returns the effective uid and gid of the daemon, which rootless
+/// containers map to root
+fn host_ids() -> (u32, u32) {
+    // safe: geteuid and getegid always succeed and touch no memory
+    unsafe { (libc::geteuid(), libc::getegid()) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options() -> RuntimeSpecOptions {
+        RuntimeSpecOptions {
+            bundle_path: "/bundle".into(),
+            command: "/bin/sh".into(),
+            args: vec!["-c".into(), "echo hi".into()],
+            tty: true,
+            env: vec!["FOO=bar".into(), "PATH=/bin".into()],
+            cwd: "/work".into(),
+            user: User {
+                uid: 0,
+                gid: 0,
+                additional_gids: vec![10],
+            },
+            id_mappings: IdMappings {
+                uid_mappings: rootless_mappings(1000, vec![(100000, 65536)]),
+                gid_mappings: rootless_mappings(1001, vec![]),
+            },
+            mounts: vec![mount("/data", "bind", "/host/data", &["rbind", "ro"])],
+            resources: Resources::default(),
+            annotations: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn rootless_namespaces() {
+        let spec = Spec::rootless(&options());
+        let kinds: Vec<&str> = spec
+            .linux
+            .namespaces
+            .iter()
+            .map(|namespace| namespace.kind.as_str())
+            .collect();
+        assert_eq!(kinds, vec!["pid", "ipc", "uts", "mount", "user"]);
+        assert!(spec
+            .linux
+            .namespaces
+            .iter()
+            .all(|namespace| namespace.path.is_empty()));
+    }
+
+    #[test]
+    fn rootless_maps_given_ids() {
+        let spec = Spec::rootless(&options());
+        assert_eq!(
+            spec.linux.uid_mappings,
+            vec![
+                IdMapping {
+                    container_id: 0,
+                    host_id: 1000,
+                    size: 1,
+                },
+                IdMapping {
+                    container_id: 1,
+                    host_id: 100000,
+                    size: 65536,
+                },
+            ]
+        );
+        assert_eq!(
+            spec.linux.gid_mappings,
+            vec![IdMapping {
+                container_id: 0,
+                host_id: 1001,
+                size: 1,
+            }]
+        );
+        assert_eq!(spec.id_mappings(), options().id_mappings);
+    }
+
+    #[test]
+    fn maps_subordinate_ids_after_root() {
+        let subuid = "# delegated ids\nother:100000:65536\nuser:165536:1000\n1000:300000:10\nuser:400000:0\n";
+        let ranges = subordinate_ranges(subuid, "user", 1000);
+        assert_eq!(ranges, vec![(165536, 1000), (300000, 10)]);
+        let id_mappings = IdMappings {
+            uid_mappings: rootless_mappings(1000, ranges),
+            gid_mappings: rootless_mappings(1000, vec![]),
+        };
+        assert_eq!(id_mappings.uid_mappings[1].container_id, 1);
+        assert_eq!(id_mappings.uid_mappings[2].container_id, 1001);
+        for uid in [0, 1, 1000, 1001, 1010] {
+            assert!(id_mappings.maps_uid(uid));
+        }
+        assert!(!id_mappings.maps_uid(1011));
+        assert!(id_mappings.maps_gid(0));
+        assert!(!id_mappings.maps_gid(1));
+    }
+
+    #[test]
+    fn rootless_mounts() {
+        let spec = Spec::rootless(&options());
+        let destinations: Vec<&str> = spec
+            .mounts
+            .iter()
+            .map(|mount| mount.destination.as_str())
+            .collect();
+        assert_eq!(
+            destinations,
+            vec![
+                "/proc",
+                "/dev",
+                "/dev/pts",
+                "/dev/shm",
+                "/dev/mqueue",
+                "/sys",
+                "/data"
+            ]
+        );
+        // rootless containers bind the host's sysfs
+        assert_eq!(spec.mounts[5].kind, "none");
+        assert!(spec.mounts[5].options.contains(&"rbind".to_string()));
+        assert_eq!(spec.mounts[6].source, "/host/data");
+    }
+
+    #[test]
+    fn rootless_process() {
+        let spec = Spec::rootless(&options());
+        assert!(spec.process.terminal);
+        assert_eq!(spec.process.args, vec!["/bin/sh", "-c", "echo hi"]);
+        // the request's env is added to the defaults, replacing PATH
+        assert_eq!(
+            spec.process.env,
+            vec!["PATH=/bin", DEFAULT_TERM_ENV, "FOO=bar"]
+        );
+        assert_eq!(spec.process.cwd, "/work");
+        assert_eq!(spec.process.user, options().user);
+        assert!(spec.process.no_new_privileges);
+    }
+
+    #[test]
+    fn rootless_defaults_cwd_to_root() {
+        let mut opts = options();
+        opts.cwd = String::new();
+        assert_eq!(Spec::rootless(&opts).process.cwd, "/");
+    }
+
+    #[test]
+    fn rootless_only_sets_given_limits() {
+        let mut opts = options();
+        assert_eq!(Spec::rootless(&opts).linux.resources, None);
+        opts.resources.pids_limit = Some(10);
+        let resources = Spec::rootless(&opts).linux.resources.unwrap();
+        assert_eq!(resources.pids, Some(Pids { limit: 10 }));
+        assert_eq!(resources.memory, None);
+        assert_eq!(resources.cpu, None);
+    }
+
+    #[test]
+    fn rootless_annotations() {
+        let mut opts = options();
+        opts.annotations
+            .insert("org.example.key".into(), "value".into());
+        let spec = Spec::rootless(&opts);
+        assert_eq!(spec.annotations, opts.annotations);
+    }
+
+    #[test]
+    fn write_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let bundle_path = dir.path().to_string_lossy().to_string();
+        let spec = Spec::rootless(&options());
+        spec.write(&bundle_path).unwrap();
+        assert_eq!(Spec::load(&bundle_path).unwrap(), spec);
+    }
+}
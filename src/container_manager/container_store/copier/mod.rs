@@ -0,0 +1,445 @@
+use log::debug;
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::fs::{copy, create_dir, hard_link, read_dir, read_link, symlink_metadata, Metadata};
+use std::io::{Error, ErrorKind};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{symlink, FileTypeExt, MetadataExt};
+use std::path::{Path, PathBuf};
+
+// the largest extended attribute value linux supports
+const XATTR_SIZE_MAX: usize = 64 * 1024;
+
+/// Attributes are the metadata of a file system entry besides its contents
+pub struct Attributes {
+    pub uid: u32,
+    pub gid: u32,
+    // permission bits, including the setuid, setgid and sticky bits
+    pub mode: u32,
+    pub atime: libc::timespec,
+    pub mtime: libc::timespec,
+    // extended attributes, by name
+    pub xattrs: Vec<(CString, Vec<u8>)>,
+}
+
+/// copy_tree copies the contents of the directory `from` into the existing
+/// directory `to`, as faithfully as the daemon's privileges allow:
+/// - symlinks are copied as symlinks, never followed
+/// - ownership, mode bits, access and modification times and extended
+///   attributes are kept for every entry, `to` included
+/// - files hardlinked together within `from` stay hardlinked together
+/// - fifos and, when we're privileged enough, device nodes are recreated.
+///   sockets are skipped, as they're only meaningful to the process that
+///   bound them
+///
+/// unprivileged daemons can't give entries away or create device nodes, so
+/// those are skipped rather than failing the copy
+pub fn copy_tree<P, Q>(from: P, to: Q) -> Result<(), Error>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    let from = from.as_ref();
+    let to = to.as_ref();
+    for path in [from, to] {
+        if !symlink_metadata(path)
+            .map(|metadata| metadata.is_dir())
+            .unwrap_or(false)
+        {
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                format!("{:?} is not a directory", path),
+            ));
+        }
+    }
+    let mut copier = Copier {
+        privileged: is_privileged(),
+        links: HashMap::new(),
+    };
+    copier.copy_dir_contents(from, to)?;
+    copier
+        .copy_attributes(from, to, &symlink_metadata(from)?)
+        .map_err(|err| with_path(err, from))
+}
+
+/// is_privileged returns whether the daemon may chown entries and create
+/// device nodes
+pub fn is_privileged() -> bool {
+    // safe: geteuid can't fail
+    unsafe { libc::geteuid() == 0 }
+}
+
+/// make_node creates the fifo or device node described by `mode` and `rdev`
+/// at `path`, returning false if we aren't privileged enough to
+pub fn make_node(path: &Path, mode: u32, rdev: u64) -> Result<bool, Error> {
+    let c_path = c_path(path)?;
+    // safe: c_path is a valid nul terminated string for the duration of the call
+    if unsafe { libc::mknod(c_path.as_ptr(), mode, rdev) } == 0 {
+        return Ok(true);
+    }
+    let err = Error::last_os_error();
+    if err.kind() == ErrorKind::PermissionDenied && mode & libc::S_IFMT != libc::S_IFIFO {
+        return Ok(false);
+    }
+    Err(err)
+}
+
+/// set_attributes gives the entry at `path` ownership, extended attributes,
+/// mode bits and times, in that order since changing the owner clears
+/// setuid and setgid bits and file capabilities. symlinks aren't followed.
+/// ownership and extended attributes we aren't privileged enough to set, or
+/// that the file system doesn't support, are skipped
+pub fn set_attributes(path: &Path, attributes: &Attributes, privileged: bool) -> Result<(), Error> {
+    let c_path = c_path(path)?;
+    // safe: c_path is a valid nul terminated string for the duration of the call
+    if unsafe { libc::lchown(c_path.as_ptr(), attributes.uid, attributes.gid) } != 0 {
+        let err = Error::last_os_error();
+        if privileged || err.kind() != ErrorKind::PermissionDenied {
+            return Err(err);
+        }
+    }
+    for (name, value) in &attributes.xattrs {
+        // safe: the strings are nul terminated and value outlives the call
+        let set = unsafe {
+            libc::lsetxattr(
+                c_path.as_ptr(),
+                name.as_ptr(),
+                value.as_ptr() as *const libc::c_void,
+                value.len(),
+                0,
+            )
+        };
+        if set != 0 {
+            let err = Error::last_os_error();
+            let unsupported = err.raw_os_error() == Some(libc::ENOTSUP);
+            let forbidden = !privileged && err.kind() == ErrorKind::PermissionDenied;
+            if !unsupported && !forbidden {
+                return Err(err);
+            }
+            debug!(
+                "skipping extended attribute {:?} of {:?}: {}",
+                name, path, err
+            );
+        }
+    }
+    // symlinks have no mode bits of their own, chmod would follow them
+    if !symlink_metadata(path)?.file_type().is_symlink() {
+        // safe: c_path is a valid nul terminated string for the duration of the call
+        if unsafe { libc::chmod(c_path.as_ptr(), attributes.mode & 0o7777) } != 0 {
+            return Err(Error::last_os_error());
+        }
+    }
+    let times = [attributes.atime, attributes.mtime];
+    // safe: c_path is a valid nul terminated string and times holds two
+    // timespecs for the duration of the call
+    let stamped = unsafe {
+        libc::utimensat(
+            libc::AT_FDCWD,
+            c_path.as_ptr(),
+            times.as_ptr(),
+            libc::AT_SYMLINK_NOFOLLOW,
+        )
+    };
+    if stamped != 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(())
+}
+
+struct Copier {
+    privileged: bool,
+    // copies of the files with more than one link, by their device and inode
+    links: HashMap<(u64, u64), PathBuf>,
+}
+
+impl Copier {
+    fn copy_dir_contents(&mut self, from: &Path, to: &Path) -> Result<(), Error> {
+        for from_entry in read_dir(from).map_err(|err| with_path(err, from))? {
+            let from_entry = from_entry.map_err(|err| with_path(err, from))?;
+            self.copy_entry(&from_entry.path(), &to.join(from_entry.file_name()))?;
+        }
+        Ok(())
+    }
+
+    fn copy_entry(&mut self, from: &Path, to: &Path) -> Result<(), Error> {
+        let metadata = symlink_metadata(from).map_err(|err| with_path(err, from))?;
+        if metadata.is_dir() {
+            create_dir(to).map_err(|err| with_path(err, from))?;
+            self.copy_dir_contents(from, to)?;
+        } else if !self
+            .copy_non_dir(from, to, &metadata)
+            .map_err(|err| with_path(err, from))?
+        {
+            return Ok(());
+        }
+        // directories are stamped after their contents so that copying the
+        // contents doesn't change their modification time
+        self.copy_attributes(from, to, &metadata)
+            .map_err(|err| with_path(err, from))
+    }
+
+    /// copy_non_dir copies anything but a directory, returning whether `to`
+    /// still needs the attributes of `from`
+    fn copy_non_dir(&mut self, from: &Path, to: &Path, metadata: &Metadata) -> Result<bool, Error> {
+        let file_type = metadata.file_type();
+        if file_type.is_symlink() {
+            symlink(read_link(from)?, to)?;
+        } else if file_type.is_file() {
+            // directories can't be hardlinked, so only files are tracked
+            if metadata.nlink() > 1 {
+                let inode = (metadata.dev(), metadata.ino());
+                if let Some(linked) = self.links.get(&inode) {
+                    // links share their attributes with the first copy
+                    hard_link(linked, to)?;
+                    return Ok(false);
+                }
+                self.links.insert(inode, to.to_path_buf());
+            }
+            copy(from, to)?;
+        } else if file_type.is_socket() {
+            debug!("skipping socket {:?}", from);
+            return Ok(false);
+        } else if !make_node(to, metadata.mode(), metadata.rdev())? {
+            debug!("skipping device node {:?}, not privileged", from);
+            return Ok(false);
+        }
+        Ok(true)
+    }
+
+    fn copy_attributes(&self, from: &Path, to: &Path, metadata: &Metadata) -> Result<(), Error> {
+        let attributes = Attributes {
+            uid: metadata.uid(),
+            gid: metadata.gid(),
+            mode: metadata.mode(),
+            atime: libc::timespec {
+                tv_sec: metadata.atime(),
+                tv_nsec: metadata.atime_nsec(),
+            },
+            mtime: libc::timespec {
+                tv_sec: metadata.mtime(),
+                tv_nsec: metadata.mtime_nsec(),
+            },
+            xattrs: read_xattrs(from)?,
+        };
+        set_attributes(to, &attributes, self.privileged)
+    }
+}
+
+/// read_xattrs returns the extended attributes of `path`, without following
+/// symlinks
+pub fn read_xattrs(path: &Path) -> Result<Vec<(CString, Vec<u8>)>, Error> {
+    let c_path = c_path(path)?;
+    let names = list_xattrs(&c_path)?;
+    let mut xattrs = vec![];
+    let mut value = vec![0u8; XATTR_SIZE_MAX];
+    for name in names
+        .split(|byte| *byte == 0)
+        .filter(|name| !name.is_empty())
+    {
+        let name = CString::new(name).map_err(|err| Error::new(ErrorKind::InvalidData, err))?;
+        // safe: the strings are nul terminated and value holds XATTR_SIZE_MAX
+        // bytes for the duration of the call
+        let size = unsafe {
+            libc::lgetxattr(
+                c_path.as_ptr(),
+                name.as_ptr(),
+                value.as_mut_ptr() as *mut libc::c_void,
+                value.len(),
+            )
+        };
+        if size < 0 {
+            let err = Error::last_os_error();
+            // the attribute was removed since we listed it
+            if err.raw_os_error() == Some(libc::ENODATA) {
+                continue;
+            }
+            return Err(err);
+        }
+        xattrs.push((name, value[..size as usize].to_vec()));
+    }
+    Ok(xattrs)
+}
+
+/// list_xattrs returns the nul separated names of the extended attributes
+/// of `path`, without following symlinks
+fn list_xattrs(path: &CString) -> Result<Vec<u8>, Error> {
+    loop {
+        // safe: path is a valid nul terminated string, and a null buffer only
+        // asks for the size of the list
+        let size = unsafe { libc::llistxattr(path.as_ptr(), std::ptr::null_mut(), 0) };
+        if size < 0 {
+            let err = Error::last_os_error();
+            if err.raw_os_error() == Some(libc::ENOTSUP) {
+                return Ok(vec![]);
+            }
+            return Err(err);
+        }
+        if size == 0 {
+            return Ok(vec![]);
+        }
+        let mut names = vec![0u8; size as usize];
+        // safe: names holds size bytes for the duration of the call
+        let listed = unsafe {
+            libc::llistxattr(
+                path.as_ptr(),
+                names.as_mut_ptr() as *mut libc::c_char,
+                names.len(),
+            )
+        };
+        if listed >= 0 {
+            names.truncate(listed as usize);
+            return Ok(names);
+        }
+        let err = Error::last_os_error();
+        // attributes were added since we asked for the size, try again
+        if err.raw_os_error() != Some(libc::ERANGE) {
+            return Err(err);
+        }
+    }
+}
+
+/// with_path adds the path that failed to be copied to `err`
+fn with_path(err: Error, path: &Path) -> Error {
+    Error::new(err.kind(), format!("failed to copy {:?}: {}", path, err))
+}
+
+fn c_path(path: &Path) -> Result<CString, Error> {
+    CString::new(path.as_os_str().as_bytes())
+        .map_err(|err| Error::new(ErrorKind::InvalidInput, err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::{create_dir_all, metadata, read_to_string, set_permissions, write, Permissions};
+    use std::os::unix::fs::PermissionsExt;
+    use tempfile::tempdir;
+
+    /// set_mtime sets the access and modification times of `path`, without
+    /// following symlinks
+    fn set_mtime(path: &Path, seconds: i64) {
+        let metadata = symlink_metadata(path).unwrap();
+        let time = libc::timespec {
+            tv_sec: seconds,
+            tv_nsec: 500,
+        };
+        let attributes = Attributes {
+            uid: metadata.uid(),
+            gid: metadata.gid(),
+            mode: metadata.mode(),
+            atime: time,
+            mtime: time,
+            xattrs: vec![],
+        };
+        set_attributes(path, &attributes, is_privileged()).unwrap();
+    }
+
+    #[test]
+    fn copies_symlinks_as_symlinks() {
+        let dir = tempdir().unwrap();
+        let (from, to) = (dir.path().join("from"), dir.path().join("to"));
+        create_dir_all(from.join("bin")).unwrap();
+        create_dir_all(&to).unwrap();
+        write(from.join("bin/busybox"), "busybox").unwrap();
+        symlink("busybox", from.join("bin/sh")).unwrap();
+        // absolute and dangling symlinks aren't followed either
+        symlink("/etc/shadow", from.join("shadow")).unwrap();
+        symlink("missing", from.join("dangling")).unwrap();
+        symlink("bin", from.join("sbin")).unwrap();
+        copy_tree(&from, &to).unwrap();
+        for (link, target) in &[
+            ("bin/sh", "busybox"),
+            ("shadow", "/etc/shadow"),
+            ("dangling", "missing"),
+            ("sbin", "bin"),
+        ] {
+            let path = to.join(link);
+            assert!(symlink_metadata(&path).unwrap().file_type().is_symlink());
+            assert_eq!(read_link(&path).unwrap(), Path::new(target));
+        }
+        assert_eq!(read_to_string(to.join("bin/sh")).unwrap(), "busybox");
+    }
+
+    #[test]
+    fn preserves_hardlinks() {
+        let dir = tempdir().unwrap();
+        let (from, to) = (dir.path().join("from"), dir.path().join("to"));
+        create_dir_all(from.join("a")).unwrap();
+        create_dir_all(from.join("b")).unwrap();
+        create_dir_all(&to).unwrap();
+        write(from.join("a/file"), "linked").unwrap();
+        hard_link(from.join("a/file"), from.join("b/file")).unwrap();
+        write(from.join("single"), "single").unwrap();
+        copy_tree(&from, &to).unwrap();
+        let (a, b) = (
+            metadata(to.join("a/file")).unwrap(),
+            metadata(to.join("b/file")).unwrap(),
+        );
+        assert_eq!((a.dev(), a.ino()), (b.dev(), b.ino()));
+        assert_eq!(a.nlink(), 2);
+        // the copies aren't linked to the originals
+        assert_ne!(a.ino(), metadata(from.join("a/file")).unwrap().ino());
+        assert_eq!(metadata(to.join("single")).unwrap().nlink(), 1);
+    }
+
+    #[test]
+    fn preserves_modes_and_times() {
+        let dir = tempdir().unwrap();
+        let (from, to) = (dir.path().join("from"), dir.path().join("to"));
+        create_dir_all(from.join("dir")).unwrap();
+        create_dir_all(&to).unwrap();
+        write(from.join("dir/script"), "#!/bin/sh\n").unwrap();
+        write(from.join("dir/private"), "").unwrap();
+        symlink("script", from.join("dir/link")).unwrap();
+        set_permissions(from.join("dir/script"), Permissions::from_mode(0o4755)).unwrap();
+        set_permissions(from.join("dir/private"), Permissions::from_mode(0o600)).unwrap();
+        set_permissions(from.join("dir"), Permissions::from_mode(0o1750)).unwrap();
+        for (i, path) in ["dir/script", "dir/private", "dir/link", "dir", ""]
+            .iter()
+            .enumerate()
+        {
+            set_mtime(&from.join(path), 1_000_000_000 + i as i64);
+        }
+        copy_tree(&from, &to).unwrap();
+        for path in &["dir/script", "dir/private", "dir/link", "dir", ""] {
+            let (original, copy) = (
+                symlink_metadata(from.join(path)).unwrap(),
+                symlink_metadata(to.join(path)).unwrap(),
+            );
+            assert_eq!(original.mode(), copy.mode(), "mode of {:?}", path);
+            assert_eq!(
+                (original.mtime(), original.mtime_nsec()),
+                (copy.mtime(), copy.mtime_nsec()),
+                "mtime of {:?}",
+                path
+            );
+        }
+        assert_eq!(
+            metadata(to.join("dir/script")).unwrap().mode() & 0o7777,
+            0o4755
+        );
+    }
+
+    #[test]
+    fn recreates_fifos() {
+        let dir = tempdir().unwrap();
+        let (from, to) = (dir.path().join("from"), dir.path().join("to"));
+        create_dir_all(&from).unwrap();
+        create_dir_all(&to).unwrap();
+        assert!(make_node(&from.join("fifo"), libc::S_IFIFO | 0o644, 0).unwrap());
+        copy_tree(&from, &to).unwrap();
+        assert!(symlink_metadata(to.join("fifo"))
+            .unwrap()
+            .file_type()
+            .is_fifo());
+    }
+
+    #[test]
+    fn requires_existing_directories() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("file");
+        write(&file, "").unwrap();
+        assert!(copy_tree(dir.path(), dir.path().join("missing")).is_err());
+        assert!(copy_tree(&file, dir.path()).is_err());
+    }
+}
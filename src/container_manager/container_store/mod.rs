@@ -1,11 +1,25 @@
-use crate::container::{Container, ID};
+mod copier;
+mod snapshotter;
+pub mod tarball;
+
+pub use copier::is_privileged;
+
+use crate::container::{Container, ContainerExit, ID};
+use crate::volume::Volume;
+use log::debug;
+use snapshotter::{Snapshot, SnapshotDirs, SnapshotKind};
 use std::error::Error;
 use std::fmt;
 use std::fs::{
-    copy, create_dir, create_dir_all, read_dir, read_to_string, remove_dir_all, rename, write,
+    canonicalize, create_dir_all, read_dir, read_to_string, remove_dir_all, remove_file, rename,
+    write, File,
 };
 use std::path::Path;
 
+// name of the file in a container's bundle that the runtime records the pid
+// of the container's init process in
+pub const CONTAINER_PIDFILE: &str = "container.pidfile";
+
 #[derive(Debug)]
 pub struct ContainerStore {
     root_dir: String,
@@ -14,69 +28,58 @@ pub struct ContainerStore {
 // this could stand to be in its own module, we're cheating a little here by
 // wrapping extra information into std::io::Errors for the sake of not
 // moving this into its own module
-fn copy_dir<P, Q>(from: P, to: Q) -> Result<(), std::io::Error>
-where
-    P: AsRef<Path>,
-    Q: AsRef<Path>,
-{
-    let from = from.as_ref();
-    if !from.exists() {
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::NotFound,
-            format!("{:?} does not exist", from),
-        ));
-    }
-
-    let to = to.as_ref();
-    if !to.exists() {
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::NotFound,
-            format!("{:?} does not exist", from),
-        ));
-    }
-
-    for from_entry in read_dir(from)? {
-        let from_entry_path = from_entry?.path();
-        let from_entry_last_component = match from_entry_path.components().last() {
-            Some(last_component) => last_component,
-            None => {
-                return Err(std::io::Error::new(
-                    std::io::ErrorKind::NotFound,
-                    format!(
-                        "last component not found in `from` path: {:?}",
-                        from_entry_path
-                    ),
-                ))
-            }
-        };
-
-        let mut to_entry_path = to.to_path_buf();
-        to_entry_path.push(from_entry_last_component);
-
-        if from_entry_path.is_dir() {
-            create_dir(&to_entry_path)?;
-            copy_dir(from_entry_path, to_entry_path)?;
-        } else {
-            match copy(from_entry_path, to_entry_path) {
-                Ok(_) => (),
-                Err(err) => {
-                    return Err(std::io::Error::new(
-                        err.kind(),
-                        format!("failed to copy file: {}", err),
-                    ));
-                }
-            }
-        }
-    }
-    Ok(())
-}
-
 #[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum ContainerStoreError {
     // represents an error creating the main containers dir
     CreateContainersDirError {
         source: std::io::Error,
     },
+    // represents an error creating the console sockets dir
+    CreateConsolesDirError {
+        source: std::io::Error,
+    },
+    // represents an error creating the main volumes dir
+    CreateVolumesDirError {
+        source: std::io::Error,
+    },
+    // represents an error reading the main volumes dir
+    ReadVolumesDirError {
+        source: std::io::Error,
+    },
+    // represents an error when a volume we're trying to create already exists
+    VolumeAlreadyExistsError {
+        name: String,
+    },
+    // represents an error when a volume is not found
+    VolumeNotFoundError {
+        name: String,
+    },
+    // represents an error creating the directory of a volume
+    CreateVolumeDirError {
+        name: String,
+        source: std::io::Error,
+    },
+    // represents an error persisting the state of a volume
+    PersistVolumeStateError {
+        name: String,
+        source: std::io::Error,
+    },
+    // represents an error reading a volume state file
+    ReadVolumeStateFileError {
+        name: String,
+        source: std::io::Error,
+    },
+    // represents an error parsing volume state
+    ParseVolumeStateError {
+        name: String,
+        source: serde_json::Error,
+    },
+    // represents an error removing the directory of a volume
+    RemoveVolumeDirError {
+        name: String,
+        source: std::io::Error,
+    },
     // represents an error reading the main containers dir
     ReadContainersDirError {
         source: std::io::Error,
@@ -96,6 +99,41 @@ pub enum ContainerStoreError {
         container_id: ID,
         source: std::io::Error,
     },
+    // represents an error extracting a rootfs tarball into a container bundle
+    ExtractRootfsTarballError {
+        container_id: ID,
+        source: std::io::Error,
+    },
+    // represents an error resolving the rootfs a container is created from
+    ResolveSourceRootfsError {
+        rootfs: String,
+        source: std::io::Error,
+    },
+    // represents an error mounting the overlay rootfs of a container again
+    MountRootfsError {
+        container_id: ID,
+        source: std::io::Error,
+    },
+    // represents an error unmounting the overlay rootfs of a container
+    UnmountRootfsError {
+        container_id: ID,
+        source: std::io::Error,
+    },
+    // represents an error persisting how the rootfs of a container is backed
+    PersistSnapshotError {
+        container_id: ID,
+        source: std::io::Error,
+    },
+    // represents an error reading how the rootfs of a container is backed
+    ReadSnapshotError {
+        container_id: ID,
+        source: std::io::Error,
+    },
+    // represents an error parsing how the rootfs of a container is backed
+    ParseSnapshotError {
+        container_id: ID,
+        source: serde_json::Error,
+    },
     // represents an error when a container directory already exists that
     // we're trying to create
     ContainerDirAlreadyExistsError {
@@ -126,6 +164,26 @@ pub enum ContainerStoreError {
         container_id: ID,
         source: serde_json::Error,
     },
+    // represents an error reading a container exit file
+    ReadContainerExitFileError {
+        container_id: ID,
+        source: std::io::Error,
+    },
+    // represents an error parsing a container exit file
+    ParseContainerExitError {
+        container_id: ID,
+        source: serde_json::Error,
+    },
+    // represents an error reading the pid of a container's init process
+    ReadContainerPidFileError {
+        container_id: ID,
+        source: std::io::Error,
+    },
+    // represents an error opening a container log file
+    OpenContainerLogError {
+        container_id: ID,
+        source: std::io::Error,
+    },
     // represents an error where the container id is not in the filesystem path
     ContainerIDNotInPathError {
         container_dir: String,
@@ -144,6 +202,30 @@ impl fmt::Display for ContainerStoreError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
             Self::CreateContainersDirError { .. } => write!(f, "failed to create containers dir"),
+            Self::CreateConsolesDirError { .. } => write!(f, "failed to create consoles dir"),
+            Self::CreateVolumesDirError { .. } => write!(f, "failed to create volumes dir"),
+            Self::ReadVolumesDirError { .. } => write!(f, "failed to read volumes dir"),
+            Self::VolumeAlreadyExistsError { ref name } => {
+                write!(f, "volume with name {} already exists", name)
+            }
+            Self::VolumeNotFoundError { ref name } => {
+                write!(f, "volume with name {} not found", name)
+            }
+            Self::CreateVolumeDirError { ref name, .. } => {
+                write!(f, "failed to create volume dir for name {}", name)
+            }
+            Self::PersistVolumeStateError { ref name, .. } => {
+                write!(f, "failed to persist volume state for name {}", name)
+            }
+            Self::ReadVolumeStateFileError { ref name, .. } => {
+                write!(f, "failed to read volume state file for name {}", name)
+            }
+            Self::ParseVolumeStateError { ref name, .. } => {
+                write!(f, "failed to parse volume state for name {}", name)
+            }
+            Self::RemoveVolumeDirError { ref name, .. } => {
+                write!(f, "failed to remove volume dir for name {}", name)
+            }
             Self::ReadContainersDirError { .. } => write!(f, "failed to read containers dir"),
             Self::CreateSpecificContainerDirError {
                 ref container_id, ..
@@ -166,6 +248,51 @@ impl fmt::Display for ContainerStoreError {
                 "failed to copy rootfs dir for container_id {}",
                 container_id
             ),
+            Self::ExtractRootfsTarballError {
+                ref container_id, ..
+            } => write!(
+                f,
+                "failed to extract rootfs tarball for container_id {}",
+                container_id
+            ),
+            Self::ResolveSourceRootfsError { ref rootfs, .. } => {
+                write!(f, "failed to resolve rootfs {}", rootfs)
+            }
+            Self::MountRootfsError {
+                ref container_id, ..
+            } => write!(
+                f,
+                "failed to mount rootfs for container_id {}",
+                container_id
+            ),
+            Self::UnmountRootfsError {
+                ref container_id, ..
+            } => write!(
+                f,
+                "failed to unmount rootfs for container_id {}",
+                container_id
+            ),
+            Self::PersistSnapshotError {
+                ref container_id, ..
+            } => write!(
+                f,
+                "failed to persist rootfs snapshot for container_id {}",
+                container_id
+            ),
+            Self::ReadSnapshotError {
+                ref container_id, ..
+            } => write!(
+                f,
+                "failed to read rootfs snapshot for container_id {}",
+                container_id
+            ),
+            Self::ParseSnapshotError {
+                ref container_id, ..
+            } => write!(
+                f,
+                "failed to parse rootfs snapshot for container_id {}",
+                container_id
+            ),
             Self::ContainerDirAlreadyExistsError { ref container_id } => write!(
                 f,
                 "container directory already exists for container_id {}",
@@ -206,6 +333,34 @@ impl fmt::Display for ContainerStoreError {
                 "failed to read parse container state for container_id {}",
                 container_id
             ),
+            Self::ReadContainerExitFileError {
+                ref container_id, ..
+            } => write!(
+                f,
+                "failed to read container exit file for container_id {}",
+                container_id
+            ),
+            Self::ParseContainerExitError {
+                ref container_id, ..
+            } => write!(
+                f,
+                "failed to parse container exit for container_id {}",
+                container_id
+            ),
+            Self::ReadContainerPidFileError {
+                ref container_id, ..
+            } => write!(
+                f,
+                "failed to read container pid file for container_id {}",
+                container_id
+            ),
+            Self::OpenContainerLogError {
+                ref container_id, ..
+            } => write!(
+                f,
+                "failed to open container log for container_id {}",
+                container_id
+            ),
             Self::ContainerIDNotInPathError { ref container_dir } => write!(
                 f,
                 "container id was not at the end of the container_dir path: {}",
@@ -220,16 +375,37 @@ impl Error for ContainerStoreError {
     fn source(&self) -> Option<&(dyn Error + 'static)> {
         match *self {
             Self::CreateContainersDirError { ref source } => Some(source),
+            Self::CreateConsolesDirError { ref source } => Some(source),
+            Self::CreateVolumesDirError { ref source } => Some(source),
+            Self::ReadVolumesDirError { ref source } => Some(source),
+            Self::VolumeAlreadyExistsError { .. } => None,
+            Self::VolumeNotFoundError { .. } => None,
+            Self::CreateVolumeDirError { ref source, .. } => Some(source),
+            Self::PersistVolumeStateError { ref source, .. } => Some(source),
+            Self::ReadVolumeStateFileError { ref source, .. } => Some(source),
+            Self::ParseVolumeStateError { ref source, .. } => Some(source),
+            Self::RemoveVolumeDirError { ref source, .. } => Some(source),
             Self::ReadContainersDirError { ref source } => Some(source),
             Self::CreateSpecificContainerDirError { ref source, .. } => Some(source),
             Self::CreateRootfsDirError { ref source, .. } => Some(source),
             Self::CopyRootfsDirError { ref source, .. } => Some(source),
+            Self::ExtractRootfsTarballError { ref source, .. } => Some(source),
+            Self::ResolveSourceRootfsError { ref source, .. } => Some(source),
+            Self::MountRootfsError { ref source, .. } => Some(source),
+            Self::UnmountRootfsError { ref source, .. } => Some(source),
+            Self::PersistSnapshotError { ref source, .. } => Some(source),
+            Self::ReadSnapshotError { ref source, .. } => Some(source),
+            Self::ParseSnapshotError { ref source, .. } => Some(source),
             Self::ContainerDirAlreadyExistsError { .. } => None,
             Self::SerializeContainerStateError { ref source, .. } => Some(source),
             Self::PersistContainerStateError { ref source, .. } => Some(source),
             Self::RenameContainerStateFileError { ref source, .. } => Some(source),
             Self::ReadContainerStateFileError { ref source, .. } => Some(source),
             Self::ParseContainerStateError { ref source, .. } => Some(source),
+            Self::ReadContainerExitFileError { ref source, .. } => Some(source),
+            Self::ParseContainerExitError { ref source, .. } => Some(source),
+            Self::ReadContainerPidFileError { ref source, .. } => Some(source),
+            Self::OpenContainerLogError { ref source, .. } => Some(source),
             Self::ContainerIDNotInPathError { .. } => None,
             Self::IOError(_) => None,
         }
@@ -238,13 +414,18 @@ impl Error for ContainerStoreError {
 
 impl ContainerStore {
     pub fn new(root_dir: String) -> Result<ContainerStore, ContainerStoreError> {
-        let cs = ContainerStore { root_dir: root_dir };
-        let _ = create_dir_all(cs.containers_dir())
+        let cs = ContainerStore { root_dir };
+        create_dir_all(cs.containers_dir())
             .map_err(|source| ContainerStoreError::CreateContainersDirError { source })?;
+        create_dir_all(cs.consoles_dir())
+            .map_err(|source| ContainerStoreError::CreateConsolesDirError { source })?;
+        create_dir_all(cs.volumes_dir())
+            .map_err(|source| ContainerStoreError::CreateVolumesDirError { source })?;
         Ok(cs)
     }
 
-    /// create_container_directory creates the container directory on disk and returns the directory
+    /// create_container_directory creates the container directory, including
+    /// its logs directory, on disk
     pub fn create_container_directory(&self, container_id: &ID) -> Result<(), ContainerStoreError> {
         let container_dir = self.specific_container_dir(container_id);
         if Path::new(&container_dir).exists() {
@@ -252,7 +433,7 @@ impl ContainerStore {
                 container_id: container_id.clone(),
             });
         }
-        create_dir_all(&container_dir).map_err(|source| {
+        create_dir_all(self.logs_dir(container_id)).map_err(|source| {
             ContainerStoreError::CreateSpecificContainerDirError {
                 container_id: container_id.clone(),
                 source,
@@ -260,32 +441,177 @@ impl ContainerStore {
         })
     }
 
-    /// remove_container_directory deletes the container directory on disk
-    pub fn remove_container_directory(&self, container_id: &ID) {
+    /// remove_container_directory unmounts the container rootfs, if it's an
+    /// overlay, and deletes the container directory on disk. nothing is
+    /// deleted if the rootfs can't be unmounted, since deleting through a
+    /// mounted overlay would reach into the directories it's made of
+    pub fn remove_container_directory(&self, container_id: &ID) -> Result<(), ContainerStoreError> {
+        if let Some(snapshot) = self.read_snapshot(container_id)? {
+            snapshotter::unmount(&snapshot, &self.rootfs_dir(container_id)).map_err(|source| {
+                ContainerStoreError::UnmountRootfsError {
+                    container_id: container_id.clone(),
+                    source,
+                }
+            })?;
+        }
         let container_dir = self.specific_container_dir(container_id);
         let _ = remove_dir_all(&container_dir);
+        Ok(())
+    }
+
+    /// remove_container_exit_file deletes the exit recorded by a container's
+    /// shim on disk
+    pub fn remove_container_exit_file(&self, container_id: &ID) {
+        let _ = remove_file(self.container_exit_file(container_id));
+    }
+
+    /// remove_exec_process_file deletes the process spec of an exec on disk
+    pub fn remove_exec_process_file(&self, container_id: &ID, exec_id: &ID) {
+        let _ = remove_file(self.exec_process_file(container_id, exec_id));
     }
 
-    /// create_container_bundle copies the root filesystem of a container to
-    /// the container bundle directory
+    /// create_container_bundle prepares the root filesystem of a container in
+    /// the container bundle directory. `rootfs` is either a directory or a
+    /// tarball of one. tarballs are extracted into the bundle. directories
+    /// are overlaid where overlays can be mounted, and copied otherwise
     pub fn create_container_bundle(
         &self,
         container_id: &ID,
         rootfs: &str,
     ) -> Result<String, ContainerStoreError> {
-        // copy the rootfs of the container
+        let lower_dir = canonicalize(rootfs)
+            .map_err(|source| ContainerStoreError::ResolveSourceRootfsError {
+                rootfs: rootfs.to_string(),
+                source,
+            })?
+            .to_string_lossy()
+            .to_string();
+        if tarball::is_tarball(&lower_dir) {
+            return self.extract_container_bundle(container_id, lower_dir);
+        }
         let rootfs_dir = self.rootfs_dir(container_id);
-        let _ = create_dir_all(&rootfs_dir).map_err(|source| {
+        let upper_dir = self.snapshot_upper_dir(container_id);
+        let work_dir = self.snapshot_work_dir(container_id);
+        for dir in [&rootfs_dir, &upper_dir, &work_dir] {
+            create_dir_all(dir).map_err(|source| ContainerStoreError::CreateRootfsDirError {
+                container_id: container_id.clone(),
+                source,
+            })?;
+        }
+        // mount an overlay of the rootfs, falling back to copying it
+        let dirs = SnapshotDirs {
+            lower_dir: &lower_dir,
+            upper_dir: &upper_dir,
+            work_dir: &work_dir,
+            merged_dir: &rootfs_dir,
+        };
+        let kind = match snapshotter::mount_overlay(&dirs) {
+            Ok(kind) => kind,
+            Err(err) => {
+                debug!(
+                    "unable to mount overlay rootfs of container `{}`, copying it instead: `{}`",
+                    container_id, err
+                );
+                let _ = remove_dir_all(self.snapshot_dir(container_id));
+                copier::copy_tree(&lower_dir, &rootfs_dir).map_err(|source| {
+                    ContainerStoreError::CopyRootfsDirError {
+                        container_id: container_id.clone(),
+                        source,
+                    }
+                })?;
+                SnapshotKind::Copy
+            }
+        };
+        let snapshot = Snapshot { kind, lower_dir };
+        if let Err(err) = self.persist_snapshot(container_id, &snapshot) {
+            // without the snapshot on disk, nothing would unmount the rootfs
+            let _ = snapshotter::unmount(&snapshot, &rootfs_dir);
+            return Err(err);
+        }
+        Ok(self.bundle_dir(container_id))
+    }
+
+    fn extract_container_bundle(
+        &self,
+        container_id: &ID,
+        tarball: String,
+    ) -> Result<String, ContainerStoreError> {
+        let rootfs_dir = self.rootfs_dir(container_id);
+        create_dir_all(&rootfs_dir).map_err(|source| {
             ContainerStoreError::CreateRootfsDirError {
                 container_id: container_id.clone(),
                 source,
             }
         })?;
-        copy_dir(rootfs, rootfs_dir).map_err(|source| ContainerStoreError::CopyRootfsDirError {
+        tarball::extract_tarball(&tarball, &rootfs_dir).map_err(|source| {
+            ContainerStoreError::ExtractRootfsTarballError {
+                container_id: container_id.clone(),
+                source,
+            }
+        })?;
+        // an extracted tarball is owned by the container, like a copy
+        let snapshot = Snapshot {
+            kind: SnapshotKind::Copy,
+            lower_dir: tarball,
+        };
+        self.persist_snapshot(container_id, &snapshot)?;
+        Ok(self.bundle_dir(container_id))
+    }
+
+    /// remount_container_rootfs mounts the overlay rootfs of a container again
+    /// if it isn't mounted, as after the host reboots
+    pub fn remount_container_rootfs(&self, container_id: &ID) -> Result<(), ContainerStoreError> {
+        // containers created before snapshots were recorded have copies
+        let snapshot = match self.read_snapshot(container_id)? {
+            Some(snapshot) => snapshot,
+            None => return Ok(()),
+        };
+        let dirs = SnapshotDirs {
+            lower_dir: &snapshot.lower_dir,
+            upper_dir: &self.snapshot_upper_dir(container_id),
+            work_dir: &self.snapshot_work_dir(container_id),
+            merged_dir: &self.rootfs_dir(container_id),
+        };
+        snapshotter::remount(&snapshot, &dirs).map_err(|source| {
+            ContainerStoreError::MountRootfsError {
+                container_id: container_id.clone(),
+                source,
+            }
+        })
+    }
+
+    fn persist_snapshot(
+        &self,
+        container_id: &ID,
+        snapshot: &Snapshot,
+    ) -> Result<(), ContainerStoreError> {
+        let persist_error = |source| ContainerStoreError::PersistSnapshotError {
             container_id: container_id.clone(),
             source,
+        };
+        let serialized_snapshot = serde_json::to_string(snapshot).map_err(|err| {
+            persist_error(std::io::Error::new(std::io::ErrorKind::InvalidData, err))
         })?;
-        Ok(self.bundle_dir(container_id))
+        write(self.snapshot_file(container_id), serialized_snapshot).map_err(persist_error)
+    }
+
+    fn read_snapshot(&self, container_id: &ID) -> Result<Option<Snapshot>, ContainerStoreError> {
+        let snapshot_string = match read_to_string(self.snapshot_file(container_id)) {
+            Ok(snapshot_string) => snapshot_string,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(source) => {
+                return Err(ContainerStoreError::ReadSnapshotError {
+                    container_id: container_id.clone(),
+                    source,
+                })
+            }
+        };
+        serde_json::from_str(&snapshot_string)
+            .map(Some)
+            .map_err(|source| ContainerStoreError::ParseSnapshotError {
+                container_id: container_id.clone(),
+                source,
+            })
     }
 
     /// atomic_persist_container_state atomically stores a container's state
@@ -339,6 +665,59 @@ impl ContainerStore {
         })
     }
 
+    /// read_container_exit reads the exit recorded by a container's shim, if
+    /// the container has exited
+    pub fn read_container_exit(
+        &self,
+        container_id: &ID,
+    ) -> Result<Option<ContainerExit>, ContainerStoreError> {
+        let container_exit_string = match read_to_string(self.container_exit_file(container_id)) {
+            Ok(container_exit_string) => container_exit_string,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(source) => {
+                return Err(ContainerStoreError::ReadContainerExitFileError {
+                    container_id: container_id.clone(),
+                    source,
+                })
+            }
+        };
+        serde_json::from_str(&container_exit_string)
+            .map(Some)
+            .map_err(|source| ContainerStoreError::ParseContainerExitError {
+                container_id: container_id.clone(),
+                source,
+            })
+    }
+
+    /// read_container_pid reads the pid of a container's init process, as
+    /// recorded by the runtime when it created the container
+    pub fn read_container_pid(&self, container_id: &ID) -> Result<i32, ContainerStoreError> {
+        let pidfile = format!("{}/{}", self.bundle_dir(container_id), CONTAINER_PIDFILE);
+        read_to_string(pidfile)
+            .and_then(|pid| {
+                pid.trim().parse().map_err(|_| {
+                    std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        format!("invalid pid `{}`", pid.trim()),
+                    )
+                })
+            })
+            .map_err(|source| ContainerStoreError::ReadContainerPidFileError {
+                container_id: container_id.clone(),
+                source,
+            })
+    }
+
+    /// open_container_log opens a container's log file for reading
+    pub fn open_container_log(&self, container_id: &ID) -> Result<File, ContainerStoreError> {
+        File::open(self.container_log_file(container_id)).map_err(|source| {
+            ContainerStoreError::OpenContainerLogError {
+                container_id: container_id.clone(),
+                source,
+            }
+        })
+    }
+
     /// list_container_ids lists known container ids from state on disk
     pub fn list_container_ids(&self) -> Result<Vec<ID>, ContainerStoreError> {
         let mut container_ids = vec![];
@@ -346,7 +725,7 @@ impl ContainerStore {
             .map_err(|source| ContainerStoreError::ReadContainersDirError { source })?;
         for container_dir in container_dirs {
             let container_dir = container_dir?.path();
-            let container_id = container_dir.components().last().ok_or(
+            let container_id = container_dir.components().next_back().ok_or(
                 ContainerStoreError::ContainerIDNotInPathError {
                     container_dir: container_dir.to_string_lossy().to_string(),
                 },
@@ -356,6 +735,120 @@ impl ContainerStore {
         Ok(container_ids)
     }
 
+    /// create_volume creates the directory of a volume, including its data
+    /// directory, and stores its state on disk
+    pub fn create_volume(&self, volume: &Volume) -> Result<(), ContainerStoreError> {
+        let volume_dir = self.volume_dir(&volume.name);
+        if Path::new(&volume_dir).exists() {
+            return Err(ContainerStoreError::VolumeAlreadyExistsError {
+                name: volume.name.clone(),
+            });
+        }
+        create_dir_all(self.volume_data_dir(&volume.name)).map_err(|source| {
+            ContainerStoreError::CreateVolumeDirError {
+                name: volume.name.clone(),
+                source,
+            }
+        })?;
+        // a volume counts as created once its state is on disk
+        let serialized_volume = serde_json::to_string(volume)
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))
+            .and_then(|serialized_volume| {
+                write(self.volume_state_file(&volume.name), serialized_volume)
+            });
+        if let Err(source) = serialized_volume {
+            let _ = remove_dir_all(&volume_dir);
+            return Err(ContainerStoreError::PersistVolumeStateError {
+                name: volume.name.clone(),
+                source,
+            });
+        }
+        Ok(())
+    }
+
+    /// read_volume reads a volume's state off disk
+    pub fn read_volume(&self, name: &str) -> Result<Volume, ContainerStoreError> {
+        let volume_state_string = match read_to_string(self.volume_state_file(name)) {
+            Ok(volume_state_string) => volume_state_string,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                return Err(ContainerStoreError::VolumeNotFoundError {
+                    name: name.to_string(),
+                })
+            }
+            Err(source) => {
+                return Err(ContainerStoreError::ReadVolumeStateFileError {
+                    name: name.to_string(),
+                    source,
+                })
+            }
+        };
+        serde_json::from_str(&volume_state_string).map_err(|source| {
+            ContainerStoreError::ParseVolumeStateError {
+                name: name.to_string(),
+                source,
+            }
+        })
+    }
+
+    /// list_volumes lists the volumes on disk
+    pub fn list_volumes(&self) -> Result<Vec<Volume>, ContainerStoreError> {
+        let mut volumes = vec![];
+        let volume_dirs = read_dir(self.volumes_dir())
+            .map_err(|source| ContainerStoreError::ReadVolumesDirError { source })?;
+        for volume_dir in volume_dirs {
+            let name = volume_dir?.file_name().to_string_lossy().to_string();
+            match self.read_volume(&name) {
+                Ok(volume) => volumes.push(volume),
+                // a volume that is being created or removed
+                Err(ContainerStoreError::VolumeNotFoundError { .. }) => (),
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(volumes)
+    }
+
+    /// remove_volume deletes a volume, including its data, on disk
+    pub fn remove_volume(&self, name: &str) -> Result<(), ContainerStoreError> {
+        // remove the state first, so a partially removed volume isn't listed
+        match remove_file(self.volume_state_file(name)) {
+            Ok(()) => (),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                return Err(ContainerStoreError::VolumeNotFoundError {
+                    name: name.to_string(),
+                })
+            }
+            Err(source) => {
+                return Err(ContainerStoreError::RemoveVolumeDirError {
+                    name: name.to_string(),
+                    source,
+                })
+            }
+        }
+        remove_dir_all(self.volume_dir(name)).map_err(|source| {
+            ContainerStoreError::RemoveVolumeDirError {
+                name: name.to_string(),
+                source,
+            }
+        })
+    }
+
+    /// volume_data_dir is the directory containers mounting a volume see
+    pub fn volume_data_dir(&self, name: &str) -> String {
+        format!("{}/data", self.volume_dir(name))
+    }
+
+    fn volume_state_file(&self, name: &str) -> String {
+        format!("{}/volume.state", self.volume_dir(name))
+    }
+
+    fn volume_dir(&self, name: &str) -> String {
+        format!("{}/{}", self.volumes_dir(), name)
+    }
+
+    fn volumes_dir(&self) -> String {
+        format!("{}/volumes", self.root_dir)
+    }
+
     fn container_state_file(&self, container_id: &ID) -> String {
         format!(
             "{}/container.state",
@@ -363,6 +856,75 @@ impl ContainerStore {
         )
     }
 
+    /// container_exit_file is where a container's shim records its exit
+    pub fn container_exit_file(&self, container_id: &ID) -> String {
+        format!(
+            "{}/container.exit",
+            self.specific_container_dir(container_id)
+        )
+    }
+
+    /// container_log_file is where a container's stdout and stderr are logged
+    pub fn container_log_file(&self, container_id: &ID) -> String {
+        format!("{}/container.log", self.logs_dir(container_id))
+    }
+
+    /// exec_process_file is where the process spec of an exec is written
+    pub fn exec_process_file(&self, container_id: &ID, exec_id: &ID) -> String {
+        format!(
+            "{}/exec-{}.json",
+            self.specific_container_dir(container_id),
+            exec_id
+        )
+    }
+
+    /// container_resources_file is where the cgroup limits to apply to a
+    /// running container are written
+    pub fn container_resources_file(&self, container_id: &ID) -> String {
+        format!(
+            "{}/resources.json",
+            self.specific_container_dir(container_id)
+        )
+    }
+
+    /// container_attach_socket is where a container's shim serves clients
+    /// attaching to the container
+    pub fn container_attach_socket(&self, container_id: &ID) -> String {
+        format!("{}/attach.sock", self.specific_container_dir(container_id))
+    }
+
+    /// console_socket is where the runtime sends the terminal of the process
+    /// with the given id, a container or exec id. console sockets live
+    /// outside of container directories to stay within the length limit of
+    /// socket paths
+    pub fn console_socket(&self, id: &ID) -> String {
+        format!("{}/{}.sock", self.consoles_dir(), id)
+    }
+
+    /// snapshot_file records how the container rootfs is backed
+    fn snapshot_file(&self, container_id: &ID) -> String {
+        format!(
+            "{}/snapshot.json",
+            self.specific_container_dir(container_id)
+        )
+    }
+
+    /// snapshot_dir holds the directories of an overlay rootfs besides its
+    /// lower dir
+    fn snapshot_dir(&self, container_id: &ID) -> String {
+        format!("{}/snapshot", self.specific_container_dir(container_id))
+    }
+
+    /// snapshot_upper_dir holds the changes a container made to its overlay
+    /// rootfs
+    fn snapshot_upper_dir(&self, container_id: &ID) -> String {
+        format!("{}/upper", self.snapshot_dir(container_id))
+    }
+
+    fn snapshot_work_dir(&self, container_id: &ID) -> String {
+        format!("{}/work", self.snapshot_dir(container_id))
+    }
+
     fn temp_container_state_file(&self, container_id: &ID) -> String {
         format!("{}.temp", self.container_state_file(container_id))
     }
@@ -375,11 +937,20 @@ impl ContainerStore {
         format!("{}/containers", self.root_dir)
     }
 
-    fn bundle_dir(&self, container_id: &ID) -> String {
+    fn consoles_dir(&self) -> String {
+        format!("{}/consoles", self.root_dir)
+    }
+
+    fn logs_dir(&self, container_id: &ID) -> String {
+        format!("{}/logs", self.specific_container_dir(container_id))
+    }
+
+    /// bundle_dir is the container's OCI bundle
+    pub fn bundle_dir(&self, container_id: &ID) -> String {
         format!("{}/bundle", self.specific_container_dir(container_id))
     }
 
-    fn rootfs_dir(&self, container_id: &ID) -> String {
+    pub fn rootfs_dir(&self, container_id: &ID) -> String {
         format!("{}/rootfs", self.bundle_dir(container_id))
     }
 }
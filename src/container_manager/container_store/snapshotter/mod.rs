@@ -0,0 +1,180 @@
+use log::debug;
+use serde::{Deserialize, Serialize};
+use std::ffi::CString;
+use std::fs::metadata;
+use std::io::{Error, ErrorKind};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+// fuse-overlayfs lets unprivileged daemons, which can't mount overlayfs
+// themselves, get an overlay anyway
+const FUSE_OVERLAYFS: &str = "fuse-overlayfs";
+// unmounts fuse filesystems for unprivileged users, newest first
+const FUSERMOUNTS: &[&str] = &["fusermount3", "fusermount"];
+
+/// SnapshotKind is how the rootfs of a container is backed
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SnapshotKind {
+    // an overlayfs mount with the source rootfs as its read-only lower dir
+    Overlay,
+    // the same, mounted through fuse-overlayfs
+    FuseOverlay,
+    // a full copy of the source rootfs
+    Copy,
+}
+
+/// Snapshot records how the rootfs of a container was prepared, so that it
+/// can be mounted again after the host reboots and unmounted before the
+/// container is removed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub kind: SnapshotKind,
+    // the source rootfs, unused by copies
+    pub lower_dir: String,
+}
+
+/// SnapshotDirs are the directories an overlay snapshot is made of
+pub struct SnapshotDirs<'a> {
+    pub lower_dir: &'a str,
+    // where changes made by the container are written
+    pub upper_dir: &'a str,
+    // scratch space for overlayfs, on the same filesystem as upper_dir
+    pub work_dir: &'a str,
+    // where the merged rootfs is mounted
+    pub merged_dir: &'a str,
+}
+
+/// mount_overlay mounts an overlay of `dirs`, through the kernel if we're
+/// allowed to and through fuse-overlayfs otherwise, and returns which of
+/// them it used
+pub fn mount_overlay(dirs: &SnapshotDirs) -> Result<SnapshotKind, Error> {
+    match mount_kernel_overlay(dirs) {
+        Ok(()) => Ok(SnapshotKind::Overlay),
+        Err(err) => {
+            debug!(
+                "kernel overlay mount failed, trying fuse-overlayfs: {}",
+                err
+            );
+            mount_fuse_overlay(dirs).map(|()| SnapshotKind::FuseOverlay)
+        }
+    }
+}
+
+/// remount mounts the overlay of a snapshot again if it isn't mounted, as
+/// after the host reboots
+pub fn remount(snapshot: &Snapshot, dirs: &SnapshotDirs) -> Result<(), Error> {
+    if is_mount_point(dirs.merged_dir)? {
+        return Ok(());
+    }
+    match snapshot.kind {
+        SnapshotKind::Overlay => mount_kernel_overlay(dirs),
+        SnapshotKind::FuseOverlay => mount_fuse_overlay(dirs),
+        SnapshotKind::Copy => Ok(()),
+    }
+}
+
+/// unmount lazily unmounts the overlay of a snapshot mounted at
+/// `merged_dir`, if it's mounted
+pub fn unmount(snapshot: &Snapshot, merged_dir: &str) -> Result<(), Error> {
+    if snapshot.kind == SnapshotKind::Copy || !is_mount_point(merged_dir)? {
+        return Ok(());
+    }
+    let target = c_path(merged_dir)?;
+    // safe: target is a valid nul terminated string for the duration of the call
+    if unsafe { libc::umount2(target.as_ptr(), libc::MNT_DETACH) } == 0 {
+        return Ok(());
+    }
+    let err = Error::last_os_error();
+    if snapshot.kind != SnapshotKind::FuseOverlay {
+        return Err(err);
+    }
+    // unprivileged users unmount fuse filesystems through a setuid helper
+    for fusermount in FUSERMOUNTS {
+        let status = Command::new(fusermount)
+            .arg("-u")
+            .arg("-z")
+            .arg(merged_dir)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status();
+        if let Ok(status) = status {
+            if status.success() {
+                return Ok(());
+            }
+        }
+    }
+    Err(err)
+}
+
+fn mount_kernel_overlay(dirs: &SnapshotDirs) -> Result<(), Error> {
+    let source = c_path("overlay")?;
+    let target = c_path(dirs.merged_dir)?;
+    let fstype = c_path("overlay")?;
+    let data = c_path(&overlay_options(dirs)?)?;
+    // safe: all pointers are valid nul terminated strings for the duration of
+    // the call
+    let mounted = unsafe {
+        libc::mount(
+            source.as_ptr(),
+            target.as_ptr(),
+            fstype.as_ptr(),
+            0,
+            data.as_ptr() as *const libc::c_void,
+        )
+    };
+    if mounted != 0 {
+        return Err(Error::last_os_error());
+    }
+    Ok(())
+}
+
+fn mount_fuse_overlay(dirs: &SnapshotDirs) -> Result<(), Error> {
+    let out = Command::new(FUSE_OVERLAYFS)
+        .arg("-o")
+        .arg(overlay_options(dirs)?)
+        .arg(dirs.merged_dir)
+        .stdin(Stdio::null())
+        .output()?;
+    if !out.status.success() {
+        return Err(Error::other(format!(
+            "{} failed: {}",
+            FUSE_OVERLAYFS,
+            String::from_utf8_lossy(&out.stderr).trim()
+        )));
+    }
+    Ok(())
+}
+
+/// overlay_options are the mount options of an overlay of `dirs`. overlayfs
+/// has no way to escape the separators of its options, so paths containing
+/// them can't be overlaid
+fn overlay_options(dirs: &SnapshotDirs) -> Result<String, Error> {
+    let paths = [dirs.lower_dir, dirs.upper_dir, dirs.work_dir];
+    if let Some(path) = paths.iter().find(|path| path.contains([',', ':', '\\'])) {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("{} can't be used in an overlay", path),
+        ));
+    }
+    Ok(format!(
+        "lowerdir={},upperdir={},workdir={}",
+        dirs.lower_dir, dirs.upper_dir, dirs.work_dir
+    ))
+}
+
+/// is_mount_point returns whether a filesystem is mounted at `path`, which
+/// then lives on a different device than its parent
+fn is_mount_point(path: &str) -> Result<bool, Error> {
+    let parent = Path::new(path)
+        .parent()
+        .ok_or_else(|| Error::new(ErrorKind::InvalidInput, format!("{} has no parent", path)))?;
+    Ok(metadata(path)?.dev() != metadata(parent)?.dev())
+}
+
+fn c_path(path: &str) -> Result<CString, Error> {
+    CString::new(Path::new(path).as_os_str().as_bytes())
+        .map_err(|err| Error::new(ErrorKind::InvalidInput, err))
+}
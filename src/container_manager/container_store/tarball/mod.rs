@@ -0,0 +1,1271 @@
+use super::copier::{is_privileged, make_node, read_xattrs, set_attributes, Attributes};
+use log::debug;
+use std::collections::{HashMap, HashSet};
+use std::ffi::{CString, OsStr, OsString};
+use std::fs::{
+    create_dir, hard_link, read_dir, read_link, remove_dir_all, remove_file, symlink_metadata,
+    File, Metadata, OpenOptions,
+};
+use std::io::{copy, BufReader, Error, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{symlink, FileTypeExt, MetadataExt, OpenOptionsExt};
+use std::path::{Component, Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+
+const BLOCK_SIZE: usize = 512;
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+const USTAR_MAGIC: &[u8] = b"ustar\0";
+const USTAR_VERSION: &[u8] = b"00";
+// the largest pax or GNU extension entry extracted, far more than any path
+// or set of extended attributes needs
+const MAX_EXTENSION_SIZE: u64 = 1 << 20;
+// name of the pax extended headers we write, as GNU tar names them
+const PAX_HEADER_NAME: &[u8] = b"././@PaxHeader";
+// pax records holding extended attributes, as written by GNU tar and bsdtar
+const PAX_XATTR_PREFIX: &str = "SCHILY.xattr.";
+// image layers mark the entries of the layers below them that they delete
+// with a whiteout file, `.wh.NAME`, and directories whose contents they
+// replace with an opaque whiteout file within them
+const WHITEOUT_PREFIX: &str = ".wh.";
+const OPAQUE_WHITEOUT: &str = ".wh..wh..opq";
+// other `.wh..wh.` files are bookkeeping of the tools that made the layer
+const WHITEOUT_META_PREFIX: &str = ".wh..wh.";
+
+/// is_tarball returns whether `path` is a file, which rootfs' are given as
+/// tarballs, rather than a directory
+pub fn is_tarball(path: &str) -> bool {
+    symlink_metadata(path)
+        .map(|metadata| metadata.is_file())
+        .unwrap_or(false)
+}
+
+/// extract_tarball extracts the tar archive at `tarball`, compressed with
+/// gzip or zstd or not at all, into the existing directory `to`. entries
+/// keep their ownership, mode bits, modification times and extended
+/// attributes as far as the daemon's privileges allow, as copy_tree does.
+/// entries that would escape `to`, through `..` components or symlinks
+/// extracted before them, fail the extraction
+pub fn extract_tarball<P, Q>(tarball: P, to: Q) -> Result<(), Error>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    unpack(tarball.as_ref(), to.as_ref(), false)
+}
+
+/// apply_layer extracts the image layer at `layer` over the layers already
+/// extracted into `to`, as extract_tarball does, deleting the entries its
+/// whiteouts mark as deleted
+pub fn apply_layer<P, Q>(layer: P, to: Q) -> Result<(), Error>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    unpack(layer.as_ref(), to.as_ref(), true)
+}
+
+/// extract_archive extracts the uncompressed tar archive read from `archive`
+/// into the existing directory `to`, as extract_tarball does, except that
+/// existing directories are never replaced by other entries. with `name`,
+/// the archive must hold a single top-level entry, which is extracted as
+/// `name`. entries are extracted by path, so a tree that may change
+/// meanwhile must be confined, as copies into containers are
+pub fn extract_archive<P>(archive: &mut dyn Read, to: P, name: Option<&OsStr>) -> Result<(), Error>
+where
+    P: AsRef<Path>,
+{
+    let mut extractor = Extractor {
+        root: to.as_ref().to_path_buf(),
+        privileged: is_privileged(),
+        whiteouts: false,
+        replace_dirs: false,
+        name: name.map(OsStr::to_os_string),
+        top: None,
+        extracted: HashSet::new(),
+        dirs: vec![],
+    };
+    extractor.extract(archive)
+}
+
+fn unpack(tarball: &Path, to: &Path, whiteouts: bool) -> Result<(), Error> {
+    let mut file = File::open(tarball)?;
+    let mut magic = [0u8; 4];
+    let magic_len = read_full(&mut file, &mut magic)?;
+    file.seek(SeekFrom::Start(0))?;
+    let magic = &magic[..magic_len];
+    let decompressor = if magic.starts_with(GZIP_MAGIC) {
+        Some("gzip")
+    } else if magic.starts_with(ZSTD_MAGIC) {
+        Some("zstd")
+    } else {
+        None
+    };
+
+    let mut extractor = Extractor {
+        root: to.to_path_buf(),
+        privileged: is_privileged(),
+        whiteouts,
+        replace_dirs: true,
+        name: None,
+        top: None,
+        extracted: HashSet::new(),
+        dirs: vec![],
+    };
+    match decompressor {
+        None => extractor.extract(&mut BufReader::new(file)),
+        Some(decompressor) => {
+            let mut child = Command::new(decompressor)
+                .arg("-dc")
+                .stdin(file)
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .spawn()
+                .map_err(|err| {
+                    Error::new(
+                        err.kind(),
+                        format!("failed to run {}: {}", decompressor, err),
+                    )
+                })?;
+            let mut stdout = BufReader::new(child.stdout.take().unwrap());
+            // archives are padded past their end, which the decompressor
+            // would fail to write if we stopped reading at the end
+            let extracted = extractor
+                .extract(&mut stdout)
+                .and_then(|()| copy(&mut stdout, &mut std::io::sink()).map(|_| ()));
+            drop(stdout);
+            if extracted.is_err() {
+                let _ = child.kill();
+            }
+            let decompressed = wait_for_decompressor(decompressor, child);
+            extracted.and(decompressed)
+        }
+    }
+}
+
+/// write_tarball writes the contents of the directory `from` to `to` as an
+/// uncompressed pax tar archive, keeping what extract_tarball restores:
+/// - ownership, mode bits, modification times and extended attributes
+/// - symlinks, as symlinks
+/// - files hardlinked together within `from`, as hardlinks
+/// - fifos and device nodes. sockets are skipped
+///
+/// entries are archived in name order, so the same tree makes the same
+/// archive
+pub fn write_tarball<P>(from: P, to: &mut dyn Write) -> Result<(), Error>
+where
+    P: AsRef<Path>,
+{
+    let mut archiver = Archiver {
+        out: to,
+        links: HashMap::new(),
+    };
+    archiver.write_dir_contents(from.as_ref(), Path::new(""))?;
+    archiver.finish()
+}
+
+/// write_tarball_entry writes `path` to `to` as write_tarball does, as the
+/// single top-level entry `name` of the archive, followed by its contents if
+/// it's a directory. a symlink at `path` is archived as a symlink. like
+/// extract_archive, this walks the tree by path
+pub fn write_tarball_entry<P>(path: P, name: &OsStr, to: &mut dyn Write) -> Result<(), Error>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    let mut archiver = Archiver {
+        out: to,
+        links: HashMap::new(),
+    };
+    let metadata = symlink_metadata(path).map_err(|err| with_path(err, path))?;
+    archiver
+        .write_entry(path, Path::new(name), &metadata)
+        .map_err(|err| with_path(err, path))?;
+    if metadata.is_dir() {
+        archiver.write_dir_contents(path, Path::new(name))?;
+    }
+    archiver.finish()
+}
+
+fn wait_for_decompressor(decompressor: &str, child: Child) -> Result<(), Error> {
+    let out = child.wait_with_output()?;
+    if !out.status.success() {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "{} failed: {}",
+                decompressor,
+                String::from_utf8_lossy(&out.stderr).trim()
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Header is a tar entry header, with any pax or GNU extensions applied
+struct Header {
+    path: Vec<u8>,
+    link_path: Vec<u8>,
+    entry_type: u8,
+    size: u64,
+    mode: u32,
+    uid: u32,
+    gid: u32,
+    mtime: libc::timespec,
+    dev_major: u32,
+    dev_minor: u32,
+    xattrs: Vec<(CString, Vec<u8>)>,
+}
+
+/// Extensions are the overrides extension entries make to the header of
+/// the next regular entry
+#[derive(Default)]
+struct Extensions {
+    path: Option<Vec<u8>>,
+    link_path: Option<Vec<u8>>,
+    size: Option<u64>,
+    uid: Option<u32>,
+    gid: Option<u32>,
+    mtime: Option<libc::timespec>,
+    xattrs: Vec<(CString, Vec<u8>)>,
+}
+
+struct Extractor {
+    root: PathBuf,
+    privileged: bool,
+    // whether whiteouts are applied, rather than extracted as files
+    whiteouts: bool,
+    // whether entries replace existing directories with their path, as the
+    // layers of an image do
+    replace_dirs: bool,
+    // the name the single top-level entry of the archive is extracted as,
+    // and its name in the archive once seen
+    name: Option<OsString>,
+    top: Option<OsString>,
+    // the paths extracted so far, which opaque whiteouts keep
+    extracted: HashSet<PathBuf>,
+    // directories and their attributes, set once all entries are extracted
+    // so that extracting their contents neither changes their modification
+    // time nor is prevented by their mode
+    dirs: Vec<(PathBuf, Attributes)>,
+}
+
+impl Extractor {
+    fn extract(&mut self, archive: &mut dyn Read) -> Result<(), Error> {
+        let mut extensions = Extensions::default();
+        let mut block = [0u8; BLOCK_SIZE];
+        loop {
+            if read_full(archive, &mut block)? < BLOCK_SIZE {
+                return Err(invalid_data("unexpected end of archive"));
+            }
+            // archives end with zeroed blocks
+            if block.iter().all(|byte| *byte == 0) {
+                break;
+            }
+            let mut header = parse_header(&block)?;
+            let is_extension = [b'x', b'g', b'L', b'K'].contains(&header.entry_type);
+            if !is_extension {
+                apply_extensions(&mut header, std::mem::take(&mut extensions));
+            } else if header.size > MAX_EXTENSION_SIZE {
+                // extensions are read into memory, so their size is capped
+                // rather than trusted
+                return Err(invalid_data("extension entry is too large"));
+            }
+            let mut data = archive.take(header.size);
+            match header.entry_type {
+                // pax extended header for the next entry
+                b'x' => parse_pax(&read_data(&mut data)?, &mut extensions)?,
+                // pax global header, none of which we need
+                b'g' => {
+                    read_data(&mut data)?;
+                }
+                // GNU long path and long link path of the next entry
+                b'L' => extensions.path = Some(trim_nul(&read_data(&mut data)?).to_vec()),
+                b'K' => extensions.link_path = Some(trim_nul(&read_data(&mut data)?).to_vec()),
+                _ => {
+                    let path = String::from_utf8_lossy(&header.path).to_string();
+                    self.extract_entry(&header, &mut data)
+                        .map_err(|err| Error::new(err.kind(), format!("{}: {}", path, err)))?;
+                }
+            }
+            // skip whatever data the entry didn't use, and the padding
+            copy(&mut data, &mut std::io::sink())?;
+            let padding = (BLOCK_SIZE as u64 - header.size % BLOCK_SIZE as u64) % BLOCK_SIZE as u64;
+            copy(&mut archive.take(padding), &mut std::io::sink())?;
+        }
+        for (dir, attributes) in self.dirs.iter().rev() {
+            // unless a later whiteout deleted it
+            if symlink_metadata(dir).is_ok() {
+                set_attributes(dir, attributes, self.privileged)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn extract_entry(&mut self, header: &Header, data: &mut dyn Read) -> Result<(), Error> {
+        let relative_path = entry_path(&header.path)?;
+        // only directories may stand for the root itself
+        if relative_path.as_os_str().is_empty() && header.entry_type != b'5' {
+            return Err(invalid_data("entry has no path"));
+        }
+        let relative_path = self.rename_top(relative_path)?;
+        let path = self.prepare_parent(&relative_path)?;
+        if self.whiteouts {
+            let name = relative_path
+                .file_name()
+                .map(|name| name.to_string_lossy())
+                .unwrap_or_default();
+            if name.starts_with(WHITEOUT_PREFIX) {
+                return self.apply_whiteout(&path, &name);
+            }
+        }
+        self.extracted.insert(path.clone());
+        let attributes = Attributes {
+            uid: header.uid,
+            gid: header.gid,
+            mode: header.mode,
+            atime: header.mtime,
+            mtime: header.mtime,
+            xattrs: header.xattrs.clone(),
+        };
+        let existing = symlink_metadata(&path).ok();
+        if header.entry_type == b'5' {
+            match existing {
+                Some(ref metadata) if metadata.is_dir() => (),
+                Some(_) => {
+                    remove_file(&path)?;
+                    create_dir(&path)?;
+                }
+                None => create_dir(&path)?,
+            }
+            self.dirs.push((path, attributes));
+            return Ok(());
+        }
+        // later entries replace earlier ones with the same path
+        match existing {
+            Some(ref metadata) if metadata.is_dir() => {
+                if !self.replace_dirs {
+                    return Err(invalid_data(
+                        "a directory can't be replaced by a non-directory",
+                    ));
+                }
+                remove_dir_all(&path)?
+            }
+            Some(_) => remove_file(&path)?,
+            None => (),
+        }
+        match header.entry_type {
+            b'0' | b'\0' | b'7' => {
+                let mut file = OpenOptions::new()
+                    .write(true)
+                    .create_new(true)
+                    .mode(0o600)
+                    .open(&path)?;
+                copy(data, &mut file)?;
+            }
+            b'1' => {
+                // links share their attributes with their target
+                let target = self.rename_top(entry_path(&header.link_path)?)?;
+                let target = self.root.join(target);
+                self.check_parents(&target)?;
+                return hard_link(target, &path);
+            }
+            b'2' => symlink(OsStr::from_bytes(&header.link_path), &path)?,
+            b'3' | b'4' | b'6' => {
+                let file_type = match header.entry_type {
+                    b'3' => libc::S_IFCHR,
+                    b'4' => libc::S_IFBLK,
+                    _ => libc::S_IFIFO,
+                };
+                let rdev = libc::makedev(header.dev_major, header.dev_minor);
+                if !make_node(&path, file_type | (header.mode & 0o7777), rdev)? {
+                    debug!("skipping device node {:?}, not privileged", path);
+                    return Ok(());
+                }
+            }
+            entry_type => {
+                return Err(invalid_data(&format!(
+                    "unsupported entry type `{}`",
+                    entry_type as char
+                )))
+            }
+        }
+        set_attributes(&path, &attributes, self.privileged)
+    }
+
+    /// apply_whiteout deletes what the whiteout file `name` at `path` marks
+    /// as deleted from the layers below it
+    fn apply_whiteout(&self, path: &Path, name: &str) -> Result<(), Error> {
+        let parent = path.parent().unwrap_or(&self.root);
+        if name == OPAQUE_WHITEOUT {
+            for entry in read_dir(parent)? {
+                let entry_path = entry?.path();
+                if !self.extracted.contains(&entry_path) {
+                    remove_entry(&entry_path)?;
+                }
+            }
+            return Ok(());
+        }
+        if name.starts_with(WHITEOUT_META_PREFIX) {
+            return Ok(());
+        }
+        // whiteouts only delete their siblings, never the directory they're
+        // in or its parent
+        let deleted_name = &name[WHITEOUT_PREFIX.len()..];
+        if deleted_name.is_empty()
+            || deleted_name == "."
+            || deleted_name == ".."
+            || deleted_name.contains('/')
+        {
+            return Err(invalid_data(&format!("invalid whiteout `{}`", name)));
+        }
+        let deleted = parent.join(deleted_name);
+        match symlink_metadata(&deleted) {
+            Ok(_) => remove_entry(&deleted),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// rename_top replaces the top-level component of `relative_path` with
+    /// the name the archive's top-level entry is extracted as, if it has one
+    fn rename_top(&mut self, relative_path: PathBuf) -> Result<PathBuf, Error> {
+        let name = match self.name {
+            Some(ref name) => name.clone(),
+            None => return Ok(relative_path),
+        };
+        let mut components = relative_path.components();
+        let top = match components.next() {
+            Some(component) => component.as_os_str().to_os_string(),
+            None => return Err(invalid_data("entry has no path")),
+        };
+        match self.top {
+            Some(ref archived_top) if *archived_top != top => {
+                return Err(invalid_data("archive holds more than one top-level entry"))
+            }
+            Some(_) => (),
+            None => self.top = Some(top),
+        }
+        let mut renamed = PathBuf::from(name);
+        // joining an empty path would add a trailing slash
+        renamed.extend(components);
+        Ok(renamed)
+    }
+
+    /// prepare_parent creates the missing parent directories of
+    /// `relative_path` within the root, and returns its full path
+    fn prepare_parent(&self, relative_path: &Path) -> Result<PathBuf, Error> {
+        let mut path = self.root.clone();
+        for component in relative_path
+            .parent()
+            .into_iter()
+            .flat_map(Path::components)
+        {
+            path.push(component);
+            match symlink_metadata(&path) {
+                Ok(metadata) if metadata.is_dir() => (),
+                Ok(_) => return Err(not_a_directory(&path)),
+                Err(err) if err.kind() == ErrorKind::NotFound => create_dir(&path)?,
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(self.root.join(relative_path))
+    }
+
+    /// check_parents makes sure the parents of `path` within the root are
+    /// all directories, rather than symlinks that might lead out of it
+    fn check_parents(&self, path: &Path) -> Result<(), Error> {
+        let relative_path = path.strip_prefix(&self.root).unwrap_or(path);
+        let mut path = self.root.clone();
+        for component in relative_path
+            .parent()
+            .into_iter()
+            .flat_map(Path::components)
+        {
+            path.push(component);
+            if !symlink_metadata(&path)?.is_dir() {
+                return Err(not_a_directory(&path));
+            }
+        }
+        Ok(())
+    }
+}
+
+struct Archiver<'a> {
+    out: &'a mut dyn Write,
+    // archived paths of the files with more than one link, by their device
+    // and inode
+    links: HashMap<(u64, u64), Vec<u8>>,
+}
+
+impl Archiver<'_> {
+    fn write_dir_contents(&mut self, dir: &Path, relative_dir: &Path) -> Result<(), Error> {
+        let mut entries = read_dir(dir)
+            .map_err(|err| with_path(err, dir))?
+            .map(|entry| entry.map(|entry| entry.file_name()))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| with_path(err, dir))?;
+        entries.sort();
+        for name in entries {
+            let path = dir.join(&name);
+            let relative_path = relative_dir.join(&name);
+            let metadata = symlink_metadata(&path).map_err(|err| with_path(err, &path))?;
+            self.write_entry(&path, &relative_path, &metadata)
+                .map_err(|err| with_path(err, &path))?;
+            if metadata.is_dir() {
+                self.write_dir_contents(&path, &relative_path)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn write_entry(
+        &mut self,
+        path: &Path,
+        relative_path: &Path,
+        metadata: &Metadata,
+    ) -> Result<(), Error> {
+        let file_type = metadata.file_type();
+        let mut entry_path = relative_path.as_os_str().as_bytes().to_vec();
+        let mut link_path = vec![];
+        let mut size = 0;
+        let entry_type = if file_type.is_dir() {
+            entry_path.push(b'/');
+            b'5'
+        } else if file_type.is_symlink() {
+            link_path = read_link(path)?.as_os_str().as_bytes().to_vec();
+            b'2'
+        } else if file_type.is_file() {
+            let inode = (metadata.dev(), metadata.ino());
+            match self.links.get(&inode) {
+                // links share their attributes with the first one archived
+                Some(linked) if metadata.nlink() > 1 => {
+                    link_path = linked.clone();
+                    b'1'
+                }
+                _ => {
+                    if metadata.nlink() > 1 {
+                        self.links.insert(inode, entry_path.clone());
+                    }
+                    size = metadata.len();
+                    b'0'
+                }
+            }
+        } else if file_type.is_char_device() {
+            b'3'
+        } else if file_type.is_block_device() {
+            b'4'
+        } else if file_type.is_fifo() {
+            b'6'
+        } else {
+            debug!("skipping socket {:?}", path);
+            return Ok(());
+        };
+
+        let mut block = [0u8; BLOCK_SIZE];
+        let mut records = vec![];
+        let mtime = metadata.mtime();
+        let mtime_nsec = metadata.mtime_nsec();
+        if mtime < 0 || mtime_nsec != 0 {
+            let mtime = format!("{}.{:09}", mtime, mtime_nsec);
+            records.extend(pax_record("mtime", mtime.as_bytes()));
+        }
+        if !put_bytes(&mut block[0..100], &entry_path) {
+            // readers without pax support get the path truncated
+            block[0..100].copy_from_slice(&entry_path[..100]);
+            records.extend(pax_record("path", &entry_path));
+        }
+        put_number(&mut block[100..108], u64::from(metadata.mode() & 0o7777));
+        if !put_number(&mut block[108..116], u64::from(metadata.uid())) {
+            records.extend(pax_record("uid", metadata.uid().to_string().as_bytes()));
+        }
+        if !put_number(&mut block[116..124], u64::from(metadata.gid())) {
+            records.extend(pax_record("gid", metadata.gid().to_string().as_bytes()));
+        }
+        if !put_number(&mut block[124..136], size) {
+            records.extend(pax_record("size", size.to_string().as_bytes()));
+        }
+        put_number(&mut block[136..148], mtime.max(0) as u64);
+        block[156] = entry_type;
+        if !put_bytes(&mut block[157..257], &link_path) {
+            block[157..257].copy_from_slice(&link_path[..100]);
+            records.extend(pax_record("linkpath", &link_path));
+        }
+        block[257..263].copy_from_slice(USTAR_MAGIC);
+        block[263..265].copy_from_slice(USTAR_VERSION);
+        if entry_type == b'3' || entry_type == b'4' {
+            put_number(
+                &mut block[329..337],
+                u64::from(libc::major(metadata.rdev())),
+            );
+            put_number(
+                &mut block[337..345],
+                u64::from(libc::minor(metadata.rdev())),
+            );
+        }
+        for (name, value) in read_xattrs(path)? {
+            let key = format!("{}{}", PAX_XATTR_PREFIX, name.to_string_lossy());
+            records.extend(pax_record(&key, &value));
+        }
+
+        if !records.is_empty() {
+            let mut pax_block = [0u8; BLOCK_SIZE];
+            put_bytes(&mut pax_block[0..100], PAX_HEADER_NAME);
+            put_number(&mut pax_block[100..108], 0o644);
+            put_number(&mut pax_block[124..136], records.len() as u64);
+            pax_block[156] = b'x';
+            pax_block[257..263].copy_from_slice(USTAR_MAGIC);
+            pax_block[263..265].copy_from_slice(USTAR_VERSION);
+            self.write_block(&mut pax_block)?;
+            self.write_data(&mut records.as_slice(), records.len() as u64)?;
+        }
+        self.write_block(&mut block)?;
+        if size > 0 {
+            self.write_data(&mut File::open(path)?, size)?;
+        }
+        Ok(())
+    }
+
+    /// finish ends the archive with its two zeroed blocks
+    fn finish(&mut self) -> Result<(), Error> {
+        self.out.write_all(&[0u8; 2 * BLOCK_SIZE])?;
+        self.out.flush()
+    }
+
+    /// write_block checksums and writes a header block
+    fn write_block(&mut self, block: &mut [u8; BLOCK_SIZE]) -> Result<(), Error> {
+        // the checksum is computed with its own field as spaces
+        block[148..156].copy_from_slice(b"        ");
+        let sum: u64 = block.iter().map(|byte| u64::from(*byte)).sum();
+        block[148..156].copy_from_slice(format!("{:06o}\0 ", sum).as_bytes());
+        self.out.write_all(block)
+    }
+
+    /// write_data writes the `size` bytes of an entry's data, and the
+    /// padding to the next block
+    fn write_data(&mut self, data: &mut dyn Read, size: u64) -> Result<(), Error> {
+        let written = copy(&mut data.take(size), &mut self.out)?;
+        if written != size {
+            return Err(invalid_data("file shrank while it was archived"));
+        }
+        let padding = (BLOCK_SIZE as u64 - size % BLOCK_SIZE as u64) % BLOCK_SIZE as u64;
+        self.out.write_all(&[0u8; BLOCK_SIZE][..padding as usize])
+    }
+}
+
+/// pax_record formats a pax extended header record, `LENGTH KEY=VALUE\n`,
+/// where LENGTH counts its own digits
+fn pax_record(key: &str, value: &[u8]) -> Vec<u8> {
+    let unprefixed_len = 1 + key.len() + 1 + value.len() + 1;
+    let mut len = unprefixed_len;
+    while len != unprefixed_len + len.to_string().len() {
+        len = unprefixed_len + len.to_string().len();
+    }
+    [
+        format!("{} {}=", len, key).as_bytes(),
+        value,
+        b"\n".as_ref(),
+    ]
+    .concat()
+}
+
+/// put_bytes copies `value` into a header field, returning false if it
+/// doesn't fit
+fn put_bytes(field: &mut [u8], value: &[u8]) -> bool {
+    if value.len() > field.len() {
+        return false;
+    }
+    field[..value.len()].copy_from_slice(value);
+    true
+}
+
+/// put_number writes `value` into a numeric header field in octal,
+/// returning false if it doesn't fit
+fn put_number(field: &mut [u8], value: u64) -> bool {
+    let digits = format!("{:0width$o}", value, width = field.len() - 1);
+    if digits.len() > field.len() - 1 {
+        return false;
+    }
+    put_bytes(field, digits.as_bytes())
+}
+
+fn with_path(err: Error, path: &Path) -> Error {
+    Error::new(err.kind(), format!("failed to archive {:?}: {}", path, err))
+}
+
+fn remove_entry(path: &Path) -> Result<(), Error> {
+    if symlink_metadata(path)?.is_dir() {
+        remove_dir_all(path)
+    } else {
+        remove_file(path)
+    }
+}
+
+/// entry_path is the path of an entry relative to the root it's extracted
+/// into. absolute paths are taken as relative to the root, and paths with
+/// `..` components are rejected
+fn entry_path(path: &[u8]) -> Result<PathBuf, Error> {
+    let mut entry_path = PathBuf::new();
+    for component in Path::new(OsStr::from_bytes(path)).components() {
+        match component {
+            Component::Normal(component) => entry_path.push(component),
+            Component::RootDir | Component::CurDir => (),
+            Component::ParentDir | Component::Prefix(_) => {
+                return Err(invalid_data("path leads outside of the rootfs"))
+            }
+        }
+    }
+    Ok(entry_path)
+}
+
+/// parse_header parses the tar header in `block`
+fn parse_header(block: &[u8; BLOCK_SIZE]) -> Result<Header, Error> {
+    let checksum = parse_number(&block[148..156])?;
+    // the checksum is computed with its own field as spaces
+    let sum: u64 = block
+        .iter()
+        .enumerate()
+        .map(|(i, byte)| if (148..156).contains(&i) { b' ' } else { *byte } as u64)
+        .sum();
+    if checksum != sum {
+        return Err(invalid_data("invalid header checksum"));
+    }
+
+    let mut path = trim_nul(&block[0..100]).to_vec();
+    // ustar splits long paths into a prefix and a name
+    let prefix = trim_nul(&block[345..500]);
+    if &block[257..263] == USTAR_MAGIC && !prefix.is_empty() {
+        path = [prefix, b"/", &path].concat();
+    }
+    Ok(Header {
+        path,
+        link_path: trim_nul(&block[157..257]).to_vec(),
+        entry_type: block[156],
+        size: parse_number(&block[124..136])?,
+        mode: parse_number(&block[100..108])? as u32,
+        uid: parse_number(&block[108..116])? as u32,
+        gid: parse_number(&block[116..124])? as u32,
+        mtime: libc::timespec {
+            tv_sec: parse_number(&block[136..148])? as libc::time_t,
+            tv_nsec: 0,
+        },
+        dev_major: parse_number(&block[329..337])? as u32,
+        dev_minor: parse_number(&block[337..345])? as u32,
+        xattrs: vec![],
+    })
+}
+
+/// apply_extensions overrides the fields of `header` that the extension
+/// entries before it set
+fn apply_extensions(header: &mut Header, extensions: Extensions) {
+    if let Some(path) = extensions.path {
+        header.path = path;
+    }
+    if let Some(link_path) = extensions.link_path {
+        header.link_path = link_path;
+    }
+    if let Some(size) = extensions.size {
+        header.size = size;
+    }
+    if let Some(uid) = extensions.uid {
+        header.uid = uid;
+    }
+    if let Some(gid) = extensions.gid {
+        header.gid = gid;
+    }
+    if let Some(mtime) = extensions.mtime {
+        header.mtime = mtime;
+    }
+    header.xattrs = extensions.xattrs;
+}
+
+/// parse_pax parses pax extended header records, `LENGTH KEY=VALUE\n`, into
+/// `extensions`
+fn parse_pax(mut records: &[u8], extensions: &mut Extensions) -> Result<(), Error> {
+    let invalid = || invalid_data("invalid pax extended header");
+    while !records.is_empty() {
+        let space = records
+            .iter()
+            .position(|byte| *byte == b' ')
+            .ok_or_else(invalid)?;
+        let length: usize = std::str::from_utf8(&records[..space])
+            .ok()
+            .and_then(|length| length.parse().ok())
+            .ok_or_else(invalid)?;
+        if length <= space || length > records.len() || records[length - 1] != b'\n' {
+            return Err(invalid());
+        }
+        let record = &records[space + 1..length - 1];
+        records = &records[length..];
+        let equals = record
+            .iter()
+            .position(|byte| *byte == b'=')
+            .ok_or_else(invalid)?;
+        let key = String::from_utf8_lossy(&record[..equals]);
+        let value = &record[equals + 1..];
+        let number = || -> Result<u64, Error> {
+            std::str::from_utf8(value)
+                .ok()
+                .and_then(|value| value.parse().ok())
+                .ok_or_else(invalid)
+        };
+        match key.as_ref() {
+            "path" => extensions.path = Some(value.to_vec()),
+            "linkpath" => extensions.link_path = Some(value.to_vec()),
+            "size" => extensions.size = Some(number()?),
+            "uid" => extensions.uid = Some(number()? as u32),
+            "gid" => extensions.gid = Some(number()? as u32),
+            "mtime" => extensions.mtime = Some(parse_pax_time(value).ok_or_else(invalid)?),
+            key if key.starts_with(PAX_XATTR_PREFIX) => {
+                let name = CString::new(&key[PAX_XATTR_PREFIX.len()..]).map_err(|_| invalid())?;
+                extensions.xattrs.push((name, value.to_vec()));
+            }
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+/// parse_pax_time parses pax times, seconds with an optional fraction
+fn parse_pax_time(value: &[u8]) -> Option<libc::timespec> {
+    let value = std::str::from_utf8(value).ok()?;
+    let mut parts = value.splitn(2, '.');
+    let tv_sec = parts.next()?.parse().ok()?;
+    let tv_nsec = match parts.next() {
+        Some(fraction) => {
+            // nanoseconds are the first nine digits of the fraction
+            let digits: String = fraction
+                .chars()
+                .chain("000000000".chars())
+                .take(9)
+                .collect();
+            digits.parse().ok()?
+        }
+        None => 0,
+    };
+    Some(libc::timespec { tv_sec, tv_nsec })
+}
+
+/// parse_number parses a numeric header field, in octal or, for values too
+/// large for it, in big-endian base-256 flagged by the high bit
+fn parse_number(field: &[u8]) -> Result<u64, Error> {
+    if field.first().is_some_and(|byte| byte & 0x80 != 0) {
+        return Ok(field[1..]
+            .iter()
+            .fold(u64::from(field[0] & 0x7f), |number, byte| {
+                (number << 8) | u64::from(*byte)
+            }));
+    }
+    let digits = std::str::from_utf8(field)
+        .map_err(|_| invalid_data("invalid numeric header field"))?
+        .trim_matches(|c: char| c == '\0' || c == ' ');
+    if digits.is_empty() {
+        return Ok(0);
+    }
+    u64::from_str_radix(digits, 8).map_err(|_| invalid_data("invalid numeric header field"))
+}
+
+fn read_data(data: &mut dyn Read) -> Result<Vec<u8>, Error> {
+    let mut buf = vec![];
+    data.read_to_end(&mut buf)?;
+    Ok(buf)
+}
+
+/// read_full reads into `buf` until it's full or the reader is exhausted,
+/// returning how much was read
+fn read_full(reader: &mut dyn Read, buf: &mut [u8]) -> Result<usize, Error> {
+    let mut read = 0;
+    while read < buf.len() {
+        match reader.read(&mut buf[read..]) {
+            Ok(0) => break,
+            Ok(n) => read += n,
+            Err(err) if err.kind() == ErrorKind::Interrupted => (),
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(read)
+}
+
+fn trim_nul(field: &[u8]) -> &[u8] {
+    let end = field
+        .iter()
+        .position(|byte| *byte == 0)
+        .unwrap_or(field.len());
+    &field[..end]
+}
+
+fn not_a_directory(path: &Path) -> Error {
+    invalid_data(&format!(
+        "{:?} is not a directory, entries can't be extracted beneath it",
+        path
+    ))
+}
+
+fn invalid_data(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::{create_dir_all, metadata, read_to_string, set_permissions, write, Permissions};
+    use std::os::unix::fs::PermissionsExt;
+    use tempfile::tempdir;
+
+    /// Entry is an entry of an archive built by hand
+    struct Entry<'a> {
+        entry_type: u8,
+        path: &'a [u8],
+        link_path: &'a [u8],
+        data: &'a [u8],
+    }
+
+    fn file<'a>(path: &'a str, data: &'a str) -> Entry<'a> {
+        Entry {
+            entry_type: b'0',
+            path: path.as_bytes(),
+            link_path: b"",
+            data: data.as_bytes(),
+        }
+    }
+
+    fn link<'a>(entry_type: u8, path: &'a str, link_path: &'a str) -> Entry<'a> {
+        Entry {
+            entry_type,
+            path: path.as_bytes(),
+            link_path: link_path.as_bytes(),
+            data: b"",
+        }
+    }
+
+    /// archive builds an archive of `entries`, written as they are, which
+    /// write_tarball would refuse to
+    fn archive(entries: &[Entry]) -> Vec<u8> {
+        let mut out = vec![];
+        let mut archiver = Archiver {
+            out: &mut out,
+            links: HashMap::new(),
+        };
+        for entry in entries {
+            let mut block = [0u8; BLOCK_SIZE];
+            put_bytes(&mut block[0..100], entry.path);
+            put_number(&mut block[100..108], 0o755);
+            put_number(&mut block[124..136], entry.data.len() as u64);
+            block[156] = entry.entry_type;
+            put_bytes(&mut block[157..257], entry.link_path);
+            block[257..263].copy_from_slice(USTAR_MAGIC);
+            block[263..265].copy_from_slice(USTAR_VERSION);
+            archiver.write_block(&mut block).unwrap();
+            let mut data = entry.data;
+            archiver
+                .write_data(&mut data, entry.data.len() as u64)
+                .unwrap();
+        }
+        archiver.finish().unwrap();
+        out
+    }
+
+    /// extract extracts `archive` into `to` like extract_tarball
+    fn extract(archive: &[u8], to: &Path) -> Result<(), Error> {
+        let dir = tempdir().unwrap();
+        let tarball = dir.path().join("archive.tar");
+        write(&tarball, archive).unwrap();
+        extract_tarball(&tarball, to)
+    }
+
+    #[test]
+    fn entry_paths_stay_within_root() {
+        assert_eq!(entry_path(b"etc/passwd").unwrap(), Path::new("etc/passwd"));
+        assert_eq!(
+            entry_path(b"./etc/./passwd").unwrap(),
+            Path::new("etc/passwd")
+        );
+        // absolute paths are relative to the root
+        assert_eq!(entry_path(b"/etc/passwd").unwrap(), Path::new("etc/passwd"));
+        assert_eq!(entry_path(b"/").unwrap(), Path::new(""));
+        for path in &[&b".."[..], b"../etc", b"etc/../../passwd", b"/../etc"] {
+            assert_eq!(
+                entry_path(path).unwrap_err().kind(),
+                ErrorKind::InvalidData,
+                "{:?}",
+                String::from_utf8_lossy(path)
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_parent_dir_entries() {
+        let dir = tempdir().unwrap();
+        let root = dir.path().join("root");
+        create_dir_all(&root).unwrap();
+        assert!(extract(&archive(&[file("../escaped", "escaped")]), &root).is_err());
+        assert!(!dir.path().join("escaped").exists());
+    }
+
+    #[test]
+    fn rejects_entries_beneath_symlinks() {
+        let dir = tempdir().unwrap();
+        let (root, outside) = (dir.path().join("root"), dir.path().join("outside"));
+        create_dir_all(&root).unwrap();
+        create_dir_all(&outside).unwrap();
+        let outside_path = outside.to_str().unwrap();
+        for target in &[outside_path, "../outside"] {
+            let result = extract(
+                &archive(&[link(b'2', "link", target), file("link/escaped", "escaped")]),
+                &root,
+            );
+            assert_eq!(result.unwrap_err().kind(), ErrorKind::InvalidData);
+            assert!(!outside.join("escaped").exists());
+            remove_file(root.join("link")).unwrap();
+        }
+    }
+
+    #[test]
+    fn rejects_hardlinks_outside_root() {
+        let dir = tempdir().unwrap();
+        let (root, outside) = (dir.path().join("root"), dir.path().join("outside"));
+        create_dir_all(&root).unwrap();
+        create_dir_all(&outside).unwrap();
+        write(outside.join("secret"), "secret").unwrap();
+        // through `..`
+        assert!(extract(
+            &archive(&[link(b'1', "secret", "../outside/secret")]),
+            &root
+        )
+        .is_err());
+        // and through a symlink extracted before it
+        let result = extract(
+            &archive(&[
+                link(b'2', "link", outside.to_str().unwrap()),
+                link(b'1', "secret", "link/secret"),
+            ]),
+            &root,
+        );
+        assert!(result.is_err());
+        assert!(!root.join("secret").exists());
+        assert_eq!(metadata(outside.join("secret")).unwrap().nlink(), 1);
+    }
+
+    #[test]
+    fn extracts_gnu_long_names() {
+        let dir = tempdir().unwrap();
+        let long_dir = "d".repeat(80);
+        let long_path = format!("{}/{}", long_dir, "f".repeat(80));
+        let long_link = format!("{}/{}", long_dir, "l".repeat(80));
+        let root = dir.path();
+        let tar = archive(&[
+            Entry {
+                entry_type: b'L',
+                path: b"././@LongLink",
+                link_path: b"",
+                data: long_path.as_bytes(),
+            },
+            file("truncated", "long"),
+            Entry {
+                entry_type: b'L',
+                path: b"././@LongLink",
+                link_path: b"",
+                data: long_link.as_bytes(),
+            },
+            Entry {
+                entry_type: b'K',
+                path: b"././@LongLink",
+                link_path: b"",
+                data: long_path.as_bytes(),
+            },
+            link(b'2', "truncated", "truncated"),
+        ]);
+        extract(&tar, root).unwrap();
+        assert_eq!(read_to_string(root.join(&long_path)).unwrap(), "long");
+        assert_eq!(
+            read_link(root.join(&long_link)).unwrap(),
+            Path::new(&long_path)
+        );
+        assert!(!root.join("truncated").exists());
+    }
+
+    #[test]
+    fn round_trips_pax_long_names() {
+        let dir = tempdir().unwrap();
+        let (from, to) = (dir.path().join("from"), dir.path().join("to"));
+        let long_dir = from.join("d".repeat(120));
+        create_dir_all(&long_dir).unwrap();
+        create_dir_all(&to).unwrap();
+        let long_name = "f".repeat(200);
+        let long_target = format!("{}/{}", "d".repeat(120), long_name);
+        write(long_dir.join(&long_name), "long").unwrap();
+        symlink(&long_target, from.join("link")).unwrap();
+        let mut tar = vec![];
+        write_tarball(&from, &mut tar).unwrap();
+        extract(&tar, &to).unwrap();
+        assert_eq!(read_to_string(to.join(&long_target)).unwrap(), "long");
+        assert_eq!(read_link(to.join("link")).unwrap(), Path::new(&long_target));
+    }
+
+    #[test]
+    fn round_trips_trees() {
+        let dir = tempdir().unwrap();
+        let (from, to) = (dir.path().join("from"), dir.path().join("to"));
+        create_dir_all(from.join("bin")).unwrap();
+        create_dir_all(from.join("empty")).unwrap();
+        create_dir_all(&to).unwrap();
+        write(from.join("bin/busybox"), "busybox").unwrap();
+        set_permissions(from.join("bin/busybox"), Permissions::from_mode(0o4755)).unwrap();
+        hard_link(from.join("bin/busybox"), from.join("bin/ls")).unwrap();
+        symlink("busybox", from.join("bin/sh")).unwrap();
+        symlink("/missing", from.join("dangling")).unwrap();
+        assert!(make_node(&from.join("fifo"), libc::S_IFIFO | 0o640, 0).unwrap());
+        set_permissions(from.join("empty"), Permissions::from_mode(0o700)).unwrap();
+        let mtime = libc::timespec {
+            tv_sec: 1_000_000_000,
+            tv_nsec: 123_456_789,
+        };
+        let busybox = symlink_metadata(from.join("bin/busybox")).unwrap();
+        set_attributes(
+            &from.join("bin/busybox"),
+            &Attributes {
+                uid: busybox.uid(),
+                gid: busybox.gid(),
+                mode: busybox.mode(),
+                atime: mtime,
+                mtime,
+                xattrs: vec![],
+            },
+            is_privileged(),
+        )
+        .unwrap();
+
+        let mut tar = vec![];
+        write_tarball(&from, &mut tar).unwrap();
+        // the same tree makes the same archive
+        let mut again = vec![];
+        write_tarball(&from, &mut again).unwrap();
+        assert_eq!(tar, again);
+        extract(&tar, &to).unwrap();
+
+        assert_eq!(read_to_string(to.join("bin/busybox")).unwrap(), "busybox");
+        let (busybox, ls) = (
+            metadata(to.join("bin/busybox")).unwrap(),
+            metadata(to.join("bin/ls")).unwrap(),
+        );
+        assert_eq!(busybox.ino(), ls.ino());
+        assert_eq!(busybox.mode() & 0o7777, 0o4755);
+        assert_eq!(
+            (busybox.mtime(), busybox.mtime_nsec()),
+            (mtime.tv_sec, mtime.tv_nsec)
+        );
+        assert_eq!(read_link(to.join("bin/sh")).unwrap(), Path::new("busybox"));
+        assert_eq!(
+            read_link(to.join("dangling")).unwrap(),
+            Path::new("/missing")
+        );
+        let fifo = symlink_metadata(to.join("fifo")).unwrap();
+        assert!(fifo.file_type().is_fifo());
+        assert_eq!(fifo.mode() & 0o7777, 0o640);
+        assert_eq!(metadata(to.join("empty")).unwrap().mode() & 0o7777, 0o700);
+    }
+
+    #[test]
+    fn rejects_corrupt_archives() {
+        let dir = tempdir().unwrap();
+        let mut tar = archive(&[file("file", "data")]);
+        tar[0] = b'g';
+        assert_eq!(
+            extract(&tar, dir.path()).unwrap_err().kind(),
+            ErrorKind::InvalidData
+        );
+        let tar = archive(&[file("file", "data")]);
+        assert!(extract(&tar[..BLOCK_SIZE], dir.path()).is_err());
+    }
+
+    /// apply applies the layer `archive` over `to` like apply_layer
+    fn apply(archive: &[u8], to: &Path) -> Result<(), Error> {
+        let dir = tempdir().unwrap();
+        let layer = dir.path().join("layer.tar");
+        write(&layer, archive).unwrap();
+        apply_layer(&layer, to)
+    }
+
+    #[test]
+    fn applies_whiteouts() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        create_dir_all(root.join("etc/apk")).unwrap();
+        create_dir_all(root.join("opaque/old")).unwrap();
+        write(root.join("etc/deleted"), "").unwrap();
+        write(root.join("etc/kept"), "").unwrap();
+        write(root.join("opaque/old/file"), "").unwrap();
+        apply(
+            &archive(&[
+                file("etc/.wh.deleted", ""),
+                file("etc/.wh.apk", ""),
+                file("etc/.wh.missing", ""),
+                file("opaque/new", "new"),
+                file("opaque/.wh..wh..opq", ""),
+                file(".wh..wh.plnk", ""),
+            ]),
+            root,
+        )
+        .unwrap();
+        assert!(!root.join("etc/deleted").exists());
+        assert!(!root.join("etc/apk").exists());
+        assert!(root.join("etc/kept").exists());
+        // opaque whiteouts keep what the layer itself adds
+        assert!(!root.join("opaque/old").exists());
+        assert_eq!(read_to_string(root.join("opaque/new")).unwrap(), "new");
+        // whiteout files aren't extracted
+        assert!(!root.join("etc/.wh.deleted").exists());
+        assert!(!root.join(".wh..wh.plnk").exists());
+    }
+
+    #[test]
+    fn rejects_whiteouts_of_parents() {
+        let dir = tempdir().unwrap();
+        let (root, sibling) = (dir.path().join("root"), dir.path().join("sibling"));
+        create_dir_all(root.join("etc")).unwrap();
+        create_dir_all(&sibling).unwrap();
+        write(root.join("etc/passwd"), "").unwrap();
+        for whiteout in &[
+            ".wh...",
+            "etc/.wh...",
+            ".wh..",
+            "etc/.wh..",
+            ".wh.",
+            "etc/.wh.",
+        ] {
+            let result = apply(&archive(&[file(whiteout, "")]), &root);
+            assert_eq!(
+                result.unwrap_err().kind(),
+                ErrorKind::InvalidData,
+                "{}",
+                whiteout
+            );
+            assert!(sibling.exists(), "{}", whiteout);
+            assert!(root.join("etc/passwd").exists(), "{}", whiteout);
+        }
+    }
+
+    #[test]
+    fn rejects_oversized_extensions() {
+        let dir = tempdir().unwrap();
+        for entry_type in b"xgLK" {
+            // the declared size is rejected before any of it is read
+            let mut block = [0u8; BLOCK_SIZE];
+            put_bytes(&mut block[0..100], PAX_HEADER_NAME);
+            put_number(&mut block[124..136], 1 << 30);
+            block[156] = *entry_type;
+            block[257..263].copy_from_slice(USTAR_MAGIC);
+            block[263..265].copy_from_slice(USTAR_VERSION);
+            let mut tar = vec![];
+            Archiver {
+                out: &mut tar,
+                links: HashMap::new(),
+            }
+            .write_block(&mut block)
+            .unwrap();
+            let err = extract_archive(&mut tar.as_slice(), dir.path(), None).unwrap_err();
+            assert_eq!(err.kind(), ErrorKind::InvalidData);
+            assert!(err.to_string().contains("too large"), "{}", err);
+        }
+    }
+}
@@ -0,0 +1,1279 @@
+use super::container_store::tarball::{apply_layer, extract_tarball, write_tarball};
+use crate::container::rand_id;
+use crate::image::{normalize_reference, Image, ImageConfig};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
+use std::fs::{
+    copy, create_dir_all, read_dir, read_to_string, remove_dir_all, remove_file, rename,
+    symlink_metadata, write, File,
+};
+use std::io::{BufWriter, Write};
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
+use std::time::SystemTime;
+
+// the only digest algorithm images are verified with
+const SHA256_PREFIX: &str = "sha256:";
+// annotations of oci image layout indexes naming their images
+const CONTAINERD_IMAGE_NAME: &str = "io.containerd.image.name";
+const OCI_REF_NAME: &str = "org.opencontainers.image.ref.name";
+// how deeply oci indexes may nest
+const MAX_INDEX_DEPTH: usize = 4;
+
+#[derive(Debug)]
+pub struct ImageStore {
+    root_dir: String,
+}
+
+#[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
+pub enum ImageStoreError {
+    // represents an error creating the directories of the image store
+    CreateImagesDirError {
+        source: std::io::Error,
+    },
+    // represents an error creating the directory an import is staged in
+    CreateStagingDirError {
+        source: std::io::Error,
+    },
+    // represents an error extracting an image archive
+    ExtractArchiveError {
+        path: String,
+        source: std::io::Error,
+    },
+    // represents a path that is neither a `docker save` archive nor an oci image layout
+    UnsupportedArchiveError {
+        path: String,
+    },
+    // represents an error reading a manifest, index or config of an image
+    ReadManifestError {
+        path: String,
+        source: std::io::Error,
+    },
+    // represents an error parsing a manifest, index or config of an image
+    ParseManifestError {
+        path: String,
+        source: serde_json::Error,
+    },
+    // represents a path within an image archive that leads outside of it
+    InvalidArchivePathError {
+        path: String,
+    },
+    // represents an image archive without images for this platform
+    NoImagesError {
+        path: String,
+    },
+    // represents an error giving a reference to an archive of many images
+    ReferenceMultipleImagesError {
+        path: String,
+    },
+    // represents a digest that isn't a sha256 digest
+    UnsupportedDigestError {
+        digest: String,
+    },
+    // represents content that doesn't match its digest
+    DigestMismatchError {
+        digest: String,
+        actual: String,
+    },
+    // represents an image whose config lists a different number of layers than its manifest
+    LayerCountMismatchError {
+        id: String,
+    },
+    // represents an error archiving a rootfs into a layer
+    ArchiveRootfsError {
+        rootfs_dir: String,
+        source: std::io::Error,
+    },
+    // represents an error computing the digest of a blob or storing it
+    StoreBlobError {
+        path: String,
+        source: std::io::Error,
+    },
+    // represents an error unpacking a layer into the rootfs of an image
+    UnpackLayerError {
+        digest: String,
+        source: std::io::Error,
+    },
+    // represents an error persisting the state of an image
+    PersistImageStateError {
+        id: String,
+        source: std::io::Error,
+    },
+    // represents an error reading the state of an image
+    ReadImageStateError {
+        id: String,
+        source: std::io::Error,
+    },
+    // represents an error parsing the state of an image
+    ParseImageStateError {
+        id: String,
+        source: serde_json::Error,
+    },
+    // represents an error reading the images dir
+    ReadImagesDirError {
+        source: std::io::Error,
+    },
+    // represents an error removing an image or its unused blobs
+    RemoveImageError {
+        id: String,
+        source: std::io::Error,
+    },
+    // represents a reference or id that no image has
+    ImageNotFoundError {
+        image: String,
+    },
+    // represents an id prefix more than one image has
+    AmbiguousImageError {
+        image: String,
+    },
+}
+
+impl fmt::Display for ImageStoreError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Self::CreateImagesDirError { .. } => write!(f, "failed to create images dir"),
+            Self::CreateStagingDirError { .. } => {
+                write!(f, "failed to create dir to stage image import in")
+            }
+            Self::ExtractArchiveError { ref path, .. } => {
+                write!(f, "failed to extract image archive {}", path)
+            }
+            Self::UnsupportedArchiveError { ref path } => write!(
+                f,
+                "{} is neither a docker save archive nor an oci image layout",
+                path
+            ),
+            Self::ReadManifestError { ref path, .. } => {
+                write!(f, "failed to read image manifest {}", path)
+            }
+            Self::ParseManifestError { ref path, .. } => {
+                write!(f, "failed to parse image manifest {}", path)
+            }
+            Self::InvalidArchivePathError { ref path } => {
+                write!(
+                    f,
+                    "image archive path {} leads outside of the archive",
+                    path
+                )
+            }
+            Self::NoImagesError { ref path } => {
+                write!(f, "{} holds no images for this platform", path)
+            }
+            Self::ReferenceMultipleImagesError { ref path } => write!(
+                f,
+                "{} holds more than one image, a reference can only name one",
+                path
+            ),
+            Self::UnsupportedDigestError { ref digest } => {
+                write!(f, "unsupported digest {}, expected sha256", digest)
+            }
+            Self::DigestMismatchError {
+                ref digest,
+                ref actual,
+            } => write!(f, "content of {} has digest {}", digest, actual),
+            Self::LayerCountMismatchError { ref id } => write!(
+                f,
+                "config of image {} lists a different number of layers than its manifest",
+                id
+            ),
+            Self::ArchiveRootfsError { ref rootfs_dir, .. } => {
+                write!(f, "failed to archive rootfs {}", rootfs_dir)
+            }
+            Self::StoreBlobError { ref path, .. } => write!(f, "failed to store blob {}", path),
+            Self::UnpackLayerError { ref digest, .. } => {
+                write!(f, "failed to unpack layer {}", digest)
+            }
+            Self::PersistImageStateError { ref id, .. } => {
+                write!(f, "failed to persist state of image {}", id)
+            }
+            Self::ReadImageStateError { ref id, .. } => {
+                write!(f, "failed to read state of image {}", id)
+            }
+            Self::ParseImageStateError { ref id, .. } => {
+                write!(f, "failed to parse state of image {}", id)
+            }
+            Self::ReadImagesDirError { .. } => write!(f, "failed to read images dir"),
+            Self::RemoveImageError { ref id, .. } => write!(f, "failed to remove image {}", id),
+            Self::ImageNotFoundError { ref image } => write!(f, "image {} not found", image),
+            Self::AmbiguousImageError { ref image } => {
+                write!(f, "image id prefix {} matches more than one image", image)
+            }
+        }
+    }
+}
+
+impl Error for ImageStoreError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            Self::CreateImagesDirError { ref source } => Some(source),
+            Self::CreateStagingDirError { ref source } => Some(source),
+            Self::ExtractArchiveError { ref source, .. } => Some(source),
+            Self::UnsupportedArchiveError { .. } => None,
+            Self::ReadManifestError { ref source, .. } => Some(source),
+            Self::ParseManifestError { ref source, .. } => Some(source),
+            Self::InvalidArchivePathError { .. } => None,
+            Self::NoImagesError { .. } => None,
+            Self::ReferenceMultipleImagesError { .. } => None,
+            Self::UnsupportedDigestError { .. } => None,
+            Self::DigestMismatchError { .. } => None,
+            Self::LayerCountMismatchError { .. } => None,
+            Self::ArchiveRootfsError { ref source, .. } => Some(source),
+            Self::StoreBlobError { ref source, .. } => Some(source),
+            Self::UnpackLayerError { ref source, .. } => Some(source),
+            Self::PersistImageStateError { ref source, .. } => Some(source),
+            Self::ReadImageStateError { ref source, .. } => Some(source),
+            Self::ParseImageStateError { ref source, .. } => Some(source),
+            Self::ReadImagesDirError { ref source } => Some(source),
+            Self::RemoveImageError { ref source, .. } => Some(source),
+            Self::ImageNotFoundError { .. } => None,
+            Self::AmbiguousImageError { .. } => None,
+        }
+    }
+}
+
+/// ArchivedImage is an image found in an archive or layout, yet to be
+/// verified and stored
+struct ArchivedImage {
+    references: Vec<String>,
+    config: Blob,
+    layers: Vec<Blob>,
+}
+
+/// Blob is a file of an archived image, along with the digest it should
+/// have if the archive records one
+struct Blob {
+    path: PathBuf,
+    digest: Option<String>,
+}
+
+/// DockerManifest is an entry of the manifest.json of a `docker save`
+/// archive
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct DockerManifest {
+    config: String,
+    #[serde(default)]
+    repo_tags: Option<Vec<String>>,
+    layers: Vec<String>,
+}
+
+/// OciManifest is an oci image index or image manifest, which have either
+/// manifests or a config and layers
+#[derive(Deserialize)]
+struct OciManifest {
+    #[serde(default)]
+    manifests: Vec<Descriptor>,
+    config: Option<Descriptor>,
+    #[serde(default)]
+    layers: Vec<Descriptor>,
+}
+
+#[derive(Deserialize)]
+struct Descriptor {
+    digest: String,
+    #[serde(default)]
+    annotations: HashMap<String, String>,
+    platform: Option<Platform>,
+}
+
+#[derive(Deserialize)]
+struct Platform {
+    architecture: String,
+    os: String,
+}
+
+/// ConfigFile is the part of an image config we use
+#[derive(Deserialize)]
+struct ConfigFile {
+    config: Option<ContainerConfig>,
+    rootfs: Option<RootFs>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ContainerConfig {
+    entrypoint: Option<Vec<String>>,
+    cmd: Option<Vec<String>>,
+    env: Option<Vec<String>>,
+    working_dir: Option<String>,
+    user: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct RootFs {
+    // digests of the uncompressed layers, bottom first
+    diff_ids: Vec<String>,
+}
+
+impl ImageStore {
+    pub fn new(root_dir: &str) -> Result<ImageStore, ImageStoreError> {
+        let is = ImageStore {
+            root_dir: format!("{}/images", root_dir),
+        };
+        // imports interrupted by the daemon stopping leave their staging
+        // dirs behind
+        let _ = remove_dir_all(is.staging_dir());
+        for dir in [is.blobs_dir(), is.images_dir(), is.staging_dir()] {
+            create_dir_all(dir)
+                .map_err(|source| ImageStoreError::CreateImagesDirError { source })?;
+        }
+        Ok(is)
+    }
+
+    /// import_images does the following:
+    /// - extract the archive at `path`, unless it's a directory already
+    /// - find the images in its `docker save` manifest or oci index
+    /// - store the config and layers of each image as blobs, verifying their
+    ///   digests
+    /// - unpack the layers of each image not imported before into its rootfs
+    /// - move the references of each image to it from any other image, and
+    ///   persist the images' state
+    ///
+    /// `reference` is added to the references of the image, if the archive
+    /// holds just one
+    pub fn import_images(
+        &self,
+        path: &str,
+        reference: Option<&str>,
+    ) -> Result<Vec<Image>, ImageStoreError> {
+        let staging_dir = format!("{}/{}", self.staging_dir(), rand_id());
+        create_dir_all(&staging_dir)
+            .map_err(|source| ImageStoreError::CreateStagingDirError { source })?;
+        let images = self.import_staged_images(path, reference, &staging_dir);
+        let _ = remove_dir_all(&staging_dir);
+        images
+    }
+
+    fn import_staged_images(
+        &self,
+        path: &str,
+        reference: Option<&str>,
+        staging_dir: &str,
+    ) -> Result<Vec<Image>, ImageStoreError> {
+        let is_archive = symlink_metadata(path)
+            .map(|metadata| metadata.is_file())
+            .unwrap_or(false);
+        let layout_dir = if is_archive {
+            let layout_dir = format!("{}/archive", staging_dir);
+            create_dir_all(&layout_dir)
+                .map_err(|source| ImageStoreError::CreateStagingDirError { source })?;
+            extract_tarball(path, &layout_dir).map_err(|source| {
+                ImageStoreError::ExtractArchiveError {
+                    path: path.to_string(),
+                    source,
+                }
+            })?;
+            PathBuf::from(layout_dir)
+        } else {
+            PathBuf::from(path)
+        };
+        // newer `docker save` archives are oci image layouts too, but only
+        // their manifest.json has the references of their images
+        let mut archived_images = if layout_dir.join("manifest.json").exists() {
+            read_docker_manifest(&layout_dir)?
+        } else if layout_dir.join("index.json").exists() {
+            read_oci_index(&layout_dir)?
+        } else {
+            return Err(ImageStoreError::UnsupportedArchiveError {
+                path: path.to_string(),
+            });
+        };
+        if archived_images.is_empty() {
+            return Err(ImageStoreError::NoImagesError {
+                path: path.to_string(),
+            });
+        }
+        if let Some(reference) = reference {
+            if archived_images.len() > 1 {
+                return Err(ImageStoreError::ReferenceMultipleImagesError {
+                    path: path.to_string(),
+                });
+            }
+            archived_images[0]
+                .references
+                .push(normalize_reference(reference));
+        }
+        archived_images
+            .into_iter()
+            .map(|archived_image| self.store_image(archived_image, staging_dir))
+            .collect()
+    }
+
+    /// commit_image does the following:
+    /// - archive the directory `rootfs_dir` into a single layer
+    /// - write an image config of `config` and the layer
+    /// - store them as an image, as imported images are stored, and give it
+    ///   `reference`
+    pub fn commit_image(
+        &self,
+        rootfs_dir: &str,
+        config: &ImageConfig,
+        reference: &str,
+    ) -> Result<Image, ImageStoreError> {
+        let staging_dir = format!("{}/{}", self.staging_dir(), rand_id());
+        create_dir_all(&staging_dir)
+            .map_err(|source| ImageStoreError::CreateStagingDirError { source })?;
+        let image = self.commit_staged_image(rootfs_dir, config, reference, &staging_dir);
+        let _ = remove_dir_all(&staging_dir);
+        image
+    }
+
+    fn commit_staged_image(
+        &self,
+        rootfs_dir: &str,
+        config: &ImageConfig,
+        reference: &str,
+        staging_dir: &str,
+    ) -> Result<Image, ImageStoreError> {
+        let archive_error = |source| ImageStoreError::ArchiveRootfsError {
+            rootfs_dir: rootfs_dir.to_string(),
+            source,
+        };
+        let layer_path = PathBuf::from(format!("{}/layer.tar", staging_dir));
+        let mut layer = BufWriter::new(File::create(&layer_path).map_err(archive_error)?);
+        write_tarball(rootfs_dir, &mut layer).map_err(archive_error)?;
+        layer
+            .into_inner()
+            .map_err(|err| archive_error(err.into_error()))?
+            .sync_all()
+            .map_err(archive_error)?;
+        let diff_id = sha256_digest(&layer_path).map_err(archive_error)?;
+        let config_file = serde_json::json!({
+            "architecture": platform_architecture(),
+            "os": "linux",
+            "config": {
+                "Entrypoint": config.entrypoint,
+                "Cmd": config.cmd,
+                "Env": config.env,
+                "WorkingDir": config.working_dir,
+                "User": config.user,
+            },
+            "rootfs": {
+                "type": "layers",
+                "diff_ids": [diff_id],
+            },
+        });
+        let config_path = PathBuf::from(format!("{}/config.json", staging_dir));
+        File::create(&config_path)
+            .and_then(|mut file| file.write_all(config_file.to_string().as_bytes()))
+            .map_err(archive_error)?;
+        self.store_image(
+            ArchivedImage {
+                references: vec![normalize_reference(reference)],
+                config: Blob {
+                    path: config_path,
+                    digest: None,
+                },
+                layers: vec![Blob {
+                    path: layer_path,
+                    digest: Some(diff_id),
+                }],
+            },
+            staging_dir,
+        )
+    }
+
+    /// store_image stores the blobs of an archived image, unpacks its rootfs
+    /// if it wasn't imported before, and gives it its references
+    fn store_image(
+        &self,
+        archived_image: ArchivedImage,
+        staging_dir: &str,
+    ) -> Result<Image, ImageStoreError> {
+        let id = self.store_blob(&archived_image.config, staging_dir)?;
+        let config_path = self.blob_path(&id);
+        let config_file: ConfigFile = read_json(Path::new(&config_path))?;
+        let diff_ids = config_file
+            .rootfs
+            .map(|rootfs| rootfs.diff_ids)
+            .unwrap_or_default();
+        if diff_ids.len() != archived_image.layers.len() {
+            return Err(ImageStoreError::LayerCountMismatchError { id });
+        }
+        let mut layers = vec![];
+        for (layer, diff_id) in archived_image.layers.into_iter().zip(diff_ids) {
+            // `docker save` archives record no digests of their own, but
+            // their layers are uncompressed, so have the digests the config
+            // lists
+            let layer = Blob {
+                digest: layer.digest.or(Some(diff_id)),
+                ..layer
+            };
+            layers.push(self.store_blob(&layer, staging_dir)?);
+        }
+        let config = config_file
+            .config
+            .map_or_else(ImageConfig::default, |config| ImageConfig {
+                entrypoint: config.entrypoint.unwrap_or_default(),
+                cmd: config.cmd.unwrap_or_default(),
+                env: config.env.unwrap_or_default(),
+                working_dir: config.working_dir.unwrap_or_default(),
+                user: config.user.unwrap_or_default(),
+            });
+
+        let mut image = match self.read_image(&id) {
+            Ok(image) => image,
+            Err(ImageStoreError::ImageNotFoundError { .. }) => {
+                let image = Image {
+                    id: id.clone(),
+                    references: vec![],
+                    layers,
+                    config,
+                    created_at: SystemTime::now(),
+                };
+                self.unpack_image(&image)?;
+                image
+            }
+            Err(err) => return Err(err),
+        };
+        // a reference names one image, so it moves to the newly imported one
+        for reference in archived_image.references {
+            for mut other_image in self.list_images()? {
+                if other_image.id != image.id && other_image.references.contains(&reference) {
+                    other_image.references.retain(|other| *other != reference);
+                    self.persist_image(&other_image)?;
+                }
+            }
+            if !image.references.contains(&reference) {
+                image.references.push(reference);
+            }
+        }
+        self.persist_image(&image)?;
+        Ok(image)
+    }
+
+    /// store_blob copies `blob` into the blob store, verifying its digest if
+    /// it has one, and returns its digest
+    fn store_blob(&self, blob: &Blob, staging_dir: &str) -> Result<String, ImageStoreError> {
+        // the copy is what's verified, so the blob can't change in between
+        let staged_path = format!("{}/{}", staging_dir, rand_id());
+        let store_error = |source| ImageStoreError::StoreBlobError {
+            path: blob.path.to_string_lossy().to_string(),
+            source,
+        };
+        copy(&blob.path, &staged_path).map_err(store_error)?;
+        let digest = sha256_digest(Path::new(&staged_path)).map_err(store_error)?;
+        if let Some(ref expected) = blob.digest {
+            verify_digest(expected, &digest)?;
+        }
+        rename(&staged_path, self.blob_path(&digest)).map_err(store_error)?;
+        Ok(digest)
+    }
+
+    /// unpack_image unpacks the layers of an image into its rootfs, bottom
+    /// first
+    fn unpack_image(&self, image: &Image) -> Result<(), ImageStoreError> {
+        let rootfs_dir = self.rootfs_dir(&image.id);
+        create_dir_all(&rootfs_dir).map_err(|source| ImageStoreError::UnpackLayerError {
+            digest: image.id.clone(),
+            source,
+        })?;
+        for layer in &image.layers {
+            if let Err(source) = apply_layer(self.blob_path(layer), &rootfs_dir) {
+                let _ = remove_dir_all(self.image_dir(&image.id));
+                return Err(ImageStoreError::UnpackLayerError {
+                    digest: layer.clone(),
+                    source,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// find_image returns the image with the reference `image`, with the
+    /// default tag if it has none, or else the image whose id starts with
+    /// `image`
+    pub fn find_image(&self, image: &str) -> Result<Image, ImageStoreError> {
+        let images = self.list_images()?;
+        let reference = normalize_reference(image);
+        if let Some(found) = images
+            .iter()
+            .find(|candidate| candidate.references.contains(&reference))
+        {
+            return Ok(found.clone());
+        }
+        let id_prefix = image.strip_prefix(SHA256_PREFIX).unwrap_or(image);
+        let not_found = || ImageStoreError::ImageNotFoundError {
+            image: image.to_string(),
+        };
+        if id_prefix.is_empty() || !id_prefix.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(not_found());
+        }
+        let mut matches = images.into_iter().filter(|candidate| {
+            candidate
+                .id
+                .strip_prefix(SHA256_PREFIX)
+                .is_some_and(|hex| hex.starts_with(id_prefix))
+        });
+        match (matches.next(), matches.next()) {
+            (Some(found), None) => Ok(found),
+            (Some(_), Some(_)) => Err(ImageStoreError::AmbiguousImageError {
+                image: image.to_string(),
+            }),
+            (None, _) => Err(not_found()),
+        }
+    }
+
+    /// read_image reads an image's state off disk
+    pub fn read_image(&self, id: &str) -> Result<Image, ImageStoreError> {
+        let image_state_string = match read_to_string(self.image_state_file(id)) {
+            Ok(image_state_string) => image_state_string,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                return Err(ImageStoreError::ImageNotFoundError {
+                    image: id.to_string(),
+                })
+            }
+            Err(source) => {
+                return Err(ImageStoreError::ReadImageStateError {
+                    id: id.to_string(),
+                    source,
+                })
+            }
+        };
+        serde_json::from_str(&image_state_string).map_err(|source| {
+            ImageStoreError::ParseImageStateError {
+                id: id.to_string(),
+                source,
+            }
+        })
+    }
+
+    /// list_images lists the images on disk
+    pub fn list_images(&self) -> Result<Vec<Image>, ImageStoreError> {
+        let mut images = vec![];
+        let image_dirs = read_dir(self.images_dir())
+            .map_err(|source| ImageStoreError::ReadImagesDirError { source })?;
+        for image_dir in image_dirs {
+            let image_dir =
+                image_dir.map_err(|source| ImageStoreError::ReadImagesDirError { source })?;
+            let id = format!(
+                "{}{}",
+                SHA256_PREFIX,
+                image_dir.file_name().to_string_lossy()
+            );
+            match self.read_image(&id) {
+                Ok(image) => images.push(image),
+                // an image that is being imported or removed
+                Err(ImageStoreError::ImageNotFoundError { .. }) => (),
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(images)
+    }
+
+    /// persist_image writes the state of an image to disk. an image counts
+    /// as imported once its state is on disk
+    pub fn persist_image(&self, image: &Image) -> Result<(), ImageStoreError> {
+        let persist_error = |source| ImageStoreError::PersistImageStateError {
+            id: image.id.clone(),
+            source,
+        };
+        let serialized_image = serde_json::to_string(image).map_err(|err| {
+            persist_error(std::io::Error::new(std::io::ErrorKind::InvalidData, err))
+        })?;
+        // write the state aside first, so it's never seen half written
+        let temp_state_file = format!("{}.tmp", self.image_state_file(&image.id));
+        write(&temp_state_file, serialized_image).map_err(persist_error)?;
+        rename(&temp_state_file, self.image_state_file(&image.id)).map_err(persist_error)
+    }
+
+    /// remove_image deletes an image, including its rootfs, on disk, along
+    /// with the blobs no other image uses
+    pub fn remove_image(&self, id: &str) -> Result<(), ImageStoreError> {
+        let remove_error = |source| ImageStoreError::RemoveImageError {
+            id: id.to_string(),
+            source,
+        };
+        // remove the state first, so a partially removed image isn't listed
+        match remove_file(self.image_state_file(id)) {
+            Ok(()) => (),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                return Err(ImageStoreError::ImageNotFoundError {
+                    image: id.to_string(),
+                })
+            }
+            Err(source) => return Err(remove_error(source)),
+        }
+        remove_dir_all(self.image_dir(id)).map_err(remove_error)?;
+        // remove the blobs that no remaining image uses
+        let mut used_blobs = HashSet::new();
+        for image in self.list_images()? {
+            used_blobs.insert(image.id);
+            used_blobs.extend(image.layers);
+        }
+        for blob in read_dir(self.blobs_dir()).map_err(remove_error)? {
+            let blob = blob.map_err(remove_error)?;
+            let digest = format!("{}{}", SHA256_PREFIX, blob.file_name().to_string_lossy());
+            if !used_blobs.contains(&digest) {
+                remove_file(blob.path()).map_err(remove_error)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// rootfs_dir is the directory an image is unpacked into, which
+    /// containers created from the image are created from
+    pub fn rootfs_dir(&self, id: &str) -> String {
+        format!("{}/rootfs", self.image_dir(id))
+    }
+
+    fn image_state_file(&self, id: &str) -> String {
+        format!("{}/image.state", self.image_dir(id))
+    }
+
+    fn image_dir(&self, id: &str) -> String {
+        format!(
+            "{}/{}",
+            self.images_dir(),
+            id.strip_prefix(SHA256_PREFIX).unwrap_or(id)
+        )
+    }
+
+    fn images_dir(&self) -> String {
+        format!("{}/images", self.root_dir)
+    }
+
+    /// blob_path is where the blob with `digest` is stored
+    fn blob_path(&self, digest: &str) -> String {
+        format!(
+            "{}/{}",
+            self.blobs_dir(),
+            digest.strip_prefix(SHA256_PREFIX).unwrap_or(digest)
+        )
+    }
+
+    fn blobs_dir(&self) -> String {
+        format!("{}/blobs/sha256", self.root_dir)
+    }
+
+    fn staging_dir(&self) -> String {
+        format!("{}/tmp", self.root_dir)
+    }
+}
+
+/// read_docker_manifest finds the images of a `docker save` archive
+/// extracted into `layout_dir`
+fn read_docker_manifest(layout_dir: &Path) -> Result<Vec<ArchivedImage>, ImageStoreError> {
+    let manifests: Vec<DockerManifest> = read_json(&layout_dir.join("manifest.json"))?;
+    manifests
+        .into_iter()
+        .map(|manifest| {
+            Ok(ArchivedImage {
+                references: manifest
+                    .repo_tags
+                    .unwrap_or_default()
+                    .iter()
+                    .map(|reference| normalize_reference(reference))
+                    .collect(),
+                config: Blob {
+                    path: layout_path(layout_dir, &manifest.config)?,
+                    digest: None,
+                },
+                layers: manifest
+                    .layers
+                    .iter()
+                    .map(|layer| {
+                        Ok(Blob {
+                            path: layout_path(layout_dir, layer)?,
+                            digest: None,
+                        })
+                    })
+                    .collect::<Result<_, _>>()?,
+            })
+        })
+        .collect()
+}
+
+/// read_oci_index finds the images of the oci image layout `layout_dir`.
+/// indexes of images for several platforms resolve to the image for this
+/// platform
+fn read_oci_index(layout_dir: &Path) -> Result<Vec<ArchivedImage>, ImageStoreError> {
+    let index: OciManifest = read_json(&layout_dir.join("index.json"))?;
+    let mut archived_images = vec![];
+    for descriptor in index.manifests {
+        // the image name annotation containerd writes is a whole reference,
+        // while the oci one may be just a tag
+        let reference = descriptor
+            .annotations
+            .get(CONTAINERD_IMAGE_NAME)
+            .or_else(|| descriptor.annotations.get(OCI_REF_NAME))
+            .filter(|reference| reference.contains(':') || reference.contains('/'));
+        let references = reference
+            .map(|reference| vec![normalize_reference(reference)])
+            .unwrap_or_default();
+        if let Some(manifest) = resolve_oci_manifest(layout_dir, &descriptor, 0)? {
+            let config = manifest
+                .config
+                .ok_or_else(|| ImageStoreError::NoImagesError {
+                    path: descriptor.digest.clone(),
+                })?;
+            archived_images.push(ArchivedImage {
+                references,
+                config: oci_blob(layout_dir, &config.digest)?,
+                layers: manifest
+                    .layers
+                    .iter()
+                    .map(|layer| oci_blob(layout_dir, &layer.digest))
+                    .collect::<Result<_, _>>()?,
+            });
+        }
+    }
+    Ok(archived_images)
+}
+
+/// resolve_oci_manifest reads the manifest `descriptor` points to, following
+/// indexes to the manifest for this platform, if they have one
+fn resolve_oci_manifest(
+    layout_dir: &Path,
+    descriptor: &Descriptor,
+    depth: usize,
+) -> Result<Option<OciManifest>, ImageStoreError> {
+    let blob = oci_blob(layout_dir, &descriptor.digest)?;
+    let digest = sha256_digest(&blob.path).map_err(|source| ImageStoreError::StoreBlobError {
+        path: blob.path.to_string_lossy().to_string(),
+        source,
+    })?;
+    verify_digest(&descriptor.digest, &digest)?;
+    let manifest: OciManifest = read_json(&blob.path)?;
+    if manifest.manifests.is_empty() || depth >= MAX_INDEX_DEPTH {
+        return Ok(Some(manifest));
+    }
+    let architecture = platform_architecture();
+    let platform_descriptor = manifest.manifests.iter().find(|candidate| {
+        candidate
+            .platform
+            .as_ref()
+            .is_none_or(|platform| platform.os == "linux" && platform.architecture == architecture)
+    });
+    match platform_descriptor {
+        Some(platform_descriptor) => {
+            resolve_oci_manifest(layout_dir, platform_descriptor, depth + 1)
+        }
+        None => Ok(None),
+    }
+}
+
+/// oci_blob is the blob with `digest` in an oci image layout
+fn oci_blob(layout_dir: &Path, digest: &str) -> Result<Blob, ImageStoreError> {
+    let hex = digest_hex(digest)?;
+    Ok(Blob {
+        path: layout_dir.join("blobs/sha256").join(hex),
+        digest: Some(digest.to_string()),
+    })
+}
+
+/// platform_architecture is the name oci images give the architecture the
+/// daemon runs on
+fn platform_architecture() -> &'static str {
+    match std::env::consts::ARCH {
+        "x86_64" => "amd64",
+        "aarch64" => "arm64",
+        "powerpc64" => "ppc64le",
+        architecture => architecture,
+    }
+}
+
+/// layout_path is `path` within `layout_dir`, as long as it doesn't lead
+/// outside of it
+fn layout_path(layout_dir: &Path, path: &str) -> Result<PathBuf, ImageStoreError> {
+    let stays_within = Path::new(path)
+        .components()
+        .all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+    if !stays_within {
+        return Err(ImageStoreError::InvalidArchivePathError {
+            path: path.to_string(),
+        });
+    }
+    Ok(layout_dir.join(path))
+}
+
+/// digest_hex returns the hex digits of a sha256 digest
+fn digest_hex(digest: &str) -> Result<&str, ImageStoreError> {
+    match digest.strip_prefix(SHA256_PREFIX) {
+        Some(hex) if hex.len() == 64 && hex.chars().all(|c| c.is_ascii_hexdigit()) => Ok(hex),
+        _ => Err(ImageStoreError::UnsupportedDigestError {
+            digest: digest.to_string(),
+        }),
+    }
+}
+
+fn verify_digest(expected: &str, actual: &str) -> Result<(), ImageStoreError> {
+    digest_hex(expected)?;
+    if !expected.eq_ignore_ascii_case(actual) {
+        return Err(ImageStoreError::DigestMismatchError {
+            digest: expected.to_string(),
+            actual: actual.to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// sha256_digest computes the sha256 digest of the file at `path`, with
+/// coreutils' sha256sum
+fn sha256_digest(path: &Path) -> Result<String, std::io::Error> {
+    let out = Command::new("sha256sum")
+        .stdin(File::open(path)?)
+        .output()?;
+    if !out.status.success() {
+        return Err(std::io::Error::other(format!(
+            "sha256sum failed: {}",
+            String::from_utf8_lossy(&out.stderr).trim()
+        )));
+    }
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    let hex = stdout.split_whitespace().next().unwrap_or_default();
+    Ok(format!("{}{}", SHA256_PREFIX, hex))
+}
+
+fn read_json<T>(path: &Path) -> Result<T, ImageStoreError>
+where
+    T: serde::de::DeserializeOwned,
+{
+    let contents = read_to_string(path).map_err(|source| ImageStoreError::ReadManifestError {
+        path: path.to_string_lossy().to_string(),
+        source,
+    })?;
+    serde_json::from_str(&contents).map_err(|source| ImageStoreError::ParseManifestError {
+        path: path.to_string_lossy().to_string(),
+        source,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::fs::read;
+    use tempfile::{tempdir, TempDir};
+
+    /// layer archives a directory holding `files`, as image layers are
+    fn layer(dir: &Path, files: &[(&str, &str)]) -> Vec<u8> {
+        let layer_dir = dir.join(rand_id());
+        create_dir_all(&layer_dir).unwrap();
+        for (path, contents) in files {
+            let path = layer_dir.join(path);
+            create_dir_all(path.parent().unwrap()).unwrap();
+            write(path, contents).unwrap();
+        }
+        let mut layer = vec![];
+        write_tarball(&layer_dir, &mut layer).unwrap();
+        remove_dir_all(&layer_dir).unwrap();
+        layer
+    }
+
+    /// config is an image config running `cmd`, with the layers `diff_ids`
+    fn config(cmd: &str, diff_ids: &[&String]) -> Vec<u8> {
+        json!({
+            "architecture": platform_architecture(),
+            "os": "linux",
+            "config": { "Cmd": [cmd] },
+            "rootfs": { "type": "layers", "diff_ids": diff_ids },
+        })
+        .to_string()
+        .into_bytes()
+    }
+
+    /// put_blob writes `data` into the blobs of the oci image layout
+    /// `layout_dir`, and returns its digest
+    fn put_blob(layout_dir: &Path, data: &[u8]) -> String {
+        let blobs_dir = layout_dir.join("blobs/sha256");
+        create_dir_all(&blobs_dir).unwrap();
+        let staged_path = blobs_dir.join(rand_id());
+        write(&staged_path, data).unwrap();
+        let digest = sha256_digest(&staged_path).unwrap();
+        rename(&staged_path, blobs_dir.join(digest_hex(&digest).unwrap())).unwrap();
+        digest
+    }
+
+    /// put_manifest writes an image manifest of `config` and `layers` into
+    /// the blobs of `layout_dir`, and returns its digest
+    fn put_manifest(layout_dir: &Path, config: &[u8], layers: &[&[u8]]) -> String {
+        let layers: Vec<_> = layers
+            .iter()
+            .map(|layer| json!({ "digest": put_blob(layout_dir, layer) }))
+            .collect();
+        let manifest = json!({
+            "schemaVersion": 2,
+            "config": { "digest": put_blob(layout_dir, config) },
+            "layers": layers,
+        });
+        put_blob(layout_dir, manifest.to_string().as_bytes())
+    }
+
+    /// oci_layout writes an oci image layout of an image named `reference`
+    /// with a layer holding `files`
+    fn oci_layout(dir: &Path, reference: &str, files: &[(&str, &str)]) -> PathBuf {
+        let layout_dir = dir.join(rand_id());
+        let layer = layer(dir, files);
+        let diff_id = put_blob(&layout_dir, &layer);
+        let manifest = put_manifest(&layout_dir, &config("run", &[&diff_id]), &[&layer]);
+        write_index(&layout_dir, json!({ "digest": manifest }), reference);
+        layout_dir
+    }
+
+    /// write_index writes the index of `layout_dir`, naming the image
+    /// `descriptor` points to `reference`
+    fn write_index(layout_dir: &Path, mut descriptor: serde_json::Value, reference: &str) {
+        descriptor["annotations"] = json!({ CONTAINERD_IMAGE_NAME: reference });
+        let index = json!({ "schemaVersion": 2, "manifests": [descriptor] });
+        write(layout_dir.join("index.json"), index.to_string()).unwrap();
+    }
+
+    /// docker_archive writes a `docker save` archive of an image tagged
+    /// `reference`, with a layer holding `files` that its manifest lists as
+    /// `layer_path`
+    fn docker_archive(
+        dir: &Path,
+        reference: &str,
+        files: &[(&str, &str)],
+        layer_path: &str,
+    ) -> PathBuf {
+        let archive_dir = dir.join(rand_id());
+        create_dir_all(archive_dir.join("layer")).unwrap();
+        let layer = layer(dir, files);
+        write(archive_dir.join("layer/layer.tar"), &layer).unwrap();
+        let diff_id = sha256_digest(&archive_dir.join("layer/layer.tar")).unwrap();
+        write(archive_dir.join("config.json"), config("run", &[&diff_id])).unwrap();
+        let manifest = json!([{
+            "Config": "config.json",
+            "RepoTags": [reference],
+            "Layers": [layer_path],
+        }]);
+        write(archive_dir.join("manifest.json"), manifest.to_string()).unwrap();
+        let archive_path = dir.join(format!("{}.tar", rand_id()));
+        let mut archive = File::create(&archive_path).unwrap();
+        write_tarball(&archive_dir, &mut archive).unwrap();
+        archive_path
+    }
+
+    fn new_store() -> (TempDir, ImageStore) {
+        let dir = tempdir().unwrap();
+        let image_store = ImageStore::new(dir.path().to_str().unwrap()).unwrap();
+        (dir, image_store)
+    }
+
+    fn import(image_store: &ImageStore, path: &Path) -> Result<Vec<Image>, ImageStoreError> {
+        image_store.import_images(path.to_str().unwrap(), None)
+    }
+
+    fn rootfs_file(image_store: &ImageStore, image: &Image, path: &str) -> String {
+        read_to_string(Path::new(&image_store.rootfs_dir(&image.id)).join(path)).unwrap()
+    }
+
+    #[test]
+    fn imports_oci_layouts() {
+        let (dir, image_store) = new_store();
+        let layout_dir = oci_layout(dir.path(), "example.com/app:1", &[("etc/app", "oci")]);
+
+        let images = import(&image_store, &layout_dir).unwrap();
+        assert_eq!(images.len(), 1);
+        assert_eq!(images[0].references, vec!["example.com/app:1"]);
+        assert_eq!(images[0].layers.len(), 1);
+        assert_eq!(images[0].config.cmd, vec!["run"]);
+        assert_eq!(rootfs_file(&image_store, &images[0], "etc/app"), "oci");
+        let found = image_store.find_image("example.com/app:1").unwrap();
+        assert_eq!(found.id, images[0].id);
+        assert_eq!(
+            image_store.find_image(images[0].short_id()).unwrap().id,
+            images[0].id
+        );
+    }
+
+    #[test]
+    fn imports_docker_archives() {
+        let (dir, image_store) = new_store();
+        let archive_path = docker_archive(
+            dir.path(),
+            "app:1",
+            &[("etc/app", "docker")],
+            "layer/layer.tar",
+        );
+
+        let images = image_store
+            .import_images(archive_path.to_str().unwrap(), Some("app"))
+            .unwrap();
+        assert_eq!(images.len(), 1);
+        assert_eq!(images[0].references, vec!["app:1", "app:latest"]);
+        assert_eq!(rootfs_file(&image_store, &images[0], "etc/app"), "docker");
+    }
+
+    #[test]
+    fn rejects_corrupted_blobs() {
+        let (dir, image_store) = new_store();
+        let layout_dir = oci_layout(dir.path(), "app:1", &[("etc/app", "oci")]);
+        let manifest: OciManifest = read_json(&layout_dir.join("index.json")).unwrap();
+        let manifest: OciManifest = read_json(
+            &oci_blob(&layout_dir, &manifest.manifests[0].digest)
+                .unwrap()
+                .path,
+        )
+        .unwrap();
+        let layer_path = oci_blob(&layout_dir, &manifest.layers[0].digest)
+            .unwrap()
+            .path;
+        let mut corrupted = read(&layer_path).unwrap();
+        corrupted[0] ^= 1;
+        write(&layer_path, corrupted).unwrap();
+        assert!(matches!(
+            import(&image_store, &layout_dir),
+            Err(ImageStoreError::DigestMismatchError { .. })
+        ));
+
+        // `docker save` layers are checked against the config's diff ids
+        let archive_dir = dir.path().join("docker");
+        create_dir_all(&archive_dir).unwrap();
+        let diff_id = format!("{}{}", SHA256_PREFIX, "0".repeat(64));
+        write(archive_dir.join("config.json"), config("run", &[&diff_id])).unwrap();
+        write(archive_dir.join("layer.tar"), layer(dir.path(), &[])).unwrap();
+        let manifest = json!([{ "Config": "config.json", "Layers": ["layer.tar"] }]);
+        write(archive_dir.join("manifest.json"), manifest.to_string()).unwrap();
+        assert!(matches!(
+            import(&image_store, &archive_dir),
+            Err(ImageStoreError::DigestMismatchError { .. })
+        ));
+        assert!(image_store.list_images().unwrap().is_empty());
+    }
+
+    #[test]
+    fn rejects_paths_outside_archives() {
+        let (dir, image_store) = new_store();
+        let archive_path = docker_archive(
+            dir.path(),
+            "app:1",
+            &[("etc/app", "docker")],
+            "../layer.tar",
+        );
+
+        assert!(matches!(
+            import(&image_store, &archive_path),
+            Err(ImageStoreError::InvalidArchivePathError { .. })
+        ));
+        assert!(image_store.list_images().unwrap().is_empty());
+    }
+
+    #[test]
+    fn reimports_move_references() {
+        let (dir, image_store) = new_store();
+        let first_layout = oci_layout(dir.path(), "app:1", &[("etc/app", "first")]);
+        let second_layout = oci_layout(dir.path(), "app:1", &[("etc/app", "second")]);
+
+        let first = import(&image_store, &first_layout).unwrap().remove(0);
+        let second = import(&image_store, &second_layout).unwrap().remove(0);
+        assert_ne!(first.id, second.id);
+        assert!(image_store
+            .read_image(&first.id)
+            .unwrap()
+            .references
+            .is_empty());
+        assert_eq!(image_store.find_image("app:1").unwrap().id, second.id);
+
+        // importing an image again gives the reference back without
+        // unpacking it again
+        let reimported = import(&image_store, &first_layout).unwrap().remove(0);
+        assert_eq!(reimported.id, first.id);
+        assert_eq!(reimported.created_at, first.created_at);
+        assert_eq!(image_store.find_image("app:1").unwrap().id, first.id);
+        assert!(image_store
+            .read_image(&second.id)
+            .unwrap()
+            .references
+            .is_empty());
+        assert_eq!(image_store.list_images().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn removing_images_removes_unused_blobs() {
+        let (dir, image_store) = new_store();
+        let base = layer(dir.path(), &[("etc/base", "base")]);
+        let extra = layer(dir.path(), &[("etc/extra", "extra")]);
+        let layout_dir = dir.path().join("layout");
+        let base_id = put_blob(&layout_dir, &base);
+        let extra_id = put_blob(&layout_dir, &extra);
+        let base_manifest = put_manifest(&layout_dir, &config("base", &[&base_id]), &[&base]);
+        let both_manifest = put_manifest(
+            &layout_dir,
+            &config("both", &[&base_id, &extra_id]),
+            &[&base, &extra],
+        );
+        write_index(&layout_dir, json!({ "digest": base_manifest }), "base:1");
+        let base_image = import(&image_store, &layout_dir).unwrap().remove(0);
+        write_index(&layout_dir, json!({ "digest": both_manifest }), "both:1");
+        let both_image = import(&image_store, &layout_dir).unwrap().remove(0);
+        assert_eq!(rootfs_file(&image_store, &both_image, "etc/base"), "base");
+        assert_eq!(rootfs_file(&image_store, &both_image, "etc/extra"), "extra");
+
+        let blobs = || {
+            let mut blobs: Vec<_> = read_dir(image_store.blobs_dir())
+                .unwrap()
+                .map(|blob| {
+                    format!(
+                        "{}{}",
+                        SHA256_PREFIX,
+                        blob.unwrap().file_name().to_string_lossy()
+                    )
+                })
+                .collect();
+            blobs.sort();
+            blobs
+        };
+        assert_eq!(blobs().len(), 4);
+        image_store.remove_image(&base_image.id).unwrap();
+        let mut used_blobs = vec![both_image.id.clone(), base_id, extra_id];
+        used_blobs.sort();
+        assert_eq!(blobs(), used_blobs);
+        assert!(!Path::new(&image_store.image_dir(&base_image.id)).exists());
+        assert!(matches!(
+            image_store.find_image("base:1"),
+            Err(ImageStoreError::ImageNotFoundError { .. })
+        ));
+
+        image_store.remove_image(&both_image.id).unwrap();
+        assert!(blobs().is_empty());
+        assert!(matches!(
+            image_store.remove_image(&both_image.id),
+            Err(ImageStoreError::ImageNotFoundError { .. })
+        ));
+    }
+
+    #[test]
+    fn resolves_platform_indexes() {
+        let (dir, image_store) = new_store();
+        let layout_dir = dir.path().join("layout");
+        let native = layer(dir.path(), &[("etc/arch", "native")]);
+        let foreign = layer(dir.path(), &[("etc/arch", "foreign")]);
+        let native_id = put_blob(&layout_dir, &native);
+        let foreign_id = put_blob(&layout_dir, &foreign);
+        let native_manifest =
+            put_manifest(&layout_dir, &config("native", &[&native_id]), &[&native]);
+        let foreign_manifest =
+            put_manifest(&layout_dir, &config("foreign", &[&foreign_id]), &[&foreign]);
+        let foreign_platform = json!({ "os": "linux", "architecture": "foreign" });
+        let native_platform = json!({ "os": "linux", "architecture": platform_architecture() });
+        let put_index = |manifests: serde_json::Value| {
+            let index = json!({ "schemaVersion": 2, "manifests": manifests });
+            put_blob(&layout_dir, index.to_string().as_bytes())
+        };
+
+        let index = put_index(json!([
+            { "digest": foreign_manifest, "platform": foreign_platform },
+            { "digest": native_manifest, "platform": native_platform },
+        ]));
+        write_index(&layout_dir, json!({ "digest": index }), "app:1");
+        let images = import(&image_store, &layout_dir).unwrap();
+        assert_eq!(images.len(), 1);
+        assert_eq!(images[0].config.cmd, vec!["native"]);
+        assert_eq!(rootfs_file(&image_store, &images[0], "etc/arch"), "native");
+
+        // an index with no image for this platform has no images to import
+        let index =
+            put_index(json!([{ "digest": foreign_manifest, "platform": foreign_platform }]));
+        write_index(&layout_dir, json!({ "digest": index }), "app:2");
+        assert!(matches!(
+            import(&image_store, &layout_dir),
+            Err(ImageStoreError::NoImagesError { .. })
+        ));
+    }
+}
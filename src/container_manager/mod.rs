@@ -1,23 +1,86 @@
 mod container_map;
-mod container_runtime;
+pub mod container_runtime;
 mod container_store;
+mod image_store;
+mod user;
 
-use crate::container::{new as new_container, rand_id, Container, Status, ID};
+pub use container_store::tarball;
+
+use crate::attach::Frame;
+use crate::console::{self, ConsoleSocket};
+use crate::container::{
+    self, new as new_container, rand_id, Container, ContainerFilter, Mount, Resources,
+    RestartPolicy, Status, DEFAULT_STOP_SIGNAL, DEFAULT_STOP_TIMEOUT, ID,
+};
+use crate::container_log::{LogFilter, LogLine, LogReader};
+use crate::image::{self, Image, ImageConfig};
+use crate::volume::{self, Volume};
 use container_map::{ContainerMap, ContainerMapError};
+use container_runtime::spec::{self, IdMappings, LinuxResources, Spec, User};
 use container_runtime::{
-    ContainerRuntime, ContainerRuntimeError, RuntimeCreateOptions, RuntimeSpecOptions,
+    exit_code, parse_signal, ContainerRuntimeError, ContainerStats, ExecOutput, RuncMethod,
+    Runtime, RuntimeCreateOptions, RuntimeSpecOptions,
 };
-use container_store::{ContainerStore, ContainerStoreError};
-use log::error;
+use container_store::tarball::{extract_archive, write_tarball, write_tarball_entry};
+use container_store::{is_privileged, ContainerStore, ContainerStoreError, CONTAINER_PIDFILE};
+use image_store::{ImageStore, ImageStoreError};
+use log::{error, warn};
+use std::collections::HashMap;
 use std::error::Error;
+use std::ffi::OsString;
 use std::fmt;
-use std::time::SystemTime;
+use std::fs::{read_link, symlink_metadata, File};
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::Shutdown;
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::UnixStream;
+use std::path::{Component, Path, PathBuf};
+use std::process::Child;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime};
+use user::{resolve_user, UserError};
+
+// how often a followed container log is checked for new output
+const LOGS_FOLLOW_INTERVAL: Duration = Duration::from_millis(250);
+// how often the console socket of an interactive exec is checked for the
+// process' terminal
+const CONSOLE_POLL_INTERVAL: Duration = Duration::from_millis(10);
+// how often the resource usage of a container is sampled for its stats
+const STATS_INTERVAL: Duration = Duration::from_secs(1);
+// how often a stopping container is checked for having stopped
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(100);
+// how long a container is given to exit after it is sent SIGKILL, which it
+// can't ignore but may take a while to die from
+const KILL_TIMEOUT: Duration = Duration::from_secs(10);
+// how long to wait before first restarting an exited container, doubled on
+// every restart of a container that keeps exiting soon after starting
+const RESTART_BACKOFF_INITIAL: Duration = Duration::from_secs(1);
+const RESTART_BACKOFF_MAX: Duration = Duration::from_secs(60);
+// a container that ran at least this long restarts without backing off
+const RESTART_BACKOFF_RESET: Duration = Duration::from_secs(10);
+// how many symlinks resolving a path in a container may follow, as Linux
+// allows
+const MAX_SYMLINKS: usize = 40;
 
 #[derive(Debug)]
 pub struct ContainerManager {
     container_map: ContainerMap,
     container_store: ContainerStore,
-    container_runtime: ContainerRuntime,
+    container_runtime: Box<dyn Runtime>,
+    // how long each container waited before its last restart
+    restart_delays: Mutex<HashMap<ID, Duration>>,
+    // held by each container's restarts, and while stopping or deleting it,
+    // so a container isn't restarted while it is being stopped or deleted
+    container_locks: Mutex<HashMap<ID, Arc<Mutex<()>>>>,
+    // held while checking whether a volume is in use and acting on it, so
+    // a volume isn't removed while a container mounting it is created
+    volumes_lock: Mutex<()>,
+    image_store: ImageStore,
+    // held while checking whether an image is in use and acting on it, so
+    // an image isn't removed while a container is created from it
+    images_lock: Mutex<()>,
 }
 
 pub struct ContainerOptions {
@@ -25,6 +88,69 @@ pub struct ContainerOptions {
     pub command: String,
     pub args: Vec<String>,
     pub rootfs_path: String,
+    // reference or id of the image to create the container from instead of
+    // rootfs_path. the image's entrypoint, cmd, env, working dir and user
+    // apply where the options leave them unset
+    pub image: String,
+    // allocate a pseudo-terminal for the container process
+    pub tty: bool,
+    // signal to stop the container with, SIGTERM if empty
+    pub stop_signal: String,
+    // seconds to wait for the container to stop before killing it, 10 if None
+    pub stop_timeout: Option<u64>,
+    // whether to restart the container after it exits, `no` if empty
+    pub restart_policy: String,
+    // KEY=VALUE pairs added to, or overriding, the default environment
+    pub env: Vec<String>,
+    // working directory of the container process, `/` if empty
+    pub cwd: String,
+    // user[:group] to run the container process as, root if empty
+    pub user: String,
+    // host paths and volumes to mount into the container. volumes that
+    // don't exist yet are created
+    pub mounts: Vec<Mount>,
+    // cgroup limits of the container
+    pub resources: Resources,
+    // key/value pairs describing the container
+    pub labels: HashMap<String, String>,
+    // OCI annotations to add to the container's runtime spec
+    pub annotations: HashMap<String, String>,
+}
+
+pub struct StopOptions {
+    // signal to stop the container with, the container's stop signal if empty
+    pub signal: String,
+    // seconds to wait for the container to stop before killing it, the
+    // container's stop timeout if None
+    pub timeout: Option<u64>,
+}
+
+pub struct LogsOptions {
+    // keep returning new output until the container stops
+    pub follow: bool,
+    // only return the last `tail` lines, all lines if None
+    pub tail: Option<usize>,
+    pub filter: LogFilter,
+}
+
+pub struct ExecOptions {
+    pub command: String,
+    pub args: Vec<String>,
+    // KEY=VALUE pairs added to, or overriding, the container's environment
+    pub env: Vec<String>,
+    // working directory of the process, the container's if empty
+    pub cwd: String,
+    // user[:group] to run the process as, the container's user if empty
+    pub user: String,
+}
+
+/// Stats is the resource usage of a container over an interval
+pub struct Stats {
+    // when the usage was read, at the end of the interval
+    pub read_at: SystemTime,
+    // CPU time consumed over the interval, as a percentage of one CPU
+    pub cpu_percent: f64,
+    pub usage: ContainerStats,
 }
 
 struct InternalCreateContainerError {
@@ -35,24 +161,180 @@ struct InternalCreateContainerError {
 #[derive(Debug)]
 pub enum ContainerManagerError {
     // represents an error creating the container store
-    CreateContainerStoreError { source: ContainerStoreError },
+    CreateContainerStoreError {
+        source: ContainerStoreError,
+    },
     // represents an error reloading the container manager
-    ReloadError { source: ContainerStoreError },
+    ReloadError {
+        source: ContainerStoreError,
+    },
     // represents an error from the container store
-    ContainerStoreError { source: ContainerStoreError },
+    ContainerStoreError {
+        source: ContainerStoreError,
+    },
     // represents an error when a container is not found
-    ContainerNotFoundError { container_id: ID },
+    ContainerNotFoundError {
+        container_id: ID,
+    },
     // represents an error from the container map
-    ContainerMapError { source: ContainerMapError },
+    ContainerMapError {
+        source: ContainerMapError,
+    },
     // represents an error from the container runtime
-    ContainerRuntimeError { source: ContainerRuntimeError },
+    ContainerRuntimeError {
+        source: ContainerRuntimeError,
+    },
     // represents an error trying to create a container that's not in a created state
-    StartContainerNotInCreatedStateError { container_id: ID },
+    StartContainerNotInCreatedStateError {
+        container_id: ID,
+    },
     // represents an error trying to stop a container that's not in a running state
-    StopContainerNotInRunningStateError { container_id: ID },
+    StopContainerNotInRunningStateError {
+        container_id: ID,
+    },
+    // represents an error trying to signal a container that's not in a running (or paused)
+    // state
+    KillContainerNotInRunningStateError {
+        container_id: ID,
+    },
+    // represents a container that hasn't exited after being sent SIGKILL
+    KillContainerTimeoutError {
+        container_id: ID,
+    },
+    // represents a container name that isn't valid
+    InvalidContainerNameError {
+        name: String,
+    },
+    // represents a label key that isn't valid
+    InvalidLabelKeyError {
+        key: String,
+    },
+    // represents an annotation with an empty key
+    InvalidAnnotationKeyError,
+    // represents an error parsing a signal to send to a container
+    InvalidSignalError {
+        signal: String,
+    },
+    // represents an error parsing a container's restart policy
+    InvalidRestartPolicyError {
+        policy: String,
+    },
+    // represents an error trying to pause a container that's not in a running state
+    PauseContainerNotInRunningStateError {
+        container_id: ID,
+    },
+    // represents an error trying to resume a container that's not in a paused state
+    ResumeContainerNotInPausedStateError {
+        container_id: ID,
+    },
     // represents an error trying to delete a container that's not in a deleteable (created or
     // stopped) state
-    DeleteContainerNotInDeleteableStateError { container_id: ID },
+    DeleteContainerNotInDeleteableStateError {
+        container_id: ID,
+    },
+    // represents an error reading a container's log
+    ReadContainerLogError {
+        container_id: ID,
+        source: std::io::Error,
+    },
+    // represents an error trying to exec in a container that's not in a running state
+    ExecContainerNotInRunningStateError {
+        container_id: ID,
+    },
+    // represents an error resolving the user to run a process as
+    ResolveUserError {
+        source: UserError,
+    },
+    // represents an error trying to attach to a container that's not in an attachable (created
+    // or running) state
+    AttachContainerNotInAttachableStateError {
+        container_id: ID,
+    },
+    // represents an error connecting to the attach socket served by a container's shim
+    ConnectAttachSocketError {
+        container_id: ID,
+        source: std::io::Error,
+    },
+    // represents an error receiving the terminal of an interactive exec
+    ExecTerminalError {
+        container_id: ID,
+        source: std::io::Error,
+    },
+    // represents an error getting stats of a container that isn't running or paused
+    StatsContainerNotInRunningStateError {
+        container_id: ID,
+    },
+    // represents an error updating a container that isn't created, running or paused
+    UpdateContainerNotInUpdateableStateError {
+        container_id: ID,
+    },
+    // represents a volume name that isn't valid
+    InvalidVolumeNameError {
+        name: String,
+    },
+    // represents an error removing a volume that a container mounts
+    VolumeInUseError {
+        name: String,
+        container_id: ID,
+    },
+    // represents a host path to mount that doesn't exist
+    MountSourceNotFoundError {
+        source_path: String,
+    },
+    // represents an error from the image store
+    ImageStoreError {
+        source: ImageStoreError,
+    },
+    // represents an image reference that isn't valid
+    InvalidImageReferenceError {
+        reference: String,
+    },
+    // represents an error removing an image that a container was created from
+    ImageInUseError {
+        image: String,
+        container_id: ID,
+    },
+    // represents an error creating a container without a command from an
+    // image without an entrypoint or cmd
+    MissingCommandError {
+        image: String,
+    },
+    // represents an error trying to commit a container that's not in a committable (created or
+    // stopped) state
+    CommitContainerNotInCommittableStateError {
+        container_id: ID,
+    },
+    // represents an error confining a copy or export to a container's filesystem
+    ContainerRootError {
+        container_id: ID,
+        source: std::io::Error,
+    },
+    // represents an error archiving a container's rootfs to export it
+    ExportContainerError {
+        container_id: ID,
+        source: std::io::Error,
+    },
+    // represents a path to copy from or to that doesn't exist in a container
+    ContainerPathNotFoundError {
+        container_id: ID,
+        path: String,
+    },
+    // represents an error trying to copy the root directory out of a container
+    CopyContainerRootError {
+        container_id: ID,
+    },
+    // represents an error archiving a path in a container to copy it out
+    CopyFromContainerError {
+        container_id: ID,
+        path: String,
+        source: std::io::Error,
+    },
+    // represents an error extracting an archive into a path in a container
+    CopyToContainerError {
+        container_id: ID,
+        path: String,
+        source: std::io::Error,
+    },
 }
 
 impl fmt::Display for ContainerManagerError {
@@ -76,11 +358,178 @@ impl fmt::Display for ContainerManagerError {
                 "container with container_id {} is not in a running state",
                 container_id
             ),
+            Self::KillContainerNotInRunningStateError { ref container_id } => write!(
+                f,
+                "container with container_id {} is not in a running state",
+                container_id
+            ),
+            Self::KillContainerTimeoutError { ref container_id } => write!(
+                f,
+                "container with container_id {} did not exit after being killed",
+                container_id
+            ),
+            Self::InvalidContainerNameError { ref name } => write!(
+                f,
+                "invalid container name `{}`, expected [a-zA-Z0-9][a-zA-Z0-9_.-]*",
+                name
+            ),
+            Self::InvalidLabelKeyError { ref key } => write!(
+                f,
+                "invalid label key `{}`, expected [a-zA-Z0-9][a-zA-Z0-9_./-]*",
+                key
+            ),
+            Self::InvalidAnnotationKeyError => write!(f, "invalid annotation with an empty key"),
+            Self::InvalidSignalError { ref signal } => write!(f, "invalid signal `{}`", signal),
+            Self::InvalidRestartPolicyError { ref policy } => write!(
+                f,
+                "invalid restart policy `{}`, expected no, on-failure[:max], always or \
+                 unless-stopped",
+                policy
+            ),
+            Self::PauseContainerNotInRunningStateError { ref container_id } => write!(
+                f,
+                "container with container_id {} is not in a running state",
+                container_id
+            ),
+            Self::ResumeContainerNotInPausedStateError { ref container_id } => write!(
+                f,
+                "container with container_id {} is not in a paused state",
+                container_id
+            ),
             Self::DeleteContainerNotInDeleteableStateError { ref container_id } => write!(
                 f,
                 "container with container_id {} is not in a deleteable (created or stopped) state",
                 container_id
             ),
+            Self::ReadContainerLogError {
+                ref container_id, ..
+            } => write!(
+                f,
+                "failed to read log of container with container_id {}",
+                container_id
+            ),
+            Self::ExecContainerNotInRunningStateError { ref container_id } => write!(
+                f,
+                "container with container_id {} is not in a running state",
+                container_id
+            ),
+            Self::ResolveUserError { ref source } => source.fmt(f),
+            Self::AttachContainerNotInAttachableStateError { ref container_id } => write!(
+                f,
+                "container with container_id {} is not in an attachable (created or running) state",
+                container_id
+            ),
+            Self::ConnectAttachSocketError {
+                ref container_id, ..
+            } => write!(
+                f,
+                "failed to connect to attach socket of container with container_id {}",
+                container_id
+            ),
+            Self::ExecTerminalError {
+                ref container_id, ..
+            } => write!(
+                f,
+                "failed to receive terminal of exec in container with container_id {}",
+                container_id
+            ),
+            Self::StatsContainerNotInRunningStateError { ref container_id } => write!(
+                f,
+                "container with container_id {} is not in a running or paused state",
+                container_id
+            ),
+            Self::UpdateContainerNotInUpdateableStateError { ref container_id } => write!(
+                f,
+                "container with container_id {} is not in an updateable (created, running or \
+                 paused) state",
+                container_id
+            ),
+            Self::InvalidVolumeNameError { ref name } => write!(
+                f,
+                "invalid volume name `{}`, expected [a-zA-Z0-9][a-zA-Z0-9_.-]*",
+                name
+            ),
+            Self::VolumeInUseError {
+                ref name,
+                ref container_id,
+            } => write!(
+                f,
+                "volume with name {} is in use by container with container_id {}",
+                name, container_id
+            ),
+            Self::MountSourceNotFoundError { ref source_path } => {
+                write!(f, "mount source {} does not exist", source_path)
+            }
+            Self::ImageStoreError { ref source } => source.fmt(f),
+            Self::InvalidImageReferenceError { ref reference } => write!(
+                f,
+                "invalid image reference `{}`, expected NAME[:TAG]",
+                reference
+            ),
+            Self::ImageInUseError {
+                ref image,
+                ref container_id,
+            } => write!(
+                f,
+                "image {} is in use by container with container_id {}",
+                image, container_id
+            ),
+            Self::MissingCommandError { ref image } => write!(
+                f,
+                "no command given, and image {} has no entrypoint or cmd",
+                image
+            ),
+            Self::CommitContainerNotInCommittableStateError { ref container_id } => write!(
+                f,
+                "container with container_id {} is not in a committable (created or stopped) state",
+                container_id
+            ),
+            Self::ExportContainerError {
+                ref container_id, ..
+            } => write!(
+                f,
+                "failed to export rootfs of container with container_id {}",
+                container_id
+            ),
+            Self::ContainerPathNotFoundError {
+                ref container_id,
+                ref path,
+            } => write!(
+                f,
+                "path {} not found in container with container_id {}",
+                path, container_id
+            ),
+            Self::ContainerRootError {
+                ref container_id, ..
+            } => write!(
+                f,
+                "failed to enter the filesystem of container with container_id {}",
+                container_id
+            ),
+            Self::CopyContainerRootError { ref container_id } => write!(
+                f,
+                "can't copy the root directory of container with container_id {}, export it \
+                 instead",
+                container_id
+            ),
+            Self::CopyFromContainerError {
+                ref container_id,
+                ref path,
+                ..
+            } => write!(
+                f,
+                "failed to copy {} from container with container_id {}",
+                path, container_id
+            ),
+            Self::CopyToContainerError {
+                ref container_id,
+                ref path,
+                ..
+            } => write!(
+                f,
+                "failed to copy to {} in container with container_id {}",
+                path, container_id
+            ),
         }
     }
 }
@@ -96,7 +545,38 @@ impl Error for ContainerManagerError {
             Self::ContainerRuntimeError { ref source } => source.source(),
             Self::StartContainerNotInCreatedStateError { .. } => None,
             Self::StopContainerNotInRunningStateError { .. } => None,
+            Self::KillContainerNotInRunningStateError { .. } => None,
+            Self::KillContainerTimeoutError { .. } => None,
+            Self::InvalidContainerNameError { .. } => None,
+            Self::InvalidLabelKeyError { .. } => None,
+            Self::InvalidAnnotationKeyError => None,
+            Self::InvalidSignalError { .. } => None,
+            Self::InvalidRestartPolicyError { .. } => None,
+            Self::PauseContainerNotInRunningStateError { .. } => None,
+            Self::ResumeContainerNotInPausedStateError { .. } => None,
             Self::DeleteContainerNotInDeleteableStateError { .. } => None,
+            Self::ReadContainerLogError { ref source, .. } => Some(source),
+            Self::ExecContainerNotInRunningStateError { .. } => None,
+            Self::ResolveUserError { ref source } => source.source(),
+            Self::AttachContainerNotInAttachableStateError { .. } => None,
+            Self::ConnectAttachSocketError { ref source, .. } => Some(source),
+            Self::ExecTerminalError { ref source, .. } => Some(source),
+            Self::StatsContainerNotInRunningStateError { .. } => None,
+            Self::UpdateContainerNotInUpdateableStateError { .. } => None,
+            Self::InvalidVolumeNameError { .. } => None,
+            Self::VolumeInUseError { .. } => None,
+            Self::MountSourceNotFoundError { .. } => None,
+            Self::ImageStoreError { ref source } => source.source(),
+            Self::InvalidImageReferenceError { .. } => None,
+            Self::ImageInUseError { .. } => None,
+            Self::MissingCommandError { .. } => None,
+            Self::CommitContainerNotInCommittableStateError { .. } => None,
+            Self::ContainerRootError { ref source, .. } => Some(source),
+            Self::ExportContainerError { ref source, .. } => Some(source),
+            Self::ContainerPathNotFoundError { .. } => None,
+            Self::CopyContainerRootError { .. } => None,
+            Self::CopyFromContainerError { ref source, .. } => Some(source),
+            Self::CopyToContainerError { ref source, .. } => Some(source),
         }
     }
 }
@@ -125,9 +605,19 @@ impl From<ContainerRuntimeError> for ContainerManagerError {
 
 impl From<ContainerStoreError> for ContainerManagerError {
     fn from(err: ContainerStoreError) -> ContainerManagerError {
-        match err {
-            _ => ContainerManagerError::ContainerStoreError { source: err },
-        }
+        ContainerManagerError::ContainerStoreError { source: err }
+    }
+}
+
+impl From<ImageStoreError> for ContainerManagerError {
+    fn from(err: ImageStoreError) -> ContainerManagerError {
+        ContainerManagerError::ImageStoreError { source: err }
+    }
+}
+
+impl From<UserError> for ContainerManagerError {
+    fn from(err: UserError) -> ContainerManagerError {
+        ContainerManagerError::ResolveUserError { source: err }
     }
 }
 
@@ -136,13 +626,19 @@ impl From<ContainerStoreError> for ContainerManagerError {
 impl ContainerManager {
     pub fn new(
         root_dir: String,
-        runtime_path: String,
+        container_runtime: Box<dyn Runtime>,
     ) -> Result<ContainerManager, ContainerManagerError> {
+        let image_store = ImageStore::new(&root_dir)?;
         let container_store = ContainerStore::new(root_dir)?;
         let container_manager = ContainerManager {
             container_map: ContainerMap::new(),
             container_store,
-            container_runtime: ContainerRuntime::new(runtime_path),
+            container_runtime,
+            restart_delays: Mutex::new(HashMap::new()),
+            container_locks: Mutex::new(HashMap::new()),
+            volumes_lock: Mutex::new(()),
+            image_store,
+            images_lock: Mutex::new(()),
         };
         container_manager.reload()?;
         Ok(container_manager)
@@ -152,7 +648,9 @@ impl ContainerManager {
     /// - reads all container state files off disk
     ///     - if any of these state files fail to be parsed, we assume the
     ///       container is corrupted and remove it
-    /// - adds the container to the in-memory store
+    /// - adds the container to the in-memory store. containers restarting
+    ///   `Always` are restarted again even if they were stopped
+    /// - mounts overlay rootfs' that were unmounted, as by a reboot
     /// - syncs the container state with the container runtime
     fn reload(&self) -> Result<(), ContainerManagerError> {
         // get container ids off disk
@@ -162,20 +660,34 @@ impl ContainerManager {
             .map_err(|source| ContainerManagerError::ReloadError { source })?;
         for container_id in container_ids {
             // parse container state file
-            let container = match self.container_store.read_container_state(&container_id) {
+            let mut container = match self.container_store.read_container_state(&container_id) {
                 Ok(container) => container,
                 Err(err) => {
                     error!(
                         "unable to parse state of container `{}`, err: `{}`. Removing container.",
                         container_id, err
                     );
-                    self.container_store
-                        .remove_container_directory(&container_id);
+                    self.remove_container_directory(&container_id);
                     continue;
                 }
             };
-            // add container to in-memory store
-            match self.container_map.add(container) {
+            if container.restart_policy == RestartPolicy::Always {
+                container.stopped_by_user = false;
+            }
+            // add container to in-memory store. containers created before
+            // names were unique may share a name, which only the first takes
+            let added = match self.container_map.add(container.clone()) {
+                Err(ContainerMapError::ContainerNameInUseError { name, container_id }) => {
+                    warn!(
+                        "container `{}` is named `{}` like container `{}`, it can only be found \
+                         by its id",
+                        container.id, name, container_id
+                    );
+                    self.container_map.add_unnamed(container)
+                }
+                added => added,
+            };
+            match added {
                 Ok(_) => (),
                 Err(err) => {
                     error!(
@@ -185,6 +697,14 @@ impl ContainerManager {
                     continue;
                 }
             }
+            // mount the container rootfs again if it's an overlay that isn't
+            // mounted anymore
+            if let Err(err) = self.container_store.remount_container_rootfs(&container_id) {
+                error!(
+                    "unable to mount rootfs of container `{}`, err: `{:?}`. Continuing.",
+                    container_id, err
+                );
+            }
             // sync container with container runtime
             match self.sync_container_status_with_runtime(&container_id) {
                 Ok(_) => (),
@@ -193,8 +713,7 @@ impl ContainerManager {
                         "unable to sync state of container `{}`, err: `{:?}`. Removing container.",
                         container_id, err
                     );
-                    self.container_store
-                        .remove_container_directory(&container_id);
+                    self.remove_container_directory(&container_id);
                     self.container_map.remove(&container_id);
                     continue;
                 }
@@ -203,10 +722,43 @@ impl ContainerManager {
         Ok(())
     }
 
+    /// resolve_container_id returns the id of the container `reference`
+    /// refers to, by its id, its name or an unambiguous prefix of its id.
+    /// every method taking a container_id accepts any of them
+    fn resolve_container_id(&self, reference: &str) -> Result<ID, ContainerManagerError> {
+        self.container_map
+            .resolve(reference)
+            .map_err(|err| err.into())
+    }
+
+    /// container_lock returns the lock of a container's restarts, stops and
+    /// deletion
+    fn container_lock(&self, container_id: &ID) -> Arc<Mutex<()>> {
+        self.container_locks
+            .lock()
+            .unwrap()
+            .entry(container_id.clone())
+            .or_default()
+            .clone()
+    }
+
     fn rollback_container_create(&self, container_id: &ID) {
-        self.container_map.remove(&container_id);
-        self.container_store
-            .remove_container_directory(&container_id)
+        self.container_map.remove(container_id);
+        self.remove_container_directory(container_id);
+    }
+
+    /// remove_container_directory removes a container directory on disk where
+    /// there's no caller to report failing to, logging it instead
+    fn remove_container_directory(&self, container_id: &ID) {
+        if let Err(err) = self
+            .container_store
+            .remove_container_directory(container_id)
+        {
+            error!(
+                "unable to remove directory of container `{}`, err: `{}`",
+                container_id, err
+            );
+        }
     }
 
     /// create_container does the following:
@@ -217,31 +769,131 @@ impl ContainerManager {
         &self,
         opts: ContainerOptions,
     ) -> Result<String, ContainerManagerError> {
-        self.create_container_helper(opts).or_else(|err| {
+        self.create_container_helper(opts).map_err(|err| {
             // best effort rollback
             self.rollback_container_create(&err.container_id);
-            return Err(err.source);
+            err.source
         })
     }
 
     /// create_container_helper does the following:
     /// - generate container id
-    /// - create and store the in-memory container structure
-    /// - create the container directory on disk
+    /// - validate the container's name, labels, annotations, stop signal,
+    ///   restart policy and mounts
+    /// - resolve the image the container is created from, if any, and apply
+    ///   its config where the options leave it unset
+    /// - store the in-memory container structure, then create the volumes the
+    ///   container mounts that don't exist yet
+    /// - create the container directory, including its logs directory, on disk
     /// - create the container bundle:
-    ///     - copy the rootfs into the container bundle
-    ///     - generate the runc spec for the container
-    /// - create the container (runc exec)
+    ///     - overlay, copy or extract the rootfs into the container bundle
+    ///     - resolve the container's user against the rootfs
+    ///     - generate the runc spec for the container, including its mounts
+    /// - create the container (runc create, supervised by the shim, which
+    ///   receives the container's terminal if it has one)
     /// - update container status, write those to disk
     fn create_container_helper(
         &self,
-        opts: ContainerOptions,
+        mut opts: ContainerOptions,
     ) -> Result<String, InternalCreateContainerError> {
         // generate container id
         let container_id = rand_id();
+        // validate name, which must also be unique once the container is
+        // stored
+        if !opts.name.is_empty() && !container::is_valid_name(&opts.name) {
+            return Err(InternalCreateContainerError {
+                container_id,
+                source: ContainerManagerError::InvalidContainerNameError { name: opts.name },
+            });
+        }
+        // validate labels and annotations
+        validate_labels(opts.labels.keys())
+            .and_then(|()| validate_annotations(&opts.annotations))
+            .map_err(|source| InternalCreateContainerError {
+                container_id: container_id.clone(),
+                source,
+            })?;
+        // validate stop signal
+        let stop_signal = if opts.stop_signal.is_empty() {
+            DEFAULT_STOP_SIGNAL.to_string()
+        } else {
+            opts.stop_signal.clone()
+        };
+        validate_signal(&stop_signal).map_err(|source| InternalCreateContainerError {
+            container_id: container_id.clone(),
+            source,
+        })?;
+        // validate restart policy
+        let restart_policy = if opts.restart_policy.is_empty() {
+            RestartPolicy::No
+        } else {
+            RestartPolicy::parse(&opts.restart_policy).ok_or_else(|| {
+                InternalCreateContainerError {
+                    container_id: container_id.clone(),
+                    source: ContainerManagerError::InvalidRestartPolicyError {
+                        policy: opts.restart_policy.clone(),
+                    },
+                }
+            })?
+        };
+        // resolve image, it can't be removed until the container is stored
+        let images_lock = self.images_lock.lock().unwrap();
+        let image = if opts.image.is_empty() {
+            None
+        } else {
+            let image = self.image_store.find_image(&opts.image).map_err(|err| {
+                InternalCreateContainerError {
+                    container_id: container_id.clone(),
+                    source: err.into(),
+                }
+            })?;
+            apply_image_config(&mut opts, &image.config);
+            if opts.command.is_empty() {
+                return Err(InternalCreateContainerError {
+                    container_id: container_id.clone(),
+                    source: ContainerManagerError::MissingCommandError {
+                        image: opts.image.clone(),
+                    },
+                });
+            }
+            Some(image)
+        };
+        let rootfs_path = match image {
+            Some(ref image) => self.image_store.rootfs_dir(&image.id),
+            None => opts.rootfs_path.clone(),
+        };
         // create & store in-memory container structure
-        let container: Container =
-            new_container(&container_id, &opts.name, &opts.command, &opts.args);
+        let mut container: Container = new_container(
+            &container_id,
+            &opts.name,
+            &opts.command,
+            &opts.args,
+            opts.tty,
+            &stop_signal,
+            opts.stop_timeout.unwrap_or(DEFAULT_STOP_TIMEOUT),
+        );
+        container.restart_policy = restart_policy;
+        container.mounts = opts.mounts;
+        container.resources = opts.resources.clone();
+        container.image = image.map(|image| image.id).unwrap_or_default();
+        container.labels = opts.labels;
+        container.annotations = opts.annotations.clone();
+        // validate mounts, and resolve them to the paths to mount
+        let spec_mounts =
+            self.spec_mounts(&container.mounts)
+                .map_err(|source| InternalCreateContainerError {
+                    container_id: container_id.clone(),
+                    source,
+                })?;
+        let volumes: Vec<String> = container
+            .mounts
+            .iter()
+            .filter_map(|mount| mount.volume())
+            .map(|name| name.to_string())
+            .collect();
+        // store the container before creating its volumes, so a name conflict
+        // doesn't leave volumes behind
+        let volumes_lock = self.volumes_lock.lock().unwrap();
         let container_id =
             self.container_map
                 .add(container)
@@ -249,6 +901,19 @@ impl ContainerManager {
                     container_id: container_id.clone(),
                     source: err.into(),
                 })?;
+        for name in &volumes {
+            match self.container_store.create_volume(&volume::new(name)) {
+                Ok(()) | Err(ContainerStoreError::VolumeAlreadyExistsError { .. }) => (),
+                Err(err) => {
+                    return Err(InternalCreateContainerError {
+                        container_id: container_id.clone(),
+                        source: err.into(),
+                    })
+                }
+            }
+        }
+        drop(volumes_lock);
+        drop(images_lock);
         // create container directory on disk
         self.container_store
             .create_container_directory(&container_id)
@@ -259,14 +924,41 @@ impl ContainerManager {
         // create container bundle on disk
         let container_bundle_dir = self
             .container_store
-            .create_container_bundle(&container_id, &opts.rootfs_path)
+            .create_container_bundle(&container_id, &rootfs_path)
             .map_err(|err| InternalCreateContainerError {
                 container_id: container_id.clone(),
                 source: err.into(),
             })?;
+        // resolve container user against the rootfs and the ids the
+        // container maps
+        let id_mappings = IdMappings::for_daemon();
+        let user = if opts.user.is_empty() {
+            User::default()
+        } else {
+            resolve_user(
+                &self.container_store.rootfs_dir(&container_id),
+                &opts.user,
+                &id_mappings,
+            )
+            .map_err(|err| InternalCreateContainerError {
+                container_id: container_id.clone(),
+                source: err.into(),
+            })?
+        };
         // create container runtime spec on disk
-        let spec_opts =
-            RuntimeSpecOptions::new(container_bundle_dir.clone(), opts.command, opts.args);
+        let spec_opts = RuntimeSpecOptions {
+            bundle_path: container_bundle_dir,
+            command: opts.command,
+            args: opts.args,
+            tty: opts.tty,
+            env: opts.env,
+            cwd: opts.cwd,
+            user,
+            id_mappings,
+            mounts: spec_mounts,
+            resources: opts.resources,
+            annotations: opts.annotations,
+        };
         self.container_runtime
             .new_runtime_spec(&spec_opts)
             .map_err(|err| InternalCreateContainerError {
@@ -274,11 +966,7 @@ impl ContainerManager {
                 source: err.into(),
             })?;
         // create container
-        let create_opts = RuntimeCreateOptions::new(
-            container_bundle_dir.clone(),
-            "container.pidfile".into(),
-            container_id.clone(),
-        );
+        let create_opts = self.runtime_create_options(&container_id, opts.tty);
         self.container_runtime
             .create_container(create_opts)
             .map_err(|err| InternalCreateContainerError {
@@ -309,6 +997,7 @@ impl ContainerManager {
     /// - start the container via the container runtime
     /// - update container start time and status, then persist
     pub fn start_container(&self, container_id: &ID) -> Result<(), ContainerManagerError> {
+        let container_id = &self.resolve_container_id(container_id)?;
         // ensure container exists and is in created state
         match self.container_map.get(container_id) {
             Ok(container) => {
@@ -329,39 +1018,250 @@ impl ContainerManager {
         //     running and allows future calls to get/list to synchronize with runc.
         //     one other way we could consider doing this is polling runc until we
         //     see that the container is running and then updating.
-        self.update_container_started_at(&container_id, SystemTime::now())?;
-        self.update_container_status(&container_id, Status::Running)?;
-        self.atomic_persist_container_state(&container_id)
+        self.update_container_started_at(container_id, SystemTime::now())?;
+        self.update_container_status(container_id, Status::Running)?;
+        self.atomic_persist_container_state(container_id)
     }
 
     /// stop_container does the following:
+    /// - wait for any restart of the container to finish
+    /// - ensure container exists and is in running or paused state, or is
+    ///   waiting to be restarted, in which case the restart is cancelled
+    /// - record that the container was stopped, so it isn't restarted
+    /// - send the stop signal to the container via the container runtime, and
+    ///   wait up to the stop timeout for it to stop
+    /// - if it hasn't stopped, send a SIGKILL to the container via the
+    ///   container runtime, and wait for it to stop, so it can't be started
+    ///   again while its process is still exiting
+    /// - synchronize container status with the container runtime, picking up
+    ///   its exit, then persist
+    pub fn stop_container(
+        &self,
+        container_id: &ID,
+        opts: StopOptions,
+    ) -> Result<(), ContainerManagerError> {
+        let container_id = &self.resolve_container_id(container_id)?;
+        let container_lock = self.container_lock(container_id);
+        let _container_guard = container_lock.lock().unwrap();
+        // ensure container exists and is in running state, paused containers
+        // can be stopped too by killing and then resuming them
+        let container = self.container_map.get(container_id)?;
+        if container.status == Status::Stopped && should_restart(&container) {
+            self.container_map
+                .update_stopped_by_user(container_id, true)?;
+            return self.atomic_persist_container_state(container_id);
+        }
+        if container.status != Status::Running && container.status != Status::Paused {
+            return Err(ContainerManagerError::StopContainerNotInRunningStateError {
+                container_id: container_id.clone(),
+            });
+        }
+        let signal = if opts.signal.is_empty() {
+            &container.stop_signal
+        } else {
+            &opts.signal
+        };
+        let signal = validate_signal(signal)?;
+        let timeout = Duration::from_secs(opts.timeout.unwrap_or(container.stop_timeout));
+        // record that the container was stopped before it exits, so it isn't
+        // restarted in the meantime
+        self.container_map
+            .update_stopped_by_user(container_id, true)?;
+        // a frozen container can't handle the stop signal until it is resumed,
+        // so it is killed straight away
+        if container.status == Status::Running
+            && self.signal_container_and_wait(container_id, signal, timeout, false)?
+        {
+            // the container stopped on its own, pick up its exit
+            return self.sync_container_status_with_runtime(container_id);
+        }
+        // send SIGKILL to container via the container runtime, and wait for
+        // it to exit. frozen processes only act on the SIGKILL once they are
+        // thawed, so a paused container is resumed after it's sent
+        if !self.signal_container_and_wait(
+            container_id,
+            libc::SIGKILL,
+            KILL_TIMEOUT,
+            container.status == Status::Paused,
+        )? {
+            return Err(ContainerManagerError::KillContainerTimeoutError {
+                container_id: container_id.clone(),
+            });
+        }
+        self.sync_container_status_with_runtime(container_id)
+    }
+
+    /// signal_container_and_wait sends `signal` to a container, resuming it
+    /// afterwards with `resume`, and waits up to `timeout` for it to stop,
+    /// returning whether it did
+    fn signal_container_and_wait(
+        &self,
+        container_id: &ID,
+        signal: i32,
+        timeout: Duration,
+        resume: bool,
+    ) -> Result<bool, ContainerManagerError> {
+        let deadline = Instant::now() + timeout;
+        let signalled = self
+            .container_runtime
+            .kill_container(container_id, signal)
+            .and_then(|()| {
+                if resume {
+                    self.container_runtime.resume_container(container_id)
+                } else {
+                    Ok(())
+                }
+            });
+        if let Err(err) = signalled {
+            // the container may have stopped since its state was checked
+            if self.container_runtime.get_container_status(container_id)? == Status::Stopped {
+                return Ok(true);
+            }
+            return Err(err.into());
+        }
+        loop {
+            if self.container_runtime.get_container_status(container_id)? == Status::Stopped {
+                return Ok(true);
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(false);
+            }
+            thread::sleep(STOP_POLL_INTERVAL.min(deadline - now));
+        }
+    }
+
+    /// kill_container does the following:
+    /// - ensure container exists and is in running or paused state
+    /// - send `signal` to the container via the container runtime, leaving
+    ///   its status to be synchronized with the container runtime
+    pub fn kill_container(
+        &self,
+        container_id: &ID,
+        signal: &str,
+    ) -> Result<(), ContainerManagerError> {
+        let container_id = &self.resolve_container_id(container_id)?;
+        // ensure container exists and is in running or paused state
+        let container = self.container_map.get(container_id)?;
+        if container.status != Status::Running && container.status != Status::Paused {
+            return Err(ContainerManagerError::KillContainerNotInRunningStateError {
+                container_id: container_id.clone(),
+            });
+        }
+        let signal = validate_signal(signal)?;
+        // send signal to container via the container runtime
+        self.container_runtime
+            .kill_container(container_id, signal)
+            .map_err(|err| err.into())
+    }
+
+    /// pause_container does the following:
     /// - ensure container exists and is in running state
-    /// - send a SIGKILL to the container via the container runtime
+    /// - freeze the container via the container runtime
     /// - update container status, then persist
-    pub fn stop_container(&self, container_id: &ID) -> Result<(), ContainerManagerError> {
+    pub fn pause_container(&self, container_id: &ID) -> Result<(), ContainerManagerError> {
+        let container_id = &self.resolve_container_id(container_id)?;
         // ensure container exists and is in running state
         match self.container_map.get(container_id) {
             Ok(container) => {
                 if container.status != Status::Running {
-                    return Err(ContainerManagerError::StopContainerNotInRunningStateError {
-                        container_id: container_id.clone(),
-                    });
+                    return Err(
+                        ContainerManagerError::PauseContainerNotInRunningStateError {
+                            container_id: container_id.clone(),
+                        },
+                    );
                 }
             }
             Err(err) => return Err(err.into()),
         }
-        // send SIGKILL to container via the container runtime
-        self.container_runtime.kill_container(container_id)?;
+        // freeze container via the container runtime
+        self.container_runtime.pause_container(container_id)?;
         // update container status and persist to disk
-        self.update_container_status(&container_id, Status::Stopped)?;
-        self.atomic_persist_container_state(&container_id)
+        self.update_container_status(container_id, Status::Paused)?;
+        self.atomic_persist_container_state(container_id)
+    }
+
+    /// resume_container does the following:
+    /// - ensure container exists and is in paused state
+    /// - thaw the container via the container runtime
+    /// - update container status, then persist
+    pub fn resume_container(&self, container_id: &ID) -> Result<(), ContainerManagerError> {
+        let container_id = &self.resolve_container_id(container_id)?;
+        // ensure container exists and is in paused state
+        match self.container_map.get(container_id) {
+            Ok(container) => {
+                if container.status != Status::Paused {
+                    return Err(
+                        ContainerManagerError::ResumeContainerNotInPausedStateError {
+                            container_id: container_id.clone(),
+                        },
+                    );
+                }
+            }
+            Err(err) => return Err(err.into()),
+        }
+        // thaw container via the container runtime
+        self.container_runtime.resume_container(container_id)?;
+        // update container status and persist to disk
+        self.update_container_status(container_id, Status::Running)?;
+        self.atomic_persist_container_state(container_id)
+    }
+
+    /// update_container does the following:
+    /// - ensure container exists and is in created, running or paused state
+    /// - apply the container's limits, updated by `resources`, via the
+    ///   container runtime
+    /// - record the limits in the container's runtime spec, so restarts keep
+    ///   them
+    /// - update container limits, then persist
+    pub fn update_container(
+        &self,
+        container_id: &ID,
+        resources: Resources,
+    ) -> Result<(), ContainerManagerError> {
+        let container_id = &self.resolve_container_id(container_id)?;
+        // ensure container exists and is in created, running or paused state
+        let mut updated_resources = match self.container_map.get(container_id) {
+            Ok(container) => match container.status {
+                Status::Created | Status::Running | Status::Paused => container.resources,
+                _ => {
+                    return Err(
+                        ContainerManagerError::UpdateContainerNotInUpdateableStateError {
+                            container_id: container_id.clone(),
+                        },
+                    )
+                }
+            },
+            Err(err) => return Err(err.into()),
+        };
+        updated_resources.update(&resources);
+        // apply limits via the container runtime
+        let linux_resources = LinuxResources::from_resources(&updated_resources);
+        let resources_file = self.container_store.container_resources_file(container_id);
+        linux_resources.write(&resources_file)?;
+        self.container_runtime
+            .update_container(container_id, &resources_file)?;
+        // record limits in the container runtime spec
+        let bundle_dir = self.container_store.bundle_dir(container_id);
+        let mut spec = Spec::load(&bundle_dir)?;
+        spec.linux.resources = Some(linux_resources);
+        spec.write(&bundle_dir)?;
+        // update container limits and persist to disk
+        self.container_map
+            .update_resources(container_id, updated_resources)?;
+        self.atomic_persist_container_state(container_id)
     }
 
     /// delete_container does the following:
+    /// - wait for any restart of the container to finish
     /// - ensure container exists and is in stopped state
     /// - tell the container runtime to delete the container
-    /// - remove remnants of container in memory and on disk
+    /// - remove remnants of container in memory and on disk, failing if its
+    ///   rootfs can't be unmounted
     pub fn delete_container(&self, container_id: &ID) -> Result<(), ContainerManagerError> {
+        let container_id = &self.resolve_container_id(container_id)?;
+        let container_lock = self.container_lock(container_id);
+        let _container_guard = container_lock.lock().unwrap();
         // ensure container exists and is in stopped state
         match self.container_map.get(container_id) {
             Ok(container) => {
@@ -378,9 +1278,11 @@ impl ContainerManager {
         // instruct container runtime to delete container
         self.container_runtime.delete_container(container_id)?;
         // remove container from memory and disk
-        self.container_map.remove(&container_id);
+        self.container_map.remove(container_id);
+        self.restart_delays.lock().unwrap().remove(container_id);
+        self.container_locks.lock().unwrap().remove(container_id);
         self.container_store
-            .remove_container_directory(&container_id);
+            .remove_container_directory(container_id)?;
         Ok(())
     }
 
@@ -392,17 +1294,39 @@ impl ContainerManager {
         &self,
         container_id: &ID,
     ) -> Result<Box<Container>, ContainerManagerError> {
+        let container_id = &self.resolve_container_id(container_id)?;
         self.sync_container_status_with_runtime(container_id)?;
         self.container_map
             .get(container_id)
             .map_err(|err| err.into())
     }
 
+    /// update_container_labels does the following:
+    /// - ensure container exists
+    /// - validate the keys of the labels to set
+    /// - set `labels` on the container, replacing the values of labels it
+    ///   already has, and remove the labels keyed by `remove`, then persist
+    pub fn update_container_labels(
+        &self,
+        container_id: &ID,
+        labels: HashMap<String, String>,
+        remove: &[String],
+    ) -> Result<(), ContainerManagerError> {
+        let container_id = &self.resolve_container_id(container_id)?;
+        validate_labels(labels.keys())?;
+        self.container_map
+            .update_labels(container_id, labels, remove)?;
+        self.atomic_persist_container_state(container_id)
+    }
+
     /// list_containers does the following:
     /// - for every known container, synchronize container state with the
     ///   container runtime, which fails if any of the containers do not exist
-    /// - return container states from memory
-    pub fn list_containers(&self) -> Result<Vec<Container>, ContainerManagerError> {
+    /// - return the states from memory of the containers `filter` selects
+    pub fn list_containers(
+        &self,
+        filter: &ContainerFilter,
+    ) -> Result<Vec<Container>, ContainerManagerError> {
         match self.container_map.list() {
             Ok(containers) => {
                 for container in containers.iter() {
@@ -411,42 +1335,908 @@ impl ContainerManager {
             }
             Err(err) => return Err(err.into()),
         };
-        self.container_map.list().map_err(|err| err.into())
+        let containers = self.container_map.list()?;
+        Ok(containers
+            .into_iter()
+            .filter(|container| filter.matches(container))
+            .collect())
     }
 
-    /// sync_container_status_with_runtime does the following:
-    /// - get container state from the container runtime
-    /// - persist in memory and to disk
-    fn sync_container_status_with_runtime(
+    /// container_logs does the following:
+    /// - ensure container exists
+    /// - read the lines of the container's log selected by `opts`, and pass
+    ///   them to `send`
+    /// - when following, keep passing batches of new lines to `send` until
+    ///   the container stops. batches may be empty, which gives `send` the
+    ///   chance to stop following by returning false
+    pub fn container_logs<F>(
         &self,
         container_id: &ID,
-    ) -> Result<(), ContainerManagerError> {
-        let status = self.container_runtime.get_container_status(container_id)?;
-        // update container status in memory and persist to disk
-        self.update_container_status(&container_id, status)?;
-        self.atomic_persist_container_state(&container_id)
+        opts: LogsOptions,
+        mut send: F,
+    ) -> Result<(), ContainerManagerError>
+    where
+        F: FnMut(Vec<LogLine>) -> bool,
+    {
+        let container_id = &self.resolve_container_id(container_id)?;
+        // ensure container exists
+        self.container_map.get(container_id)?;
+        let mut log_reader = LogReader::new(self.container_store.open_container_log(container_id)?);
+        let read_lines = |log_reader: &mut LogReader| {
+            log_reader
+                .read_lines()
+                .map(|log_lines| {
+                    log_lines
+                        .into_iter()
+                        .filter(|log_line| opts.filter.matches(log_line))
+                        .collect::<Vec<LogLine>>()
+                })
+                .map_err(|source| ContainerManagerError::ReadContainerLogError {
+                    container_id: container_id.clone(),
+                    source,
+                })
+        };
+        // send existing output
+        let mut log_lines = read_lines(&mut log_reader)?;
+        if let Some(tail) = opts.tail {
+            log_lines.drain(..log_lines.len().saturating_sub(tail));
+        }
+        if !send(log_lines) || !opts.follow {
+            return Ok(());
+        }
+        // follow new output
+        loop {
+            // check whether the container has stopped before reading, so that
+            // output written right before it stopped isn't missed
+            let stopped = self.get_container(container_id)?.status == Status::Stopped;
+            if !send(read_lines(&mut log_reader)?) || stopped {
+                return Ok(());
+            }
+            thread::sleep(LOGS_FOLLOW_INTERVAL);
+        }
     }
 
-    /// update_container_status updates container status in memory
-    fn update_container_status(
+    /// container_stats does the following:
+    /// - ensure container exists and is in running or paused state
+    /// - sample the container's resource usage via the container runtime
+    ///   every STATS_INTERVAL, and pass the usage over each interval to `send`
+    /// - when streaming, keep sampling until the container stops or `send`
+    ///   returns false, otherwise stop after the first interval
+    pub fn container_stats<F>(
         &self,
         container_id: &ID,
-        status: Status,
-    ) -> Result<(), ContainerManagerError> {
-        self.container_map
-            .update_status(container_id, status)
-            .map_err(|err| err.into())
+        stream: bool,
+        mut send: F,
+    ) -> Result<(), ContainerManagerError>
+    where
+        F: FnMut(Stats) -> bool,
+    {
+        let container_id = &self.resolve_container_id(container_id)?;
+        let running = |status: Status| status == Status::Running || status == Status::Paused;
+        // ensure container exists and is in running or paused state
+        if !running(self.get_container(container_id)?.status) {
+            return Err(
+                ContainerManagerError::StatsContainerNotInRunningStateError {
+                    container_id: container_id.clone(),
+                },
+            );
+        }
+        // cpu usage is reported over the interval between two samples
+        let mut previous_usage = self.container_runtime.container_stats(container_id)?;
+        let mut previous_read_at = Instant::now();
+        loop {
+            thread::sleep(STATS_INTERVAL);
+            if !running(self.get_container(container_id)?.status) {
+                return Ok(());
+            }
+            let usage = self.container_runtime.container_stats(container_id)?;
+            let read_at = Instant::now();
+            let interval = read_at.duration_since(previous_read_at).as_nanos() as f64;
+            let cpu_usage = usage.cpu_usage.saturating_sub(previous_usage.cpu_usage) as f64;
+            let stats = Stats {
+                read_at: SystemTime::now(),
+                cpu_percent: cpu_usage / interval * 100.0,
+                usage: usage.clone(),
+            };
+            if !send(stats) || !stream {
+                return Ok(());
+            }
+            previous_usage = usage;
+            previous_read_at = read_at;
+        }
     }
 
-    /// update_container_created_at updates container creation time in memory
-    fn update_container_created_at(
+    /// exec_container does the following:
+    /// - build the process to run in the container and write it to disk
+    /// - run the process in the container via the container runtime, waiting
+    ///   for it to exit
+    /// - remove the process spec and return the process' exit code and output
+    pub fn exec_container(
         &self,
         container_id: &ID,
-        created_at: SystemTime,
-    ) -> Result<(), ContainerManagerError> {
-        self.container_map
-            .update_creation_time(container_id, created_at)
-            .map_err(|err| err.into())
+        opts: ExecOptions,
+    ) -> Result<ExecOutput, ContainerManagerError> {
+        let container_id = &self.resolve_container_id(container_id)?;
+        let exec_id = rand_id();
+        let process_file = self.write_exec_process(container_id, &exec_id, opts, false)?;
+        // run the process, then clean up its spec regardless of the outcome
+        let exec_output = self
+            .container_runtime
+            .exec_container(container_id, &process_file);
+        self.container_store
+            .remove_exec_process_file(container_id, &exec_id);
+        exec_output.map_err(|err| err.into())
+    }
+
+    /// interactive_exec_container does the following:
+    /// - build the process to run in the container, with a pseudo-terminal
+    ///   if `tty` is set, and write it to disk
+    /// - start the process in the container via the container runtime
+    /// - relay stdin and resize frames from `input` to the process, and pass
+    ///   frames of the process' output to `send`, until the process exits
+    /// - pass the process' exit frame to `send`, unless `send` has returned
+    ///   false for an earlier frame
+    /// - remove the process spec
+    pub fn interactive_exec_container<F>(
+        &self,
+        container_id: &ID,
+        opts: ExecOptions,
+        tty: bool,
+        input: Receiver<Frame>,
+        send: F,
+    ) -> Result<(), ContainerManagerError>
+    where
+        F: FnMut(Frame) -> bool,
+    {
+        let container_id = &self.resolve_container_id(container_id)?;
+        let exec_id = rand_id();
+        let process_file = self.write_exec_process(container_id, &exec_id, opts, tty)?;
+        let result =
+            self.interactive_exec_helper(container_id, &exec_id, &process_file, tty, input, send);
+        self.container_store
+            .remove_exec_process_file(container_id, &exec_id);
+        result
+    }
+
+    fn interactive_exec_helper<F>(
+        &self,
+        container_id: &ID,
+        exec_id: &ID,
+        process_file: &str,
+        tty: bool,
+        input: Receiver<Frame>,
+        mut send: F,
+    ) -> Result<(), ContainerManagerError>
+    where
+        F: FnMut(Frame) -> bool,
+    {
+        let terminal_error = |source| ContainerManagerError::ExecTerminalError {
+            container_id: container_id.clone(),
+            source,
+        };
+        let console_socket = if tty {
+            Some(
+                ConsoleSocket::bind(&self.container_store.console_socket(exec_id))
+                    .map_err(terminal_error)?,
+            )
+        } else {
+            None
+        };
+        let mut child = self.container_runtime.exec_container_interactive(
+            container_id,
+            process_file,
+            console_socket
+                .as_ref()
+                .map(|console_socket| console_socket.path()),
+        )?;
+        let (output_tx, output_rx) = mpsc::channel();
+        let mut copiers = vec![copy_exec_output(
+            child.stderr.take().unwrap(),
+            Frame::Stderr,
+            output_tx.clone(),
+        )];
+        match console_socket {
+            Some(console_socket) => {
+                if let Some(terminal) =
+                    accept_exec_terminal(&console_socket, &mut child).map_err(terminal_error)?
+                {
+                    let terminal_reader = terminal.try_clone().map_err(terminal_error)?;
+                    copiers.push(copy_exec_output(terminal_reader, Frame::Stdout, output_tx));
+                    thread::spawn(move || forward_exec_terminal_input(input, terminal));
+                }
+            }
+            None => {
+                copiers.push(copy_exec_output(
+                    child.stdout.take().unwrap(),
+                    Frame::Stdout,
+                    output_tx,
+                ));
+                let mut stdin = child.stdin.take().unwrap();
+                thread::spawn(move || {
+                    for frame in input {
+                        if let Frame::Stdin(input) = frame {
+                            if stdin.write_all(&input).is_err() {
+                                return;
+                            }
+                        }
+                    }
+                    // the client closed its input, dropping stdin passes that
+                    // on to the process
+                });
+            }
+        }
+        // relay output until the process exits, but keep draining it if the
+        // client goes away so the process isn't blocked on its output
+        let mut client_gone = false;
+        for frame in output_rx {
+            if !client_gone && !send(frame) {
+                client_gone = true;
+            }
+        }
+        for copier in copiers {
+            let _ = copier.join();
+        }
+        let status = child
+            .wait()
+            .map_err(|source| ContainerRuntimeError::RuncError {
+                method: RuncMethod::Exec,
+                container_id: container_id.clone(),
+                source,
+            })?;
+        if !client_gone {
+            send(Frame::Exit(exit_code(&status)));
+        }
+        Ok(())
+    }
+
+    /// write_exec_process does the following:
+    /// - ensure container exists and is in running state
+    /// - build the process to exec from the container's own process spec,
+    ///   overridden by `opts`
+    /// - write the process spec to disk, returning its path
+    fn write_exec_process(
+        &self,
+        container_id: &ID,
+        exec_id: &ID,
+        opts: ExecOptions,
+        tty: bool,
+    ) -> Result<String, ContainerManagerError> {
+        // ensure container exists and is in running state
+        if self.get_container(container_id)?.status != Status::Running {
+            return Err(ContainerManagerError::ExecContainerNotInRunningStateError {
+                container_id: container_id.clone(),
+            });
+        }
+        // build the process from the container's process spec
+        let spec = Spec::load(&self.container_store.bundle_dir(container_id))?;
+        let id_mappings = spec.id_mappings();
+        let mut process = spec.process;
+        process.terminal = tty;
+        process.args = vec![opts.command];
+        process.args.extend(opts.args);
+        process.set_env(&opts.env);
+        if !opts.cwd.is_empty() {
+            process.cwd = opts.cwd;
+        }
+        if !opts.user.is_empty() {
+            process.user = resolve_user(
+                &self.container_store.rootfs_dir(container_id),
+                &opts.user,
+                &id_mappings,
+            )?;
+        }
+        let process_file = self
+            .container_store
+            .exec_process_file(container_id, exec_id);
+        process.write(&process_file)?;
+        Ok(process_file)
+    }
+
+    /// attach_container does the following:
+    /// - ensure container exists and is in created or running state
+    /// - connect to the attach socket served by the container's shim
+    /// - relay stdin and resize frames from `input` to the container, and pass
+    ///   frames of the container's output to `send`, until the container
+    ///   exits (ending with its exit frame), `send` returns false or `input`
+    ///   is closed
+    pub fn attach_container<F>(
+        &self,
+        container_id: &ID,
+        input: Receiver<Frame>,
+        mut send: F,
+    ) -> Result<(), ContainerManagerError>
+    where
+        F: FnMut(Frame) -> bool,
+    {
+        let container_id = &self.resolve_container_id(container_id)?;
+        // ensure container exists and is in created or running state
+        let status = self.get_container(container_id)?.status;
+        if status != Status::Created && status != Status::Running {
+            return Err(
+                ContainerManagerError::AttachContainerNotInAttachableStateError {
+                    container_id: container_id.clone(),
+                },
+            );
+        }
+        let connect_error = |source| ContainerManagerError::ConnectAttachSocketError {
+            container_id: container_id.clone(),
+            source,
+        };
+        let mut conn =
+            UnixStream::connect(self.container_store.container_attach_socket(container_id))
+                .map_err(connect_error)?;
+        let mut conn_input = conn.try_clone().map_err(connect_error)?;
+        thread::spawn(move || {
+            for frame in input {
+                if frame.write_to(&mut conn_input).is_err() {
+                    return;
+                }
+            }
+            // the client detached, end the session
+            let _ = conn_input.shutdown(Shutdown::Both);
+        });
+        while let Ok(Some(frame)) = Frame::read_from(&mut conn) {
+            if !send(frame) {
+                break;
+            }
+        }
+        let _ = conn.shutdown(Shutdown::Both);
+        Ok(())
+    }
+
+    /// restart_exited_containers does the following, for every container with
+    /// a restart policy:
+    /// - synchronize running containers with the container runtime, to notice
+    ///   them exiting
+    /// - restart exited containers whose restart policy asks for it, once
+    ///   they have waited out their backoff
+    pub fn restart_exited_containers(&self) {
+        let containers = match self.container_map.list() {
+            Ok(containers) => containers,
+            Err(err) => {
+                error!("unable to list containers, err: `{:?}`.", err);
+                return;
+            }
+        };
+        for container in containers {
+            if container.restart_policy == RestartPolicy::No {
+                continue;
+            }
+            match self.restart_exited_container(container.id()) {
+                Ok(_) => (),
+                Err(err) => error!(
+                    "unable to restart container `{}`, err: `{:?}`. Continuing.",
+                    container.id(),
+                    err
+                ),
+            }
+        }
+    }
+
+    /// restart_exited_container restarts a container if it has exited, its
+    /// restart policy asks for it and it has waited out its backoff. a
+    /// container being stopped or deleted is left to the next check
+    fn restart_exited_container(&self, container_id: &ID) -> Result<(), ContainerManagerError> {
+        let container_lock = self.container_lock(container_id);
+        let _container_guard = match container_lock.try_lock() {
+            Ok(container_guard) => container_guard,
+            Err(_) => return Ok(()),
+        };
+        if self.container_map.get(container_id)?.status == Status::Running {
+            self.sync_container_status_with_runtime(container_id)?;
+        }
+        let container = self.container_map.get(container_id)?;
+        if container.status != Status::Stopped {
+            return Ok(());
+        }
+        // the exit is only known once the shim has recorded it
+        let finished_at = match container.finished_at {
+            Some(finished_at) => finished_at,
+            None => return Ok(()),
+        };
+        if !should_restart(&container) {
+            return Ok(());
+        }
+        let delay = self.restart_delay(&container, finished_at);
+        if SystemTime::now() < finished_at + delay {
+            return Ok(());
+        }
+        self.restart_delays
+            .lock()
+            .unwrap()
+            .insert(container_id.clone(), delay);
+        self.restart_container(container_id)
+    }
+
+    /// restart_delay is how long an exited container waits before it is
+    /// restarted, doubling the delay of its last restart if it exited soon
+    /// after starting
+    fn restart_delay(&self, container: &Container, finished_at: SystemTime) -> Duration {
+        let ran_for = container
+            .started_at
+            .and_then(|started_at| finished_at.duration_since(started_at).ok())
+            .unwrap_or_default();
+        if ran_for >= RESTART_BACKOFF_RESET {
+            return RESTART_BACKOFF_INITIAL;
+        }
+        match self.restart_delays.lock().unwrap().get(container.id()) {
+            Some(delay) => (*delay * 2).min(RESTART_BACKOFF_MAX),
+            None => RESTART_BACKOFF_INITIAL,
+        }
+    }
+
+    /// restart_container does the following:
+    /// - delete the exited container via the container runtime
+    /// - remove the exit recorded by its shim
+    /// - recreate the container from its bundle (runc create, supervised by a
+    ///   new shim), then start it via the container runtime
+    /// - update container restart count and time, and status, then persist
+    fn restart_container(&self, container_id: &ID) -> Result<(), ContainerManagerError> {
+        let container = self.container_map.get(container_id)?;
+        // delete the exited container
+        self.container_runtime.delete_container(container_id)?;
+        self.container_store
+            .remove_container_exit_file(container_id);
+        // recreate and start the container
+        let create_opts = self.runtime_create_options(container_id, container.tty);
+        self.container_runtime.create_container(create_opts)?;
+        self.container_runtime.start_container(container_id)?;
+        // update container restart and status, then persist to disk
+        self.container_map
+            .update_restart(container_id, SystemTime::now())?;
+        self.update_container_status(container_id, Status::Running)?;
+        self.atomic_persist_container_state(container_id)
+    }
+
+    /// create_volume does the following:
+    /// - validate the volume name, generating one if it's empty
+    /// - create the volume on disk
+    /// - return the volume name
+    pub fn create_volume(&self, name: &str) -> Result<String, ContainerManagerError> {
+        let name = if name.is_empty() {
+            rand_id()
+        } else {
+            name.to_string()
+        };
+        if !volume::is_valid_name(&name) {
+            return Err(ContainerManagerError::InvalidVolumeNameError { name });
+        }
+        self.container_store.create_volume(&volume::new(&name))?;
+        Ok(name)
+    }
+
+    /// list_volumes returns all volumes from disk
+    pub fn list_volumes(&self) -> Result<Vec<Volume>, ContainerManagerError> {
+        self.container_store
+            .list_volumes()
+            .map_err(|err| err.into())
+    }
+
+    /// remove_volume does the following:
+    /// - ensure no container mounts the volume, whatever its status
+    /// - remove the volume, including its data, from disk
+    pub fn remove_volume(&self, name: &str) -> Result<(), ContainerManagerError> {
+        let _volumes_lock = self.volumes_lock.lock().unwrap();
+        // ensure no container mounts the volume
+        let containers = self.container_map.list()?;
+        if let Some(container) = containers.iter().find(|container| {
+            container
+                .mounts
+                .iter()
+                .any(|mount| mount.volume() == Some(name))
+        }) {
+            return Err(ContainerManagerError::VolumeInUseError {
+                name: name.to_string(),
+                container_id: container.id().clone(),
+            });
+        }
+        self.container_store
+            .remove_volume(name)
+            .map_err(|err| err.into())
+    }
+
+    /// import_image does the following:
+    /// - validate the reference to give the image, if any
+    /// - import the images of the archive or image layout at `path`,
+    ///   verifying their content and unpacking their layers, to disk
+    /// - return the imported images
+    pub fn import_image(
+        &self,
+        path: &str,
+        reference: &str,
+    ) -> Result<Vec<Image>, ContainerManagerError> {
+        if !reference.is_empty() && !image::is_valid_reference(reference) {
+            return Err(ContainerManagerError::InvalidImageReferenceError {
+                reference: reference.to_string(),
+            });
+        }
+        let _images_lock = self.images_lock.lock().unwrap();
+        let reference = if reference.is_empty() {
+            None
+        } else {
+            Some(reference)
+        };
+        self.image_store
+            .import_images(path, reference)
+            .map_err(|err| err.into())
+    }
+
+    /// list_images returns all images from disk
+    pub fn list_images(&self) -> Result<Vec<Image>, ContainerManagerError> {
+        self.image_store.list_images().map_err(|err| err.into())
+    }
+
+    /// remove_image does the following:
+    /// - find the image by reference or id
+    /// - if it was found by one of several references, remove just that
+    ///   reference
+    /// - otherwise, ensure no container was created from the image, whatever
+    ///   its status, and remove the image, including its rootfs and the blobs
+    ///   no other image uses, from disk
+    pub fn remove_image(&self, image: &str) -> Result<(), ContainerManagerError> {
+        let _images_lock = self.images_lock.lock().unwrap();
+        let mut found = self.image_store.find_image(image)?;
+        let reference = image::normalize_reference(image);
+        if found.references.len() > 1 && found.references.contains(&reference) {
+            found.references.retain(|other| *other != reference);
+            return self
+                .image_store
+                .persist_image(&found)
+                .map_err(|err| err.into());
+        }
+        // ensure no container was created from the image
+        let containers = self.container_map.list()?;
+        if let Some(container) = containers
+            .iter()
+            .find(|container| container.image == found.id)
+        {
+            return Err(ContainerManagerError::ImageInUseError {
+                image: image.to_string(),
+                container_id: container.id().clone(),
+            });
+        }
+        self.image_store
+            .remove_image(&found.id)
+            .map_err(|err| err.into())
+    }
+
+    /// commit_container does the following:
+    /// - validate the reference to give the image
+    /// - ensure container exists and is in created or stopped state, so its
+    ///   rootfs doesn't change while it's archived
+    /// - take the image config from the container's runtime spec: its
+    ///   command, env, working directory and user
+    /// - archive the container rootfs into a new image, with the reference
+    /// - return the image
+    pub fn commit_container(
+        &self,
+        container_id: &ID,
+        reference: &str,
+    ) -> Result<Image, ContainerManagerError> {
+        let container_id = &self.resolve_container_id(container_id)?;
+        if !image::is_valid_reference(reference) {
+            return Err(ContainerManagerError::InvalidImageReferenceError {
+                reference: reference.to_string(),
+            });
+        }
+        // ensure container exists and is in created or stopped state
+        let container = self.container_map.get(container_id)?;
+        if container.status != Status::Created && container.status != Status::Stopped {
+            return Err(
+                ContainerManagerError::CommitContainerNotInCommittableStateError {
+                    container_id: container_id.clone(),
+                },
+            );
+        }
+        // the spec has the full command, including an image's entrypoint, so
+        // it becomes the cmd of the new image
+        let spec = Spec::load(&self.container_store.bundle_dir(container_id))?;
+        let user = &spec.process.user;
+        let config = ImageConfig {
+            entrypoint: vec![],
+            cmd: spec.process.args.clone(),
+            env: spec.process.env.clone(),
+            working_dir: spec.process.cwd.clone(),
+            user: if user.uid == 0 && user.gid == 0 {
+                String::new()
+            } else {
+                format!("{}:{}", user.uid, user.gid)
+            },
+        };
+        let _images_lock = self.images_lock.lock().unwrap();
+        self.image_store
+            .commit_image(
+                &self.container_store.rootfs_dir(container_id),
+                &config,
+                reference,
+            )
+            .map_err(|err| err.into())
+    }
+
+    /// export_container does the following:
+    /// - ensure container exists
+    /// - confine the export to the container rootfs, with within_root
+    /// - archive the container rootfs as an uncompressed tar archive into
+    ///   `out`, keeping ownership, modes, times, extended attributes and links
+    ///
+    /// the rootfs of a running container may change while it's archived,
+    /// which can fail the export or archive a mix of old and new contents
+    pub fn export_container(
+        &self,
+        container_id: &ID,
+        out: &mut (dyn Write + Send),
+    ) -> Result<(), ContainerManagerError> {
+        let container_id = &self.resolve_container_id(container_id)?;
+        // ensure container exists
+        self.container_map.get(container_id)?;
+        let rootfs_dir = PathBuf::from(self.container_store.rootfs_dir(container_id));
+        self.within_root(container_id, &rootfs_dir, |root| {
+            write_tarball(root, out).map_err(|source| ContainerManagerError::ExportContainerError {
+                container_id: container_id.clone(),
+                source,
+            })
+        })
+    }
+
+    /// copy_from_container does the following:
+    /// - ensure container exists
+    /// - confine the copy to the container's filesystem, with within_root
+    /// - resolve `path` within the container's filesystem, following a last
+    ///   symlink only if `path` ends with `/`
+    /// - archive what it names into `out` as an uncompressed tar archive, with
+    ///   it as the single top-level entry, named after it, followed by its
+    ///   contents if it's a directory
+    pub fn copy_from_container(
+        &self,
+        container_id: &ID,
+        path: &str,
+        out: &mut (dyn Write + Send),
+    ) -> Result<(), ContainerManagerError> {
+        let container_id = &self.resolve_container_id(container_id)?;
+        let copy_error = |source| ContainerManagerError::CopyFromContainerError {
+            container_id: container_id.clone(),
+            path: path.to_string(),
+            source,
+        };
+        let root = self.container_root(container_id)?;
+        self.within_root(container_id, &root, |root| {
+            let resolved = resolve_path(root, Path::new(path), path.ends_with('/'))
+                .and_then(|resolved| symlink_metadata(root.join(&resolved)).map(|_| resolved));
+            let resolved = match resolved {
+                Ok(resolved) => resolved,
+                Err(err) if err.kind() == ErrorKind::NotFound => {
+                    return Err(ContainerManagerError::ContainerPathNotFoundError {
+                        container_id: container_id.clone(),
+                        path: path.to_string(),
+                    })
+                }
+                Err(err) => return Err(copy_error(err)),
+            };
+            let name = match resolved.file_name() {
+                Some(name) => name.to_os_string(),
+                None => {
+                    return Err(ContainerManagerError::CopyContainerRootError {
+                        container_id: container_id.clone(),
+                    })
+                }
+            };
+            write_tarball_entry(root.join(resolved), &name, out).map_err(copy_error)
+        })
+    }
+
+    /// copy_to_container does the following:
+    /// - ensure container exists
+    /// - confine the copy to the container's filesystem, with within_root
+    /// - resolve `path` within the container's filesystem
+    /// - extract the uncompressed tar archive read from `archive` into it if
+    ///   it's a directory. otherwise, the archive's single top-level entry is
+    ///   extracted as `path`, replacing what's there unless it's a directory,
+    ///   within its parent directory, which must exist
+    pub fn copy_to_container(
+        &self,
+        container_id: &ID,
+        path: &str,
+        archive: &mut (dyn Read + Send),
+    ) -> Result<(), ContainerManagerError> {
+        let container_id = &self.resolve_container_id(container_id)?;
+        let copy_error = |source| ContainerManagerError::CopyToContainerError {
+            container_id: container_id.clone(),
+            path: path.to_string(),
+            source,
+        };
+        let root = self.container_root(container_id)?;
+        self.within_root(container_id, &root, |root| {
+            let resolved = resolve_path(root, Path::new(path), true).map_err(copy_error)?;
+            let destination = root.join(&resolved);
+            if destination.is_dir() {
+                return extract_archive(archive, destination, None).map_err(copy_error);
+            }
+            // the root is a directory, so the resolved path has a parent and
+            // name
+            let parent = resolved.parent().unwrap_or(&resolved);
+            let is_dir = symlink_metadata(root.join(parent)).map(|metadata| metadata.is_dir());
+            match is_dir {
+                Ok(true) => (),
+                Ok(false) => return Err(copy_error(io::Error::from_raw_os_error(libc::ENOTDIR))),
+                Err(err) if err.kind() == ErrorKind::NotFound => {
+                    return Err(ContainerManagerError::ContainerPathNotFoundError {
+                        container_id: container_id.clone(),
+                        path: Path::new("/").join(parent).display().to_string(),
+                    })
+                }
+                Err(err) => return Err(copy_error(err)),
+            }
+            extract_archive(archive, root.join(parent), resolved.file_name()).map_err(copy_error)
+        })
+    }
+
+    /// container_root is the root of a container's filesystem. while the
+    /// container is running or paused, that's the root of its init process,
+    /// so that what's mounted into the container is included
+    fn container_root(&self, container_id: &ID) -> Result<PathBuf, ContainerManagerError> {
+        let status = self.get_container(container_id)?.status;
+        if status == Status::Running || status == Status::Paused {
+            let pid = self.container_store.read_container_pid(container_id)?;
+            return Ok(PathBuf::from(format!("/proc/{}/root", pid)));
+        }
+        Ok(PathBuf::from(self.container_store.rootfs_dir(container_id)))
+    }
+
+    /// within_root runs `f` on the directory `root` of a container's
+    /// filesystem, such that the container can't lead the paths `f` resolves
+    /// out of it by changing the tree meanwhile:
+    /// - a privileged daemon runs `f` on a thread of its own whose root is
+    ///   `root`, so `f` is given `/`, and the kernel resolves even symlinks
+    ///   swapped in partway through within it
+    /// - otherwise, a running container is paused while `f` runs, so none
+    ///   of its processes can change the tree
+    fn within_root<F, T>(
+        &self,
+        container_id: &ID,
+        root: &Path,
+        f: F,
+    ) -> Result<T, ContainerManagerError>
+    where
+        F: FnOnce(&Path) -> Result<T, ContainerManagerError> + Send,
+        T: Send,
+    {
+        if is_privileged() {
+            let root_error = |source| ContainerManagerError::ContainerRootError {
+                container_id: container_id.clone(),
+                source,
+            };
+            let root = File::open(root).map_err(root_error)?;
+            return thread::scope(|scope| {
+                scope
+                    .spawn(|| {
+                        change_thread_root(&root).map_err(root_error)?;
+                        f(Path::new("/"))
+                    })
+                    .join()
+                    .unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+            });
+        }
+        let paused = self.container_map.get(container_id)?.status == Status::Running;
+        if paused {
+            self.container_runtime.pause_container(container_id)?;
+        }
+        let result = f(root);
+        if paused {
+            self.container_runtime.resume_container(container_id)?;
+        }
+        result
+    }
+
+    /// spec_mounts validates `mounts` and turns them into the bind mounts of
+    /// a runtime spec. host paths must exist, while volumes are mounted from
+    /// their data directory
+    fn spec_mounts(&self, mounts: &[Mount]) -> Result<Vec<spec::Mount>, ContainerManagerError> {
+        mounts
+            .iter()
+            .map(|mount| {
+                let source = match mount.volume() {
+                    Some(name) => {
+                        if !volume::is_valid_name(name) {
+                            return Err(ContainerManagerError::InvalidVolumeNameError {
+                                name: name.to_string(),
+                            });
+                        }
+                        self.container_store.volume_data_dir(name)
+                    }
+                    None => {
+                        if !Path::new(&mount.source).exists() {
+                            return Err(ContainerManagerError::MountSourceNotFoundError {
+                                source_path: mount.source.clone(),
+                            });
+                        }
+                        mount.source.clone()
+                    }
+                };
+                let propagation = if mount.propagation.is_empty() {
+                    "rprivate".to_string()
+                } else {
+                    mount.propagation.clone()
+                };
+                Ok(spec::Mount {
+                    destination: mount.destination.clone(),
+                    kind: "bind".into(),
+                    source,
+                    options: vec![
+                        "rbind".into(),
+                        if mount.read_only { "ro" } else { "rw" }.into(),
+                        propagation,
+                    ],
+                })
+            })
+            .collect()
+    }
+
+    /// runtime_create_options are the options to create a container from its
+    /// bundle with
+    fn runtime_create_options(&self, container_id: &ID, tty: bool) -> RuntimeCreateOptions {
+        RuntimeCreateOptions::new(
+            self.container_store.bundle_dir(container_id),
+            CONTAINER_PIDFILE.into(),
+            container_id.clone(),
+            self.container_store.container_exit_file(container_id),
+            self.container_store.container_log_file(container_id),
+            self.container_store.container_attach_socket(container_id),
+            if tty {
+                Some(self.container_store.console_socket(container_id))
+            } else {
+                None
+            },
+        )
+    }
+
+    /// sync_container_status_with_runtime does the following:
+    /// - get container state from the container runtime
+    /// - if the container has stopped, pick up the exit code, finish time and
+    ///   OOM kills recorded by its shim, logging OOM kills the first time
+    /// - persist in memory and to disk
+    fn sync_container_status_with_runtime(
+        &self,
+        container_id: &ID,
+    ) -> Result<(), ContainerManagerError> {
+        let status = self.container_runtime.get_container_status(container_id)?;
+        if status == Status::Stopped {
+            if let Some(container_exit) = self.container_store.read_container_exit(container_id)? {
+                let previous_status = self.container_map.get(container_id)?.status;
+                if container_exit.oom_killed && previous_status != Status::Stopped {
+                    warn!(
+                        "container `{}` was killed by the OOM killer, exit code: {}",
+                        container_id, container_exit.exit_code
+                    );
+                }
+                self.container_map
+                    .update_exit(container_id, &container_exit)?;
+            }
+        }
+        // update container status in memory and persist to disk
+        self.update_container_status(container_id, status)?;
+        self.atomic_persist_container_state(container_id)
+    }
+
+    /// update_container_status updates container status in memory
+    fn update_container_status(
+        &self,
+        container_id: &ID,
+        status: Status,
+    ) -> Result<(), ContainerManagerError> {
+        self.container_map
+            .update_status(container_id, status)
+            .map_err(|err| err.into())
+    }
+
+    /// update_container_created_at updates container creation time in memory
+    fn update_container_created_at(
+        &self,
+        container_id: &ID,
+        created_at: SystemTime,
+    ) -> Result<(), ContainerManagerError> {
+        self.container_map
+            .update_creation_time(container_id, created_at)
+            .map_err(|err| err.into())
     }
 
     /// update_container_started_at updates container start time in memory
@@ -467,10 +2257,713 @@ impl ContainerManager {
     ) -> Result<(), ContainerManagerError> {
         let container = self
             .container_map
-            .get(&container_id)
+            .get(container_id)
             .map_err(|source| ContainerManagerError::ContainerMapError { source })?;
         self.container_store
             .atomic_persist_container_state(&container)
             .map_err(|err| err.into())
     }
 }
+
+/// should_restart decides whether an exited container is restarted, by its
+/// restart policy
+fn should_restart(container: &Container) -> bool {
+    if container.stopped_by_user {
+        return false;
+    }
+    match container.restart_policy {
+        RestartPolicy::No => false,
+        RestartPolicy::OnFailure { max_retries } => {
+            container.exit_code != 0
+                && max_retries.is_none_or(|max_retries| container.restart_count < max_retries)
+        }
+        RestartPolicy::Always | RestartPolicy::UnlessStopped => true,
+    }
+}
+
+/// apply_image_config fills in the options a container is created with
+/// from the config of its image. as with docker, a command replaces the
+/// image's cmd and runs with the image's entrypoint, and env is added to the
+/// image's env
+fn apply_image_config(opts: &mut ContainerOptions, config: &ImageConfig) {
+    let mut argv = config.entrypoint.clone();
+    if opts.command.is_empty() {
+        argv.extend(config.cmd.iter().cloned());
+    } else {
+        argv.push(std::mem::take(&mut opts.command));
+        argv.append(&mut opts.args);
+    }
+    if !argv.is_empty() {
+        opts.command = argv.remove(0);
+        opts.args = argv;
+    }
+    let mut env = config.env.clone();
+    env.append(&mut opts.env);
+    opts.env = env;
+    if opts.cwd.is_empty() {
+        opts.cwd = config.working_dir.clone();
+    }
+    if opts.user.is_empty() {
+        opts.user = config.user.clone();
+    }
+}
+
+/// resolve_path resolves `path` within the directory `root` as if `root` was
+/// the root directory: `..` components stop at it, and symlinks, absolute
+/// ones included, are resolved within it. a last symlink is only followed
+/// with `follow_last`. the resolved path is returned relative to `root`, and
+/// may not exist
+fn resolve_path(root: &Path, path: &Path, follow_last: bool) -> io::Result<PathBuf> {
+    let mut resolved = PathBuf::new();
+    // the components left to resolve, last first
+    let mut components: Vec<OsString> = vec![];
+    push_components(&mut components, path);
+    let mut links = 0;
+    while let Some(component) = components.pop() {
+        if component == ".." {
+            resolved.pop();
+            continue;
+        }
+        resolved.push(&component);
+        if components.is_empty() && !follow_last {
+            break;
+        }
+        let full_path = root.join(&resolved);
+        match symlink_metadata(&full_path) {
+            Ok(metadata) if metadata.file_type().is_symlink() => {
+                links += 1;
+                if links > MAX_SYMLINKS {
+                    return Err(io::Error::from_raw_os_error(libc::ELOOP));
+                }
+                let target = read_link(&full_path)?;
+                resolved.pop();
+                if target.is_absolute() {
+                    resolved = PathBuf::new();
+                }
+                push_components(&mut components, &target);
+            }
+            Ok(_) => (),
+            // what's below is resolved as missing too
+            Err(err) if err.kind() == ErrorKind::NotFound => (),
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(resolved)
+}
+
+/// change_thread_root makes the directory `root` the root and working
+/// directory of the calling thread alone
+fn change_thread_root(root: &File) -> io::Result<()> {
+    // safe: unsharing the file system attributes only detaches the calling
+    // thread's root, working directory and umask from the rest of the process
+    if unsafe { libc::unshare(libc::CLONE_FS) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // safe: root is an open directory for the duration of the call
+    if unsafe { libc::fchdir(root.as_raw_fd()) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // safe: the path is a nul terminated string
+    if unsafe { libc::chroot(b".\0".as_ptr() as *const libc::c_char) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// push_components pushes the components of `path` onto the components left
+/// to resolve, to be resolved before them
+fn push_components(components: &mut Vec<OsString>, path: &Path) {
+    for component in path.components().rev() {
+        match component {
+            Component::Normal(name) => components.push(name.to_os_string()),
+            Component::ParentDir => components.push("..".into()),
+            Component::RootDir | Component::CurDir | Component::Prefix(_) => (),
+        }
+    }
+}
+
+/// validate_labels validates the keys of labels to set on a container
+fn validate_labels<'a>(
+    mut keys: impl Iterator<Item = &'a String>,
+) -> Result<(), ContainerManagerError> {
+    match keys.find(|key| !container::is_valid_label_key(key)) {
+        Some(key) => Err(ContainerManagerError::InvalidLabelKeyError { key: key.clone() }),
+        None => Ok(()),
+    }
+}
+
+/// validate_annotations validates the annotations of a container, whose keys
+/// the runtime spec requires not to be empty
+fn validate_annotations(
+    annotations: &HashMap<String, String>,
+) -> Result<(), ContainerManagerError> {
+    if annotations.contains_key("") {
+        return Err(ContainerManagerError::InvalidAnnotationKeyError);
+    }
+    Ok(())
+}
+
+/// validate_signal parses a signal to send to a container
+fn validate_signal(signal: &str) -> Result<i32, ContainerManagerError> {
+    parse_signal(signal).ok_or_else(|| ContainerManagerError::InvalidSignalError {
+        signal: signal.to_string(),
+    })
+}
+
+/// accept_exec_terminal waits for the runtime to send the terminal of an
+/// interactive exec, or to exit without doing so
+fn accept_exec_terminal(
+    console_socket: &ConsoleSocket,
+    child: &mut Child,
+) -> std::io::Result<Option<File>> {
+    loop {
+        if let Some(terminal) = console_socket.try_accept_master()? {
+            return Ok(Some(terminal));
+        }
+        if child.try_wait()?.is_some() {
+            return Ok(None);
+        }
+        thread::sleep(CONSOLE_POLL_INTERVAL);
+    }
+}
+
+/// forward_exec_terminal_input copies stdin and resize frames to the terminal
+/// of an interactive exec until the client closes its input
+fn forward_exec_terminal_input(input: Receiver<Frame>, mut terminal: File) {
+    for frame in input {
+        match frame {
+            // the client sees failures through the process' output
+            Frame::Stdin(input) => {
+                let _ = terminal.write_all(&input);
+            }
+            Frame::Resize(size) => {
+                let _ = console::set_window_size(&terminal, size);
+            }
+            _ => (),
+        }
+    }
+}
+
+/// copy_exec_output sends the output of an interactive exec as frames built
+/// by `frame` until `output` is closed
+fn copy_exec_output<R: Read + Send + 'static>(
+    mut output: R,
+    frame: fn(Vec<u8>) -> Frame,
+    output_tx: Sender<Frame>,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let mut buf = [0u8; 4096];
+        loop {
+            let read = match output.read(&mut buf) {
+                Ok(0) => return,
+                Ok(read) => read,
+                Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                // reading a terminal fails with EIO once the other side is closed
+                Err(_) => return,
+            };
+            if output_tx.send(frame(buf[..read].to_vec())).is_err() {
+                return;
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use container_runtime::fake::FakeRuntime;
+    use std::fs::{create_dir_all, rename, write, File};
+    use std::os::unix::fs::symlink;
+    use std::sync::Barrier;
+    use tempfile::TempDir;
+
+    /// new_manager creates a container manager rooted in a temporary
+    /// directory, driving the fake runtime, and a rootfs tarball to create
+    /// containers from
+    fn new_manager() -> (TempDir, ContainerManager, String) {
+        new_manager_with_runtime(FakeRuntime::new())
+    }
+
+    fn new_manager_with_runtime(runtime: FakeRuntime) -> (TempDir, ContainerManager, String) {
+        let dir = tempfile::tempdir().unwrap();
+        let rootfs_dir = dir.path().join("rootfs");
+        create_dir_all(rootfs_dir.join("bin")).unwrap();
+        write(rootfs_dir.join("bin/sh"), "#!/bin/sh\n").unwrap();
+        let rootfs = dir.path().join("rootfs.tar");
+        write_tarball(&rootfs_dir, &mut File::create(&rootfs).unwrap()).unwrap();
+        let cm = ContainerManager::new(
+            dir.path().join("lib").to_string_lossy().to_string(),
+            Box::new(runtime),
+        )
+        .unwrap();
+        (dir, cm, rootfs.to_string_lossy().to_string())
+    }
+
+    fn options(name: &str, rootfs: &str) -> ContainerOptions {
+        ContainerOptions {
+            name: name.to_string(),
+            command: "/bin/sh".to_string(),
+            args: vec![],
+            rootfs_path: rootfs.to_string(),
+            image: String::new(),
+            tty: false,
+            stop_signal: String::new(),
+            stop_timeout: None,
+            restart_policy: String::new(),
+            env: vec![],
+            cwd: String::new(),
+            user: String::new(),
+            mounts: vec![],
+            resources: Resources::default(),
+            labels: HashMap::new(),
+            annotations: HashMap::new(),
+        }
+    }
+
+    fn stop_options() -> StopOptions {
+        StopOptions {
+            signal: String::new(),
+            timeout: Some(1),
+        }
+    }
+
+    #[test]
+    fn container_lifecycle() {
+        let (_dir, cm, rootfs) = new_manager();
+        let container_id = cm.create_container(options("web", &rootfs)).unwrap();
+        let container = cm.get_container(&container_id).unwrap();
+        assert_eq!(container.status, Status::Created);
+        assert!(container.created_at.is_some());
+        assert!(Path::new(&cm.container_store.rootfs_dir(&container_id))
+            .join("bin/sh")
+            .exists());
+
+        cm.start_container(&container_id).unwrap();
+        let container = cm.get_container(&container_id).unwrap();
+        assert_eq!(container.status, Status::Running);
+        assert!(container.started_at.is_some());
+
+        cm.stop_container(&container_id, stop_options()).unwrap();
+        let container = cm.get_container(&container_id).unwrap();
+        assert_eq!(container.status, Status::Stopped);
+        assert_eq!(container.exit_code, 128 + libc::SIGTERM);
+        assert!(container.stopped_by_user);
+
+        cm.delete_container(&container_id).unwrap();
+        assert!(matches!(
+            cm.get_container(&container_id),
+            Err(ContainerManagerError::ContainerNotFoundError { .. })
+        ));
+        assert!(!Path::new(&cm.container_store.bundle_dir(&container_id)).exists());
+        assert!(cm
+            .list_containers(&ContainerFilter::default())
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn start_requires_created_container() {
+        let (_dir, cm, rootfs) = new_manager();
+        let container_id = cm.create_container(options("", &rootfs)).unwrap();
+        cm.start_container(&container_id).unwrap();
+        assert!(matches!(
+            cm.start_container(&container_id),
+            Err(ContainerManagerError::StartContainerNotInCreatedStateError { .. })
+        ));
+    }
+
+    #[test]
+    fn stop_requires_running_container() {
+        let (_dir, cm, rootfs) = new_manager();
+        let container_id = cm.create_container(options("", &rootfs)).unwrap();
+        assert!(matches!(
+            cm.stop_container(&container_id, stop_options()),
+            Err(ContainerManagerError::StopContainerNotInRunningStateError { .. })
+        ));
+    }
+
+    #[test]
+    fn delete_refuses_running_container() {
+        let (_dir, cm, rootfs) = new_manager();
+        let container_id = cm.create_container(options("", &rootfs)).unwrap();
+        cm.start_container(&container_id).unwrap();
+        assert!(matches!(
+            cm.delete_container(&container_id),
+            Err(ContainerManagerError::DeleteContainerNotInDeleteableStateError { .. })
+        ));
+        assert_eq!(
+            cm.get_container(&container_id).unwrap().status,
+            Status::Running
+        );
+    }
+
+    #[test]
+    fn stop_kills_paused_container() {
+        let (_dir, cm, rootfs) = new_manager();
+        let container_id = cm.create_container(options("", &rootfs)).unwrap();
+        cm.start_container(&container_id).unwrap();
+        cm.pause_container(&container_id).unwrap();
+        cm.stop_container(&container_id, stop_options()).unwrap();
+        let container = cm.get_container(&container_id).unwrap();
+        assert_eq!(container.status, Status::Stopped);
+        assert_eq!(container.exit_code, 128 + libc::SIGKILL);
+    }
+
+    #[test]
+    fn stop_waits_for_killed_container_to_exit() {
+        let runtime = FakeRuntime::new().ignoring(libc::SIGTERM).exiting_after(3);
+        let (_dir, cm, rootfs) = new_manager_with_runtime(runtime);
+        let container_id = cm.create_container(options("", &rootfs)).unwrap();
+        cm.start_container(&container_id).unwrap();
+        let stop_opts = StopOptions {
+            signal: String::new(),
+            timeout: Some(0),
+        };
+        cm.stop_container(&container_id, stop_opts).unwrap();
+        // the container is only stopped once the runtime reports it exited
+        let container = cm.container_map.get(&container_id).unwrap();
+        assert_eq!(container.status, Status::Stopped);
+        assert_eq!(container.exit_code, 128 + libc::SIGKILL);
+        assert_eq!(
+            cm.container_runtime
+                .get_container_status(&container_id)
+                .unwrap(),
+            Status::Stopped
+        );
+    }
+
+    #[test]
+    fn delete_waits_for_restart() {
+        let gate = Arc::new(Barrier::new(2));
+        let runtime = FakeRuntime::new().holding_recreates(gate.clone());
+        let (_dir, cm, rootfs) = new_manager_with_runtime(runtime);
+        let mut opts = options("", &rootfs);
+        opts.restart_policy = "always".to_string();
+        let container_id = cm.create_container(opts).unwrap();
+        cm.start_container(&container_id).unwrap();
+        cm.kill_container(&container_id, "SIGKILL").unwrap();
+        thread::sleep(RESTART_BACKOFF_INITIAL);
+
+        thread::scope(|scope| {
+            let restart = scope.spawn(|| cm.restart_exited_containers());
+            // the container is recreated, but not started yet
+            gate.wait();
+            let delete = scope.spawn(|| cm.delete_container(&container_id));
+            thread::sleep(STOP_POLL_INTERVAL);
+            let deleted_during_restart = delete.is_finished();
+            gate.wait();
+            restart.join().unwrap();
+            assert!(!deleted_during_restart);
+            // the restarted container is running, so can't be deleted
+            assert!(matches!(
+                delete.join().unwrap(),
+                Err(ContainerManagerError::DeleteContainerNotInDeleteableStateError { .. })
+            ));
+        });
+        let container = cm.get_container(&container_id).unwrap();
+        assert_eq!(container.status, Status::Running);
+        assert_eq!(container.restart_count, 1);
+
+        cm.stop_container(&container_id, stop_options()).unwrap();
+        cm.delete_container(&container_id).unwrap();
+        assert!(cm.container_store.list_container_ids().unwrap().is_empty());
+    }
+
+    #[test]
+    fn failed_create_is_rolled_back() {
+        let (dir, cm, _rootfs) = new_manager();
+        let missing = dir.path().join("missing").to_string_lossy().to_string();
+        assert!(cm.create_container(options("web", &missing)).is_err());
+        assert!(cm
+            .list_containers(&ContainerFilter::default())
+            .unwrap()
+            .is_empty());
+        assert!(cm.container_store.list_container_ids().unwrap().is_empty());
+    }
+
+    #[test]
+    fn deleting_frees_name() {
+        let (_dir, cm, rootfs) = new_manager();
+        let name = "web".to_string();
+        let container_id = cm.create_container(options(&name, &rootfs)).unwrap();
+        assert!(cm.create_container(options(&name, &rootfs)).is_err());
+        assert_eq!(cm.get_container(&name).unwrap().id, container_id);
+        cm.delete_container(&name).unwrap();
+        let container_id = cm.create_container(options(&name, &rootfs)).unwrap();
+        assert_eq!(cm.get_container(&name).unwrap().id, container_id);
+    }
+
+    #[test]
+    fn name_conflict_leaves_no_volumes_behind() {
+        let (_dir, cm, rootfs) = new_manager();
+        cm.create_container(options("web", &rootfs)).unwrap();
+        let mut opts = options("web", &rootfs);
+        opts.mounts = vec![Mount {
+            source: "data".to_string(),
+            destination: "/data".to_string(),
+            read_only: false,
+            propagation: String::new(),
+        }];
+        assert!(cm.create_container(opts).is_err());
+        assert!(cm.list_volumes().unwrap().is_empty());
+        assert_eq!(
+            cm.list_containers(&ContainerFilter::default())
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn resolve_path_stops_parent_dirs_at_root() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        for path in &[
+            "../etc/passwd",
+            "/../../etc/passwd",
+            "etc/../../../etc/passwd",
+        ] {
+            assert_eq!(
+                resolve_path(root, Path::new(path), true).unwrap(),
+                Path::new("etc/passwd"),
+                "{}",
+                path
+            );
+        }
+        assert_eq!(
+            resolve_path(root, Path::new(".."), true).unwrap(),
+            Path::new("")
+        );
+    }
+
+    #[test]
+    fn resolve_path_resolves_symlinks_within_root() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        create_dir_all(root.join("usr/lib")).unwrap();
+        // absolute symlinks are resolved against the root, not the host
+        symlink("/usr/lib", root.join("lib")).unwrap();
+        symlink("/etc/passwd", root.join("passwd")).unwrap();
+        // and relative ones can't climb out of it
+        symlink("../../../../etc", root.join("usr/etc")).unwrap();
+        symlink("../lib/missing", root.join("usr/lib/dangling")).unwrap();
+        for (path, resolved) in &[
+            ("lib/libc.so", "usr/lib/libc.so"),
+            ("passwd", "etc/passwd"),
+            ("usr/etc/passwd", "etc/passwd"),
+            ("lib/dangling", "usr/lib/missing"),
+            // `..` applies to where a symlink leads, as in the kernel
+            ("lib/../passwd", "usr/passwd"),
+        ] {
+            assert_eq!(
+                resolve_path(root, Path::new(path), true).unwrap(),
+                Path::new(resolved),
+                "{}",
+                path
+            );
+        }
+    }
+
+    #[test]
+    fn resolve_path_follows_last_symlink_on_request() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        create_dir_all(root.join("usr/lib")).unwrap();
+        symlink("/usr/lib", root.join("lib")).unwrap();
+        assert_eq!(
+            resolve_path(root, Path::new("lib"), false).unwrap(),
+            Path::new("lib")
+        );
+        assert_eq!(
+            resolve_path(root, Path::new("lib"), true).unwrap(),
+            Path::new("usr/lib")
+        );
+        // symlinks before the last are followed regardless
+        symlink("lib", root.join("libs")).unwrap();
+        assert_eq!(
+            resolve_path(root, Path::new("libs/x"), false).unwrap(),
+            Path::new("usr/lib/x")
+        );
+    }
+
+    #[test]
+    fn resolve_path_detects_symlink_loops() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        symlink("b", root.join("a")).unwrap();
+        symlink("/a", root.join("b")).unwrap();
+        let err = resolve_path(root, Path::new("a/file"), true).unwrap_err();
+        assert_eq!(err.raw_os_error(), Some(libc::ELOOP));
+    }
+
+    /// Hooked passes reads and writes through to `inner`, running `hook`
+    /// before the first
+    struct Hooked<T, F: FnOnce()> {
+        inner: T,
+        hook: Option<F>,
+    }
+
+    impl<T, F: FnOnce()> Hooked<T, F> {
+        fn run_hook(&mut self) {
+            if let Some(hook) = self.hook.take() {
+                hook();
+            }
+        }
+    }
+
+    impl<T: Read, F: FnOnce()> Read for Hooked<T, F> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.run_hook();
+            self.inner.read(buf)
+        }
+    }
+
+    impl<T: Write, F: FnOnce()> Write for Hooked<T, F> {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.run_hook();
+            self.inner.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.inner.flush()
+        }
+    }
+
+    /// swap_in_symlink replaces the directory `dir` with a symlink to
+    /// `target`, as a container racing a copy would
+    fn swap_in_symlink(dir: &Path, target: &Path) {
+        rename(dir, dir.with_extension("moved")).unwrap();
+        symlink(target, dir).unwrap();
+    }
+
+    /// host_dir creates a directory outside of any container, holding a
+    /// secret file
+    fn host_dir(dir: &TempDir) -> PathBuf {
+        let host_dir = dir.path().join("host");
+        create_dir_all(&host_dir).unwrap();
+        write(host_dir.join("secret"), "host secret").unwrap();
+        host_dir
+    }
+
+    #[test]
+    fn copy_from_container_stays_within_swapped_dirs() {
+        // unprivileged daemons pause the container instead, which the fake
+        // runtime doesn't run processes to be paused in
+        if !is_privileged() {
+            return;
+        }
+        let (dir, cm, rootfs) = new_manager();
+        let host_dir = host_dir(&dir);
+        let container_id = cm.create_container(options("", &rootfs)).unwrap();
+        let data_dir = Path::new(&cm.container_store.rootfs_dir(&container_id)).join("data");
+        create_dir_all(&data_dir).unwrap();
+        write(data_dir.join("file"), "container data").unwrap();
+
+        let gate = Barrier::new(2);
+        let mut out = Hooked {
+            inner: vec![],
+            hook: Some(|| {
+                gate.wait();
+                gate.wait();
+            }),
+        };
+        thread::scope(|scope| {
+            let copy = scope.spawn(|| cm.copy_from_container(&container_id, "/data", &mut out));
+            // the directory is resolved, and its entry is being written
+            gate.wait();
+            swap_in_symlink(&data_dir, &host_dir);
+            gate.wait();
+            // the symlink is resolved within the container, which has no
+            // such directory
+            assert!(matches!(
+                copy.join().unwrap(),
+                Err(ContainerManagerError::CopyFromContainerError { .. })
+            ));
+        });
+        assert!(!String::from_utf8_lossy(&out.inner).contains("host secret"));
+    }
+
+    #[test]
+    fn copy_to_container_stays_within_swapped_dirs() {
+        if !is_privileged() {
+            return;
+        }
+        let (dir, cm, rootfs) = new_manager();
+        let host_dir = host_dir(&dir);
+        let container_id = cm.create_container(options("", &rootfs)).unwrap();
+        let dest_dir = Path::new(&cm.container_store.rootfs_dir(&container_id)).join("dest");
+        create_dir_all(&dest_dir).unwrap();
+        let source_dir = dir.path().join("source");
+        create_dir_all(&source_dir).unwrap();
+        write(source_dir.join("planted"), "planted").unwrap();
+        let mut archive = vec![];
+        write_tarball(&source_dir, &mut archive).unwrap();
+
+        let gate = Barrier::new(2);
+        let mut input = Hooked {
+            inner: archive.as_slice(),
+            hook: Some(|| {
+                gate.wait();
+                gate.wait();
+            }),
+        };
+        thread::scope(|scope| {
+            let copy = scope.spawn(|| cm.copy_to_container(&container_id, "/dest", &mut input));
+            // the destination is resolved, and the archive is being read
+            gate.wait();
+            swap_in_symlink(&dest_dir, &host_dir);
+            gate.wait();
+            assert!(matches!(
+                copy.join().unwrap(),
+                Err(ContainerManagerError::CopyToContainerError { .. })
+            ));
+        });
+        assert!(!host_dir.join("planted").exists());
+    }
+
+    #[test]
+    fn committed_container_carries_over() {
+        let (_dir, cm, rootfs) = new_manager();
+        let mut opts = options("base", &rootfs);
+        opts.args = vec!["-c".to_string(), "echo $GREETING".to_string()];
+        opts.env = vec!["GREETING=hello".to_string()];
+        opts.cwd = "/srv".to_string();
+        let base_id = cm.create_container(opts).unwrap();
+        let base_rootfs = cm.container_store.rootfs_dir(&base_id);
+        create_dir_all(Path::new(&base_rootfs).join("srv")).unwrap();
+        write(Path::new(&base_rootfs).join("srv/app.conf"), "port=80\n").unwrap();
+        cm.start_container(&base_id).unwrap();
+        // running containers can't be committed
+        assert!(matches!(
+            cm.commit_container(&base_id, "app:1"),
+            Err(ContainerManagerError::CommitContainerNotInCommittableStateError { .. })
+        ));
+        cm.stop_container(&base_id, stop_options()).unwrap();
+        let image = cm.commit_container(&base_id, "app:1").unwrap();
+        assert_eq!(image.config.cmd, vec!["/bin/sh", "-c", "echo $GREETING"]);
+
+        // without a command of its own, the new container runs the image's
+        let mut opts = options("app", "");
+        opts.command = String::new();
+        opts.image = "app:1".to_string();
+        let app_id = cm.create_container(opts).unwrap();
+        let app_rootfs = cm.container_store.rootfs_dir(&app_id);
+        assert_eq!(
+            std::fs::read_to_string(Path::new(&app_rootfs).join("srv/app.conf")).unwrap(),
+            "port=80\n"
+        );
+        let process = Spec::load(&cm.container_store.bundle_dir(&app_id))
+            .unwrap()
+            .process;
+        assert_eq!(process.args, vec!["/bin/sh", "-c", "echo $GREETING"]);
+        assert!(process.env.contains(&"GREETING=hello".to_string()));
+        assert_eq!(process.cwd, "/srv");
+        // the image can't be removed while a container uses it
+        assert!(cm.remove_image("app:1").is_err());
+
+        // unmount the new container's rootfs, if it was overlaid
+        cm.delete_container(&app_id).unwrap();
+        cm.delete_container(&base_id).unwrap();
+        cm.remove_image("app:1").unwrap();
+    }
+}
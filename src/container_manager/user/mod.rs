@@ -0,0 +1,350 @@
+use super::container_runtime::spec::{IdMappings, User};
+use super::resolve_path;
+use std::error::Error;
+use std::fmt;
+use std::fs::read_to_string;
+use std::io::ErrorKind;
+use std::path::Path;
+
+#[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
+pub enum UserError {
+    // represents an error parsing the user to run a process as
+    InvalidUserError {
+        user: String,
+    },
+    // represents a user name that isn't listed in the root filesystem's passwd file
+    UnknownUserError {
+        user: String,
+    },
+    // represents a group name that isn't listed in the root filesystem's group file
+    UnknownGroupError {
+        group: String,
+    },
+    // represents a user or group that the container's user namespace doesn't map
+    UnmappedUserError {
+        user: String,
+    },
+    // represents an error reading the passwd or group file of a root filesystem
+    ReadUserDatabaseError {
+        path: String,
+        source: std::io::Error,
+    },
+}
+
+impl fmt::Display for UserError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Self::InvalidUserError { ref user } => {
+                write!(f, "invalid user `{}`, expected user[:group]", user)
+            }
+            Self::UnknownUserError { ref user } => {
+                write!(f, "user `{}` not found in /etc/passwd", user)
+            }
+            Self::UnknownGroupError { ref group } => {
+                write!(f, "group `{}` not found in /etc/group", group)
+            }
+            Self::UnmappedUserError { ref user } => write!(
+                f,
+                "user `{}` isn't mapped into the container's user namespace",
+                user
+            ),
+            Self::ReadUserDatabaseError { ref path, .. } => write!(f, "failed to read {}", path),
+        }
+    }
+}
+
+impl Error for UserError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            Self::InvalidUserError { .. } => None,
+            Self::UnknownUserError { .. } => None,
+            Self::UnknownGroupError { .. } => None,
+            Self::UnmappedUserError { .. } => None,
+            Self::ReadUserDatabaseError { ref source, .. } => Some(source),
+        }
+    }
+}
+
+/// PasswdEntry is a line of /etc/passwd
+struct PasswdEntry {
+    name: String,
+    uid: u32,
+    gid: u32,
+}
+
+/// GroupEntry is a line of /etc/group
+struct GroupEntry {
+    name: String,
+    gid: u32,
+    members: Vec<String>,
+}
+
+/// resolve_user resolves `user[:group]`, with the user and group each given
+/// by name or by id, against the passwd and group files of the root
+/// filesystem at `rootfs`. without a group the process runs with the user's
+/// primary group, or a gid equal to its uid if the user isn't listed in
+/// passwd, along with the supplementary groups the user is a member of.
+/// users and groups `id_mappings` don't map are rejected, and supplementary
+/// groups they don't map are dropped
+pub fn resolve_user(rootfs: &str, user: &str, id_mappings: &IdMappings) -> Result<User, UserError> {
+    let mut parts = user.splitn(2, ':');
+    let user_part = parts.next().unwrap_or_default();
+    let group_part = parts.next();
+    if user_part.is_empty() || group_part == Some("") {
+        return Err(UserError::InvalidUserError {
+            user: user.to_string(),
+        });
+    }
+
+    let passwd = read_passwd(rootfs)?;
+    let (uid, passwd_entry) = match user_part.parse::<u32>() {
+        // numeric uids need not be listed
+        Ok(uid) => (uid, passwd.into_iter().find(|entry| entry.uid == uid)),
+        Err(_) => {
+            let entry = passwd
+                .into_iter()
+                .find(|entry| entry.name == user_part)
+                .ok_or_else(|| UserError::UnknownUserError {
+                    user: user_part.to_string(),
+                })?;
+            (entry.uid, Some(entry))
+        }
+    };
+
+    let groups = read_groups(rootfs)?;
+    let gid = match group_part {
+        Some(group_part) => match group_part.parse::<u32>() {
+            Ok(gid) => gid,
+            Err(_) => groups
+                .iter()
+                .find(|entry| entry.name == group_part)
+                .map(|entry| entry.gid)
+                .ok_or_else(|| UserError::UnknownGroupError {
+                    group: group_part.to_string(),
+                })?,
+        },
+        None => passwd_entry.as_ref().map_or(uid, |entry| entry.gid),
+    };
+    // an explicit group replaces the user's groups, as with `su -g`
+    let additional_gids = match (group_part, passwd_entry) {
+        (None, Some(entry)) => groups
+            .iter()
+            .filter(|group| group.gid != gid && group.members.contains(&entry.name))
+            .map(|group| group.gid)
+            .filter(|&gid| id_mappings.maps_gid(gid))
+            .collect(),
+        _ => vec![],
+    };
+    if !id_mappings.maps_uid(uid) || !id_mappings.maps_gid(gid) {
+        return Err(UserError::UnmappedUserError {
+            user: user.to_string(),
+        });
+    }
+
+    Ok(User {
+        uid,
+        gid,
+        additional_gids,
+    })
+}
+
+fn read_passwd(rootfs: &str) -> Result<Vec<PasswdEntry>, UserError> {
+    let passwd = read_user_database(rootfs, "etc/passwd")?;
+    // name:password:uid:gid:gecos:home:shell
+    Ok(passwd
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split(':').collect();
+            if fields.len() < 4 {
+                return None;
+            }
+            Some(PasswdEntry {
+                name: fields[0].to_string(),
+                uid: fields[2].parse().ok()?,
+                gid: fields[3].parse().ok()?,
+            })
+        })
+        .collect())
+}
+
+fn read_groups(rootfs: &str) -> Result<Vec<GroupEntry>, UserError> {
+    let groups = read_user_database(rootfs, "etc/group")?;
+    // name:password:gid:member,member
+    Ok(groups
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split(':').collect();
+            if fields.len() < 3 {
+                return None;
+            }
+            Some(GroupEntry {
+                name: fields[0].to_string(),
+                gid: fields[2].parse().ok()?,
+                members: fields
+                    .get(3)
+                    .map(|members| {
+                        members
+                            .split(',')
+                            .filter(|member| !member.is_empty())
+                            .map(|member| member.to_string())
+                            .collect()
+                    })
+                    .unwrap_or_default(),
+            })
+        })
+        .collect())
+}
+
+/// read_user_database reads `path` within the root filesystem at `rootfs`,
+/// as empty if it doesn't exist. symlinks are resolved within the root
+/// filesystem, as they would be inside the container
+fn read_user_database(rootfs: &str, path: &str) -> Result<String, UserError> {
+    let read_error = |source| UserError::ReadUserDatabaseError {
+        path: format!("/{}", path),
+        source,
+    };
+    let rootfs = Path::new(rootfs);
+    let resolved_path = resolve_path(rootfs, Path::new(path), true).map_err(read_error)?;
+    match read_to_string(rootfs.join(resolved_path)) {
+        Ok(database) => Ok(database),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(String::new()),
+        Err(source) => Err(read_error(source)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::container_manager::container_runtime::spec::IdMapping;
+    use std::fs::{create_dir, write};
+    use std::os::unix::fs::symlink;
+    use tempfile::{tempdir, TempDir};
+
+    /// rootfs returns a root filesystem with a passwd and group file
+    fn rootfs() -> TempDir {
+        let dir = tempdir().unwrap();
+        create_dir(dir.path().join("etc")).unwrap();
+        write(
+            dir.path().join("etc/passwd"),
+            "root:x:0:0:root:/root:/bin/sh\nnobody:x:65534:65534:nobody:/:/bin/false\n",
+        )
+        .unwrap();
+        write(
+            dir.path().join("etc/group"),
+            "root:x:0:root\nwheel:x:10:root,nobody\nnogroup:x:65534:\n",
+        )
+        .unwrap();
+        dir
+    }
+
+    /// id_mappings maps root, and `subordinate_ids` ids after it, as
+    /// rootless daemons do
+    fn id_mappings(subordinate_ids: u32) -> IdMappings {
+        let mut mappings = vec![IdMapping {
+            container_id: 0,
+            host_id: 1000,
+            size: 1,
+        }];
+        if subordinate_ids > 0 {
+            mappings.push(IdMapping {
+                container_id: 1,
+                host_id: 100000,
+                size: subordinate_ids,
+            });
+        }
+        IdMappings {
+            uid_mappings: mappings.clone(),
+            gid_mappings: mappings,
+        }
+    }
+
+    #[test]
+    fn resolves_root() {
+        let dir = rootfs();
+        let rootfs = dir.path().to_str().unwrap();
+        for user in &["root", "0", "root:root", "0:0"] {
+            let user = resolve_user(rootfs, user, &id_mappings(0)).unwrap();
+            assert_eq!((user.uid, user.gid), (0, 0));
+            // root's other groups aren't mapped
+            assert!(user.additional_gids.is_empty());
+        }
+    }
+
+    #[test]
+    fn resolves_mapped_users() {
+        let dir = rootfs();
+        let rootfs = dir.path().to_str().unwrap();
+        let id_mappings = id_mappings(65536);
+        let user = resolve_user(rootfs, "nobody", &id_mappings).unwrap();
+        assert_eq!((user.uid, user.gid), (65534, 65534));
+        assert_eq!(user.additional_gids, vec![10]);
+        let user = resolve_user(rootfs, "root", &id_mappings).unwrap();
+        assert_eq!(user.additional_gids, vec![10]);
+        let user = resolve_user(rootfs, "1000:wheel", &id_mappings).unwrap();
+        assert_eq!((user.uid, user.gid), (1000, 10));
+        assert!(user.additional_gids.is_empty());
+    }
+
+    #[test]
+    fn rejects_unmapped_users() {
+        let dir = rootfs();
+        let rootfs = dir.path().to_str().unwrap();
+        for user in &["nobody", "1000", "root:wheel", "0:10", "nobody:root"] {
+            match resolve_user(rootfs, user, &id_mappings(0)) {
+                Err(UserError::UnmappedUserError { user: unmapped }) => assert_eq!(&unmapped, user),
+                result => panic!("expected `{}` to be unmapped, got {:?}", user, result),
+            }
+        }
+        // ids past the subordinate ones aren't mapped either
+        assert!(matches!(
+            resolve_user(rootfs, "70000", &id_mappings(65536)),
+            Err(UserError::UnmappedUserError { .. })
+        ));
+    }
+
+    #[test]
+    fn rejects_unknown_and_invalid_users() {
+        let dir = rootfs();
+        let rootfs = dir.path().to_str().unwrap();
+        assert!(matches!(
+            resolve_user(rootfs, "missing", &id_mappings(0)),
+            Err(UserError::UnknownUserError { .. })
+        ));
+        assert!(matches!(
+            resolve_user(rootfs, "root:missing", &id_mappings(0)),
+            Err(UserError::UnknownGroupError { .. })
+        ));
+        assert!(matches!(
+            resolve_user(rootfs, ":root", &id_mappings(0)),
+            Err(UserError::InvalidUserError { .. })
+        ));
+        assert!(matches!(
+            resolve_user(rootfs, "root:", &id_mappings(0)),
+            Err(UserError::InvalidUserError { .. })
+        ));
+    }
+
+    #[test]
+    fn resolves_symlinks_within_rootfs() {
+        let dir = rootfs();
+        let rootfs = dir.path().join("rootfs");
+        create_dir(&rootfs).unwrap();
+        create_dir(rootfs.join("etc")).unwrap();
+        create_dir(rootfs.join("data")).unwrap();
+        write(rootfs.join("data/passwd"), "app:x:0:0::/:/bin/sh\n").unwrap();
+        // absolute symlinks point into the rootfs, not the host
+        symlink("/data/passwd", rootfs.join("etc/passwd")).unwrap();
+        // and relative ones can't climb out of it
+        write(dir.path().join("group"), "wheel:x:0:app\n").unwrap();
+        symlink("../../group", rootfs.join("etc/group")).unwrap();
+        let rootfs = rootfs.to_str().unwrap();
+        assert_eq!(resolve_user(rootfs, "app", &id_mappings(0)).unwrap().uid, 0);
+        // the group file resolves to the rootfs' missing /group, not the one
+        // outside of it
+        assert!(matches!(
+            resolve_user(rootfs, "app:wheel", &id_mappings(0)),
+            Err(UserError::UnknownGroupError { .. })
+        ));
+    }
+}
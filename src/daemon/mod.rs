@@ -1,33 +1,81 @@
 use chrono::offset::Utc;
-use chrono::DateTime;
+use chrono::{DateTime, SecondsFormat};
+use futures::channel::{mpsc, oneshot};
+use futures::executor::block_on;
+use futures::SinkExt;
 use log::{debug, error};
-use tonic::{transport::Server, Request, Response, Status};
+use std::sync::{self, Arc};
+use std::thread;
+use std::time::Duration;
+use tokio::task;
+use tonic::{transport::Server, Request, Response, Status, Streaming};
 
-use crate::container::Container;
-use crate::container_manager::{ContainerManager, ContainerManagerError, ContainerOptions};
+use crate::attach::Frame;
+use crate::chunk::{ChunkReader, ChunkWriter, FromChunk};
+use crate::console::WindowSize;
+use crate::container::{self, Container, ContainerFilter, Mount, Resources, MOUNT_PROPAGATIONS};
+use crate::container_log::{LogFilter, LogLine};
+use crate::container_manager::{
+    ContainerManager, ContainerManagerError, ContainerOptions, ExecOptions, LogsOptions, Stats,
+    StopOptions,
+};
+use crate::image::{self, Image};
+use crate::volume::Volume;
 
 use cruise_grpc::cruise_server::{Cruise, CruiseServer};
 use cruise_grpc::{
-    CreateContainerRequest, CreateContainerResponse, DeleteContainerRequest,
-    DeleteContainerResponse, GetContainerRequest, GetContainerResponse, ListContainersRequest,
-    ListContainersResponse, StartContainerRequest, StartContainerResponse, StopContainerRequest,
-    StopContainerResponse,
+    attach_container_request, copy_to_container_request, interactive_exec_container_request,
+    interactive_response, AttachContainerRequest, CopyToContainerRequest,
+    InteractiveExecContainerRequest, InteractiveResponse, TerminalSize,
+};
+use cruise_grpc::{
+    CommitContainerRequest, CommitContainerResponse, ContainerLogsRequest, ContainerLogsResponse,
+    ContainerStatsRequest, ContainerStatsResponse, CopyFromContainerRequest,
+    CopyFromContainerResponse, CopyToContainerResponse, CreateContainerRequest,
+    CreateContainerResponse, DeleteContainerRequest, DeleteContainerResponse, ExecContainerRequest,
+    ExecContainerResponse, ExportContainerRequest, ExportContainerResponse, GetContainerRequest,
+    GetContainerResponse, KillContainerRequest, KillContainerResponse, ListContainersRequest,
+    ListContainersResponse, PauseContainerRequest, PauseContainerResponse, ResumeContainerRequest,
+    ResumeContainerResponse, StartContainerRequest, StartContainerResponse, StopContainerRequest,
+    StopContainerResponse, UpdateContainerLabelsRequest, UpdateContainerLabelsResponse,
+    UpdateContainerRequest, UpdateContainerResponse,
+};
+use cruise_grpc::{
+    CreateVolumeRequest, CreateVolumeResponse, ListVolumesRequest, ListVolumesResponse,
+    RemoveVolumeRequest, RemoveVolumeResponse,
+};
+use cruise_grpc::{
+    ImportImageRequest, ImportImageResponse, ListImagesRequest, ListImagesResponse,
+    RemoveImageRequest, RemoveImageResponse,
 };
 
 mod cruise_grpc {
     tonic::include_proto!("cruise"); // The string specified here must match the proto package name
 }
 
+// how many responses a streaming rpc buffers before waiting on the client
+const STREAM_BUFFER_SIZE: usize = 64;
+// how often containers are checked for having exited, to restart them
+const RESTART_MONITOR_INTERVAL: Duration = Duration::from_millis(500);
+
 #[derive(Debug)]
 pub struct CruiseDaemon {
-    cm: ContainerManager,
+    // shared with the threads that serve streaming rpcs
+    cm: Arc<ContainerManager>,
 }
 
 impl CruiseDaemon {
     #[tokio::main]
-    pub async fn run_server(self: Self, port: &str) -> Result<(), Box<dyn std::error::Error>> {
+    pub async fn run_server(self, port: &str) -> Result<(), Box<dyn std::error::Error>> {
         let addr = format!("[::1]:{}", port).parse()?;
 
+        // restart exited containers according to their restart policies
+        let cm = Arc::clone(&self.cm);
+        thread::spawn(move || loop {
+            cm.restart_exited_containers();
+            thread::sleep(RESTART_MONITOR_INTERVAL);
+        });
+
         Server::builder()
             .add_service(CruiseServer::new(self))
             .serve(addr)
@@ -38,7 +86,7 @@ impl CruiseDaemon {
 }
 
 pub fn new(cm: ContainerManager) -> CruiseDaemon {
-    return CruiseDaemon { cm };
+    CruiseDaemon { cm: Arc::new(cm) }
 }
 
 fn handle_container_manager_error(err: ContainerManagerError, failure_msg: &'static str) -> Status {
@@ -50,8 +98,32 @@ fn handle_container_manager_error(err: ContainerManagerError, failure_msg: &'sta
     status
 }
 
+impl CruiseDaemon {
+    /// blocking runs `f` with the container manager on the blocking thread
+    /// pool, for calls that can take long, such as waiting on a process or
+    /// copying a rootfs, which would otherwise hold up the runtime's only
+    /// thread and with it every other rpc
+    async fn blocking<F, T>(&self, f: F) -> Result<T, Status>
+    where
+        F: FnOnce(&ContainerManager) -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let cm = Arc::clone(&self.cm);
+        task::spawn_blocking(move || f(&cm))
+            .await
+            .map_err(|err| Status::internal(format!("container manager call failed: {}", err)))
+    }
+}
+
 #[tonic::async_trait]
 impl Cruise for CruiseDaemon {
+    type ContainerLogsStream = mpsc::Receiver<Result<ContainerLogsResponse, Status>>;
+    type ContainerStatsStream = mpsc::Receiver<Result<ContainerStatsResponse, Status>>;
+    type ExportContainerStream = mpsc::Receiver<Result<ExportContainerResponse, Status>>;
+    type CopyFromContainerStream = mpsc::Receiver<Result<CopyFromContainerResponse, Status>>;
+    type InteractiveExecContainerStream = mpsc::Receiver<Result<InteractiveResponse, Status>>;
+    type AttachContainerStream = mpsc::Receiver<Result<InteractiveResponse, Status>>;
+
     async fn create_container(
         &self,
         request: Request<CreateContainerRequest>,
@@ -59,14 +131,14 @@ impl Cruise for CruiseDaemon {
         debug!("Got create container request: {:?}", request);
 
         let request = request.into_inner();
-        let container_opts = ContainerOptions {
-            name: request.name,
-            command: request.command,
-            args: request.args,
-            rootfs_path: request.rootfs_path,
-        };
+        let container_opts = map_create_container_request_to_container_options(request)
+            .map_err(Status::invalid_argument)?;
 
-        match self.cm.create_container(container_opts) {
+        // creating extracts or copies the rootfs unless it can be overlaid
+        match self
+            .blocking(move |cm| cm.create_container(container_opts))
+            .await?
+        {
             Ok(container_id) => Ok(Response::new(CreateContainerResponse { container_id })),
             Err(err) => Err(handle_container_manager_error(
                 err,
@@ -100,12 +172,115 @@ impl Cruise for CruiseDaemon {
 
         let request = request.into_inner();
 
-        match self.cm.stop_container(&request.container_id) {
+        let container_id = request.container_id;
+        let stop_opts = StopOptions {
+            signal: request.signal,
+            timeout: request.timeout.map(|timeout| timeout.seconds),
+        };
+
+        // stopping waits up to the stop timeout for the container to exit
+        match self
+            .blocking(move |cm| cm.stop_container(&container_id, stop_opts))
+            .await?
+        {
             Ok(_) => Ok(Response::new(StopContainerResponse { success: true })),
             Err(err) => Err(handle_container_manager_error(err, "stop container failed")),
         }
     }
 
+    async fn kill_container(
+        &self,
+        request: Request<KillContainerRequest>,
+    ) -> Result<Response<KillContainerResponse>, Status> {
+        debug!("Got kill container request: {:?}", request);
+
+        let request = request.into_inner();
+
+        match self
+            .cm
+            .kill_container(&request.container_id, &request.signal)
+        {
+            Ok(_) => Ok(Response::new(KillContainerResponse { success: true })),
+            Err(err) => Err(handle_container_manager_error(err, "kill container failed")),
+        }
+    }
+
+    async fn pause_container(
+        &self,
+        request: Request<PauseContainerRequest>,
+    ) -> Result<Response<PauseContainerResponse>, Status> {
+        debug!("Got pause container request: {:?}", request);
+
+        let request = request.into_inner();
+
+        match self.cm.pause_container(&request.container_id) {
+            Ok(_) => Ok(Response::new(PauseContainerResponse { success: true })),
+            Err(err) => Err(handle_container_manager_error(
+                err,
+                "pause container failed",
+            )),
+        }
+    }
+
+    async fn resume_container(
+        &self,
+        request: Request<ResumeContainerRequest>,
+    ) -> Result<Response<ResumeContainerResponse>, Status> {
+        debug!("Got resume container request: {:?}", request);
+
+        let request = request.into_inner();
+
+        match self.cm.resume_container(&request.container_id) {
+            Ok(_) => Ok(Response::new(ResumeContainerResponse { success: true })),
+            Err(err) => Err(handle_container_manager_error(
+                err,
+                "resume container failed",
+            )),
+        }
+    }
+
+    async fn update_container(
+        &self,
+        request: Request<UpdateContainerRequest>,
+    ) -> Result<Response<UpdateContainerResponse>, Status> {
+        debug!("Got update container request: {:?}", request);
+
+        let request = request.into_inner();
+        let resources = map_resources_request_to_resources(request.resources.unwrap_or_default())
+            .map_err(Status::invalid_argument)?;
+
+        match self.cm.update_container(&request.container_id, resources) {
+            Ok(_) => Ok(Response::new(UpdateContainerResponse { success: true })),
+            Err(err) => Err(handle_container_manager_error(
+                err,
+                "update container failed",
+            )),
+        }
+    }
+
+    async fn update_container_labels(
+        &self,
+        request: Request<UpdateContainerLabelsRequest>,
+    ) -> Result<Response<UpdateContainerLabelsResponse>, Status> {
+        debug!("Got update container labels request: {:?}", request);
+
+        let request = request.into_inner();
+
+        match self.cm.update_container_labels(
+            &request.container_id,
+            request.labels,
+            &request.remove,
+        ) {
+            Ok(_) => Ok(Response::new(UpdateContainerLabelsResponse {
+                success: true,
+            })),
+            Err(err) => Err(handle_container_manager_error(
+                err,
+                "update container labels failed",
+            )),
+        }
+    }
+
     async fn delete_container(
         &self,
         request: Request<DeleteContainerRequest>,
@@ -145,7 +320,10 @@ impl Cruise for CruiseDaemon {
     ) -> Result<Response<ListContainersResponse>, Status> {
         debug!("Got list containers request: {:?}", request);
 
-        match self.cm.list_containers() {
+        let filter = map_list_containers_request_to_container_filter(request.into_inner())
+            .map_err(Status::invalid_argument)?;
+
+        match self.cm.list_containers(&filter) {
             Ok(containers) => Ok(Response::new(ListContainersResponse {
                 containers: containers
                     .into_iter()
@@ -158,6 +336,768 @@ impl Cruise for CruiseDaemon {
             )),
         }
     }
+
+    async fn container_logs(
+        &self,
+        request: Request<ContainerLogsRequest>,
+    ) -> Result<Response<Self::ContainerLogsStream>, Status> {
+        debug!("Got container logs request: {:?}", request);
+
+        let request = request.into_inner();
+        let opts = map_container_logs_request_to_logs_options(&request)
+            .map_err(Status::invalid_argument)?;
+        // fail the rpc up front if the container doesn't exist
+        if let Err(err) = self.cm.get_container(&request.container_id) {
+            return Err(handle_container_manager_error(err, "container logs failed"));
+        }
+
+        // reading, and especially following, a log blocks, so stream it from
+        // its own thread
+        let (mut tx, rx) = mpsc::channel(STREAM_BUFFER_SIZE);
+        let cm = self.cm.clone();
+        thread::spawn(move || {
+            let result = cm.container_logs(&request.container_id, opts, |log_lines| {
+                for log_line in log_lines {
+                    let response = map_log_line_to_container_logs_response(log_line);
+                    if block_on(tx.send(Ok(response))).is_err() {
+                        return false;
+                    }
+                }
+                // stop following once the client goes away
+                !tx.is_closed()
+            });
+            if let Err(err) = result {
+                let status = handle_container_manager_error(err, "container logs failed");
+                let _ = block_on(tx.send(Err(status)));
+            }
+        });
+
+        Ok(Response::new(rx))
+    }
+
+    async fn container_stats(
+        &self,
+        request: Request<ContainerStatsRequest>,
+    ) -> Result<Response<Self::ContainerStatsStream>, Status> {
+        debug!("Got container stats request: {:?}", request);
+
+        let request = request.into_inner();
+        // fail the rpc up front if the container doesn't exist
+        if let Err(err) = self.cm.get_container(&request.container_id) {
+            return Err(handle_container_manager_error(
+                err,
+                "container stats failed",
+            ));
+        }
+
+        // sampling waits between samples, so stream stats from their own
+        // thread
+        let (mut tx, rx) = mpsc::channel(STREAM_BUFFER_SIZE);
+        let cm = self.cm.clone();
+        thread::spawn(move || {
+            let result = cm.container_stats(&request.container_id, request.stream, |stats| {
+                let response = map_stats_to_container_stats_response(stats);
+                // stop streaming once the client goes away
+                block_on(tx.send(Ok(response))).is_ok()
+            });
+            if let Err(err) = result {
+                let status = handle_container_manager_error(err, "container stats failed");
+                let _ = block_on(tx.send(Err(status)));
+            }
+        });
+
+        Ok(Response::new(rx))
+    }
+
+    async fn exec_container(
+        &self,
+        request: Request<ExecContainerRequest>,
+    ) -> Result<Response<ExecContainerResponse>, Status> {
+        debug!("Got exec container request: {:?}", request);
+
+        let request = request.into_inner();
+        let container_id = request.container_id.clone();
+        let exec_opts = map_exec_container_request_to_exec_options(request)
+            .map_err(Status::invalid_argument)?;
+
+        match self
+            .blocking(move |cm| cm.exec_container(&container_id, exec_opts))
+            .await?
+        {
+            Ok(exec_output) => Ok(Response::new(ExecContainerResponse {
+                exit_code: exec_output.exit_code,
+                stdout: exec_output.stdout,
+                stderr: exec_output.stderr,
+            })),
+            Err(err) => Err(handle_container_manager_error(err, "exec container failed")),
+        }
+    }
+
+    async fn interactive_exec_container(
+        &self,
+        request: Request<Streaming<InteractiveExecContainerRequest>>,
+    ) -> Result<Response<Self::InteractiveExecContainerStream>, Status> {
+        debug!("Got interactive exec container request: {:?}", request);
+
+        let mut inbound = request.into_inner();
+        let start = match inbound.message().await?.and_then(|message| message.event) {
+            Some(interactive_exec_container_request::Event::Start(start)) => start,
+            _ => {
+                return Err(Status::invalid_argument(
+                    "the first message must start the process",
+                ))
+            }
+        };
+        let tty = start.tty;
+        let request = start
+            .exec
+            .ok_or_else(|| Status::invalid_argument("no process given"))?;
+        let container_id = request.container_id.clone();
+        let exec_opts = map_exec_container_request_to_exec_options(request)
+            .map_err(Status::invalid_argument)?;
+        let input = forward_input(
+            inbound,
+            |message: InteractiveExecContainerRequest| match message.event {
+                Some(interactive_exec_container_request::Event::Stdin(input)) => {
+                    Some(Frame::Stdin(input))
+                }
+                Some(interactive_exec_container_request::Event::Resize(size)) => {
+                    Some(Frame::Resize(map_terminal_size_to_window_size(size)))
+                }
+                _ => None,
+            },
+        );
+
+        // the session lasts as long as the process, so relay it from its own
+        // thread
+        let (mut tx, rx) = mpsc::channel(STREAM_BUFFER_SIZE);
+        let cm = self.cm.clone();
+        thread::spawn(move || {
+            let result =
+                cm.interactive_exec_container(&container_id, exec_opts, tty, input, |frame| {
+                    send_session_output(&mut tx, frame)
+                });
+            if let Err(err) = result {
+                let status =
+                    handle_container_manager_error(err, "interactive exec container failed");
+                let _ = block_on(tx.send(Err(status)));
+            }
+        });
+
+        Ok(Response::new(rx))
+    }
+
+    async fn attach_container(
+        &self,
+        request: Request<Streaming<AttachContainerRequest>>,
+    ) -> Result<Response<Self::AttachContainerStream>, Status> {
+        debug!("Got attach container request: {:?}", request);
+
+        let mut inbound = request.into_inner();
+        let container_id = match inbound.message().await?.and_then(|message| message.event) {
+            Some(attach_container_request::Event::ContainerId(container_id)) => container_id,
+            _ => {
+                return Err(Status::invalid_argument(
+                    "the first message must name the container",
+                ))
+            }
+        };
+        let input = forward_input(inbound, |message: AttachContainerRequest| {
+            match message.event {
+                Some(attach_container_request::Event::Stdin(input)) => Some(Frame::Stdin(input)),
+                Some(attach_container_request::Event::Resize(size)) => {
+                    Some(Frame::Resize(map_terminal_size_to_window_size(size)))
+                }
+                _ => None,
+            }
+        });
+
+        // the session lasts as long as the client stays attached, so relay it
+        // from its own thread
+        let (mut tx, rx) = mpsc::channel(STREAM_BUFFER_SIZE);
+        let cm = self.cm.clone();
+        thread::spawn(move || {
+            let result = cm.attach_container(&container_id, input, |frame| {
+                send_session_output(&mut tx, frame)
+            });
+            if let Err(err) = result {
+                let status = handle_container_manager_error(err, "attach container failed");
+                let _ = block_on(tx.send(Err(status)));
+            }
+        });
+
+        Ok(Response::new(rx))
+    }
+
+    async fn commit_container(
+        &self,
+        request: Request<CommitContainerRequest>,
+    ) -> Result<Response<CommitContainerResponse>, Status> {
+        debug!("Got commit container request: {:?}", request);
+
+        let request = request.into_inner();
+
+        // committing archives the container's whole root filesystem
+        match self
+            .blocking(move |cm| cm.commit_container(&request.container_id, &request.reference))
+            .await?
+        {
+            Ok(image) => Ok(Response::new(CommitContainerResponse {
+                image: Some(map_image_to_image_response(image)),
+            })),
+            Err(err) => Err(handle_container_manager_error(
+                err,
+                "commit container failed",
+            )),
+        }
+    }
+
+    async fn export_container(
+        &self,
+        request: Request<ExportContainerRequest>,
+    ) -> Result<Response<Self::ExportContainerStream>, Status> {
+        debug!("Got export container request: {:?}", request);
+
+        let request = request.into_inner();
+        // fail the rpc up front if the container doesn't exist
+        if let Err(err) = self.cm.get_container(&request.container_id) {
+            return Err(handle_container_manager_error(
+                err,
+                "export container failed",
+            ));
+        }
+
+        // archiving the rootfs blocks, so stream it from its own thread
+        let (tx, rx) = mpsc::channel(STREAM_BUFFER_SIZE);
+        let cm = self.cm.clone();
+        thread::spawn(move || {
+            let mut writer = ChunkWriter::new(tx);
+            if let Err(err) = cm.export_container(&request.container_id, &mut writer) {
+                // nobody is left to tell if the client went away
+                if writer.is_closed() {
+                    return;
+                }
+                let status = handle_container_manager_error(err, "export container failed");
+                let _ = writer.send(Err(status));
+            }
+        });
+
+        Ok(Response::new(rx))
+    }
+
+    async fn copy_from_container(
+        &self,
+        request: Request<CopyFromContainerRequest>,
+    ) -> Result<Response<Self::CopyFromContainerStream>, Status> {
+        debug!("Got copy from container request: {:?}", request);
+
+        let request = request.into_inner();
+        validate_container_path(&request.path).map_err(Status::invalid_argument)?;
+        // fail the rpc up front if the container doesn't exist
+        if let Err(err) = self.cm.get_container(&request.container_id) {
+            return Err(handle_container_manager_error(
+                err,
+                "copy from container failed",
+            ));
+        }
+
+        // archiving the path blocks, so stream it from its own thread
+        let (tx, rx) = mpsc::channel(STREAM_BUFFER_SIZE);
+        let cm = self.cm.clone();
+        thread::spawn(move || {
+            let mut writer = ChunkWriter::new(tx);
+            let copied = cm.copy_from_container(&request.container_id, &request.path, &mut writer);
+            if let Err(err) = copied {
+                // nobody is left to tell if the client went away
+                if writer.is_closed() {
+                    return;
+                }
+                let status = handle_container_manager_error(err, "copy from container failed");
+                let _ = writer.send(Err(status));
+            }
+        });
+
+        Ok(Response::new(rx))
+    }
+
+    async fn copy_to_container(
+        &self,
+        request: Request<Streaming<CopyToContainerRequest>>,
+    ) -> Result<Response<CopyToContainerResponse>, Status> {
+        debug!("Got copy to container request: {:?}", request);
+
+        let mut inbound = request.into_inner();
+        let start = match inbound.message().await?.and_then(|message| message.event) {
+            Some(copy_to_container_request::Event::Start(start)) => start,
+            _ => {
+                return Err(Status::invalid_argument(
+                    "the first message must name the container and path",
+                ))
+            }
+        };
+        validate_container_path(&start.path).map_err(Status::invalid_argument)?;
+        let archive = forward_input(inbound, |message: CopyToContainerRequest| {
+            match message.event {
+                Some(copy_to_container_request::Event::Data(data)) => Some(data),
+                _ => None,
+            }
+        });
+
+        // extracting the archive blocks, so do it on its own thread
+        let (done_tx, done_rx) = oneshot::channel();
+        let cm = self.cm.clone();
+        thread::spawn(move || {
+            let mut archive = ChunkReader::new(archive);
+            let copied = cm.copy_to_container(&start.container_id, &start.path, &mut archive);
+            let _ = done_tx.send(copied);
+        });
+
+        match done_rx.await {
+            Ok(Ok(())) => Ok(Response::new(CopyToContainerResponse { success: true })),
+            Ok(Err(err)) => Err(handle_container_manager_error(
+                err,
+                "copy to container failed",
+            )),
+            Err(_) => Err(Status::internal("copy to container failed")),
+        }
+    }
+
+    async fn create_volume(
+        &self,
+        request: Request<CreateVolumeRequest>,
+    ) -> Result<Response<CreateVolumeResponse>, Status> {
+        debug!("Got create volume request: {:?}", request);
+
+        let request = request.into_inner();
+
+        match self.cm.create_volume(&request.name) {
+            Ok(name) => Ok(Response::new(CreateVolumeResponse { name })),
+            Err(err) => Err(handle_container_manager_error(err, "create volume failed")),
+        }
+    }
+
+    async fn list_volumes(
+        &self,
+        request: Request<ListVolumesRequest>,
+    ) -> Result<Response<ListVolumesResponse>, Status> {
+        debug!("Got list volumes request: {:?}", request);
+
+        match self.cm.list_volumes() {
+            Ok(volumes) => Ok(Response::new(ListVolumesResponse {
+                volumes: volumes
+                    .into_iter()
+                    .map(map_volume_to_volume_response)
+                    .collect(),
+            })),
+            Err(err) => Err(handle_container_manager_error(err, "list volumes failed")),
+        }
+    }
+
+    async fn remove_volume(
+        &self,
+        request: Request<RemoveVolumeRequest>,
+    ) -> Result<Response<RemoveVolumeResponse>, Status> {
+        debug!("Got remove volume request: {:?}", request);
+
+        let request = request.into_inner();
+
+        // removing deletes the volume's data
+        match self
+            .blocking(move |cm| cm.remove_volume(&request.name))
+            .await?
+        {
+            Ok(_) => Ok(Response::new(RemoveVolumeResponse { success: true })),
+            Err(err) => Err(handle_container_manager_error(err, "remove volume failed")),
+        }
+    }
+
+    async fn import_image(
+        &self,
+        request: Request<ImportImageRequest>,
+    ) -> Result<Response<ImportImageResponse>, Status> {
+        debug!("Got import image request: {:?}", request);
+
+        let request = request.into_inner();
+
+        // importing hashes and unpacks every layer of the image
+        match self
+            .blocking(move |cm| cm.import_image(&request.path, &request.reference))
+            .await?
+        {
+            Ok(images) => Ok(Response::new(ImportImageResponse {
+                images: images
+                    .into_iter()
+                    .map(map_image_to_image_response)
+                    .collect(),
+            })),
+            Err(err) => Err(handle_container_manager_error(err, "import image failed")),
+        }
+    }
+
+    async fn list_images(
+        &self,
+        request: Request<ListImagesRequest>,
+    ) -> Result<Response<ListImagesResponse>, Status> {
+        debug!("Got list images request: {:?}", request);
+
+        match self.cm.list_images() {
+            Ok(images) => Ok(Response::new(ListImagesResponse {
+                images: images
+                    .into_iter()
+                    .map(map_image_to_image_response)
+                    .collect(),
+            })),
+            Err(err) => Err(handle_container_manager_error(err, "list images failed")),
+        }
+    }
+
+    async fn remove_image(
+        &self,
+        request: Request<RemoveImageRequest>,
+    ) -> Result<Response<RemoveImageResponse>, Status> {
+        debug!("Got remove image request: {:?}", request);
+
+        let request = request.into_inner();
+
+        // removing waits out imports and commits, and deletes the image's rootfs
+        match self
+            .blocking(move |cm| cm.remove_image(&request.image))
+            .await?
+        {
+            Ok(_) => Ok(Response::new(RemoveImageResponse { success: true })),
+            Err(err) => Err(handle_container_manager_error(err, "remove image failed")),
+        }
+    }
+}
+
+/// forward_input forwards the input of a client stream, such as the frames of
+/// an interactive session, mapped out of its requests by `map`, to the
+/// returned receiver until the client closes its side of the stream
+fn forward_input<T, U, M>(mut inbound: Streaming<T>, map: M) -> sync::mpsc::Receiver<U>
+where
+    T: Send + 'static,
+    U: Send + 'static,
+    M: Fn(T) -> Option<U> + Send + 'static,
+{
+    let (input_tx, input_rx) = sync::mpsc::channel();
+    tokio::spawn(async move {
+        while let Ok(Some(message)) = inbound.message().await {
+            if let Some(input) = map(message) {
+                if input_tx.send(input).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+    input_rx
+}
+
+/// send_session_output sends an output frame of an interactive session to the
+/// client, returning false if the client has gone away
+fn send_session_output(
+    tx: &mut mpsc::Sender<Result<InteractiveResponse, Status>>,
+    frame: Frame,
+) -> bool {
+    let event = match frame {
+        Frame::Stdout(output) => interactive_response::Event::Stdout(output),
+        Frame::Stderr(output) => interactive_response::Event::Stderr(output),
+        Frame::Exit(exit_code) => interactive_response::Event::ExitCode(exit_code),
+        Frame::Stdin(_) | Frame::Resize(_) => return true,
+    };
+    let response = InteractiveResponse { event: Some(event) };
+    block_on(tx.send(Ok(response))).is_ok()
+}
+
+impl FromChunk for ExportContainerResponse {
+    fn from_chunk(data: Vec<u8>) -> Self {
+        ExportContainerResponse { data }
+    }
+}
+
+impl FromChunk for CopyFromContainerResponse {
+    fn from_chunk(data: Vec<u8>) -> Self {
+        CopyFromContainerResponse { data }
+    }
+}
+
+fn map_terminal_size_to_window_size(size: TerminalSize) -> WindowSize {
+    WindowSize {
+        rows: size.rows as u16,
+        cols: size.cols as u16,
+    }
+}
+
+/// map_create_container_request_to_container_options validates a create
+/// request, returning a message describing the invalid argument if it isn't
+/// valid
+fn map_create_container_request_to_container_options(
+    request: CreateContainerRequest,
+) -> Result<ContainerOptions, String> {
+    if request.rootfs_path.is_empty() == request.image.is_empty() {
+        return Err("exactly one of rootfs_path and image must be given".into());
+    }
+    if !request.image.is_empty() && !image::is_valid_reference(&request.image) {
+        return Err(format!(
+            "invalid image {}, expected NAME[:TAG] or an image id",
+            request.image
+        ));
+    }
+    if request.image.is_empty() && request.command.is_empty() {
+        return Err("command must be given with rootfs_path".into());
+    }
+    validate_process(&request.env, &request.cwd)?;
+    let mounts = request
+        .mounts
+        .into_iter()
+        .map(map_mount_request_to_mount)
+        .collect::<Result<_, _>>()?;
+    let resources = map_resources_request_to_resources(request.resources.unwrap_or_default())?;
+    Ok(ContainerOptions {
+        name: request.name,
+        command: request.command,
+        args: request.args,
+        rootfs_path: request.rootfs_path,
+        image: request.image,
+        tty: request.tty,
+        stop_signal: request.stop_signal,
+        stop_timeout: request.stop_timeout.map(|timeout| timeout.seconds),
+        restart_policy: request.restart_policy,
+        env: request.env,
+        cwd: request.cwd,
+        user: request.user,
+        mounts,
+        resources,
+        labels: request.labels,
+        annotations: request.annotations,
+    })
+}
+
+/// map_resources_request_to_resources validates the cgroup limits of a
+/// request, returning a message describing the invalid argument if they
+/// aren't valid
+fn map_resources_request_to_resources(
+    resources: cruise_grpc::Resources,
+) -> Result<Resources, String> {
+    // limits that can be lifted with -1
+    let limit = |name: &str, value: i64| match value {
+        0 => Ok(None),
+        -1 => Ok(Some(-1)),
+        value if value > 0 => Ok(Some(value)),
+        value => Err(format!(
+            "invalid {} {}, expected -1 or more than 0",
+            name, value
+        )),
+    };
+    let bounded = |name: &str, value: u64, min: u64, max: u64| match value {
+        0 => Ok(None),
+        value if value >= min && value <= max => Ok(Some(value)),
+        value => Err(format!(
+            "invalid {} {}, expected from {} to {}",
+            name, value, min, max
+        )),
+    };
+    let memory_limit = limit("memory limit", resources.memory_limit)?;
+    let memory_reservation = limit("memory reservation", resources.memory_reservation)?;
+    if let (Some(memory_limit), Some(memory_reservation)) = (memory_limit, memory_reservation) {
+        if memory_limit > 0 && (memory_reservation == -1 || memory_reservation > memory_limit) {
+            return Err(format!(
+                "memory reservation {} exceeds memory limit {}",
+                memory_reservation, memory_limit
+            ));
+        }
+    }
+    for (name, list) in [
+        ("cpuset cpus", &resources.cpuset_cpus),
+        ("cpuset mems", &resources.cpuset_mems),
+    ] {
+        if !list
+            .chars()
+            .all(|c| c.is_ascii_digit() || c == ',' || c == '-')
+        {
+            return Err(format!(
+                "invalid {} {}, expected a list such as 0-2,4",
+                name, list
+            ));
+        }
+    }
+    Ok(Resources {
+        memory_limit,
+        memory_reservation,
+        cpu_shares: bounded("cpu shares", resources.cpu_shares, 2, 262_144)?,
+        cpu_quota: limit("cpu quota", resources.cpu_quota)?,
+        cpu_period: bounded("cpu period", resources.cpu_period, 1000, 1_000_000)?,
+        cpuset_cpus: resources.cpuset_cpus,
+        cpuset_mems: resources.cpuset_mems,
+        pids_limit: limit("pids limit", resources.pids_limit)?,
+    })
+}
+
+/// map_mount_request_to_mount validates a mount of a create request,
+/// returning a message describing the invalid argument if it isn't valid
+fn map_mount_request_to_mount(mount: cruise_grpc::Mount) -> Result<Mount, String> {
+    if mount.source.is_empty() {
+        return Err(format!(
+            "no source given for mount at {}",
+            mount.destination
+        ));
+    }
+    if !mount.destination.starts_with('/') {
+        return Err(format!(
+            "invalid mount destination {}, expected an absolute path",
+            mount.destination
+        ));
+    }
+    if !mount.propagation.is_empty() && !MOUNT_PROPAGATIONS.contains(&mount.propagation.as_str()) {
+        return Err(format!(
+            "invalid mount propagation {}, expected one of {}",
+            mount.propagation,
+            MOUNT_PROPAGATIONS.join(", ")
+        ));
+    }
+    Ok(Mount {
+        source: mount.source,
+        destination: mount.destination,
+        read_only: mount.read_only,
+        propagation: mount.propagation,
+    })
+}
+
+/// map_exec_container_request_to_exec_options validates an exec request,
+/// returning a message describing the invalid argument if it isn't valid
+fn map_exec_container_request_to_exec_options(
+    request: ExecContainerRequest,
+) -> Result<ExecOptions, String> {
+    if request.command.is_empty() {
+        return Err("no command given".into());
+    }
+    validate_process(&request.env, &request.cwd)?;
+    Ok(ExecOptions {
+        command: request.command,
+        args: request.args,
+        env: request.env,
+        cwd: request.cwd,
+        user: request.user,
+    })
+}
+
+/// validate_process validates the environment and working directory of a
+/// process
+fn validate_process(env: &[String], cwd: &str) -> Result<(), String> {
+    if let Some(env) = env.iter().find(|env| !env.contains('=')) {
+        return Err(format!("invalid env {}, expected KEY=VALUE", env));
+    }
+    if !cwd.is_empty() && !cwd.starts_with('/') {
+        return Err(format!(
+            "invalid workdir {}, expected an absolute path",
+            cwd
+        ));
+    }
+    Ok(())
+}
+
+/// validate_container_path validates a path in a container to copy from or to
+fn validate_container_path(path: &str) -> Result<(), String> {
+    if !path.starts_with('/') {
+        return Err(format!("invalid path {}, expected an absolute path", path));
+    }
+    Ok(())
+}
+
+fn map_log_line_to_container_logs_response(log_line: LogLine) -> ContainerLogsResponse {
+    ContainerLogsResponse {
+        timestamp: log_line
+            .timestamp
+            .to_rfc3339_opts(SecondsFormat::Nanos, true),
+        stream: log_line.stream.to_string(),
+        line: log_line.line,
+    }
+}
+
+/// map_container_logs_request_to_logs_options validates a logs request,
+/// returning a message describing the invalid argument if it isn't valid
+fn map_container_logs_request_to_logs_options(
+    request: &ContainerLogsRequest,
+) -> Result<LogsOptions, String> {
+    let since = if request.since.is_empty() {
+        None
+    } else {
+        match DateTime::parse_from_rfc3339(&request.since) {
+            Ok(since) => Some(since.with_timezone(&Utc)),
+            Err(err) => {
+                return Err(format!(
+                    "invalid since timestamp {}: {}",
+                    request.since, err
+                ))
+            }
+        }
+    };
+    let stream = if request.stream.is_empty() {
+        None
+    } else {
+        match request.stream.parse() {
+            Ok(stream) => Some(stream),
+            Err(_) => {
+                return Err(format!(
+                    "invalid stream {}, expected stdout or stderr",
+                    request.stream
+                ))
+            }
+        }
+    };
+    Ok(LogsOptions {
+        follow: request.follow,
+        tail: match request.tail {
+            0 => None,
+            tail => Some(tail as usize),
+        },
+        filter: LogFilter { since, stream },
+    })
+}
+
+/// map_list_containers_request_to_container_filter validates a list request,
+/// returning a message describing the invalid argument if it isn't valid
+fn map_list_containers_request_to_container_filter(
+    request: ListContainersRequest,
+) -> Result<ContainerFilter, String> {
+    let labels = container::parse_label_selector(&request.label_selector).ok_or_else(|| {
+        format!(
+            "invalid label selector {}, expected comma separated KEY=VALUE, KEY!=VALUE, KEY \
+             or !KEY",
+            request.label_selector
+        )
+    })?;
+    let statuses = request
+        .statuses
+        .iter()
+        .map(|status| {
+            status.parse().map_err(|_| {
+                format!(
+                    "invalid status {}, expected created, running, paused or stopped",
+                    status
+                )
+            })
+        })
+        .collect::<Result<_, _>>()?;
+    Ok(ContainerFilter {
+        labels,
+        statuses,
+        names: request.names,
+    })
+}
+
+fn map_stats_to_container_stats_response(stats: Stats) -> ContainerStatsResponse {
+    let read_at: DateTime<Utc> = stats.read_at.into();
+    ContainerStatsResponse {
+        read_at: read_at.to_rfc3339_opts(SecondsFormat::Nanos, true),
+        cpu_percent: stats.cpu_percent,
+        cpu_usage: stats.usage.cpu_usage,
+        memory_usage: stats.usage.memory_usage,
+        memory_limit: stats.usage.memory_limit.unwrap_or_default(),
+        pids_current: stats.usage.pids_current,
+        pids_limit: stats.usage.pids_limit.unwrap_or_default(),
+        block_read_bytes: stats.usage.block_read_bytes,
+        block_write_bytes: stats.usage.block_write_bytes,
+    }
 }
 
 fn map_container_to_container_response(container: Container) -> GetContainerResponse {
@@ -180,8 +1120,61 @@ fn map_container_to_container_response(container: Container) -> GetContainerResp
             }
             None => "Not started yet.".into(),
         },
-        finished_at: "n/a".into(), // TODO: update when we have the container shim reporting finishing time
+        finished_at: match container.finished_at {
+            Some(finished_at) => {
+                let datetime: DateTime<Utc> = finished_at.into();
+                format!("{}", datetime.format("%+"))
+            }
+            None => "Not finished yet.".into(),
+        },
+        restart_policy: container.restart_policy.to_string(),
+        restart_count: container.restart_count,
+        restarted_at: match container.restarted_at {
+            Some(restarted_at) => {
+                let datetime: DateTime<Utc> = restarted_at.into();
+                format!("{}", datetime.format("%+"))
+            }
+            None => "Not restarted yet.".into(),
+        },
+        resources: Some(map_resources_to_resources_response(&container.resources)),
+        oom_killed: container.oom_killed,
+        reason: container.reason,
+        image: container.image,
+        labels: container.labels,
+        annotations: container.annotations,
         command: container.command,
         args: container.args,
+        tty: container.tty,
+    }
+}
+
+fn map_resources_to_resources_response(resources: &Resources) -> cruise_grpc::Resources {
+    // unset limits are sent as 0
+    cruise_grpc::Resources {
+        memory_limit: resources.memory_limit.unwrap_or_default(),
+        memory_reservation: resources.memory_reservation.unwrap_or_default(),
+        cpu_shares: resources.cpu_shares.unwrap_or_default(),
+        cpu_quota: resources.cpu_quota.unwrap_or_default(),
+        cpu_period: resources.cpu_period.unwrap_or_default(),
+        cpuset_cpus: resources.cpuset_cpus.clone(),
+        cpuset_mems: resources.cpuset_mems.clone(),
+        pids_limit: resources.pids_limit.unwrap_or_default(),
+    }
+}
+
+fn map_volume_to_volume_response(volume: Volume) -> cruise_grpc::Volume {
+    let created_at: DateTime<Utc> = volume.created_at.into();
+    cruise_grpc::Volume {
+        name: volume.name,
+        created_at: format!("{}", created_at.format("%+")),
+    }
+}
+
+fn map_image_to_image_response(image: Image) -> cruise_grpc::Image {
+    let created_at: DateTime<Utc> = image.created_at.into();
+    cruise_grpc::Image {
+        id: image.id,
+        references: image.references,
+        created_at: format!("{}", created_at.format("%+")),
     }
 }
@@ -0,0 +1,93 @@
+use serde::{Deserialize, Serialize};
+use std::time::SystemTime;
+
+// the tag of references that don't name one
+const DEFAULT_TAG: &str = "latest";
+// how many hex digits of an image id are shown
+pub const SHORT_ID_LEN: usize = 12;
+
+/// Image is an image imported into the daemon, unpacked into a root
+/// filesystem that containers can be created from
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Image {
+    // digest of the image config, `sha256:HEX`
+    pub id: String,
+    // NAME:TAG references to the image
+    pub references: Vec<String>,
+    // digests of the layers of the image, bottom first
+    pub layers: Vec<String>,
+    pub config: ImageConfig,
+    // when the image was imported
+    pub created_at: SystemTime,
+}
+
+/// ImageConfig is the defaults an image sets for the containers created
+/// from it
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct ImageConfig {
+    pub entrypoint: Vec<String>,
+    pub cmd: Vec<String>,
+    // KEY=VALUE pairs
+    pub env: Vec<String>,
+    pub working_dir: String,
+    pub user: String,
+}
+
+impl Image {
+    /// short_id is the first hex digits of the image id
+    pub fn short_id(&self) -> &str {
+        short_id(&self.id)
+    }
+}
+
+pub fn short_id(id: &str) -> &str {
+    let hex = id.strip_prefix("sha256:").unwrap_or(id);
+    hex.get(..SHORT_ID_LEN).unwrap_or(hex)
+}
+
+/// normalize_reference adds the default tag to references without one. a
+/// colon after the last slash separates the tag, others are a registry port
+pub fn normalize_reference(reference: &str) -> String {
+    let name_start = reference.rfind('/').map_or(0, |slash| slash + 1);
+    if reference[name_start..].contains(':') {
+        reference.to_string()
+    } else {
+        format!("{}:{}", reference, DEFAULT_TAG)
+    }
+}
+
+/// is_valid_reference returns whether `reference` can reference an image:
+/// a lowercase name of alphanumerics and `.`, `_`, `-`, `/` separators,
+/// optionally with a registry host and port, followed by an optional tag of
+/// alphanumerics, `_`, `.` or `-`
+pub fn is_valid_reference(reference: &str) -> bool {
+    let name_start = reference.rfind('/').map_or(0, |slash| slash + 1);
+    let (name, tag) = match reference[name_start..].rfind(':') {
+        Some(colon) => (
+            &reference[..name_start + colon],
+            Some(&reference[name_start + colon + 1..]),
+        ),
+        None => (reference, None),
+    };
+    let valid_name = !name.is_empty()
+        && name.split('/').enumerate().all(|(i, component)| {
+            // the first component may be a registry host, with a port
+            let registry = i == 0 && name.contains('/');
+            !component.is_empty()
+                && component.chars().all(|c| {
+                    c.is_ascii_digit()
+                        || c.is_ascii_lowercase()
+                        || c == '.'
+                        || c == '_'
+                        || c == '-'
+                        || (registry && (c.is_ascii_uppercase() || c == ':'))
+                })
+        });
+    let valid_tag = tag.is_none_or(|tag| {
+        !tag.is_empty()
+            && tag
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '-')
+    });
+    valid_name && valid_tag
+}
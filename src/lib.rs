@@ -1,5 +1,12 @@
+pub mod attach;
+pub mod chunk;
 pub mod client;
+pub mod console;
 pub mod container;
+pub mod container_log;
 pub mod container_manager;
 pub mod daemon;
+pub mod image;
 pub mod logging;
+pub mod shim;
+pub mod volume;
@@ -0,0 +1,560 @@
+use crate::attach::Frame;
+use crate::console::{self, ConsoleSocket};
+use crate::container::ContainerExit;
+use crate::container_log::{LogLine, Stream};
+use std::error::Error;
+use std::fmt;
+use std::fs::{read_to_string, remove_file, rename, write, File, OpenOptions};
+use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
+use std::os::unix::io::{FromRawFd, IntoRawFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::process::{self, Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime};
+
+// what the supervising shim reports back once the runtime has created the container
+const REPORT_OK: &str = "ok";
+// where cgroup hierarchies are mounted
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+// attached clients that can't keep up with the container's output for this
+// long are dropped, rather than stalling the container
+const ATTACH_WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub struct ShimOptions {
+    // where the container's exit code and finish time are written
+    pub exit_file: String,
+    // the pid file the runtime writes the container's init pid into
+    pub pid_file: String,
+    // where the container's stdout and stderr are logged
+    pub log_file: String,
+    // the socket clients attach to the container through
+    pub attach_socket: String,
+    // the socket the runtime sends the container's terminal over, if the
+    // container has one
+    pub console_socket: Option<String>,
+    // the runtime create invocation, e.g. `runc create --bundle ... id`
+    pub runtime_cmd: Vec<String>,
+}
+
+#[derive(Debug)]
+pub enum ShimError {
+    // represents an error creating the pipe used to report back to the caller
+    CreateReportPipeError { source: std::io::Error },
+    // represents an error forking the supervising shim process
+    ForkError { source: std::io::Error },
+    // represents an error reading the report of the supervising shim
+    ReadReportError { source: std::io::Error },
+    // represents an error the supervising shim hit while creating the container
+    CreateContainerError { message: String },
+}
+
+impl fmt::Display for ShimError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Self::CreateReportPipeError { .. } => write!(f, "failed to create shim report pipe"),
+            Self::ForkError { .. } => write!(f, "failed to fork shim"),
+            Self::ReadReportError { .. } => write!(f, "failed to read shim report"),
+            Self::CreateContainerError { ref message } => {
+                write!(f, "failed to create container: {}", message)
+            }
+        }
+    }
+}
+
+impl Error for ShimError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            Self::CreateReportPipeError { ref source } => Some(source),
+            Self::ForkError { ref source } => Some(source),
+            Self::ReadReportError { ref source } => Some(source),
+            Self::CreateContainerError { .. } => None,
+        }
+    }
+}
+
+/// run does the following:
+/// - fork a supervising shim process that detaches into its own session
+/// - wait for the supervising shim to report whether the runtime created the
+///   container, and return that result to the caller
+///
+/// Returning lets whoever launched the shim (the daemon) wait for container
+/// creation without having to reap the long-lived supervisor.
+pub fn run(opts: ShimOptions) -> Result<(), ShimError> {
+    let mut fds = [0; 2];
+    // safe: fds is a valid two element array
+    if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } != 0 {
+        return Err(ShimError::CreateReportPipeError {
+            source: std::io::Error::last_os_error(),
+        });
+    }
+    // safe: the pipe fds were just created and are owned by the File from here on
+    let (mut report_reader, report_writer) =
+        unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(fds[1])) };
+    // safe: the shim is single threaded at this point
+    match unsafe { libc::fork() } {
+        -1 => Err(ShimError::ForkError {
+            source: std::io::Error::last_os_error(),
+        }),
+        0 => {
+            drop(report_reader);
+            supervise(opts, report_writer)
+        }
+        _ => {
+            drop(report_writer);
+            let mut report = String::new();
+            report_reader
+                .read_to_string(&mut report)
+                .map_err(|source| ShimError::ReadReportError { source })?;
+            if report == REPORT_OK {
+                Ok(())
+            } else if report.is_empty() {
+                Err(ShimError::CreateContainerError {
+                    message: "shim exited without reporting".into(),
+                })
+            } else {
+                Err(ShimError::CreateContainerError { message: report })
+            }
+        }
+    }
+}
+
+/// supervise does the following, as the forked supervising shim:
+/// - detach from the caller's session and stdio
+/// - become a subreaper so the container's init process is reparented to us
+///   once the runtime's create process exits
+/// - run the runtime create command, capturing the container's output in its
+///   log file and copying it to attached clients, and report the outcome
+/// - wait for the container's init process to exit and record its exit code,
+///   finish time and whether the OOM killer killed any of its processes in
+///   the exit file
+/// - let attached clients know the container exited
+fn supervise(opts: ShimOptions, mut report_writer: File) -> ! {
+    if let Err(message) = detach() {
+        let _ = report_writer.write_all(message.as_bytes());
+        process::exit(1);
+    }
+    let attached = Attached::default();
+    let (pid, copiers) = match create_container(&opts, &attached) {
+        Ok(created) => created,
+        Err(message) => {
+            let _ = report_writer.write_all(message.as_bytes());
+            process::exit(1);
+        }
+    };
+    let _ = report_writer.write_all(REPORT_OK.as_bytes());
+    drop(report_writer);
+
+    // the container's cgroup outlives its init process until the runtime
+    // deletes the container, so look it up while the process is still around
+    let oom_control = container_oom_control(pid);
+    let oom_kills_at_start = oom_control.as_deref().and_then(oom_kills);
+    let exit_code = wait_for_exit(pid);
+    let oom_kills_at_exit = oom_control.as_deref().and_then(oom_kills);
+    // make sure all of the container's output is in the log before reporting
+    // the exit
+    for copier in copiers {
+        let _ = copier.join();
+    }
+    let container_exit = ContainerExit {
+        exit_code,
+        finished_at: SystemTime::now(),
+        oom_killed: match (oom_kills_at_start, oom_kills_at_exit) {
+            (Some(at_start), Some(at_exit)) => at_exit > at_start,
+            _ => false,
+        },
+    };
+    // the container directory is gone if the container was deleted before it
+    // exited, in which case there's nobody left to report to
+    if let Ok(serialized_exit) = serde_json::to_string(&container_exit) {
+        let temp_exit_file = format!("{}.temp", opts.exit_file);
+        if write(&temp_exit_file, serialized_exit).is_ok() {
+            let _ = rename(&temp_exit_file, &opts.exit_file);
+        }
+    }
+    attached.broadcast(&Frame::Exit(exit_code));
+    let _ = remove_file(&opts.attach_socket);
+    process::exit(0);
+}
+
+fn detach() -> Result<(), String> {
+    // safe: none of these calls touch memory we own
+    unsafe {
+        if libc::setsid() == -1 {
+            return Err(format!(
+                "failed to create session: {}",
+                std::io::Error::last_os_error()
+            ));
+        }
+        if libc::prctl(libc::PR_SET_CHILD_SUBREAPER, 1, 0, 0, 0) == -1 {
+            return Err(format!(
+                "failed to become subreaper: {}",
+                std::io::Error::last_os_error()
+            ));
+        }
+    }
+    // release the caller's stdio so it isn't held open for the container's lifetime
+    let dev_null = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/null")
+        .map_err(|err| format!("failed to open /dev/null: {}", err))?
+        .into_raw_fd();
+    for fd in 0..3 {
+        // safe: dev_null and the standard fds are valid descriptors
+        if unsafe { libc::dup2(dev_null, fd) } == -1 {
+            return Err(format!(
+                "failed to redirect stdio: {}",
+                std::io::Error::last_os_error()
+            ));
+        }
+    }
+    if dev_null > 2 {
+        // safe: dev_null is ours and has been duplicated onto stdio
+        unsafe { libc::close(dev_null) };
+    }
+    Ok(())
+}
+
+// lines the runtime writes to stderr while creating the container, kept so
+// they can be reported if creation fails. None once the container is created
+type RuntimeStderr = Arc<Mutex<Option<Vec<String>>>>;
+
+/// Attached holds the connections of the clients attached to the container,
+/// which the container's output is copied to
+#[derive(Clone, Default)]
+struct Attached(Arc<Mutex<Vec<UnixStream>>>);
+
+impl Attached {
+    fn add(&self, conn: UnixStream) {
+        self.0.lock().unwrap().push(conn);
+    }
+
+    /// broadcast sends `frame` to every attached client, dropping clients
+    /// that have gone away
+    fn broadcast(&self, frame: &Frame) {
+        self.0
+            .lock()
+            .unwrap()
+            .retain(|mut conn| frame.write_to(&mut conn).is_ok());
+    }
+}
+
+/// create_container does the following:
+/// - open the container's log file, and the console socket if the container
+///   has a terminal
+/// - run the runtime create command with its stdout and stderr piped into
+///   threads that copy them into the log file. without a terminal, the
+///   container's init process inherits these pipes, so this captures the
+///   container's output too
+/// - with a terminal, receive its master from the runtime and copy the
+///   container's output from it instead
+/// - serve the attach socket
+/// - return the pid of the container's init process and the copying threads
+fn create_container(
+    opts: &ShimOptions,
+    attached: &Attached,
+) -> Result<(libc::pid_t, Vec<JoinHandle<()>>), String> {
+    let (program, args) = match opts.runtime_cmd.split_first() {
+        Some(cmd) => cmd,
+        None => return Err("no runtime command given".into()),
+    };
+    let console_socket = match opts.console_socket {
+        Some(ref console_socket) => {
+            Some(ConsoleSocket::bind(console_socket).map_err(|err| {
+                format!("failed to bind console socket {}: {}", console_socket, err)
+            })?)
+        }
+        None => None,
+    };
+    let log_file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&opts.log_file)
+        .map_err(|err| format!("failed to open log file {}: {}", opts.log_file, err))?;
+    let log_file = Arc::new(Mutex::new(log_file));
+    let runtime_stderr: RuntimeStderr = Arc::new(Mutex::new(Some(vec![])));
+    let mut runtime = Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| format!("failed to execute {}: {}", program, err))?;
+    let mut copiers = vec![
+        copy_output(
+            runtime.stdout.take().unwrap(),
+            Stream::Stdout,
+            log_file.clone(),
+            runtime_stderr.clone(),
+            attached.clone(),
+        ),
+        copy_output(
+            runtime.stderr.take().unwrap(),
+            Stream::Stderr,
+            log_file.clone(),
+            runtime_stderr.clone(),
+            attached.clone(),
+        ),
+    ];
+    let status = runtime
+        .wait()
+        .map_err(|err| format!("failed to wait for {}: {}", program, err))?;
+    if !status.success() {
+        // nothing else holds the pipes open if the container wasn't created
+        for copier in copiers {
+            let _ = copier.join();
+        }
+        let message = runtime_stderr.lock().unwrap().take().unwrap_or_default();
+        return Err(format!(
+            "{} exited with {}: {}",
+            program,
+            status,
+            message.join("\n")
+        ));
+    }
+    runtime_stderr.lock().unwrap().take();
+    let pid = read_to_string(&opts.pid_file)
+        .map_err(|err| format!("failed to read pid file {}: {}", opts.pid_file, err))?;
+    let pid = pid
+        .trim()
+        .parse()
+        .map_err(|err| format!("failed to parse pid `{}`: {}", pid.trim(), err))?;
+    // the runtime sends the terminal before it exits, so it's waiting on the
+    // console socket by now
+    let terminal = match console_socket {
+        Some(console_socket) => match console_socket.try_accept_master() {
+            Ok(Some(terminal)) => Some(Arc::new(terminal)),
+            Ok(None) => return Err(format!("{} did not send the container's terminal", program)),
+            Err(err) => {
+                return Err(format!(
+                    "failed to receive the container's terminal: {}",
+                    err
+                ))
+            }
+        },
+        None => None,
+    };
+    if let Some(ref terminal) = terminal {
+        let terminal_reader = terminal
+            .try_clone()
+            .map_err(|err| format!("failed to clone the container's terminal: {}", err))?;
+        copiers.push(copy_terminal(terminal_reader, log_file, attached.clone()));
+    }
+    // a stale socket file would fail the bind
+    let _ = remove_file(&opts.attach_socket);
+    let attach_listener = UnixListener::bind(&opts.attach_socket).map_err(|err| {
+        format!(
+            "failed to bind attach socket {}: {}",
+            opts.attach_socket, err
+        )
+    })?;
+    serve_attach(attach_listener, terminal, attached.clone());
+    Ok((pid, copiers))
+}
+
+/// serve_attach accepts clients on the attach socket, copying the
+/// container's output to them and their input to the container's terminal.
+/// clients' input is dropped if the container has no terminal
+fn serve_attach(listener: UnixListener, terminal: Option<Arc<File>>, attached: Attached) {
+    thread::spawn(move || {
+        for conn in listener.incoming() {
+            let conn = match conn {
+                Ok(conn) => conn,
+                Err(_) => continue,
+            };
+            let output = match conn.try_clone() {
+                Ok(output) => output,
+                Err(_) => continue,
+            };
+            let _ = output.set_write_timeout(Some(ATTACH_WRITE_TIMEOUT));
+            attached.add(output);
+            let terminal = terminal.clone();
+            thread::spawn(move || forward_input(conn, terminal));
+        }
+    });
+}
+
+/// forward_input copies a client's input and resizes to the container's
+/// terminal until the client detaches
+fn forward_input(mut conn: UnixStream, terminal: Option<Arc<File>>) {
+    while let Ok(Some(frame)) = Frame::read_from(&mut conn) {
+        let terminal = match terminal {
+            Some(ref terminal) => terminal,
+            None => continue,
+        };
+        // there's nowhere to report failures, the client sees the effects
+        // through the container's output
+        match frame {
+            Frame::Stdin(input) => {
+                let _ = (&**terminal).write_all(&input);
+            }
+            Frame::Resize(size) => {
+                let _ = console::set_window_size(terminal, size);
+            }
+            _ => (),
+        }
+    }
+}
+
+/// copy_terminal copies the container's output from its terminal to
+/// attached clients as it comes, and into the log file line by line, until
+/// the container's side of the terminal is closed
+fn copy_terminal(
+    mut terminal: File,
+    log_file: Arc<Mutex<File>>,
+    attached: Attached,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let mut buf = [0u8; 4096];
+        let mut line = vec![];
+        loop {
+            let read = match terminal.read(&mut buf) {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                // reading a terminal fails with EIO once the other side is closed
+                Err(_) => break,
+            };
+            attached.broadcast(&Frame::Stdout(buf[..read].to_vec()));
+            line.extend_from_slice(&buf[..read]);
+            while let Some(end) = line.iter().position(|&b| b == b'\n') {
+                let mut complete: Vec<u8> = line.drain(..=end).collect();
+                complete.pop();
+                // terminals end lines with \r\n
+                if complete.last() == Some(&b'\r') {
+                    complete.pop();
+                }
+                write_log_line(&log_file, &LogLine::new(Stream::Stdout, &complete));
+            }
+        }
+        if !line.is_empty() {
+            write_log_line(&log_file, &LogLine::new(Stream::Stdout, &line));
+        }
+    })
+}
+
+fn write_log_line(log_file: &Mutex<File>, log_line: &LogLine) {
+    // there's nowhere to report a failed write, keep draining the output so
+    // the container doesn't block on it
+    let _ = log_file
+        .lock()
+        .unwrap()
+        .write_all(log_line.format().as_bytes());
+}
+
+/// copy_output copies lines from `output` into the log file, tagged with
+/// their timestamp and stream, and to attached clients until `output` is
+/// closed
+fn copy_output<R: Read + Send + 'static>(
+    output: R,
+    stream: Stream,
+    log_file: Arc<Mutex<File>>,
+    runtime_stderr: RuntimeStderr,
+    attached: Attached,
+) -> JoinHandle<()> {
+    thread::spawn(move || {
+        let mut output = BufReader::new(output);
+        let mut buf = vec![];
+        loop {
+            buf.clear();
+            match output.read_until(b'\n', &mut buf) {
+                Ok(0) | Err(_) => return,
+                Ok(_) => (),
+            }
+            let frame = match stream {
+                Stream::Stdout => Frame::Stdout(buf.clone()),
+                Stream::Stderr => Frame::Stderr(buf.clone()),
+            };
+            attached.broadcast(&frame);
+            if buf.last() == Some(&b'\n') {
+                buf.pop();
+            }
+            let log_line = LogLine::new(stream, &buf);
+            if stream == Stream::Stderr {
+                if let Some(lines) = runtime_stderr.lock().unwrap().as_mut() {
+                    lines.push(log_line.line.clone());
+                }
+            }
+            write_log_line(&log_file, &log_line);
+        }
+    })
+}
+
+/// container_oom_control returns the path of the file counting OOM kills in
+/// the memory cgroup of `pid`, memory.oom_control with cgroup v1 or
+/// memory.events with cgroup v2, if the process has a memory cgroup of its
+/// own. a process left in our cgroup, as when the runtime can't create
+/// cgroups, would see OOM kills of unrelated processes
+fn container_oom_control(pid: libc::pid_t) -> Option<String> {
+    let memory_cgroup = |cgroups: String| {
+        let entries: Vec<(String, String)> = cgroups
+            .lines()
+            .filter_map(|line| {
+                // hierarchy-id:controllers:path
+                let mut fields = line.splitn(3, ':').skip(1);
+                Some((fields.next()?.to_string(), fields.next()?.to_string()))
+            })
+            .collect();
+        // a cgroup v1 memory controller takes precedence over the cgroup v2
+        // `0::/path` entry, which hybrid hierarchies also have
+        let v1 = entries
+            .iter()
+            .find(|(controllers, _)| controllers.split(',').any(|c| c == "memory"))
+            .map(|(_, path)| format!("{}/memory{}/memory.oom_control", CGROUP_ROOT, path));
+        let v2 = || {
+            entries
+                .iter()
+                .find(|(controllers, _)| controllers.is_empty())
+                .map(|(_, path)| format!("{}{}/memory.events", CGROUP_ROOT, path))
+        };
+        v1.or_else(v2)
+    };
+    let oom_control = memory_cgroup(read_to_string(format!("/proc/{}/cgroup", pid)).ok()?)?;
+    let shim_oom_control = memory_cgroup(read_to_string("/proc/self/cgroup").ok()?)?;
+    if oom_control == shim_oom_control {
+        return None;
+    }
+    Some(oom_control)
+}
+
+/// oom_kills reads how many processes the OOM killer has killed in a cgroup
+/// from its memory.oom_control or memory.events file
+fn oom_kills(oom_control: &str) -> Option<u64> {
+    read_to_string(oom_control)
+        .ok()?
+        .lines()
+        .find_map(|line| line.strip_prefix("oom_kill "))
+        .and_then(|count| count.trim().parse().ok())
+}
+
+/// wait_for_exit blocks until `pid` exits and returns its exit code, using
+/// the shell convention of 128 + signal for processes killed by a signal
+fn wait_for_exit(pid: libc::pid_t) -> i32 {
+    let mut status = 0;
+    loop {
+        // safe: status is a valid pointer for the duration of the call
+        let waited = unsafe { libc::waitpid(pid, &mut status, 0) };
+        if waited == pid {
+            break;
+        }
+        let err = std::io::Error::last_os_error();
+        if err.raw_os_error() == Some(libc::EINTR) {
+            continue;
+        }
+        // the process isn't our child (e.g. it was reparented elsewhere
+        // before we became a subreaper), so we can only watch for it to go away
+        // safe: signal 0 only checks for the process' existence
+        while unsafe { libc::kill(pid, 0) } == 0 {
+            thread::sleep(Duration::from_millis(100));
+        }
+        return -1;
+    }
+    if libc::WIFEXITED(status) {
+        libc::WEXITSTATUS(status)
+    } else if libc::WIFSIGNALED(status) {
+        128 + libc::WTERMSIG(status)
+    } else {
+        -1
+    }
+}
@@ -0,0 +1,28 @@
+use serde::{Deserialize, Serialize};
+use std::time::SystemTime;
+
+/// Volume is a directory managed by the daemon that containers can mount.
+/// Volumes outlive the containers that mount them.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Volume {
+    pub name: String,
+    pub created_at: SystemTime,
+}
+
+pub fn new(name: &str) -> Volume {
+    Volume {
+        name: name.to_string(),
+        created_at: SystemTime::now(),
+    }
+}
+
+/// is_valid_name returns whether `name` can name a volume: an alphanumeric
+/// character followed by alphanumerics, `_`, `.` or `-`
+pub fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(first) if first.is_ascii_alphanumeric() => (),
+        _ => return false,
+    }
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '-')
+}